@@ -1,19 +1,103 @@
 //! Representation of the Logo script as an Abstract Syntax Tree (AST).
 
+use std::fmt;
+
+use crate::{
+    canvas::{FillPattern, PenPattern, TextAlign},
+    interpreter::angles::AngleUnit,
+};
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ASTNode {
     Command(Command),
     ControlFlow(ControlFlow),
+    /// Marks the start of a statement on a line of a script, only added with
+    /// `--coverage`.
+    Line {
+        script: usize,
+        line: usize,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
-    Float(f32),
+    Float(f64),
     Number(i32),
     Usize(usize),
     Query(Query),
     Variable(String),
     Math(Box<Math>),
+    Function(Box<Function>),
+    /// A call to a procedure that gives back a value with `OUTPUT`.
+    Call(String, Vec<Expression>),
+    /// A quoted word that isn't a number, such as `"hello`.
+    Word(String),
+    /// `WORD a b`, joining two words into one.
+    Concat(Box<(Expression, Expression)>),
+    /// A list such as `[ 90 45 [ 1 2 ] ]`, whose items are numbers, words
+    /// or lists.
+    List(Vec<Expression>),
+    /// A function taking apart a list, such as `FIRST :ANGLES`.
+    ListOp(Box<ListOp>),
+    /// `GPROP "list "prop`, a property set with `PPROP`, or an empty list if
+    /// it isn't set.
+    GetProp(String, String),
+    /// An array, a list of a fixed size whose items can be changed with
+    /// `SETITEM`.
+    Array(Vec<Expression>),
+    /// `ARRAY n`, a new array of `n` items that each start at 0.
+    MakeArray(Box<Expression>),
+    /// `READWORD`, the next line of input as a word.
+    ReadWord,
+}
+
+impl Expression {
+    /// Whether evaluating the expression has side effects, such as calling
+    /// a procedure that draws or taking the next random number, so that it
+    /// must only be evaluated once.
+    pub fn has_side_effects(&self) -> bool {
+        match self {
+            Expression::Call(..) | Expression::ReadWord | Expression::Query(Query::ReadNumber) => {
+                true
+            }
+            Expression::Math(math) => {
+                let (lhs, rhs) = math.operands();
+                lhs.has_side_effects() || rhs.has_side_effects()
+            }
+            Expression::Function(function) => {
+                matches!(**function, Function::Random(_)) || function.operand().has_side_effects()
+            }
+            Expression::Query(Query::Touching(args)) => {
+                args.0.has_side_effects() || args.1.has_side_effects() || args.2.has_side_effects()
+            }
+            Expression::Query(Query::Intersects(distance)) => distance.has_side_effects(),
+            Expression::Query(Query::Towards(point) | Query::Distance(point)) => {
+                point.0.has_side_effects() || point.1.has_side_effects()
+            }
+            Expression::Concat(words) => words.0.has_side_effects() || words.1.has_side_effects(),
+            Expression::MakeArray(size) => size.has_side_effects(),
+            Expression::ListOp(op) => op.operands().iter().any(|expr| expr.has_side_effects()),
+            _ => false,
+        }
+    }
+
+    /// Whether the expression may give a word or a list rather than a
+    /// number.
+    pub fn is_word(&self) -> bool {
+        match self {
+            Expression::Word(_)
+            | Expression::Concat(_)
+            | Expression::List(_)
+            | Expression::GetProp(..)
+            | Expression::Array(_)
+            | Expression::MakeArray(_)
+            | Expression::ReadWord
+            | Expression::Call(..)
+            | Expression::Query(Query::Color | Query::GetEnv(_)) => true,
+            Expression::ListOp(op) => !matches!(**op, ListOp::Count(_)),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -24,13 +108,273 @@ pub enum Command {
     Right(Expression),
     PenUp,
     PenDown,
+    /// `PENPAINT`, putting the pen down to paint over what's beneath.
+    PenPaint,
+    /// `PENREVERSE`, putting the pen down to invert what's beneath, so
+    /// drawing over a line again removes it.
+    PenReverse,
+    ShowTurtle,
+    HideTurtle,
+    Home,
+    /// `WRAP`, so lines leaving one edge of the canvas carry on from the
+    /// opposite edge.
+    Wrap,
+    /// `FENCE`, so moving off the canvas is an error.
+    Fence,
+    /// `WINDOW`, so the saved image grows to fit everything drawn.
+    Window,
+    /// `SETPENCOLOR index`, or a list of red, green and blue such as
+    /// `SETPENCOLOR :SAVED`.
     SetPenColor(Expression),
+    /// `SETPENCOLOR [ r g b ]`, with each channel from 0 to 255.
+    SetPenRgb(Expression, Expression, Expression),
+    /// `SETPENSIZE width`, the width of the lines drawn from now on.
+    SetPenSize(Expression),
+    /// `SETPENALPHA alpha`, how opaque the lines and shapes drawn from now on
+    /// are, from 0 (invisible) to 1 (opaque).
+    SetPenAlpha(Expression),
+    /// `SETPENHSV hue saturation value`, with the hue in degrees and the
+    /// saturation and value from 0 to 1.
+    SetPenHsv(Expression, Expression, Expression),
     Turn(Expression),
+    /// `LEFT angle` in the classic dialect, turning anticlockwise.
+    TurnLeft(Expression),
+    /// `RIGHT angle` in the classic dialect, turning clockwise.
+    TurnRight(Expression),
     SetHeading(Expression),
     SetX(Expression),
+    /// `SETPOS x y`, `SETPOS [ x y ]` or `SETXY x y`.
+    SetPos(Expression, Expression),
     SetY(Expression),
     Make(String, Expression),
+    /// `LOCAL "name`, giving a variable a value of 0 until the procedure
+    /// call or block it's in ends.
+    Local(String),
+    /// `LOCALMAKE "name value`, as `LOCAL` followed by `MAKE`.
+    LocalMake(String, Expression),
     AddAssign(String, Expression),
+    Rect(Expression, Expression),
+    Polygon(Expression, Expression),
+    Star(Expression, Expression),
+    /// `CIRCLE radius`, a circle centred on the turtle.
+    Circle(Expression),
+    /// `ELLIPSE rx ry`, an ellipse centred on the turtle, `rx` across and `ry`
+    /// along its heading.
+    Ellipse(Expression, Expression),
+    SetFillPattern(FillPattern),
+    /// `SETPENPATTERN "dashed`, how the lines drawn from now on are broken up.
+    SetPenPattern(PenPattern),
+    SetLayer(Expression),
+    /// `SETEFFECT "shadow dx dy color`
+    SetShadow(Expression, Expression, Expression),
+    /// `SETEFFECT "outline width color`
+    SetOutline(Expression, Expression),
+    /// `SETEFFECT "none`
+    ClearEffect,
+    /// `SETCLIP x y width height`, clipping everything drawn from now on to
+    /// a rectangle of the canvas.
+    SetClip(Expression, Expression, Expression, Expression),
+    /// `CLEARCLIP`, drawing unclipped again.
+    ClearClip,
+    SetSymmetry(Expression),
+    /// `SETKALEIDOSCOPE ways`, like `SETSYMMETRY` but with every copy also
+    /// mirrored.
+    SetKaleidoscope(Expression),
+    SetTrailFade(Expression),
+    /// `ERASE n`, removing the last `n` strokes drawn.
+    Erase(Expression),
+    /// `ERASE "name`, removing a procedure.
+    EraseProcedure(String),
+    /// `ERN "name`, removing a variable.
+    EraseName(String),
+    PushTransform,
+    PopTransform,
+    /// `PUSHSTATE`, saving the turtle's position, heading and pen.
+    PushState,
+    /// `POPSTATE`, restoring the last state saved without drawing.
+    PopState,
+    Scale(Expression),
+    Rotate(Expression),
+    Translate(Expression, Expression),
+    SetPosRel(Expression),
+    /// `MARK "name`, remembering the turtle's position and heading.
+    Mark(String),
+    /// `JUMPTO "name`, returning to a position remembered with `MARK`.
+    JumpTo(String),
+    SetAngleUnit(AngleUnit),
+    /// `RERANDOM seed`, starting the numbers given by `RANDOM` over from a
+    /// new seed.
+    Rerandom(Expression),
+    /// `ASSERT condition "message`
+    Assert(Condition, String),
+    /// `SETLABELFONT "serif` (or `SETLABELFONT [ Times New Roman ]`), the
+    /// font family of labels written from now on.
+    SetLabelFont(String),
+    /// `SETLABELHEIGHT size`, the height of labels written from now on.
+    SetLabelHeight(Expression),
+    /// `SETLABELALIGN "center`, where labels are written relative to the
+    /// turtle.
+    SetLabelAlign(TextAlign),
+    /// `LABEL "text`, writing text at the turtle.
+    Label(Expression),
+    /// `PPROP "list "prop value`, setting a property of a property list.
+    PProp(String, String, Expression),
+    /// `REMPROP "list "prop`, removing a property from a property list.
+    RemProp(String, String),
+    /// `SETITEM n :array value`, changing the `n`th item of the array in a
+    /// variable, counting from 1.
+    SetItem(Expression, String, Expression),
+}
+
+impl Command {
+    /// The Logo keyword the command was written with.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Command::Forward(_) => "FORWARD",
+            Command::Back(_) => "BACK",
+            Command::Left(_) => "LEFT",
+            Command::Right(_) => "RIGHT",
+            Command::PenUp => "PENUP",
+            Command::PenDown => "PENDOWN",
+            Command::PenPaint => "PENPAINT",
+            Command::PenReverse => "PENREVERSE",
+            Command::ShowTurtle => "SHOWTURTLE",
+            Command::HideTurtle => "HIDETURTLE",
+            Command::Home => "HOME",
+            Command::Wrap => "WRAP",
+            Command::Fence => "FENCE",
+            Command::Window => "WINDOW",
+            Command::SetPenColor(_) | Command::SetPenRgb(..) => "SETPENCOLOR",
+            Command::SetPenHsv(..) => "SETPENHSV",
+            Command::SetPenSize(_) => "SETPENSIZE",
+            Command::SetPenAlpha(_) => "SETPENALPHA",
+            Command::Turn(_) => "TURN",
+            Command::TurnLeft(_) => "LEFT",
+            Command::TurnRight(_) => "RIGHT",
+            Command::SetHeading(_) => "SETHEADING",
+            Command::SetX(_) => "SETX",
+            Command::SetPos(..) => "SETPOS",
+            Command::SetY(_) => "SETY",
+            Command::Make(..) => "MAKE",
+            Command::Local(_) => "LOCAL",
+            Command::LocalMake(..) => "LOCALMAKE",
+            Command::AddAssign(..) => "ADDASSIGN",
+            Command::Rect(..) => "RECT",
+            Command::Polygon(..) => "POLYGON",
+            Command::Star(..) => "STAR",
+            Command::Circle(_) => "CIRCLE",
+            Command::Ellipse(..) => "ELLIPSE",
+            Command::SetFillPattern(_) => "SETFILLPATTERN",
+            Command::SetPenPattern(_) => "SETPENPATTERN",
+            Command::SetLayer(_) => "SETLAYER",
+            Command::SetShadow(..) | Command::SetOutline(..) | Command::ClearEffect => "SETEFFECT",
+            Command::SetClip(..) => "SETCLIP",
+            Command::ClearClip => "CLEARCLIP",
+            Command::SetSymmetry(_) => "SETSYMMETRY",
+            Command::SetKaleidoscope(_) => "SETKALEIDOSCOPE",
+            Command::SetTrailFade(_) => "SETTRAILFADE",
+            Command::Erase(_) | Command::EraseProcedure(_) => "ERASE",
+            Command::EraseName(_) => "ERN",
+            Command::PushTransform => "PUSHTRANSFORM",
+            Command::PopTransform => "POPTRANSFORM",
+            Command::PushState => "PUSHSTATE",
+            Command::PopState => "POPSTATE",
+            Command::Scale(_) => "SCALE",
+            Command::Rotate(_) => "ROTATE",
+            Command::Translate(..) => "TRANSLATE",
+            Command::SetPosRel(_) => "SETPOSREL",
+            Command::Mark(_) => "MARK",
+            Command::JumpTo(_) => "JUMPTO",
+            Command::SetAngleUnit(_) => "SETANGLEUNIT",
+            Command::Rerandom(_) => "RERANDOM",
+            Command::Assert(..) => "ASSERT",
+            Command::SetLabelFont(_) => "SETLABELFONT",
+            Command::SetLabelHeight(_) => "SETLABELHEIGHT",
+            Command::SetLabelAlign(_) => "SETLABELALIGN",
+            Command::Label(_) => "LABEL",
+            Command::PProp(..) => "PPROP",
+            Command::RemProp(..) => "REMPROP",
+            Command::SetItem(..) => "SETITEM",
+        }
+    }
+
+    /// The expressions the command takes as arguments, in order.
+    pub fn args(&self) -> Vec<&Expression> {
+        match self {
+            Command::Forward(expr)
+            | Command::Back(expr)
+            | Command::Left(expr)
+            | Command::Right(expr)
+            | Command::SetPenColor(expr)
+            | Command::SetPenSize(expr)
+            | Command::SetPenAlpha(expr)
+            | Command::Circle(expr)
+            | Command::Turn(expr)
+            | Command::TurnLeft(expr)
+            | Command::TurnRight(expr)
+            | Command::SetHeading(expr)
+            | Command::SetX(expr)
+            | Command::SetY(expr)
+            | Command::Make(_, expr)
+            | Command::LocalMake(_, expr)
+            | Command::AddAssign(_, expr)
+            | Command::SetLayer(expr)
+            | Command::SetSymmetry(expr)
+            | Command::SetKaleidoscope(expr)
+            | Command::SetTrailFade(expr)
+            | Command::Erase(expr)
+            | Command::Rerandom(expr)
+            | Command::SetLabelHeight(expr)
+            | Command::Label(expr)
+            | Command::PProp(_, _, expr)
+            | Command::Scale(expr)
+            | Command::Rotate(expr)
+            | Command::SetPosRel(expr) => vec![expr],
+            Command::Rect(a, b)
+            | Command::Ellipse(a, b)
+            | Command::SetPos(a, b)
+            | Command::Polygon(a, b)
+            | Command::Star(a, b)
+            | Command::SetOutline(a, b)
+            | Command::SetItem(a, _, b)
+            | Command::Translate(a, b) => vec![a, b],
+            Command::SetShadow(a, b, c)
+            | Command::SetPenRgb(a, b, c)
+            | Command::SetPenHsv(a, b, c) => vec![a, b, c],
+            Command::SetClip(a, b, c, d) => vec![a, b, c, d],
+            Command::Assert(condition, _) => {
+                let (lhs, rhs) = condition.operands();
+                vec![lhs, rhs]
+            }
+            Command::PenUp
+            | Command::PenDown
+            | Command::PenPaint
+            | Command::PenReverse
+            | Command::ShowTurtle
+            | Command::HideTurtle
+            | Command::Home
+            | Command::Wrap
+            | Command::Fence
+            | Command::Window
+            | Command::SetFillPattern(_)
+            | Command::SetPenPattern(_)
+            | Command::SetLabelFont(_)
+            | Command::SetLabelAlign(_)
+            | Command::SetAngleUnit(_)
+            | Command::Mark(_)
+            | Command::JumpTo(_)
+            | Command::Local(_)
+            | Command::RemProp(..)
+            | Command::EraseProcedure(_)
+            | Command::EraseName(_)
+            | Command::ClearEffect
+            | Command::ClearClip
+            | Command::PushTransform
+            | Command::PopTransform
+            | Command::PushState
+            | Command::PopState => vec![],
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -39,6 +383,39 @@ pub enum Query {
     YCor,
     Heading,
     Color,
+    DeltaX,
+    DeltaY,
+    /// The total distance the turtle has travelled.
+    Odometer,
+    /// The distance the turtle has travelled with the pen down.
+    Tripmeter,
+    /// The edges of the box around everything drawn so far.
+    MinX,
+    MaxX,
+    MinY,
+    MaxY,
+    /// The value of an environment variable, read with `GETENV`.
+    GetEnv(String),
+    /// `QUERY "name`, a value from one of the environment's providers.
+    Provided(String),
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    /// `TOUCHING x y r`, whether anything has been drawn within `r` of a
+    /// point.
+    Touching(Box<(Expression, Expression, Expression)>),
+    /// `INTERSECTS d`, whether moving forward by `d` would cross anything
+    /// drawn so far.
+    Intersects(Box<Expression>),
+    /// `TOWARDS x y`, the heading from the turtle to a point.
+    Towards(Box<(Expression, Expression)>),
+    /// `DISTANCE x y`, how far the turtle is from a point.
+    Distance(Box<(Expression, Expression)>),
+    /// `READNUMBER`, the next line of input as a number.
+    ReadNumber,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -47,12 +424,334 @@ pub enum Math {
     Sub(Expression, Expression),
     Mul(Expression, Expression),
     Div(Expression, Expression),
+    /// `MOD a b`, the remainder of `a / b` taking the sign of `b`, so that
+    /// `MOD -30 360` is 330.
+    Mod(Expression, Expression),
+    /// `REMAINDER a b`, the remainder of `a / b` taking the sign of `a`.
+    Remainder(Expression, Expression),
+    Min(Expression, Expression),
+    Max(Expression, Expression),
     Eq(Expression, Expression),
     Lt(Expression, Expression),
     Gt(Expression, Expression),
     Ne(Expression, Expression),
+    /// `LE a b`, whether `a` is at most `b`.
+    Le(Expression, Expression),
+    /// `GE a b`, whether `a` is at least `b`.
+    Ge(Expression, Expression),
     And(Expression, Expression),
     Or(Expression, Expression),
+    /// `XOR a b`, whether exactly one of `a` and `b` is true.
+    Xor(Expression, Expression),
+    /// `NOISE x y`, seeded value noise between 0 and 1.
+    Noise(Expression, Expression),
+    /// `PIXEL x y`, the palette index of the color drawn at a point.
+    Pixel(Expression, Expression),
+}
+
+impl Expression {
+    /// The names of the variables the expression refers to, in order and
+    /// without repeats.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut vars = Vec::new();
+        self.collect_variables(&mut vars);
+        vars
+    }
+
+    fn collect_variables<'a>(&'a self, vars: &mut Vec<&'a str>) {
+        match self {
+            Expression::Variable(var) => {
+                if !vars.contains(&var.as_str()) {
+                    vars.push(var);
+                }
+            }
+            Expression::Query(Query::Touching(args)) => {
+                args.0.collect_variables(vars);
+                args.1.collect_variables(vars);
+                args.2.collect_variables(vars);
+            }
+            Expression::Query(Query::Intersects(distance)) => distance.collect_variables(vars),
+            Expression::Query(Query::Towards(point) | Query::Distance(point)) => {
+                point.0.collect_variables(vars);
+                point.1.collect_variables(vars);
+            }
+            Expression::Concat(words) => {
+                words.0.collect_variables(vars);
+                words.1.collect_variables(vars);
+            }
+            Expression::MakeArray(size) => size.collect_variables(vars),
+            Expression::ListOp(op) => match &**op {
+                // The `:?` of a `MAP` template is only set inside the `MAP`.
+                ListOp::Map(_, list) => list.collect_variables(vars),
+                _ => {
+                    for expr in op.operands() {
+                        expr.collect_variables(vars);
+                    }
+                }
+            },
+            Expression::Math(math) => {
+                let (lhs, rhs) = math.operands();
+                lhs.collect_variables(vars);
+                rhs.collect_variables(vars);
+            }
+            Expression::Function(function) => function.operand().collect_variables(vars),
+            Expression::Call(_, items) | Expression::List(items) | Expression::Array(items) => {
+                for item in items {
+                    item.collect_variables(vars);
+                }
+            }
+            Expression::Query(_)
+            | Expression::Float(_)
+            | Expression::Number(_)
+            | Expression::Usize(_)
+            | Expression::Word(_)
+            | Expression::GetProp(..)
+            | Expression::ReadWord => {}
+        }
+    }
+}
+
+impl Math {
+    /// The two expressions the operator is applied to.
+    pub fn operands(&self) -> (&Expression, &Expression) {
+        match self {
+            Math::Add(lhs, rhs)
+            | Math::Sub(lhs, rhs)
+            | Math::Mul(lhs, rhs)
+            | Math::Div(lhs, rhs)
+            | Math::Mod(lhs, rhs)
+            | Math::Remainder(lhs, rhs)
+            | Math::Min(lhs, rhs)
+            | Math::Max(lhs, rhs)
+            | Math::Eq(lhs, rhs)
+            | Math::Lt(lhs, rhs)
+            | Math::Gt(lhs, rhs)
+            | Math::Ne(lhs, rhs)
+            | Math::Le(lhs, rhs)
+            | Math::Ge(lhs, rhs)
+            | Math::And(lhs, rhs)
+            | Math::Or(lhs, rhs)
+            | Math::Xor(lhs, rhs)
+            | Math::Noise(lhs, rhs)
+            | Math::Pixel(lhs, rhs) => (lhs, rhs),
+        }
+    }
+}
+
+/// Writes an expression back out in Logo syntax, for error messages.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Float(val) => write!(f, "\"{}", val),
+            Expression::Number(val) => write!(f, "\"{}", val),
+            Expression::Usize(val) => write!(f, "\"{}", val),
+            Expression::Query(query) => write!(f, "{}", query),
+            Expression::Variable(var) => write!(f, ":{}", var),
+            Expression::Math(math) => write!(f, "{}", math),
+            Expression::Function(function) => write!(f, "{}", function),
+            Expression::Call(name, args) => {
+                write!(f, "{}", name)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                Ok(())
+            }
+            Expression::Word(word) => write!(f, "\"{}", word),
+            Expression::Concat(words) => write!(f, "WORD {} {}", words.0, words.1),
+            Expression::List(items) | Expression::Array(items) => {
+                let (open, close) = match self {
+                    Expression::Array(_) => ("{", "}"),
+                    _ => ("[", "]"),
+                };
+                write!(f, "{}", open)?;
+                for item in items {
+                    match item {
+                        // Items of a list are written without quotes.
+                        Expression::Word(word) => write!(f, " {}", word)?,
+                        Expression::Float(val) => write!(f, " {}", val)?,
+                        _ => write!(f, " {}", item)?,
+                    }
+                }
+                write!(f, " {}", close)
+            }
+            Expression::GetProp(list, prop) => write!(f, "GPROP \"{} \"{}", list, prop),
+            Expression::MakeArray(size) => write!(f, "ARRAY {}", size),
+            Expression::ReadWord => write!(f, "READWORD"),
+            Expression::ListOp(op) => match &**op {
+                ListOp::Map(template, list) => write!(f, "MAP [ {} ] {}", template, list),
+                _ => {
+                    write!(f, "{}", op.keyword())?;
+                    for expr in op.operands() {
+                        write!(f, " {}", expr)?;
+                    }
+                    Ok(())
+                }
+            },
+        }
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Query::XCor => write!(f, "XCOR"),
+            Query::YCor => write!(f, "YCOR"),
+            Query::Heading => write!(f, "HEADING"),
+            Query::Color => write!(f, "COLOR"),
+            Query::DeltaX => write!(f, "DELTAX"),
+            Query::Odometer => write!(f, "ODOMETER"),
+            Query::Tripmeter => write!(f, "TRIPMETER"),
+            Query::Provided(name) => write!(f, "QUERY \"{}", name),
+            Query::MinX => write!(f, "MINX"),
+            Query::MaxX => write!(f, "MAXX"),
+            Query::MinY => write!(f, "MINY"),
+            Query::MaxY => write!(f, "MAXY"),
+            Query::DeltaY => write!(f, "DELTAY"),
+            Query::GetEnv(name) => write!(f, "GETENV \"{}", name),
+            Query::Year => write!(f, "YEAR"),
+            Query::Month => write!(f, "MONTH"),
+            Query::Day => write!(f, "DAY"),
+            Query::Hour => write!(f, "HOUR"),
+            Query::Minute => write!(f, "MINUTE"),
+            Query::Second => write!(f, "SECOND"),
+            Query::ReadNumber => write!(f, "READNUMBER"),
+            Query::Touching(args) => write!(f, "TOUCHING {} {} {}", args.0, args.1, args.2),
+            Query::Intersects(distance) => write!(f, "INTERSECTS {}", distance),
+            Query::Towards(point) => write!(f, "TOWARDS {} {}", point.0, point.1),
+            Query::Distance(point) => write!(f, "DISTANCE {} {}", point.0, point.1),
+        }
+    }
+}
+
+impl fmt::Display for Math {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (op, lhs, rhs) = match self {
+            Math::Add(lhs, rhs) => ("+", lhs, rhs),
+            Math::Sub(lhs, rhs) => ("-", lhs, rhs),
+            Math::Mul(lhs, rhs) => ("*", lhs, rhs),
+            Math::Div(lhs, rhs) => ("/", lhs, rhs),
+            Math::Mod(lhs, rhs) => ("MOD", lhs, rhs),
+            Math::Remainder(lhs, rhs) => ("REMAINDER", lhs, rhs),
+            Math::Min(lhs, rhs) => ("MIN", lhs, rhs),
+            Math::Max(lhs, rhs) => ("MAX", lhs, rhs),
+            Math::Eq(lhs, rhs) => ("EQ", lhs, rhs),
+            Math::Lt(lhs, rhs) => ("LT", lhs, rhs),
+            Math::Gt(lhs, rhs) => ("GT", lhs, rhs),
+            Math::Ne(lhs, rhs) => ("NE", lhs, rhs),
+            Math::Le(lhs, rhs) => ("LE", lhs, rhs),
+            Math::Ge(lhs, rhs) => ("GE", lhs, rhs),
+            Math::And(lhs, rhs) => ("AND", lhs, rhs),
+            Math::Or(lhs, rhs) => ("OR", lhs, rhs),
+            Math::Xor(lhs, rhs) => ("XOR", lhs, rhs),
+            Math::Noise(lhs, rhs) => ("NOISE", lhs, rhs),
+            Math::Pixel(lhs, rhs) => ("PIXEL", lhs, rhs),
+        };
+        write!(f, "{} {} {}", op, lhs, rhs)
+    }
+}
+
+/// Functions of a single value. Angles are in the current angle unit, as
+/// with headings.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Function {
+    Sin(Expression),
+    Cos(Expression),
+    Tan(Expression),
+    /// `ARCTAN x`, the angle whose tangent is `x`, between -90 and 90
+    /// degrees.
+    ArcTan(Expression),
+    /// `RANDOM n`, a whole number from 0 up to but not including `n`.
+    Random(Expression),
+    /// `ROUND x`, the nearest whole number, rounding halves away from zero.
+    Round(Expression),
+    /// `INT x`, the whole number part of `x`, dropping any fraction.
+    Int(Expression),
+    Abs(Expression),
+}
+
+impl Function {
+    /// The keyword the function is written with.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Function::Sin(_) => "SIN",
+            Function::Cos(_) => "COS",
+            Function::Tan(_) => "TAN",
+            Function::ArcTan(_) => "ARCTAN",
+            Function::Random(_) => "RANDOM",
+            Function::Round(_) => "ROUND",
+            Function::Int(_) => "INT",
+            Function::Abs(_) => "ABS",
+        }
+    }
+
+    /// The expression the function is applied to.
+    pub fn operand(&self) -> &Expression {
+        match self {
+            Function::Sin(expr)
+            | Function::Cos(expr)
+            | Function::Tan(expr)
+            | Function::ArcTan(expr)
+            | Function::Random(expr)
+            | Function::Round(expr)
+            | Function::Int(expr)
+            | Function::Abs(expr) => expr,
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.keyword(), self.operand())
+    }
+}
+
+/// Functions of lists. Words can be taken apart in the same way, as lists of
+/// their characters.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListOp {
+    /// `FIRST list`, the first item.
+    First(Expression),
+    /// `BUTFIRST list`, a list of every item but the first.
+    ButFirst(Expression),
+    /// `ITEM n list`, the `n`th item, counting from 1.
+    Item(Expression, Expression),
+    /// `COUNT list`, the number of items.
+    Count(Expression),
+    /// `MAP [ template ] list`, a list of the template's value for each
+    /// item, with `:?` set to the item.
+    Map(Expression, Expression),
+}
+
+impl ListOp {
+    /// The keyword the function is written with.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            ListOp::First(_) => "FIRST",
+            ListOp::ButFirst(_) => "BUTFIRST",
+            ListOp::Item(..) => "ITEM",
+            ListOp::Count(_) => "COUNT",
+            ListOp::Map(..) => "MAP",
+        }
+    }
+
+    /// The expressions the function is applied to, in order.
+    pub fn operands(&self) -> Vec<&Expression> {
+        match self {
+            ListOp::First(list) | ListOp::ButFirst(list) | ListOp::Count(list) => vec![list],
+            ListOp::Item(index, list) | ListOp::Map(index, list) => vec![index, list],
+        }
+    }
+
+    /// The list the function is applied to.
+    pub fn list(&self) -> &Expression {
+        match self {
+            ListOp::First(list)
+            | ListOp::ButFirst(list)
+            | ListOp::Count(list)
+            | ListOp::Item(_, list)
+            | ListOp::Map(_, list) => list,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -65,6 +764,58 @@ pub enum ControlFlow {
         condition: Condition,
         block: Vec<ASTNode>,
     },
+    /// `DO.WHILE [ ... ] condition`, running the block once and then again
+    /// for as long as the condition holds.
+    DoWhile {
+        block: Vec<ASTNode>,
+        condition: Condition,
+    },
+    /// `UNTIL condition [ ... ]`, running the block once and then again until
+    /// the condition holds.
+    Until {
+        condition: Condition,
+        block: Vec<ASTNode>,
+    },
+    /// `FOR [var start end step] [ ... ]`, running the block with `var` set
+    /// to each value from `start` to `end`. Without a step, it counts by 1
+    /// towards `end`.
+    For {
+        var: String,
+        start: Expression,
+        end: Expression,
+        step: Option<Expression>,
+        block: Vec<ASTNode>,
+    },
+    /// `OUTPUT value`, leaving the procedure it's in and giving back `value`
+    /// as the result of the call.
+    Output(Expression),
+    /// `STOP`, leaving the procedure it's in, or ending the script outside of
+    /// one.
+    Stop,
+    /// `FOREACH list [ ... ]`, running the block with `:?` set to each item
+    /// of the list in turn.
+    ForEach {
+        list: Expression,
+        block: Vec<ASTNode>,
+    },
+    /// `CATCH "tag [ ... ]`, running the block but stopping it early, and
+    /// carrying on after the `CATCH`, on a `THROW "tag`. `CATCH "ERROR`
+    /// also stops the block on any error it could recover from.
+    Catch {
+        tag: String,
+        block: Vec<ASTNode>,
+    },
+    /// `THROW "tag`, leaving blocks up to the `CATCH "tag` around it.
+    Throw(String),
+    /// `REPEAT n [ ... ]`, running the block `n` times.
+    Repeat {
+        count: Expression,
+        block: Vec<ASTNode>,
+    },
+    Call {
+        name: String,
+        args: Vec<Expression>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -72,6 +823,32 @@ pub enum Condition {
     Equals(Expression, Expression),
     LessThan(Expression, Expression),
     GreaterThan(Expression, Expression),
+    LessEqual(Expression, Expression),
+    GreaterEqual(Expression, Expression),
     And(Expression, Expression),
     Or(Expression, Expression),
+    Xor(Expression, Expression),
+}
+
+impl Condition {
+    /// The two expressions the condition compares.
+    pub fn operands(&self) -> (&Expression, &Expression) {
+        match self {
+            Condition::Equals(lhs, rhs)
+            | Condition::LessThan(lhs, rhs)
+            | Condition::GreaterThan(lhs, rhs)
+            | Condition::LessEqual(lhs, rhs)
+            | Condition::GreaterEqual(lhs, rhs)
+            | Condition::And(lhs, rhs)
+            | Condition::Or(lhs, rhs)
+            | Condition::Xor(lhs, rhs) => (lhs, rhs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Procedure {
+    pub name: String,
+    pub params: Vec<String>,
+    pub block: Vec<ASTNode>,
 }
@@ -0,0 +1,139 @@
+//! Colors named by the common CSS and X11 names, for `SETPENCOLOR "name`.
+//!
+//! The palette's own names, such as `"red` and `"forest`, are looked up
+//! first and keep their palette index, so `COLOR` still gives a number for
+//! them. Any other name here is resolved to its RGB color at parse time,
+//! including the built-in palette's names when `--palette` replaces it.
+
+use unsvg::{Color, COLORS};
+
+use super::{color_index, palette::Palette, COLOR_NAMES};
+
+const fn rgb(red: u8, green: u8, blue: u8) -> Color {
+    Color { red, green, blue }
+}
+
+/// CSS and X11 colors that aren't already in the palette under that name.
+pub const CSS_COLORS: [(&str, Color); 40] = [
+    ("beige", rgb(245, 245, 220)),
+    ("chartreuse", rgb(127, 255, 0)),
+    ("chocolate", rgb(210, 105, 30)),
+    ("coral", rgb(255, 127, 80)),
+    ("crimson", rgb(220, 20, 60)),
+    ("darkblue", rgb(0, 0, 139)),
+    ("darkgreen", rgb(0, 100, 0)),
+    ("darkgrey", rgb(169, 169, 169)),
+    ("darkred", rgb(139, 0, 0)),
+    ("fuchsia", rgb(255, 0, 255)),
+    ("gold", rgb(255, 215, 0)),
+    ("goldenrod", rgb(218, 165, 32)),
+    ("hotpink", rgb(255, 105, 180)),
+    ("indigo", rgb(75, 0, 130)),
+    ("ivory", rgb(255, 255, 240)),
+    ("khaki", rgb(240, 230, 140)),
+    ("lavender", rgb(230, 230, 250)),
+    ("lightblue", rgb(173, 216, 230)),
+    ("lightgreen", rgb(144, 238, 144)),
+    ("lightgrey", rgb(211, 211, 211)),
+    ("lime", rgb(0, 255, 0)),
+    ("maroon", rgb(128, 0, 0)),
+    ("mint", rgb(189, 252, 201)),
+    ("navy", rgb(0, 0, 128)),
+    ("olive", rgb(128, 128, 0)),
+    ("orchid", rgb(218, 112, 214)),
+    ("peach", rgb(255, 218, 185)),
+    ("pink", rgb(255, 192, 203)),
+    ("plum", rgb(221, 160, 221)),
+    ("royalblue", rgb(65, 105, 225)),
+    ("scarlet", rgb(255, 36, 0)),
+    ("silver", rgb(192, 192, 192)),
+    ("skyblue", rgb(135, 206, 235)),
+    ("slategrey", rgb(112, 128, 144)),
+    ("steelblue", rgb(70, 130, 180)),
+    ("teal", rgb(0, 128, 128)),
+    ("tomato", rgb(255, 99, 71)),
+    ("turquoise", rgb(64, 224, 208)),
+    ("violet", rgb(238, 130, 238)),
+    ("wheat", rgb(245, 222, 179)),
+];
+
+/// A color given by name, either from the palette or from `CSS_COLORS`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NamedColor {
+    Palette(usize),
+    Rgb(Color),
+}
+
+/// Looks up a color by name in `palette`, then the built-in palette and
+/// then `CSS_COLORS`, case insensitively and accepting `gray` for `grey` as
+/// CSS does.
+pub fn named_color(name: &str, palette: &Palette) -> Option<NamedColor> {
+    if let Some(index) = palette.index_of_name(name) {
+        return Some(NamedColor::Palette(index));
+    }
+    if let Some(index) = color_index(name) {
+        return Some(NamedColor::Rgb(COLORS[index]));
+    }
+    let name = name.to_lowercase().replace("gray", "grey");
+    CSS_COLORS
+        .iter()
+        .find(|(css, _)| *css == name)
+        .map(|&(_, color)| NamedColor::Rgb(color))
+}
+
+/// Every name `named_color` accepts, palette names first.
+pub fn color_names(palette: &Palette) -> Vec<&str> {
+    let mut names: Vec<&str> = palette.entries().filter_map(|(_, name)| name).collect();
+    for name in COLOR_NAMES {
+        if palette.index_of_name(name).is_none() {
+            names.push(name);
+        }
+    }
+    names.extend(CSS_COLORS.iter().map(|&(name, _)| name));
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_color() {
+        let palette = Palette::default();
+        assert_eq!(named_color("RED", &palette), Some(NamedColor::Palette(4)));
+        assert_eq!(named_color("Gray", &palette), Some(NamedColor::Palette(15)));
+        assert_eq!(
+            named_color("LightGray", &palette),
+            Some(NamedColor::Rgb(rgb(211, 211, 211)))
+        );
+        assert_eq!(
+            named_color("navy", &palette),
+            Some(NamedColor::Rgb(rgb(0, 0, 128)))
+        );
+        assert_eq!(named_color("mauve", &palette), None);
+    }
+
+    #[test]
+    fn test_named_color_custom_palette() {
+        let palette = Palette::parse(
+            "#000000 ink
+#0000ff red
+",
+        )
+        .unwrap();
+        assert_eq!(named_color("ink", &palette), Some(NamedColor::Palette(0)));
+        assert_eq!(named_color("red", &palette), Some(NamedColor::Palette(1)));
+        assert_eq!(
+            named_color("forest", &palette),
+            Some(NamedColor::Rgb(COLORS[10]))
+        );
+        assert_eq!(color_names(&palette)[..3], ["ink", "red", "black"]);
+    }
+
+    #[test]
+    fn test_css_colors_not_in_palette() {
+        for (name, _) in CSS_COLORS {
+            assert_eq!(color_index(name), None, "{} is a palette name", name);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Transforms applied to the whole image as it's saved, given with
+//! `--rotate-output`, `--flip` and `--scale-output`.
+//!
+//! The turtle draws exactly as it would without them, so `XCOR`, `PIXEL`
+//! and the rest are unaffected; only the saved SVG, PNG or HTML is turned,
+//! mirrored or resized. The image is mirrored first, then rotated clockwise,
+//! then scaled.
+
+use super::viewport::Viewport;
+
+/// How far the image is rotated clockwise, in degrees.
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Rotation {
+    #[default]
+    #[value(name = "0")]
+    None,
+    #[value(name = "90")]
+    Quarter,
+    #[value(name = "180")]
+    Half,
+    #[value(name = "270")]
+    ThreeQuarters,
+}
+
+/// Which way the image is mirrored.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Flip {
+    /// Left to right.
+    Horizontal,
+    /// Top to bottom.
+    Vertical,
+}
+
+/// An affine transform `[a, b, c, d, e, f]`, taking `(x, y)` to
+/// `(a * x + c * y + e, b * x + d * y + f)` as SVG's `matrix()` does.
+pub type Matrix = [f32; 6];
+
+/// Applies `first` and then `second`.
+fn compose(first: Matrix, second: Matrix) -> Matrix {
+    let [a1, b1, c1, d1, e1, f1] = first;
+    let [a2, b2, c2, d2, e2, f2] = second;
+    [
+        a2 * a1 + c2 * b1,
+        b2 * a1 + d2 * b1,
+        a2 * c1 + c2 * d1,
+        b2 * c1 + d2 * d1,
+        a2 * e1 + c2 * f1 + e2,
+        b2 * e1 + d2 * f1 + f2,
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Export {
+    pub rotation: Rotation,
+    pub flip: Option<Flip>,
+    /// How many times larger the image is saved, above 0.
+    pub scale: f32,
+}
+
+impl Default for Export {
+    fn default() -> Self {
+        Export {
+            rotation: Rotation::None,
+            flip: None,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Export {
+    /// Whether the image is saved as it was drawn.
+    pub fn is_identity(&self) -> bool {
+        *self == Export::default()
+    }
+
+    /// Whether the image's width and height trade places.
+    pub fn swaps_sides(&self) -> bool {
+        matches!(self.rotation, Rotation::Quarter | Rotation::ThreeQuarters)
+    }
+
+    /// The width and height of the saved image, given those of the region
+    /// drawn.
+    pub fn size(&self, (width, height): (f32, f32)) -> (f32, f32) {
+        let (width, height) = if self.swaps_sides() {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        (width * self.scale, height * self.scale)
+    }
+
+    /// The transform taking a point of `viewport`, in the coordinates the
+    /// turtle draws in, to where it ends up in the saved image.
+    pub fn matrix(&self, viewport: &Viewport) -> Matrix {
+        let (width, height) = (viewport.width, viewport.height);
+        let mut matrix = [1.0, 0.0, 0.0, 1.0, -viewport.x, -viewport.y];
+        match self.flip {
+            Some(Flip::Horizontal) => matrix = compose(matrix, [-1.0, 0.0, 0.0, 1.0, width, 0.0]),
+            Some(Flip::Vertical) => matrix = compose(matrix, [1.0, 0.0, 0.0, -1.0, 0.0, height]),
+            None => {}
+        }
+        let rotation = match self.rotation {
+            Rotation::None => [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+            Rotation::Quarter => [0.0, 1.0, -1.0, 0.0, height, 0.0],
+            Rotation::Half => [-1.0, 0.0, 0.0, -1.0, width, height],
+            Rotation::ThreeQuarters => [0.0, -1.0, 1.0, 0.0, 0.0, width],
+        };
+        matrix = compose(matrix, rotation);
+        compose(matrix, [self.scale, 0.0, 0.0, self.scale, 0.0, 0.0])
+    }
+}
+
+/// Parses the factor given with `--scale-output`.
+pub fn parse_scale(scale: &str) -> Result<f32, String> {
+    match scale.trim().parse::<f32>() {
+        Ok(scale) if scale.is_finite() && scale > 0.0 => Ok(scale),
+        _ => Err(format!(
+            "Invalid scale {:?}, expected a factor above 0 such as 2 or 0.5",
+            scale
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEWPORT: Viewport = Viewport {
+        x: 10.0,
+        y: 20.0,
+        width: 100.0,
+        height: 50.0,
+    };
+
+    fn apply(matrix: Matrix, (x, y): (f32, f32)) -> (f32, f32) {
+        let [a, b, c, d, e, f] = matrix;
+        (a * x + c * y + e, b * x + d * y + f)
+    }
+
+    #[test]
+    fn test_identity() {
+        let export = Export::default();
+        assert!(export.is_identity());
+        assert_eq!(export.size((100.0, 50.0)), (100.0, 50.0));
+        assert_eq!(apply(export.matrix(&VIEWPORT), (10.0, 20.0)), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_rotations() {
+        // Where the top left corner of the viewport ends up.
+        let corners = [
+            (Rotation::Quarter, (50.0, 0.0)),
+            (Rotation::Half, (100.0, 50.0)),
+            (Rotation::ThreeQuarters, (0.0, 100.0)),
+        ];
+        for (rotation, corner) in corners {
+            let export = Export {
+                rotation,
+                ..Export::default()
+            };
+            assert_eq!(apply(export.matrix(&VIEWPORT), (10.0, 20.0)), corner);
+        }
+
+        let export = Export {
+            rotation: Rotation::Quarter,
+            ..Export::default()
+        };
+        assert_eq!(export.size((100.0, 50.0)), (50.0, 100.0));
+    }
+
+    #[test]
+    fn test_flip_then_rotate_then_scale() {
+        let export = Export {
+            rotation: Rotation::Quarter,
+            flip: Some(Flip::Horizontal),
+            scale: 2.0,
+        };
+        assert_eq!(export.size((100.0, 50.0)), (100.0, 200.0));
+        // The top left corner is mirrored to the top right, which the
+        // rotation takes to the bottom right.
+        assert_eq!(
+            apply(export.matrix(&VIEWPORT), (10.0, 20.0)),
+            (100.0, 200.0)
+        );
+        assert_eq!(apply(export.matrix(&VIEWPORT), (110.0, 70.0)), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_parse_scale() {
+        assert_eq!(parse_scale("2"), Ok(2.0));
+        assert_eq!(parse_scale("0.5"), Ok(0.5));
+        assert!(parse_scale("0").is_err());
+        assert!(parse_scale("big").is_err());
+    }
+}
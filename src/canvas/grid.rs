@@ -0,0 +1,136 @@
+//! A coordinate grid drawn beneath the drawing, given with `--grid`.
+//!
+//! The grid lines are `spacing` pixels apart and line up with the centre of
+//! the canvas, where the turtle starts, so they count out how far it has
+//! moved. The two lines through the centre are the axes and are drawn
+//! brighter. The grid is only written out with the drawing, so `PIXEL` and
+//! `TOUCHING` don't see it.
+
+use super::viewport::Viewport;
+
+/// The color of the grid lines, faint against the black background.
+pub const GRID_COLOR: &str = "#333333";
+
+/// The color of the axes through the centre of the canvas.
+pub const AXIS_COLOR: &str = "#808080";
+
+/// A line of the grid, running across the whole viewport.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridLine {
+    pub from: (f32, f32),
+    pub to: (f32, f32),
+    /// Whether the line is one of the axes through `center`.
+    pub axis: bool,
+}
+
+impl GridLine {
+    pub fn color(&self) -> &'static str {
+        if self.axis {
+            AXIS_COLOR
+        } else {
+            GRID_COLOR
+        }
+    }
+}
+
+/// The lines of a grid `spacing` apart through `center`, covering the
+/// viewport. The vertical lines come first, left to right, then the
+/// horizontal ones, top to bottom, and the axes last so they're drawn on top.
+pub fn grid_lines(spacing: f32, center: (f32, f32), viewport: &Viewport) -> Vec<GridLine> {
+    let (left, top) = (viewport.x, viewport.y);
+    let (right, bottom) = (left + viewport.width, top + viewport.height);
+    // The offsets from the centre, in lines, of each line between `from` and
+    // `to`.
+    let steps = |from: f32, to: f32, center: f32| {
+        let first = ((from - center) / spacing).ceil() as i64;
+        let last = ((to - center) / spacing).floor() as i64;
+        first..=last
+    };
+
+    let mut lines: Vec<GridLine> = steps(left, right, center.0)
+        .map(|step| {
+            let x = center.0 + step as f32 * spacing;
+            GridLine {
+                from: (x, top),
+                to: (x, bottom),
+                axis: step == 0,
+            }
+        })
+        .chain(steps(top, bottom, center.1).map(|step| {
+            let y = center.1 + step as f32 * spacing;
+            GridLine {
+                from: (left, y),
+                to: (right, y),
+                axis: step == 0,
+            }
+        }))
+        .collect();
+    lines.sort_by_key(|line| line.axis);
+    lines
+}
+
+/// Parses the grid spacing given with `--grid`, in pixels.
+pub fn parse_spacing(spacing: &str) -> Result<f32, String> {
+    match spacing.trim().parse::<f32>() {
+        Ok(spacing) if spacing.is_finite() && spacing > 0.0 => Ok(spacing),
+        _ => Err(format!(
+            "Invalid grid spacing {:?}, expected a number of pixels above 0",
+            spacing
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_lines() {
+        let viewport = Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: 100.0,
+            height: 60.0,
+        };
+        let lines = grid_lines(25.0, (50.0, 30.0), &viewport);
+
+        let verticals: Vec<f32> = lines
+            .iter()
+            .filter(|line| line.from.0 == line.to.0)
+            .map(|line| line.from.0)
+            .collect();
+        let horizontals: Vec<f32> = lines
+            .iter()
+            .filter(|line| line.from.1 == line.to.1)
+            .map(|line| line.from.1)
+            .collect();
+        assert_eq!(verticals, vec![0.0, 25.0, 75.0, 100.0, 50.0]);
+        assert_eq!(horizontals, vec![5.0, 55.0, 30.0]);
+
+        let axes: Vec<&GridLine> = lines.iter().filter(|line| line.axis).collect();
+        assert_eq!(
+            axes,
+            vec![
+                &GridLine {
+                    from: (50.0, 0.0),
+                    to: (50.0, 60.0),
+                    axis: true,
+                },
+                &GridLine {
+                    from: (0.0, 30.0),
+                    to: (100.0, 30.0),
+                    axis: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_spacing() {
+        assert_eq!(parse_spacing("20"), Ok(20.0));
+        assert_eq!(parse_spacing(" 12.5"), Ok(12.5));
+        assert!(parse_spacing("0").is_err());
+        assert!(parse_spacing("-10").is_err());
+        assert!(parse_spacing("wide").is_err());
+    }
+}
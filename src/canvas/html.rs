@@ -0,0 +1,206 @@
+//! Writes the canvas out as a self-contained HTML page that replays the
+//! drawing.
+//!
+//! The elements are embedded as JSON in drawing order, and a small player
+//! draws them onto an HTML canvas one after another, with play/pause and a
+//! slider to scrub through the drawing. Lines, outlines and labels are drawn
+//! as in the SVG, but patterned fills and effects are left out.
+
+use serde_json::{json, Value};
+
+use super::{fmt_color, viewport::Viewport, Canvas, FillPattern, Shape};
+
+/// The player page, with `__TRACE__` standing in for the drawing.
+const PLAYER: &str = include_str!("player.html");
+
+impl Canvas {
+    /// The drawing as the JSON the player replays: the region shown, the
+    /// grid lines beneath the drawing, and each element's points, color,
+    /// opacity and layer, in drawing order.
+    pub fn trace(&self) -> Value {
+        let viewport = self.viewport.unwrap_or(Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: self.width as f32,
+            height: self.height as f32,
+        });
+        let (width, height) = self.output_size();
+
+        let elements: Vec<Value> = self
+            .elements
+            .iter()
+            .map(|element| {
+                let points: Vec<[f32; 2]> = element
+                    .shape
+                    .points()
+                    .into_iter()
+                    .map(|(x, y)| [x, y])
+                    .collect();
+                let mut value = json!({
+                    "points": points,
+                    "closed": matches!(
+                        element.shape,
+                        Shape::Rect { .. }
+                            | Shape::Polygon { .. }
+                            | Shape::Circle { .. }
+                            | Shape::Ellipse { .. }
+                    ),
+                    "color": fmt_color(element.style.color),
+                    "fill": element.style.fill == FillPattern::Solid,
+                    "opacity": self.opacity(element),
+                    "width": element.style.width,
+                    "layer": element.layer,
+                });
+                if let Some((dash, gap)) = element.style.pattern.dashes(element.style.width) {
+                    value["dash"] = json!([dash, gap]);
+                }
+                if element.style.reverse {
+                    value["reverse"] = json!(true);
+                }
+                if let Some(clip) = element.clip {
+                    value["clip"] = json!([clip.x, clip.y, clip.width, clip.height]);
+                }
+                if let Shape::Text {
+                    text,
+                    size,
+                    rotation,
+                    font,
+                    align,
+                    ..
+                } = &element.shape
+                {
+                    value["text"] = json!(text);
+                    value["size"] = json!(size);
+                    value["rotation"] = json!(rotation);
+                    value["font"] = json!(font);
+                    value["align"] = json!(align.name());
+                }
+                if let Shape::Circle { center, radius } = &element.shape {
+                    value["center"] = json!([center.0, center.1]);
+                    value["radius"] = json!(radius);
+                }
+                if let Shape::Ellipse {
+                    center,
+                    rx,
+                    ry,
+                    rotation,
+                } = &element.shape
+                {
+                    value["center"] = json!([center.0, center.1]);
+                    value["radii"] = json!([rx, ry]);
+                    value["rotation"] = json!(rotation);
+                }
+                value
+            })
+            .collect();
+
+        let grid: Vec<Value> = self
+            .grid_lines()
+            .iter()
+            .map(|line| {
+                json!({
+                    "points": [[line.from.0, line.from.1], [line.to.0, line.to.1]],
+                    "color": line.color(),
+                })
+            })
+            .collect();
+
+        let mut trace = json!({
+            "width": width,
+            "height": height,
+            "origin": [viewport.x, viewport.y],
+            "elements": elements,
+        });
+        if !grid.is_empty() {
+            trace["grid"] = json!(grid);
+        }
+        if !self.export.is_identity() {
+            trace["transform"] = json!(self.export.matrix(&viewport));
+        }
+        trace
+    }
+
+    /// Renders the canvas into an HTML page that replays the drawing.
+    pub fn to_html(&self) -> String {
+        // `</` would end the script the trace is embedded in.
+        let trace = self.trace().to_string().replace("</", "<\\/");
+        PLAYER.replace("__TRACE__", &trace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unsvg::COLORS;
+
+    use super::*;
+    use crate::canvas::{PenPattern, Style};
+
+    #[test]
+    fn test_trace() {
+        let mut canvas = Canvas::new(100, 50);
+        canvas.draw_line(50.0, 25.0, 0.0, 10.0, Style::new(COLORS[4]));
+        let style = Style {
+            color: COLORS[1],
+            fill: FillPattern::Solid,
+            opacity: 1.0,
+            width: 1.0,
+            pattern: PenPattern::Solid,
+            reverse: false,
+        };
+        let points = vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)];
+        canvas.draw(Shape::Polygon { points }, style);
+
+        assert_eq!(
+            canvas.trace(),
+            json!({
+                "width": 100,
+                "height": 50,
+                "origin": [0.0, 0.0],
+                "elements": [
+                    {
+                        "points": [[50.0, 25.0], [50.0, 15.0]],
+                        "closed": false,
+                        "color": "#ff0000",
+                        "fill": false,
+                        "opacity": 1.0,
+                        "layer": 0,
+                        "width": 1.0,
+                    },
+                    {
+                        "points": [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]],
+                        "closed": true,
+                        "color": "#0000ff",
+                        "fill": true,
+                        "opacity": 1.0,
+                        "layer": 0,
+                        "width": 1.0,
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_trace_grid() {
+        let mut canvas = Canvas::new(20, 20);
+        canvas.grid = Some(10.0);
+
+        assert_eq!(
+            canvas.trace()["grid"][0],
+            json!({ "points": [[0.0, 0.0], [0.0, 20.0]], "color": "#333333" })
+        );
+        assert_eq!(canvas.trace()["grid"].as_array().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_to_html() {
+        let canvas = Canvas::new(10, 10);
+        let html = canvas.to_html();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains(
+            r#"const trace = {"elements":[],"height":10,"origin":[0.0,0.0],"width":10};"#
+        ));
+        assert!(!html.contains("__TRACE__"));
+    }
+}
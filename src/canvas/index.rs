@@ -0,0 +1,140 @@
+//! A spatial index over the canvas's elements, so that queries about what has
+//! been drawn near a point only look at the elements that could be there.
+//!
+//! The canvas is divided into square cells, and each element is recorded in
+//! every cell its outline passes through. Outlines are sampled every `STEP`
+//! pixels, so any point on an outline is within `STEP / 2` of a sample, and a
+//! query widened by that much, and by half the widest stroke, finds every
+//! element it could touch.
+//!
+//! Solid-filled elements cover their whole inside, and outlines too long to
+//! sample are impractical to index cell by cell, so both are kept in a list
+//! that every query includes.
+
+use std::collections::HashMap;
+
+use super::Shape;
+
+/// The width and height of a cell.
+const CELL: f32 = 32.0;
+/// How far apart outlines are sampled.
+const STEP: f32 = CELL / 4.0;
+/// Outlines needing more samples than this go in the list every query checks.
+const MAX_SAMPLES: f32 = 65536.0;
+
+#[derive(Debug, Clone, Default)]
+pub struct ElementIndex {
+    /// The elements whose outline passes through each cell, in drawing order.
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    /// Elements every query includes.
+    always: Vec<usize>,
+    /// Half the width of the widest stroke recorded.
+    reach: f32,
+}
+
+fn cell_of((x, y): (f32, f32)) -> (i32, i32) {
+    ((x / CELL).floor() as i32, (y / CELL).floor() as i32)
+}
+
+impl ElementIndex {
+    /// Records the element at `index` in the elements, drawn as `shape` with
+    /// a stroke reaching `radius` either side of its outline.
+    pub fn insert(&mut self, index: usize, shape: &Shape, filled: bool, radius: f32) {
+        self.reach = self.reach.max(radius);
+        let edges = shape.edges();
+        let samples = edges
+            .iter()
+            .map(|(from, to)| ((to.0 - from.0).hypot(to.1 - from.1) / STEP).ceil())
+            .sum::<f32>();
+        if filled || !samples.is_finite() || samples > MAX_SAMPLES {
+            self.always.push(index);
+            return;
+        }
+
+        for (from, to) in edges {
+            let steps = ((to.0 - from.0).hypot(to.1 - from.1) / STEP)
+                .ceil()
+                .max(1.0) as usize;
+            for i in 0..=steps {
+                let t = i as f32 / steps as f32;
+                let point = (from.0 + t * (to.0 - from.0), from.1 + t * (to.1 - from.1));
+                let cell = self.cells.entry(cell_of(point)).or_default();
+                if cell.last() != Some(&index) {
+                    cell.push(index);
+                }
+            }
+        }
+    }
+
+    /// Forgets the elements from `len` onwards, once they have been erased.
+    pub fn truncate(&mut self, len: usize) {
+        self.cells.retain(|_, indices| {
+            indices.retain(|index| *index < len);
+            !indices.is_empty()
+        });
+        self.always.retain(|index| *index < len);
+    }
+
+    /// The indices of the elements that could be drawn within the box from
+    /// `min` to `max`, in ascending order.
+    pub fn query(&self, min: (f32, f32), max: (f32, f32)) -> Vec<usize> {
+        let margin = STEP / 2.0 + self.reach;
+        let (min_x, min_y) = cell_of((min.0 - margin, min.1 - margin));
+        let (max_x, max_y) = cell_of((max.0 + margin, max.1 + margin));
+        let in_range =
+            |(x, y): &(i32, i32)| (min_x..=max_x).contains(x) && (min_y..=max_y).contains(y);
+
+        let area = (max_x as i64 - min_x as i64 + 1) * (max_y as i64 - min_y as i64 + 1);
+        let mut found: Vec<usize> = if area > self.cells.len() as i64 {
+            self.cells
+                .iter()
+                .filter(|(cell, _)| in_range(cell))
+                .flat_map(|(_, indices)| indices.iter().copied())
+                .collect()
+        } else {
+            (min_x..=max_x)
+                .flat_map(|x| (min_y..=max_y).map(move |y| (x, y)))
+                .filter_map(|cell| self.cells.get(&cell))
+                .flatten()
+                .copied()
+                .collect()
+        };
+        found.extend(&self.always);
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query() {
+        let mut index = ElementIndex::default();
+        let line = |from, to| Shape::Line { from, to };
+        index.insert(0, &line((10.0, 10.0), (200.0, 10.0)), false, 0.5);
+        index.insert(1, &line((10.0, 100.0), (10.0, 150.0)), false, 0.5);
+        index.insert(2, &line((0.0, 0.0), (1e9, 1e9)), false, 0.5);
+        let rect = Shape::Rect {
+            center: (300.0, 300.0),
+            width: 10.0,
+            height: 10.0,
+            rotation: 0.0,
+        };
+        index.insert(3, &rect, true, 0.5);
+
+        assert_eq!(index.query((150.0, 12.0), (150.0, 12.0)), vec![0, 2, 3]);
+        assert_eq!(index.query((5.0, 120.0), (15.0, 125.0)), vec![1, 2, 3]);
+        assert_eq!(index.query((500.0, 20.0), (500.0, 20.0)), vec![2, 3]);
+        assert_eq!(index.query((-1e6, -1e6), (1e6, 1e6)), vec![0, 1, 2, 3]);
+
+        index.truncate(2);
+        assert_eq!(index.query((-1e6, -1e6), (1e6, 1e6)), vec![0, 1]);
+
+        // A wide stroke reaches into cells its outline doesn't pass through.
+        index.insert(2, &line((10.0, 300.0), (100.0, 300.0)), false, 20.0);
+        assert_eq!(index.query((50.0, 318.0), (50.0, 318.0)), vec![2]);
+    }
+}
@@ -0,0 +1,1459 @@
+//! The canvas records everything the turtle draws as a list of elements, which
+//! are only turned into an image when the canvas is saved.
+//!
+//! Keeping the drawing as data rather than writing straight to an image lets
+//! us emit native SVG shapes (e.g. `<rect>` and `<polygon>`) and rasterise the
+//! very same drawing for PNG output.
+//!
+//! # Example
+//!
+//! ```rust
+//! use unsvg::COLORS;
+//!
+//! let mut canvas = Canvas::new(100, 100);
+//! let (x, y) = canvas.draw_line(50.0, 50.0, 0.0, 10.0, Style::new(COLORS[7]));
+//!
+//! assert_eq!((x, y), (50.0, 40.0));
+//! canvas.save_svg("image.svg").unwrap();
+//! ```
+
+pub mod colors;
+pub mod export;
+pub mod grid;
+mod html;
+mod index;
+pub mod palette;
+mod png;
+mod svg;
+pub mod units;
+pub mod viewport;
+
+pub use png::Antialias;
+pub use svg::{fmt_color, SplitBy, SvgStyle};
+
+use std::collections::HashMap;
+
+use unsvg::Color;
+
+use self::{
+    export::Export, grid::GridLine, index::ElementIndex, units::Length, viewport::Viewport,
+};
+
+/// Names for each color in `unsvg::COLORS`, in palette order.
+pub const COLOR_NAMES: [&str; 16] = [
+    "black", "blue", "cyan", "green", "red", "magenta", "yellow", "white", "brown", "tan",
+    "forest", "aqua", "salmon", "purple", "orange", "grey",
+];
+
+/// Looks up the palette index of a color by name, case insensitively.
+pub fn color_index(name: &str) -> Option<usize> {
+    let name = name.to_lowercase();
+    let name = if name == "gray" { "grey" } else { &name };
+    COLOR_NAMES.iter().position(|color| *color == name)
+}
+
+/// Converts a hue in degrees, wrapping around every 360, and a saturation and
+/// value from 0 to 1 into an RGB color.
+pub fn hsv_to_color(hue: f64, saturation: f64, value: f64) -> Color {
+    let hue = hue.rem_euclid(360.0) / 60.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (red, green, blue) = match hue as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let channel = |c: f64| ((c + value - chroma) * 255.0).round() as u8;
+    Color {
+        red: channel(red),
+        green: channel(green),
+        blue: channel(blue),
+    }
+}
+
+/// A shape drawn onto the canvas. Coordinates are in image space, where the
+/// origin is the top left corner.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Line {
+        from: (f32, f32),
+        to: (f32, f32),
+    },
+    /// A rectangle centred on `center`, rotated clockwise by `rotation` degrees.
+    Rect {
+        center: (f32, f32),
+        width: f32,
+        height: f32,
+        rotation: f32,
+    },
+    /// A closed polygon through each of the points in order.
+    Polygon {
+        points: Vec<(f32, f32)>,
+    },
+    Circle {
+        center: (f32, f32),
+        radius: f32,
+    },
+    /// An ellipse centred on `center` with radii `rx` across and `ry` down,
+    /// rotated clockwise by `rotation` degrees.
+    Ellipse {
+        center: (f32, f32),
+        rx: f32,
+        ry: f32,
+        rotation: f32,
+    },
+    /// Text written along its baseline in the `font` family, `size` pixels
+    /// high and rotated clockwise by `rotation` degrees from running left to
+    /// right. `align` says whether `at` is its start, middle or end.
+    Text {
+        at: (f32, f32),
+        text: String,
+        size: f32,
+        rotation: f32,
+        font: String,
+        align: TextAlign,
+    },
+}
+
+impl Shape {
+    /// The vertices of the shape, used to work out its extent. A circle's are
+    /// the ends of its horizontal and vertical diameters, and an ellipse's its
+    /// leftmost, rightmost, top and bottom points.
+    pub fn points(&self) -> Vec<(f32, f32)> {
+        match self {
+            Shape::Line { from, to } => vec![*from, *to],
+            Shape::Rect {
+                center,
+                width,
+                height,
+                rotation,
+            } => {
+                let (sin, cos) = rotation.to_radians().sin_cos();
+                [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+                    .iter()
+                    .map(|(sx, sy)| {
+                        let (dx, dy) = (sx * width / 2.0, sy * height / 2.0);
+                        (
+                            center.0 + dx * cos - dy * sin,
+                            center.1 + dx * sin + dy * cos,
+                        )
+                    })
+                    .collect()
+            }
+            Shape::Polygon { points } => points.clone(),
+            Shape::Circle { center, radius } => vec![
+                (center.0, center.1 - radius),
+                (center.0 + radius, center.1),
+                (center.0, center.1 + radius),
+                (center.0 - radius, center.1),
+            ],
+            Shape::Ellipse {
+                center,
+                rx,
+                ry,
+                rotation,
+            } => {
+                let (sin, cos) = rotation.to_radians().sin_cos();
+                // The angles around the ellipse furthest along x and along y.
+                let x_extreme = (-ry * sin).atan2(rx * cos);
+                let y_extreme = (ry * cos).atan2(rx * sin);
+                [
+                    x_extreme,
+                    y_extreme,
+                    x_extreme + std::f32::consts::PI,
+                    y_extreme + std::f32::consts::PI,
+                ]
+                .iter()
+                .map(|angle| ellipse_point(*center, *rx, *ry, *rotation, *angle))
+                .collect()
+            }
+            Shape::Text { at, .. } => vec![*at],
+        }
+    }
+
+    /// The edges of the shape's outline, as pairs of end points. Text has no
+    /// outline, and a circle's or an ellipse's is approximated by a polygon
+    /// whose edges stray no more than about a third of a pixel from it, up to
+    /// `MAX_CURVE_SIDES` sides.
+    pub fn edges(&self) -> Vec<((f32, f32), (f32, f32))> {
+        match self {
+            Shape::Line { from, to } => vec![(*from, *to)],
+            Shape::Text { .. } => vec![],
+            Shape::Circle { center, radius } => {
+                let sides = curve_sides(*radius);
+                let point = |i: usize| {
+                    let (sin, cos) = (i as f32 * std::f32::consts::TAU / sides as f32).sin_cos();
+                    (center.0 + radius * cos, center.1 + radius * sin)
+                };
+                (0..sides).map(|i| (point(i), point(i + 1))).collect()
+            }
+            Shape::Ellipse {
+                center,
+                rx,
+                ry,
+                rotation,
+            } => {
+                let sides = curve_sides(rx.max(*ry));
+                let point = |i: usize| {
+                    let angle = i as f32 * std::f32::consts::TAU / sides as f32;
+                    ellipse_point(*center, *rx, *ry, *rotation, angle)
+                };
+                (0..sides).map(|i| (point(i), point(i + 1))).collect()
+            }
+            _ => {
+                let points = self.points();
+                points
+                    .iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .map(|(from, to)| (*from, *to))
+                    .collect()
+            }
+        }
+    }
+
+    /// Whether `point` lies inside the shape. Lines and text have no inside.
+    pub fn contains(&self, point: (f32, f32)) -> bool {
+        match self {
+            Shape::Line { .. } | Shape::Text { .. } => return false,
+            Shape::Circle { center, radius } => {
+                return (point.0 - center.0).hypot(point.1 - center.1) <= *radius
+            }
+            Shape::Ellipse {
+                center,
+                rx,
+                ry,
+                rotation,
+            } => {
+                // Rotates the point back into the ellipse's own axes.
+                let (sin, cos) = rotation.to_radians().sin_cos();
+                let (dx, dy) = (point.0 - center.0, point.1 - center.1);
+                let (x, y) = (dx * cos + dy * sin, dy * cos - dx * sin);
+                return (x / rx).powi(2) + (y / ry).powi(2) <= 1.0;
+            }
+            _ => {}
+        }
+
+        // Counts how many edges a ray to the right of the point crosses.
+        self.edges()
+            .iter()
+            .filter(|((x1, y1), (x2, y2))| {
+                (*y1 > point.1) != (*y2 > point.1)
+                    && point.0 < x1 + (point.1 - y1) / (y2 - y1) * (x2 - x1)
+            })
+            .count()
+            % 2
+            == 1
+    }
+
+    /// A copy of the shape reflected left to right across the vertical line
+    /// through `x`. Text still reads forwards, but runs the other way.
+    pub fn mirrored(&self, x: f32) -> Shape {
+        let mirror = |point: (f32, f32)| (2.0 * x - point.0, point.1);
+
+        match self {
+            Shape::Line { from, to } => Shape::Line {
+                from: mirror(*from),
+                to: mirror(*to),
+            },
+            Shape::Rect {
+                center,
+                width,
+                height,
+                rotation,
+            } => Shape::Rect {
+                center: mirror(*center),
+                width: *width,
+                height: *height,
+                rotation: -rotation,
+            },
+            Shape::Polygon { points } => Shape::Polygon {
+                points: points.iter().map(|point| mirror(*point)).collect(),
+            },
+            Shape::Circle { center, radius } => Shape::Circle {
+                center: mirror(*center),
+                radius: *radius,
+            },
+            Shape::Ellipse {
+                center,
+                rx,
+                ry,
+                rotation,
+            } => Shape::Ellipse {
+                center: mirror(*center),
+                rx: *rx,
+                ry: *ry,
+                rotation: -rotation,
+            },
+            Shape::Text {
+                at,
+                text,
+                size,
+                rotation,
+                font,
+                align,
+            } => Shape::Text {
+                at: mirror(*at),
+                text: text.clone(),
+                size: *size,
+                rotation: 180.0 - rotation,
+                font: font.clone(),
+                align: *align,
+            },
+        }
+    }
+
+    /// A copy of the shape rotated clockwise by `degrees` around `origin`.
+    pub fn rotated(&self, degrees: f32, origin: (f32, f32)) -> Shape {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let rotate = |(x, y): (f32, f32)| {
+            let (dx, dy) = (x - origin.0, y - origin.1);
+            (
+                origin.0 + dx * cos - dy * sin,
+                origin.1 + dx * sin + dy * cos,
+            )
+        };
+
+        match self {
+            Shape::Line { from, to } => Shape::Line {
+                from: rotate(*from),
+                to: rotate(*to),
+            },
+            Shape::Rect {
+                center,
+                width,
+                height,
+                rotation,
+            } => Shape::Rect {
+                center: rotate(*center),
+                width: *width,
+                height: *height,
+                rotation: rotation + degrees,
+            },
+            Shape::Polygon { points } => Shape::Polygon {
+                points: points.iter().map(|point| rotate(*point)).collect(),
+            },
+            Shape::Circle { center, radius } => Shape::Circle {
+                center: rotate(*center),
+                radius: *radius,
+            },
+            Shape::Ellipse {
+                center,
+                rx,
+                ry,
+                rotation,
+            } => Shape::Ellipse {
+                center: rotate(*center),
+                rx: *rx,
+                ry: *ry,
+                rotation: rotation + degrees,
+            },
+            Shape::Text {
+                at,
+                text,
+                size,
+                rotation,
+                font,
+                align,
+            } => Shape::Text {
+                at: rotate(*at),
+                text: text.clone(),
+                size: *size,
+                rotation: rotation + degrees,
+                font: font.clone(),
+                align: *align,
+            },
+        }
+    }
+}
+
+/// How the inside of a closed shape is filled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FillPattern {
+    None,
+    Solid,
+    /// Diagonal lines.
+    Hatch,
+    /// Diagonal lines in both directions.
+    Crosshatch,
+    Dots,
+}
+
+impl FillPattern {
+    /// The names accepted by `SETFILLPATTERN`.
+    pub const NAMES: [&'static str; 5] = ["none", "solid", "hatch", "crosshatch", "dots"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FillPattern::None => "none",
+            FillPattern::Solid => "solid",
+            FillPattern::Hatch => "hatch",
+            FillPattern::Crosshatch => "crosshatch",
+            FillPattern::Dots => "dots",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FillPattern> {
+        match name.to_lowercase().as_str() {
+            "none" => Some(FillPattern::None),
+            "solid" => Some(FillPattern::Solid),
+            "hatch" => Some(FillPattern::Hatch),
+            "crosshatch" => Some(FillPattern::Crosshatch),
+            "dots" => Some(FillPattern::Dots),
+            _ => None,
+        }
+    }
+}
+
+/// How the strokes of lines and outlines are broken up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PenPattern {
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+impl PenPattern {
+    /// The names accepted by `SETPENPATTERN`.
+    pub const NAMES: [&'static str; 3] = ["solid", "dashed", "dotted"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PenPattern::Solid => "solid",
+            PenPattern::Dashed => "dashed",
+            PenPattern::Dotted => "dotted",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PenPattern> {
+        match name.to_lowercase().as_str() {
+            "solid" => Some(PenPattern::Solid),
+            "dashed" => Some(PenPattern::Dashed),
+            "dotted" => Some(PenPattern::Dotted),
+            _ => None,
+        }
+    }
+
+    /// The lengths of each dash and the gap after it for a stroke `width`
+    /// pixels wide, or `None` for a solid stroke. Both grow with the width
+    /// so wide dots stay round-ish rather than running together.
+    pub fn dashes(self, width: f32) -> Option<(f32, f32)> {
+        match self {
+            PenPattern::Solid => None,
+            PenPattern::Dashed => Some((6.0 * width, 4.0 * width)),
+            PenPattern::Dotted => Some((width, 2.0 * width)),
+        }
+    }
+}
+
+/// Where text is written relative to the point it's written at, along its
+/// baseline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+impl TextAlign {
+    /// The names accepted by `SETLABELALIGN`.
+    pub const NAMES: [&'static str; 3] = ["left", "center", "right"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TextAlign::Left => "left",
+            TextAlign::Center => "center",
+            TextAlign::Right => "right",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TextAlign> {
+        match name.to_lowercase().as_str() {
+            "left" => Some(TextAlign::Left),
+            "center" | "centre" => Some(TextAlign::Center),
+            "right" => Some(TextAlign::Right),
+            _ => None,
+        }
+    }
+
+    /// The SVG `text-anchor` with this alignment.
+    pub fn anchor(self) -> &'static str {
+        match self {
+            TextAlign::Left => "start",
+            TextAlign::Center => "middle",
+            TextAlign::Right => "end",
+        }
+    }
+}
+
+/// The pen an element was drawn with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub color: Color,
+    /// Only applies to closed shapes; lines are never filled.
+    pub fill: FillPattern,
+    /// Between 0 (invisible) and 1 (opaque).
+    pub opacity: f32,
+    /// The stroke width in pixels.
+    pub width: f32,
+    /// Whether the stroke is dashed or dotted.
+    pub pattern: PenPattern,
+    /// Drawn with `PENREVERSE`, taking the difference with whatever is
+    /// beneath instead of painting over it, so drawing the same thing twice
+    /// leaves the canvas as it was.
+    pub reverse: bool,
+}
+
+impl Style {
+    pub fn new(color: Color) -> Style {
+        Style {
+            color,
+            fill: FillPattern::None,
+            opacity: 1.0,
+            width: 1.0,
+            pattern: PenPattern::Solid,
+            reverse: false,
+        }
+    }
+}
+
+/// The color `PENREVERSE` leaves when drawing `color` over `below`: the
+/// difference of each channel.
+pub fn reverse_color(below: Color, color: Color) -> Color {
+    Color {
+        red: below.red.abs_diff(color.red),
+        green: below.green.abs_diff(color.green),
+        blue: below.blue.abs_diff(color.blue),
+    }
+}
+
+/// A shape along with the pen it was drawn with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub shape: Shape,
+    pub style: Style,
+    /// Layers are drawn in ascending order, so higher layers appear on top.
+    pub layer: usize,
+    /// Set if the element was drawn while the trail was fading.
+    pub trail: Option<Trail>,
+    /// The rectangle the element is clipped to, if any.
+    pub clip: Option<Clip>,
+}
+
+impl Element {
+    /// Whether the element is drawn over `point`.
+    pub fn covers(&self, point: (f32, f32)) -> bool {
+        if self.clip.is_some_and(|clip| !clip.contains(point)) {
+            return false;
+        }
+        (self.style.fill == FillPattern::Solid && self.shape.contains(point))
+            || self
+                .shape
+                .edges()
+                .iter()
+                .any(|(from, to)| distance_to_segment(point, *from, *to) <= self.style.width / 2.0)
+    }
+}
+
+/// A rectangle of the canvas that elements are clipped to, from its top left
+/// corner, set with `SETCLIP`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clip {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Clip {
+    /// Whether a point lies inside the rectangle, edges included.
+    pub fn contains(&self, (x, y): (f32, f32)) -> bool {
+        (self.x..=self.x + self.width).contains(&x) && (self.y..=self.y + self.height).contains(&y)
+    }
+}
+
+/// An element's place in a fading trail.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trail {
+    /// How many faded elements were drawn before this one.
+    pub index: usize,
+    /// How many elements it takes for the trail to fade out completely.
+    pub length: usize,
+}
+
+/// An effect applied to every element on a layer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// A copy of the layer, offset by (dx, dy) and recolored, drawn beneath it.
+    Shadow { dx: f32, dy: f32, color: Color },
+    /// A copy of the layer with strokes widened by `width` on each side and
+    /// recolored, drawn beneath it.
+    Outline { width: f32, color: Color },
+}
+
+#[derive(Debug, Clone)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    /// Everything drawn so far, in drawing order.
+    pub elements: Vec<Element>,
+    /// The layer new elements are drawn on.
+    pub layer: usize,
+    /// The rectangle new elements are clipped to, set with `SETCLIP`.
+    pub clip: Option<Clip>,
+    pub effects: HashMap<usize, Effect>,
+    /// How many ways each shape is repeated around the centre of the canvas,
+    /// where 1 draws shapes once.
+    pub symmetry: usize,
+    /// Whether each shape is also mirrored left to right across the centre of
+    /// the canvas before it's repeated, making a kaleidoscope.
+    pub mirror: bool,
+    /// The fade length for elements drawn from now on, where 0 turns fading
+    /// off.
+    pub trail_fade: usize,
+    /// How many faded elements have been drawn.
+    pub trail_len: usize,
+    /// The printed size given with `--size`, written into the SVG in place of
+    /// the size in pixels.
+    pub physical_size: Option<(Length, Length)>,
+    /// Decimal places coordinates are written to SVG with, set with
+    /// `--svg-precision`. `None` writes them in full.
+    pub svg_precision: Option<usize>,
+    /// How the SVG is laid out, set with `--svg-style`.
+    pub svg_style: Option<SvgStyle>,
+    /// The region written out, set with `--viewport`. `None` writes the
+    /// whole canvas.
+    pub viewport: Option<Viewport>,
+    /// How edges are smoothed in PNG output, set with `--antialias`.
+    pub antialias: Antialias,
+    /// How the image is turned, mirrored and scaled as it's saved, set with
+    /// `--rotate-output`, `--flip` and `--scale-output`.
+    pub export: Export,
+    /// The spacing of the coordinate grid written out beneath the drawing,
+    /// set with `--grid`. `None` leaves it out.
+    pub grid: Option<f32>,
+    /// Where each element is, for `PIXEL`, `TOUCHING` and `INTERSECTS`.
+    index: ElementIndex,
+    /// The index of the first element of each stroke, where a stroke is a
+    /// shape and its symmetric copies.
+    strokes: Vec<usize>,
+}
+
+/// The distance from `point` to the segment between `from` and `to`.
+pub fn distance_to_segment(point: (f32, f32), from: (f32, f32), to: (f32, f32)) -> f32 {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let len_sq = dx * dx + dy * dy;
+    let t = if len_sq == 0.0 {
+        0.0
+    } else {
+        (((point.0 - from.0) * dx + (point.1 - from.1) * dy) / len_sq).clamp(0.0, 1.0)
+    };
+    let (x, y) = (from.0 + t * dx, from.1 + t * dy);
+    ((point.0 - x).powi(2) + (point.1 - y).powi(2)).sqrt()
+}
+
+/// Whether the segment `path` meets the segment `edge` anywhere other than at
+/// its start.
+fn crosses(path: ((f32, f32), (f32, f32)), edge: ((f32, f32), (f32, f32))) -> bool {
+    const EPSILON: f32 = 1e-4;
+    let ((x1, y1), (x2, y2)) = path;
+    let ((x3, y3), (x4, y4)) = edge;
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let (ex, ey) = (x4 - x3, y4 - y3);
+    let len = dx.hypot(dy);
+    if len == 0.0 {
+        return false;
+    }
+
+    let denom = dx * ey - dy * ex;
+    if denom.abs() <= EPSILON * len * ex.hypot(ey) {
+        // Parallel, so they only meet if the edge lies on the same line and
+        // reaches past the start of the path.
+        if (((x3 - x1) * dy - (y3 - y1) * dx) / len).abs() > EPSILON {
+            return false;
+        }
+        let along = |x: f32, y: f32| ((x - x1) * dx + (y - y1) * dy) / len;
+        let (a, b) = (along(x3, y3), along(x4, y4));
+        return a.max(b) > EPSILON && a.min(b) <= len;
+    }
+
+    // How far along the path and the edge they cross.
+    let t = ((x3 - x1) * ey - (y3 - y1) * ex) / denom;
+    let u = ((x3 - x1) * dy - (y3 - y1) * dx) / denom;
+    t * len > EPSILON && t <= 1.0 && (0.0..=1.0).contains(&u)
+}
+
+/// The most sides the polygon approximating a circle or ellipse has, reached at a
+/// radius of about a million pixels. Without a limit, a huge radius would ask
+/// for more edges than there is memory.
+const MAX_CURVE_SIDES: f32 = 4096.0;
+
+/// The number of sides of the polygon approximating a curve of `radius`.
+fn curve_sides(radius: f32) -> usize {
+    (4.0 * radius.sqrt()).ceil().clamp(8.0, MAX_CURVE_SIDES) as usize
+}
+
+/// The point `angle` radians around an ellipse, measured before it's rotated
+/// clockwise by `rotation` degrees.
+fn ellipse_point(center: (f32, f32), rx: f32, ry: f32, rotation: f32, angle: f32) -> (f32, f32) {
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let (x, y) = (rx * angle.cos(), ry * angle.sin());
+    (center.0 + x * cos - y * sin, center.1 + x * sin + y * cos)
+}
+
+/// Rounds a coordinate to the nearest 1/256th, so that the coordinates we
+/// emit match those `unsvg` has always produced.
+pub fn quantize(x: f32) -> f32 {
+    (x * 256.0).round() / 256.0
+}
+
+/// Where a line `length` long from `(x, y)` ends when drawn `direction`
+/// degrees clockwise from straight up, quantized as `unsvg` does. Unlike
+/// `unsvg::get_end_coordinates` the direction needn't be whole, but whole
+/// directions give exactly the same point.
+pub fn end_coordinates(x: f32, y: f32, direction: f32, length: f32) -> (f32, f32) {
+    let (x, y) = (quantize(x), quantize(y));
+    let (sin, cos) = (direction - 90.0).to_radians().sin_cos();
+    (quantize(x + cos * length), quantize(y + sin * length))
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Canvas {
+        Canvas {
+            width,
+            height,
+            elements: Vec::new(),
+            layer: 0,
+            clip: None,
+            effects: HashMap::new(),
+            symmetry: 1,
+            mirror: false,
+            trail_fade: 0,
+            trail_len: 0,
+            physical_size: None,
+            svg_precision: None,
+            svg_style: None,
+            viewport: None,
+            antialias: Antialias::default(),
+            export: Export::default(),
+            grid: None,
+            index: ElementIndex::default(),
+            strokes: Vec::new(),
+        }
+    }
+
+    /// Get the size of the canvas as a tuple of (width, height).
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The size of the saved image in pixels, which is the viewport's if one
+    /// is set, turned and scaled as it's saved.
+    pub fn output_size(&self) -> (u32, u32) {
+        let (width, height) = match self.viewport {
+            Some(viewport) => viewport.size_px(),
+            None => (self.width, self.height),
+        };
+        if self.export.is_identity() {
+            return (width, height);
+        }
+        let (width, height) = self.export.size((width as f32, height as f32));
+        (width.ceil() as u32, height.ceil() as u32)
+    }
+
+    /// Draw a line on the canvas, taking a starting point, direction, length
+    /// and style. We return the end point of the line as a tuple of (x, y).
+    pub fn draw_line(
+        &mut self,
+        x: f32,
+        y: f32,
+        direction: f32,
+        length: f32,
+        style: Style,
+    ) -> (f32, f32) {
+        let from = (quantize(x), quantize(y));
+        let to = end_coordinates(from.0, from.1, direction, length);
+        self.draw(Shape::Line { from, to }, style);
+
+        to
+    }
+
+    /// The lines of the coordinate grid over the region written out, or
+    /// none if there's no grid.
+    pub fn grid_lines(&self) -> Vec<GridLine> {
+        let Some(spacing) = self.grid else {
+            return Vec::new();
+        };
+        let viewport = self.viewport.unwrap_or(Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: self.width as f32,
+            height: self.height as f32,
+        });
+        let center = (self.width as f32 / 2.0, self.height as f32 / 2.0);
+        grid::grid_lines(spacing, center, &viewport)
+    }
+
+    /// Draw an arbitrary shape on the canvas, along with its rotated and
+    /// mirrored copies when symmetry is on.
+    pub fn draw(&mut self, shape: Shape, style: Style) {
+        let center = (self.width as f32 / 2.0, self.height as f32 / 2.0);
+        let mut copies: Vec<Shape> = (1..self.symmetry)
+            .map(|i| shape.rotated(360.0 * i as f32 / self.symmetry as f32, center))
+            .collect();
+        if self.mirror {
+            let mirrored = shape.mirrored(center.0);
+            copies.extend(
+                (0..self.symmetry)
+                    .map(|i| mirrored.rotated(360.0 * i as f32 / self.symmetry as f32, center)),
+            );
+        }
+
+        let trail = (self.trail_fade > 0).then(|| {
+            self.trail_len += 1;
+            Trail {
+                index: self.trail_len - 1,
+                length: self.trail_fade,
+            }
+        });
+
+        self.strokes.push(self.elements.len());
+        for shape in std::iter::once(shape).chain(copies) {
+            self.index.insert(
+                self.elements.len(),
+                &shape,
+                style.fill == FillPattern::Solid,
+                style.width / 2.0,
+            );
+            self.elements.push(Element {
+                shape,
+                style,
+                layer: self.layer,
+                trail,
+                clip: self.clip,
+            });
+        }
+    }
+
+    /// Removes the `count` most recently drawn strokes, along with their
+    /// symmetric copies, or everything if fewer have been drawn.
+    pub fn erase(&mut self, count: usize) {
+        let strokes = self.strokes.len().saturating_sub(count);
+        let len = self
+            .strokes
+            .get(strokes)
+            .copied()
+            .unwrap_or(self.elements.len());
+        self.strokes.truncate(strokes);
+
+        // Later faded elements continue the trail from where the erased ones
+        // started.
+        if let Some(trail) = self.elements[len..]
+            .iter()
+            .find_map(|element| element.trail)
+        {
+            self.trail_len = trail.index;
+        }
+        self.elements.truncate(len);
+        self.index.truncate(len);
+    }
+
+    /// Repeat everything drawn from now on `ways` times around the centre of
+    /// the canvas.
+    pub fn set_symmetry(&mut self, ways: usize) {
+        self.symmetry = ways;
+        self.mirror = false;
+    }
+
+    /// Repeat everything drawn from now on `ways` times around the centre of
+    /// the canvas, along with its mirror image, so there are `2 * ways`
+    /// copies in all.
+    pub fn set_kaleidoscope(&mut self, ways: usize) {
+        self.symmetry = ways;
+        self.mirror = true;
+    }
+
+    /// Fade out everything drawn from now on over `length` elements, so the
+    /// most recent element is opaque and older ones are fainter. A length of 0
+    /// stops fading.
+    pub fn set_trail_fade(&mut self, length: usize) {
+        self.trail_fade = length;
+    }
+
+    /// The opacity an element is rendered with, once any trail fading has
+    /// been applied.
+    pub fn opacity(&self, element: &Element) -> f32 {
+        match element.trail {
+            Some(trail) => {
+                let age = (self.trail_len - 1 - trail.index) as f32;
+                element.style.opacity * (1.0 - age / trail.length as f32).max(0.0)
+            }
+            None => element.style.opacity,
+        }
+    }
+
+    /// Select the layer that subsequent drawing goes onto.
+    pub fn set_layer(&mut self, layer: usize) {
+        self.layer = layer;
+    }
+
+    /// Set or clear the effect on the current layer.
+    pub fn set_effect(&mut self, effect: Option<Effect>) {
+        match effect {
+            Some(effect) => self.effects.insert(self.layer, effect),
+            None => self.effects.remove(&self.layer),
+        };
+    }
+
+    /// The layers that have been drawn on, in ascending order.
+    pub fn layers(&self) -> Vec<usize> {
+        let mut layers: Vec<usize> = self.elements.iter().map(|e| e.layer).collect();
+        layers.sort();
+        layers.dedup();
+        layers
+    }
+
+    /// The smallest box containing everything drawn, as
+    /// `(min_x, min_y, max_x, max_y)`, or `None` if nothing has been drawn.
+    pub fn bounding_box(&self) -> Option<(f32, f32, f32, f32)> {
+        self.elements
+            .iter()
+            .flat_map(|element| element.shape.points())
+            .fold(None, |bbox, (x, y)| match bbox {
+                None => Some((x, y, x, y)),
+                Some((min_x, min_y, max_x, max_y)) => {
+                    Some((min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)))
+                }
+            })
+    }
+
+    /// Widens the region written out to take in everything drawn off the
+    /// canvas, along with half the widest stroke, as well as the canvas
+    /// itself. A viewport given with `--viewport` is left as it is.
+    pub fn fit_viewport(&mut self) {
+        let Some((min_x, min_y, max_x, max_y)) = self.bounding_box() else {
+            return;
+        };
+        if self.viewport.is_some() {
+            return;
+        }
+        let margin = self
+            .elements
+            .iter()
+            .map(|element| element.style.width / 2.0)
+            .fold(0.0, f32::max);
+        let x = (min_x - margin).floor().min(0.0);
+        let y = (min_y - margin).floor().min(0.0);
+        let right = (max_x + margin).ceil().max(self.width as f32);
+        let bottom = (max_y + margin).ceil().max(self.height as f32);
+        self.viewport = Some(Viewport {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        });
+    }
+
+    /// The color drawn at `(x, y)`, or `None` where only the background
+    /// shows. The topmost element covering the point wins: a later element
+    /// over an earlier one, and a higher layer over a lower one. Strokes
+    /// cover points within half their width of them and solid fills their
+    /// inside, while patterned fills, effects and fully faded elements are
+    /// ignored. Reversed elements are combined with what's beneath them, and
+    /// where they cancel out to black only the background shows.
+    pub fn color_at(&self, x: f32, y: f32) -> Option<Color> {
+        let mut covering: Vec<(usize, &Element)> = self
+            .index
+            .query((x, y), (x, y))
+            .into_iter()
+            .map(|index| (index, &self.elements[index]))
+            .filter(|(_, element)| self.opacity(element) > 0.0 && element.covers((x, y)))
+            .collect();
+        covering.sort_by_key(|(index, element)| (element.layer, *index));
+
+        let black = Color {
+            red: 0,
+            green: 0,
+            blue: 0,
+        };
+        covering.into_iter().fold(None, |below, (_, element)| {
+            if !element.style.reverse {
+                return Some(element.style.color);
+            }
+            let color = reverse_color(below.unwrap_or(black), element.style.color);
+            (color != black).then_some(color)
+        })
+    }
+
+    /// Whether anything has been drawn within `radius` of `(x, y)`.
+    pub fn touching(&self, x: f32, y: f32, radius: f32) -> bool {
+        self.index
+            .query((x - radius, y - radius), (x + radius, y + radius))
+            .into_iter()
+            .flat_map(|index| self.elements[index].shape.edges())
+            .any(|(from, to)| distance_to_segment((x, y), from, to) <= radius)
+    }
+
+    /// Whether a move from `from` to `to` would cross anything drawn so far,
+    /// not counting lines that only meet it at `from`, such as the line the
+    /// turtle has just drawn to get there.
+    pub fn intersects(&self, from: (f32, f32), to: (f32, f32)) -> bool {
+        let min = (from.0.min(to.0), from.1.min(to.1));
+        let max = (from.0.max(to.0), from.1.max(to.1));
+        self.index
+            .query(min, max)
+            .into_iter()
+            .flat_map(|index| self.elements[index].shape.edges())
+            .any(|edge| crosses((from, to), edge))
+    }
+
+    /// Save the canvas as an SVG file.
+    pub fn save_svg<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_svg())
+    }
+
+    /// Save the canvas as an HTML page that replays the drawing.
+    pub fn save_html<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_html())
+    }
+
+    /// Save the canvas as a PNG file.
+    pub fn save_png<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        let pixmap = self.rasterise()?;
+        pixmap.save_png(path).map_err(std::io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unsvg::COLORS;
+
+    use super::*;
+
+    #[test]
+    fn test_new_canvas() {
+        let canvas = Canvas::new(100, 50);
+
+        assert_eq!(canvas.get_dimensions(), (100, 50));
+        assert!(canvas.elements.is_empty());
+    }
+
+    #[test]
+    fn test_draw_line() {
+        let mut canvas = Canvas::new(100, 100);
+
+        let end = canvas.draw_line(50.0, 50.0, 90.0, 10.0, Style::new(COLORS[1]));
+
+        assert_eq!(end, (60.0, 50.0));
+        assert_eq!(
+            canvas.elements,
+            vec![Element {
+                shape: Shape::Line {
+                    from: (50.0, 50.0),
+                    to: (60.0, 50.0)
+                },
+                style: Style::new(COLORS[1]),
+                layer: 0,
+                trail: None,
+                clip: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fill_pattern_from_name() {
+        assert_eq!(FillPattern::from_name("hatch"), Some(FillPattern::Hatch));
+        assert_eq!(FillPattern::from_name("SOLID"), Some(FillPattern::Solid));
+        assert_eq!(FillPattern::from_name("stripes"), None);
+    }
+
+    #[test]
+    fn test_pen_pattern() {
+        assert_eq!(PenPattern::from_name("Dotted"), Some(PenPattern::Dotted));
+        assert_eq!(PenPattern::from_name("wavy"), None);
+        assert_eq!(PenPattern::Solid.dashes(2.0), None);
+        assert_eq!(PenPattern::Dashed.dashes(2.0), Some((12.0, 8.0)));
+    }
+
+    #[test]
+    fn test_hsv_to_color() {
+        let rgb = |red, green, blue| Color { red, green, blue };
+        assert_eq!(hsv_to_color(0.0, 1.0, 1.0), rgb(255, 0, 0));
+        assert_eq!(hsv_to_color(120.0, 1.0, 1.0), rgb(0, 255, 0));
+        assert_eq!(hsv_to_color(-120.0, 1.0, 1.0), rgb(0, 0, 255));
+        assert_eq!(hsv_to_color(390.0, 1.0, 1.0), rgb(255, 128, 0));
+        assert_eq!(hsv_to_color(200.0, 0.0, 0.5), rgb(128, 128, 128));
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut canvas = Canvas::new(100, 100);
+
+        canvas.set_layer(2);
+        canvas.draw_line(0.0, 0.0, 90.0, 10.0, Style::new(COLORS[1]));
+        canvas.set_layer(1);
+        canvas.draw_line(0.0, 0.0, 90.0, 10.0, Style::new(COLORS[1]));
+        canvas.draw_line(0.0, 0.0, 90.0, 10.0, Style::new(COLORS[1]));
+
+        assert_eq!(canvas.layers(), vec![1, 2]);
+        assert_eq!(canvas.elements[0].layer, 2);
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let mut canvas = Canvas::new(100, 100);
+        assert_eq!(canvas.bounding_box(), None);
+
+        canvas.draw_line(10.0, 20.0, 90.0, 30.0, Style::new(COLORS[1]));
+        canvas.draw(
+            Shape::Rect {
+                center: (50.0, 50.0),
+                width: 20.0,
+                height: 10.0,
+                rotation: 90.0,
+            },
+            Style::new(COLORS[1]),
+        );
+
+        let (min_x, min_y, max_x, max_y) = canvas.bounding_box().unwrap();
+        assert_eq!((min_x, min_y), (10.0, 20.0));
+        assert!((max_x - 55.0).abs() < 1e-4);
+        assert!((max_y - 60.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_fit_viewport() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.fit_viewport();
+        assert_eq!(canvas.viewport, None);
+
+        canvas.draw_line(50.0, 50.0, 90.0, 20.0, Style::new(COLORS[1]));
+        canvas.fit_viewport();
+        assert_eq!(
+            canvas.viewport,
+            Some(Viewport {
+                x: 0.0,
+                y: 0.0,
+                width: 100.0,
+                height: 100.0
+            })
+        );
+
+        canvas.viewport = None;
+        canvas.draw_line(50.0, 50.0, 0.0, 70.5, Style::new(COLORS[1]));
+        canvas.draw_line(50.0, 50.0, 90.0, 80.0, Style::new(COLORS[1]));
+        canvas.fit_viewport();
+        assert_eq!(
+            canvas.viewport,
+            Some(Viewport {
+                x: 0.0,
+                y: -21.0,
+                width: 131.0,
+                height: 121.0
+            })
+        );
+        assert_eq!(canvas.output_size(), (131, 121));
+    }
+
+    #[test]
+    fn test_color_at() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.draw_line(10.0, 50.0, 90.0, 80.0, Style::new(COLORS[1]));
+        canvas.draw(
+            Shape::Rect {
+                center: (50.0, 50.0),
+                width: 20.0,
+                height: 20.0,
+                rotation: 0.0,
+            },
+            Style {
+                fill: FillPattern::Solid,
+                ..Style::new(COLORS[4])
+            },
+        );
+        canvas.set_layer(1);
+        canvas.draw_line(20.0, 10.0, 180.0, 80.0, Style::new(COLORS[2]));
+        canvas.set_layer(0);
+        canvas.draw_line(20.0, 60.0, 90.0, 10.0, Style::new(COLORS[3]));
+
+        assert_eq!(canvas.color_at(15.0, 50.3), Some(COLORS[1]));
+        assert_eq!(canvas.color_at(15.0, 51.0), None);
+        // The rectangle is drawn over the first line.
+        assert_eq!(canvas.color_at(50.0, 50.0), Some(COLORS[4]));
+        // The second line is on a higher layer than the third.
+        assert_eq!(canvas.color_at(20.0, 60.0), Some(COLORS[2]));
+        assert_eq!(canvas.color_at(25.0, 60.0), Some(COLORS[3]));
+        assert_eq!(canvas.color_at(90.0, 90.0), None);
+    }
+
+    #[test]
+    fn test_color_at_reverse() {
+        let mut canvas = Canvas::new(100, 100);
+        let reverse = Style {
+            reverse: true,
+            ..Style::new(COLORS[4])
+        };
+        canvas.draw_line(10.0, 50.0, 90.0, 80.0, Style::new(COLORS[1]));
+        canvas.draw_line(50.0, 10.0, 180.0, 80.0, reverse);
+        canvas.draw_line(30.0, 10.0, 180.0, 80.0, reverse);
+        canvas.draw_line(30.0, 10.0, 180.0, 80.0, reverse);
+
+        assert_eq!(canvas.color_at(50.0, 30.0), Some(COLORS[4]));
+        assert_eq!(
+            canvas.color_at(50.0, 50.0),
+            Some(reverse_color(COLORS[1], COLORS[4]))
+        );
+        // Drawing the same line twice cancels it out.
+        assert_eq!(canvas.color_at(30.0, 30.0), None);
+        assert_eq!(canvas.color_at(30.0, 50.0), Some(COLORS[1]));
+    }
+
+    #[test]
+    fn test_circle() {
+        let mut canvas = Canvas::new(100, 100);
+        let circle = Shape::Circle {
+            center: (50.0, 50.0),
+            radius: 20.0,
+        };
+        assert!(circle.contains((60.0, 60.0)));
+        assert!(!circle.contains((65.0, 65.0)));
+
+        canvas.draw(circle, Style::new(COLORS[4]));
+        assert_eq!(canvas.color_at(50.0, 30.2), Some(COLORS[4]));
+        assert_eq!(canvas.color_at(50.0, 50.0), None);
+        assert!(canvas.touching(50.0, 75.0, 5.5));
+        assert!(!canvas.touching(50.0, 50.0, 15.0));
+        assert_eq!(canvas.bounding_box(), Some((30.0, 30.0, 70.0, 70.0)));
+
+        // A huge circle is drawn with a bounded number of edges.
+        let circle = Shape::Circle {
+            center: (50.0, 50.0),
+            radius: 1e30,
+        };
+        assert_eq!(circle.edges().len(), MAX_CURVE_SIDES as usize);
+        canvas.draw(circle, Style::new(COLORS[4]));
+    }
+
+    #[test]
+    fn test_ellipse() {
+        let mut canvas = Canvas::new(100, 100);
+        let ellipse = Shape::Ellipse {
+            center: (50.0, 50.0),
+            rx: 30.0,
+            ry: 10.0,
+            rotation: 90.0,
+        };
+        assert!(ellipse.contains((50.0, 75.0)));
+        assert!(!ellipse.contains((75.0, 50.0)));
+
+        canvas.draw(ellipse, Style::new(COLORS[4]));
+        assert_eq!(canvas.color_at(50.0, 20.2), Some(COLORS[4]));
+        assert_eq!(canvas.color_at(50.0, 50.0), None);
+        let (min_x, min_y, max_x, max_y) = canvas.bounding_box().unwrap();
+        assert!((min_x - 40.0).abs() < 1e-4 && (max_x - 60.0).abs() < 1e-4);
+        assert!((min_y - 20.0).abs() < 1e-4 && (max_y - 80.0).abs() < 1e-4);
+
+        let ellipse = Shape::Ellipse {
+            center: (50.0, 50.0),
+            rx: 1e30,
+            ry: 1.0,
+            rotation: 0.0,
+        };
+        assert_eq!(ellipse.edges().len(), MAX_CURVE_SIDES as usize);
+    }
+
+    #[test]
+    fn test_touching() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.draw_line(10.0, 50.0, 90.0, 80.0, Style::new(COLORS[1]));
+
+        assert!(canvas.touching(50.0, 52.0, 2.0));
+        assert!(!canvas.touching(50.0, 52.0, 1.5));
+        assert!(canvas.touching(5.0, 50.0, 5.0));
+        assert!(!canvas.touching(5.0, 40.0, 5.0));
+    }
+
+    #[test]
+    fn test_intersects() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.draw_line(10.0, 50.0, 90.0, 80.0, Style::new(COLORS[1]));
+
+        // Crossing the line, and stopping on it.
+        assert!(canvas.intersects((50.0, 40.0), (50.0, 60.0)));
+        assert!(canvas.intersects((50.0, 40.0), (50.0, 50.0)));
+        // Stopping short of it.
+        assert!(!canvas.intersects((50.0, 40.0), (50.0, 49.0)));
+        // Leaving from its end, or from a point on it.
+        assert!(!canvas.intersects((90.0, 50.0), (90.0, 10.0)));
+        assert!(!canvas.intersects((50.0, 50.0), (50.0, 10.0)));
+        // Running back along it.
+        assert!(canvas.intersects((90.0, 50.0), (70.0, 50.0)));
+        // Continuing straight on from its end.
+        assert!(!canvas.intersects((90.0, 50.0), (95.0, 50.0)));
+    }
+
+    #[test]
+    fn test_symmetry() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.set_symmetry(4);
+        canvas.draw_line(50.0, 50.0, 0.0, 10.0, Style::new(COLORS[1]));
+
+        assert_eq!(canvas.elements.len(), 4);
+        let ends: Vec<(f32, f32)> = canvas
+            .elements
+            .iter()
+            .map(|element| match element.shape {
+                Shape::Line { to, .. } => (to.0.round(), to.1.round()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            ends,
+            vec![(50.0, 40.0), (60.0, 50.0), (50.0, 60.0), (40.0, 50.0)]
+        );
+    }
+
+    #[test]
+    fn test_kaleidoscope() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.set_kaleidoscope(2);
+        canvas.draw_line(60.0, 50.0, 0.0, 10.0, Style::new(COLORS[1]));
+
+        assert_eq!(canvas.elements.len(), 4);
+        let ends: Vec<(f32, f32)> = canvas
+            .elements
+            .iter()
+            .map(|element| match element.shape {
+                Shape::Line { to, .. } => (to.0.round(), to.1.round()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            ends,
+            vec![(60.0, 40.0), (40.0, 60.0), (40.0, 40.0), (60.0, 60.0)]
+        );
+
+        // Erasing the line erases its mirror images too.
+        canvas.erase(1);
+        assert!(canvas.elements.is_empty());
+
+        canvas.set_symmetry(2);
+        canvas.draw_line(60.0, 50.0, 0.0, 10.0, Style::new(COLORS[1]));
+        assert_eq!(canvas.elements.len(), 2);
+    }
+
+    #[test]
+    fn test_mirrored_rect() {
+        let rect = Shape::Rect {
+            center: (60.0, 50.0),
+            width: 4.0,
+            height: 2.0,
+            rotation: 10.0,
+        };
+
+        assert_eq!(
+            rect.mirrored(50.0),
+            Shape::Rect {
+                center: (40.0, 50.0),
+                width: 4.0,
+                height: 2.0,
+                rotation: -10.0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_rotated_rect() {
+        let rect = Shape::Rect {
+            center: (60.0, 50.0),
+            width: 4.0,
+            height: 2.0,
+            rotation: 10.0,
+        };
+
+        match rect.rotated(180.0, (50.0, 50.0)) {
+            Shape::Rect {
+                center, rotation, ..
+            } => {
+                assert!((center.0 - 40.0).abs() < 1e-4);
+                assert!((center.1 - 50.0).abs() < 1e-4);
+                assert_eq!(rotation, 190.0);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_trail_fade() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.draw_line(0.0, 0.0, 90.0, 10.0, Style::new(COLORS[1]));
+        canvas.set_trail_fade(4);
+        for _ in 0..3 {
+            canvas.draw_line(0.0, 0.0, 90.0, 10.0, Style::new(COLORS[1]));
+        }
+
+        let opacities: Vec<f32> = canvas
+            .elements
+            .iter()
+            .map(|element| canvas.opacity(element))
+            .collect();
+        assert_eq!(opacities, vec![1.0, 0.5, 0.75, 1.0]);
+    }
+
+    #[test]
+    fn test_erase() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.draw_line(10.0, 10.0, 90.0, 10.0, Style::new(COLORS[1]));
+        canvas.set_symmetry(2);
+        canvas.set_trail_fade(4);
+        canvas.draw_line(10.0, 20.0, 90.0, 10.0, Style::new(COLORS[2]));
+        canvas.draw_line(10.0, 30.0, 90.0, 10.0, Style::new(COLORS[3]));
+        assert_eq!(canvas.elements.len(), 5);
+
+        canvas.erase(1);
+        assert_eq!(canvas.elements.len(), 3);
+        assert_eq!(canvas.trail_len, 1);
+        assert_eq!(canvas.color_at(15.0, 30.0), None);
+        assert_eq!(canvas.color_at(15.0, 20.0), Some(COLORS[2]));
+
+        canvas.erase(5);
+        assert!(canvas.elements.is_empty());
+        assert_eq!(canvas.color_at(15.0, 10.0), None);
+
+        canvas.erase(1);
+        assert!(canvas.elements.is_empty());
+    }
+
+    #[test]
+    fn test_set_effect() {
+        let mut canvas = Canvas::new(100, 100);
+        let effect = Effect::Outline {
+            width: 2.0,
+            color: COLORS[0],
+        };
+
+        canvas.set_layer(1);
+        canvas.set_effect(Some(effect));
+        assert_eq!(canvas.effects.get(&1), Some(&effect));
+
+        canvas.set_effect(None);
+        assert!(canvas.effects.is_empty());
+    }
+
+    #[test]
+    fn test_color_index() {
+        assert_eq!(color_index("red"), Some(4));
+        assert_eq!(color_index("GRAY"), Some(15));
+        assert_eq!(color_index("grey"), Some(15));
+        assert_eq!(color_index("mauve"), None);
+    }
+
+    #[test]
+    fn test_quantize() {
+        assert_eq!(quantize(1.0), 1.0);
+        assert_eq!(quantize(0.001), 0.0);
+        assert_eq!(quantize(0.5), 0.5);
+    }
+}
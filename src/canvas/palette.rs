@@ -0,0 +1,197 @@
+//! The colors `SETPENCOLOR` and `PIXEL` number, replaced with `--palette`.
+//!
+//! The built-in palette is the 16 colors of `unsvg::COLORS`. A palette file is
+//! either a GIMP palette (`.gpl`), whose color lines are `red green blue name`,
+//! or a list of hex colors such as `#ff8800`, one per line and optionally
+//! followed by a name. Named colors can be used as in `SETPENCOLOR "sunset`.
+//!
+//! # Example
+//!
+//! ```rust
+//! let palette = Palette::parse("#000000 ink\n#ff8800 sunset\n").unwrap();
+//!
+//! assert_eq!(palette.len(), 2);
+//! assert_eq!(palette.index_of_name("Sunset"), Some(1));
+//! ```
+
+use unsvg::{Color, COLORS};
+
+use super::COLOR_NAMES;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: Vec<Color>,
+    names: Vec<Option<String>>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            colors: COLORS.to_vec(),
+            names: COLOR_NAMES
+                .iter()
+                .map(|name| Some(name.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl Palette {
+    /// A palette of the given colors, each with an optional name. It must
+    /// have at least one color.
+    pub fn new(
+        entries: impl IntoIterator<Item = (Color, Option<String>)>,
+    ) -> Result<Palette, String> {
+        let (colors, names): (Vec<_>, Vec<_>) = entries.into_iter().unzip();
+        if colors.is_empty() {
+            return Err("The palette has no colors".to_string());
+        }
+        Ok(Palette { colors, names })
+    }
+
+    /// Parses a GIMP palette or a list of hex colors.
+    pub fn parse(text: &str) -> Result<Palette, String> {
+        let mut lines = text.lines().enumerate().peekable();
+        let gpl = lines
+            .peek()
+            .is_some_and(|(_, line)| line.trim() == "GIMP Palette");
+        if gpl {
+            lines.next();
+        }
+
+        let mut entries = Vec::new();
+        for (number, line) in lines {
+            let line = line.trim();
+            // GIMP palettes have comments and `Name:` and `Columns:` headers
+            // among their colors.
+            let header = gpl && !line.starts_with(|c: char| c.is_ascii_digit());
+            if line.is_empty() || header {
+                continue;
+            }
+            let entry = if gpl {
+                parse_gpl_line(line)
+            } else {
+                parse_hex_line(line)
+            };
+            match entry {
+                Some(entry) => entries.push(entry),
+                None => return Err(format!("Invalid color on line {}: {}", number + 1, line)),
+            }
+        }
+        Palette::new(entries)
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Color> {
+        self.colors.get(index).copied()
+    }
+
+    /// The index of the first entry with this color.
+    pub fn index_of(&self, color: Color) -> Option<usize> {
+        self.colors.iter().position(|entry| *entry == color)
+    }
+
+    /// The index of the entry with this name, compared case insensitively
+    /// and with `gray` and `grey` treated alike.
+    pub fn index_of_name(&self, name: &str) -> Option<usize> {
+        let normalize = |name: &str| name.to_lowercase().replace("gray", "grey");
+        let name = normalize(name);
+        self.names
+            .iter()
+            .position(|entry| entry.as_deref().map(normalize).as_ref() == Some(&name))
+    }
+
+    /// Each color with its name, in palette order.
+    pub fn entries(&self) -> impl Iterator<Item = (Color, Option<&str>)> {
+        self.colors
+            .iter()
+            .copied()
+            .zip(self.names.iter().map(Option::as_deref))
+    }
+}
+
+/// Parses a GIMP palette line such as `255 136 0 Sunset`.
+fn parse_gpl_line(line: &str) -> Option<(Color, Option<String>)> {
+    let mut words = line.split_whitespace();
+    let mut channel = || words.next()?.parse::<u8>().ok();
+    let color = Color {
+        red: channel()?,
+        green: channel()?,
+        blue: channel()?,
+    };
+    let name = words.collect::<Vec<_>>().join(" ");
+    Some((color, (!name.is_empty()).then_some(name)))
+}
+
+/// Parses a hex color line such as `#ff8800 sunset`.
+fn parse_hex_line(line: &str) -> Option<(Color, Option<String>)> {
+    let (hex, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let color = Color {
+        red: channel(0)?,
+        green: channel(2)?,
+        blue: channel(4)?,
+    };
+    let name = name.trim();
+    Some((color, (!name.is_empty()).then(|| name.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUNSET: Color = Color {
+        red: 255,
+        green: 136,
+        blue: 0,
+    };
+
+    #[test]
+    fn test_default_palette() {
+        let palette = Palette::default();
+        assert_eq!(palette.len(), 16);
+        assert_eq!(palette.get(4), Some(COLORS[4]));
+        assert_eq!(palette.index_of(COLORS[7]), Some(7));
+        assert_eq!(palette.index_of_name("GRAY"), Some(15));
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        let palette = Palette::parse("#000000 ink\n\nFF8800 Sunset Glow\n").unwrap();
+        assert_eq!(palette.len(), 2);
+        assert_eq!(palette.get(1), Some(SUNSET));
+        assert_eq!(palette.index_of_name("sunset glow"), Some(1));
+        assert_eq!(palette.index_of_name("red"), None);
+    }
+
+    #[test]
+    fn test_parse_gpl() {
+        let text =
+            "GIMP Palette\nName: Dusk\nColumns: 4\n# warm\n  0   0   0\n255 136   0 Sunset\n";
+        let palette = Palette::parse(text).unwrap();
+        assert_eq!(
+            palette.entries().collect::<Vec<_>>(),
+            vec![(COLORS[0], None), (SUNSET, Some("Sunset"))]
+        );
+    }
+
+    #[test]
+    fn test_parse_err() {
+        assert_eq!(
+            Palette::parse("#000000\n#ff88zz\n"),
+            Err("Invalid color on line 2: #ff88zz".to_string())
+        );
+        assert_eq!(
+            Palette::parse("GIMP Palette\n256 0 0\n"),
+            Err("Invalid color on line 2: 256 0 0".to_string())
+        );
+        assert!(Palette::parse("\n").is_err());
+    }
+}
@@ -0,0 +1,379 @@
+//! Rasterises the canvas for PNG output.
+//!
+//! The canvas is rendered to SVG and then drawn with `resvg`, so PNG output
+//! always matches the SVG output exactly. Labels are drawn with the system's
+//! fonts, which are only loaded if there are any labels.
+//!
+//! `--antialias` picks how edges are smoothed. By default `resvg` works out
+//! how much of each pixel a shape covers. `supersample` instead renders at
+//! `SUPERSAMPLE` times the size and averages each block of pixels down, which
+//! is slower but smooths thin diagonal lines further, while `none` leaves
+//! hard, jagged edges with every pixel either drawn or not.
+
+use resvg::{
+    tiny_skia::{Pixmap, PremultipliedColorU8, Transform},
+    usvg::{self, fontdb, ShapeRendering, TreeParsing, TreeTextToPath},
+};
+
+use super::{Canvas, Shape};
+
+/// How many times wider and taller `Antialias::Supersample` renders.
+const SUPERSAMPLE: u32 = 4;
+
+/// The most pixels a PNG is rendered with, supersampling included, which
+/// takes a gigabyte. Anything larger, such as from a big `--scale-output`, is
+/// refused rather than running out of memory.
+const MAX_PIXELS: u64 = 1 << 28;
+
+/// How the edges of shapes are smoothed in PNG output, set with
+/// `--antialias`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Antialias {
+    /// Each edge pixel is shaded by how much of it the shape covers.
+    #[default]
+    Coverage,
+    /// Rendered at a larger size and scaled down.
+    Supersample,
+    /// Hard edges, with no smoothing.
+    None,
+}
+
+impl Canvas {
+    /// Renders the canvas into a pixmap.
+    pub fn rasterise(&self) -> std::io::Result<Pixmap> {
+        let (width, height) = self.output_size();
+        let scale = match self.antialias {
+            Antialias::Supersample => SUPERSAMPLE,
+            Antialias::Coverage | Antialias::None => 1,
+        };
+        if (width as u64 * height as u64).saturating_mul((scale * scale) as u64) > MAX_PIXELS {
+            return Err(std::io::Error::other(format!(
+                "Image of {}x{} pixels is too large to save as a PNG",
+                width, height
+            )));
+        }
+
+        let options = usvg::Options {
+            shape_rendering: match self.antialias {
+                Antialias::None => ShapeRendering::CrispEdges,
+                Antialias::Coverage | Antialias::Supersample => ShapeRendering::GeometricPrecision,
+            },
+            ..usvg::Options::default()
+        };
+        let mut tree =
+            usvg::Tree::from_str(&self.to_svg(), &options).map_err(std::io::Error::other)?;
+        if self
+            .elements
+            .iter()
+            .any(|element| matches!(element.shape, Shape::Text { .. }))
+        {
+            tree.convert_text(&system_fonts());
+        }
+        let tree = resvg::Tree::from_usvg(&tree);
+
+        let mut pixmap = Pixmap::new(width * scale, height * scale).ok_or(
+            std::io::Error::other("Canvas must have a non-zero width and height"),
+        )?;
+        let transform = Transform::from_scale(scale as f32, scale as f32);
+        tree.render(transform, &mut pixmap.as_mut());
+
+        if scale > 1 {
+            pixmap = downsample(&pixmap, scale);
+        }
+        Ok(pixmap)
+    }
+}
+
+/// Shrinks a pixmap by `scale` in each direction, averaging each `scale` by
+/// `scale` block into one pixel.
+fn downsample(pixmap: &Pixmap, scale: u32) -> Pixmap {
+    let (width, height) = (pixmap.width() / scale, pixmap.height() / scale);
+    let mut small = Pixmap::new(width, height).expect("the pixmap is at least scale wide");
+    let pixels = pixmap.pixels();
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0u32; 4];
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    let pixel =
+                        pixels[((y * scale + dy) * pixmap.width() + x * scale + dx) as usize];
+                    sum[0] += pixel.red() as u32;
+                    sum[1] += pixel.green() as u32;
+                    sum[2] += pixel.blue() as u32;
+                    sum[3] += pixel.alpha() as u32;
+                }
+            }
+            // Averaging premultiplied channels keeps each color channel no
+            // more than the alpha.
+            let count = scale * scale;
+            let [red, green, blue, alpha] =
+                sum.map(|channel| ((channel + count / 2) / count) as u8);
+            small.pixels_mut()[(y * width + x) as usize] =
+                PremultipliedColorU8::from_rgba(red, green, blue, alpha)
+                    .expect("averaged channels stay within the alpha");
+        }
+    }
+    small
+}
+
+/// Loads the system's fonts. `fontdb` takes Arial, Times New Roman and
+/// Courier New to be the sans-serif, serif and monospace fonts, so if any of
+/// those is missing the first font found is used instead.
+fn system_fonts() -> fontdb::Database {
+    let mut fonts = fontdb::Database::new();
+    fonts.load_system_fonts();
+    let fallback = fonts
+        .faces()
+        .find_map(|face| face.families.first())
+        .map(|(family, _)| family.clone());
+    let Some(fallback) = fallback else {
+        return fonts;
+    };
+    let missing = |fonts: &fontdb::Database, family| {
+        let query = fontdb::Query {
+            families: &[family],
+            ..Default::default()
+        };
+        fonts.query(&query).is_none()
+    };
+    if missing(&fonts, fontdb::Family::SansSerif) {
+        fonts.set_sans_serif_family(fallback.clone());
+    }
+    if missing(&fonts, fontdb::Family::Serif) {
+        fonts.set_serif_family(fallback.clone());
+    }
+    if missing(&fonts, fontdb::Family::Monospace) {
+        fonts.set_monospace_family(fallback);
+    }
+    fonts
+}
+
+#[cfg(test)]
+mod tests {
+    use unsvg::COLORS;
+
+    use super::*;
+    use crate::canvas::{viewport::Viewport, FillPattern, PenPattern, Shape, Style, TextAlign};
+
+    #[test]
+    fn test_rasterise() {
+        let mut canvas = Canvas::new(10, 10);
+        canvas.draw_line(5.5, 10.0, 0.0, 10.0, Style::new(COLORS[7]));
+
+        let pixmap = canvas.rasterise().unwrap();
+        let background = pixmap.pixel(0, 0).unwrap();
+        let line = pixmap.pixel(5, 5).unwrap();
+
+        assert_eq!((background.red(), background.alpha()), (0, 255));
+        assert_eq!((line.red(), line.green(), line.blue()), (255, 255, 255));
+    }
+
+    #[test]
+    fn test_rasterise_wide_line() {
+        let mut canvas = Canvas::new(10, 10);
+        let style = Style {
+            width: 5.0,
+            ..Style::new(COLORS[7])
+        };
+        canvas.draw_line(5.0, 10.0, 0.0, 10.0, style);
+
+        let pixmap = canvas.rasterise().unwrap();
+        assert_eq!(pixmap.pixel(3, 5).unwrap().red(), 255);
+        assert_eq!(pixmap.pixel(0, 5).unwrap().red(), 0);
+    }
+
+    #[test]
+    fn test_rasterise_alpha() {
+        let mut canvas = Canvas::new(10, 10);
+        let style = Style {
+            opacity: 0.5,
+            ..Style::new(COLORS[7])
+        };
+        canvas.draw_line(5.5, 10.0, 0.0, 10.0, style);
+        canvas.draw_line(0.0, 5.5, 90.0, 10.0, style);
+
+        let pixmap = canvas.rasterise().unwrap();
+        let once = pixmap.pixel(5, 2).unwrap().red();
+        let twice = pixmap.pixel(5, 5).unwrap().red();
+
+        // Where the strokes overlap they blend to something brighter.
+        assert!((127..=128).contains(&once));
+        assert!((191..=192).contains(&twice));
+    }
+
+    #[test]
+    fn test_rasterise_dashed() {
+        let mut canvas = Canvas::new(20, 10);
+        let style = Style {
+            pattern: PenPattern::Dashed,
+            ..Style::new(COLORS[7])
+        };
+        canvas.draw_line(0.0, 5.5, 90.0, 20.0, style);
+
+        let pixmap = canvas.rasterise().unwrap();
+        // Dashes 6 pixels long with 4 pixel gaps.
+        assert_eq!(pixmap.pixel(3, 5).unwrap().red(), 255);
+        assert_eq!(pixmap.pixel(8, 5).unwrap().red(), 0);
+        assert_eq!(pixmap.pixel(13, 5).unwrap().red(), 255);
+    }
+
+    #[test]
+    fn test_rasterise_reverse() {
+        let mut canvas = Canvas::new(10, 10);
+        let reverse = Style {
+            reverse: true,
+            ..Style::new(COLORS[4])
+        };
+        canvas.draw_line(0.0, 5.5, 90.0, 10.0, Style::new(COLORS[1]));
+        canvas.draw_line(5.5, 10.0, 0.0, 10.0, reverse);
+        canvas.draw_line(2.5, 10.0, 0.0, 10.0, reverse);
+        canvas.draw_line(2.5, 10.0, 0.0, 10.0, reverse);
+
+        let pixmap = canvas.rasterise().unwrap();
+        let rgb = |x, y| {
+            let pixel = pixmap.pixel(x, y).unwrap();
+            (pixel.red(), pixel.green(), pixel.blue())
+        };
+
+        assert_eq!(rgb(5, 2), (255, 0, 0));
+        assert_eq!(rgb(5, 5), (255, 0, 255));
+        // The line drawn twice is gone, leaving the line it crossed.
+        assert_eq!(rgb(2, 2), (0, 0, 0));
+        assert_eq!(rgb(2, 5), (0, 0, 255));
+    }
+
+    #[test]
+    fn test_rasterise_viewport() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.draw_line(50.5, 60.0, 0.0, 20.0, Style::new(COLORS[7]));
+        canvas.viewport = Some(Viewport {
+            x: 40.0,
+            y: 40.0,
+            width: 20.0,
+            height: 10.0,
+        });
+
+        let pixmap = canvas.rasterise().unwrap();
+        let line = pixmap.pixel(10, 5).unwrap();
+
+        assert_eq!((pixmap.width(), pixmap.height()), (20, 10));
+        assert_eq!((line.red(), line.green(), line.blue()), (255, 255, 255));
+        assert_eq!(pixmap.pixel(2, 5).unwrap().red(), 0);
+    }
+
+    #[test]
+    fn test_rasterise_too_large() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.export.scale = 100000.0;
+
+        assert!(canvas.rasterise().is_err());
+    }
+
+    #[test]
+    fn test_rasterise_solid_fill() {
+        let mut canvas = Canvas::new(10, 10);
+        let style = Style {
+            color: COLORS[4],
+            fill: FillPattern::Solid,
+            opacity: 1.0,
+            width: 1.0,
+            pattern: PenPattern::Solid,
+            reverse: false,
+        };
+        let points = vec![(2.0, 2.0), (8.0, 2.0), (8.0, 8.0), (2.0, 8.0)];
+        canvas.draw(Shape::Polygon { points }, style);
+
+        let pixmap = canvas.rasterise().unwrap();
+        let inside = pixmap.pixel(5, 5).unwrap();
+
+        assert_eq!((inside.red(), inside.green(), inside.blue()), (255, 0, 0));
+    }
+
+    /// The red channel of a white line from `(0, 1)` to `(6, 4)` on a 6 by 6
+    /// canvas, rendered with `antialias`.
+    fn render_diagonal(antialias: Antialias) -> Vec<[u8; 6]> {
+        let mut canvas = Canvas::new(6, 6);
+        canvas.antialias = antialias;
+        let line = Shape::Line {
+            from: (0.0, 1.0),
+            to: (6.0, 4.0),
+        };
+        canvas.draw(line, Style::new(COLORS[7]));
+
+        let pixmap = canvas.rasterise().unwrap();
+        (0..6)
+            .map(|y| std::array::from_fn(|x| pixmap.pixel(x as u32, y).unwrap().red()))
+            .collect()
+    }
+
+    #[test]
+    fn test_antialias_golden() {
+        assert_eq!(
+            render_diagonal(Antialias::Coverage),
+            [
+                [63, 0, 0, 0, 0, 0],
+                [192, 191, 63, 0, 0, 0],
+                [0, 64, 192, 191, 63, 0],
+                [0, 0, 0, 64, 192, 191],
+                [0, 0, 0, 0, 0, 64],
+                [0, 0, 0, 0, 0, 0],
+            ]
+        );
+        assert_eq!(
+            render_diagonal(Antialias::Supersample),
+            [
+                [64, 1, 0, 0, 0, 0],
+                [206, 206, 80, 1, 0, 0],
+                [1, 80, 206, 206, 80, 1],
+                [0, 0, 1, 80, 206, 206],
+                [0, 0, 0, 0, 1, 64],
+                [0, 0, 0, 0, 0, 0],
+            ]
+        );
+        assert_eq!(
+            render_diagonal(Antialias::None),
+            [
+                [0, 0, 0, 0, 0, 0],
+                [255, 255, 0, 0, 0, 0],
+                [0, 0, 255, 255, 0, 0],
+                [0, 0, 0, 0, 255, 255],
+                [0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_downsample() {
+        let mut pixmap = Pixmap::new(4, 2).unwrap();
+        let white = PremultipliedColorU8::from_rgba(255, 255, 255, 255).unwrap();
+        pixmap.pixels_mut()[0] = white;
+        pixmap.pixels_mut()[1] = white;
+        pixmap.pixels_mut()[7] = white;
+
+        let small = downsample(&pixmap, 2);
+        assert_eq!((small.width(), small.height()), (2, 1));
+        assert_eq!(small.pixel(0, 0).unwrap().alpha(), 128);
+        assert_eq!(small.pixel(1, 0).unwrap().alpha(), 64);
+    }
+
+    #[test]
+    fn test_rasterise_text() {
+        let mut canvas = Canvas::new(40, 20);
+        let text = Shape::Text {
+            at: (2.0, 16.0),
+            text: "HHHH".to_string(),
+            size: 16.0,
+            rotation: 0.0,
+            font: "monospace".to_string(),
+            align: TextAlign::Left,
+        };
+        canvas.draw(text, Style::new(COLORS[7]));
+
+        let pixmap = canvas.rasterise().unwrap();
+        let lit = pixmap.pixels().iter().any(|pixel| pixel.red() > 0);
+
+        // Nothing can be drawn on a system without any fonts.
+        assert!(lit || system_fonts().is_empty());
+    }
+}
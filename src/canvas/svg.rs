@@ -0,0 +1,1047 @@
+//! Writes the canvas out as an SVG document.
+//!
+//! Lines are written in exactly the same form as `unsvg` wrote them, so
+//! images produced before the canvas existed are unchanged.
+
+use std::fmt::Write;
+
+use unsvg::{Color, COLORS};
+
+use super::{
+    units::Length, viewport::Viewport, Canvas, Clip, Effect, Element, FillPattern, Shape, Style,
+    TextAlign, COLOR_NAMES,
+};
+
+/// The style that draws an element in reverse, as `PENREVERSE` does.
+const BLEND: &str = r#" style="mix-blend-mode:difference""#;
+
+/// Formats a number the same way `usvg` does: integers are written without a
+/// fractional part and everything else is rounded to 8 decimal places.
+pub fn fmt_num(num: f32) -> String {
+    if num.fract() == 0.0 {
+        return format!("{}", num as i32);
+    }
+
+    let v = (num * 100_000_000.0).round() / 100_000_000.0;
+    format!("{}", v)
+}
+
+/// Formats a coordinate rounded to `precision` decimal places, dropping
+/// trailing zeros, or exactly as `fmt_num` does when `precision` is `None`.
+pub fn fmt_coord(num: f32, precision: Option<usize>) -> String {
+    let Some(digits) = precision else {
+        return fmt_num(num);
+    };
+
+    let num = format!("{:.*}", digits, num);
+    let num = if num.contains('.') {
+        num.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &num
+    };
+    match num {
+        "-0" => "0".to_string(),
+        num => num.to_string(),
+    }
+}
+
+/// Formats a color as a `#rrggbb` hex string.
+pub fn fmt_color(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
+
+/// How the SVG document is laid out, set with `--svg-style`. Without either,
+/// elements are written one per line with no comments.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum SvgStyle {
+    /// Indented, with a comment before the background, each layer and each
+    /// effect.
+    Pretty,
+    /// A single line with no whitespace between tags.
+    Compact,
+}
+
+/// How `--split-layers` divides the drawing into separate SVGs.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum SplitBy {
+    /// One SVG per layer.
+    Layer,
+    /// One SVG per pen color, for plotting each color with its own pen.
+    Color,
+}
+
+impl Canvas {
+    /// Renders each layer or pen color into an SVG document of its own,
+    /// named such as `layer-2` or `color-red`.
+    pub fn split_svgs(&self, by: SplitBy) -> Vec<(String, String)> {
+        let key = |element: &Element| match by {
+            SplitBy::Layer => format!("layer-{}", element.layer),
+            SplitBy::Color => {
+                let color = fmt_color(element.style.color);
+                let name = COLORS
+                    .iter()
+                    .zip(COLOR_NAMES)
+                    .find(|(palette, _)| fmt_color(**palette) == color)
+                    .map(|(_, name)| name.to_string());
+                format!("color-{}", name.unwrap_or(color[1..].to_string()))
+            }
+        };
+
+        // Layers are in the order they're drawn in, and colors in the order
+        // they were first used.
+        let keys: Vec<String> = match by {
+            SplitBy::Layer => self
+                .layers()
+                .iter()
+                .map(|layer| format!("layer-{layer}"))
+                .collect(),
+            SplitBy::Color => self
+                .elements
+                .iter()
+                .map(key)
+                .fold(Vec::new(), |mut keys, key| {
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                    keys
+                }),
+        };
+
+        keys.into_iter()
+            .map(|name| {
+                let mut canvas = self.clone();
+                canvas.elements.retain(|element| key(element) == name);
+                // The split SVGs are for plotting, which the grid would get
+                // in the way of.
+                canvas.grid = None;
+                let svg = canvas.to_svg();
+                (name, svg)
+            })
+            .collect()
+    }
+
+    /// Renders the canvas into an SVG document.
+    pub fn to_svg(&self) -> String {
+        let svg = self.svg_document();
+        match self.svg_style {
+            Some(SvgStyle::Compact) => svg.lines().map(str::trim).collect(),
+            _ => svg,
+        }
+    }
+
+    fn svg_document(&self) -> String {
+        let pretty = self.svg_style == Some(SvgStyle::Pretty);
+        let viewport = self.viewport.unwrap_or(Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: self.width as f32,
+            height: self.height as f32,
+        });
+        let (x, y) = (viewport.x, viewport.y);
+        let (width, height) = (viewport.width, viewport.height);
+        let (right, bottom) = (x + width, y + height);
+        let mut svg = String::new();
+
+        let export = self.export;
+        let (svg_width, svg_height) = match self.physical_size {
+            Some((w, h)) => {
+                let (w, h) = if export.swaps_sides() { (h, w) } else { (w, h) };
+                let scale = |length: Length| Length {
+                    value: length.value * export.scale,
+                    ..length
+                };
+                (scale(w).to_string(), scale(h).to_string())
+            }
+            None => {
+                let (w, h) = export.size((width, height));
+                (w.to_string(), h.to_string())
+            }
+        };
+        let view_box = if export.is_identity() {
+            format!("{x} {y} {width} {height}")
+        } else {
+            let (w, h) = export.size((width, height));
+            format!("0 0 {w} {h}")
+        };
+        writeln!(
+            svg,
+            r#"<svg width="{svg_width}" height="{svg_height}" viewBox="{view_box}" xmlns="http://www.w3.org/2000/svg">"#
+        )
+        .unwrap();
+        write!(svg, "{}", self.defs_to_svg()).unwrap();
+        let body_start = svg.len();
+        if pretty {
+            writeln!(svg, "    <!-- background -->").unwrap();
+        }
+        writeln!(
+            svg,
+            r##"    <path fill="#000000" stroke="none" d="M {x} {y} L {right} {y} L {right} {bottom} L {x} {bottom} Z"/>"##
+        )
+        .unwrap();
+
+        let grid = self.grid_lines();
+        if pretty && !grid.is_empty() {
+            writeln!(svg, "    <!-- grid -->").unwrap();
+        }
+        for line in grid {
+            writeln!(
+                svg,
+                r#"    <path fill="none" stroke="{}" d="M {} {} L {} {}"/>"#,
+                line.color(),
+                fmt_num(line.from.0),
+                fmt_num(line.from.1),
+                fmt_num(line.to.0),
+                fmt_num(line.to.1),
+            )
+            .unwrap();
+        }
+
+        for layer in self.layers() {
+            let elements: Vec<&Element> =
+                self.elements.iter().filter(|e| e.layer == layer).collect();
+
+            let elements: Vec<Element> = elements
+                .into_iter()
+                .filter_map(|element| {
+                    let opacity = self.opacity(element);
+                    let mut element = element.clone();
+                    element.style.opacity = opacity;
+                    (opacity > 0.0).then_some(element)
+                })
+                .collect();
+
+            if pretty {
+                writeln!(svg, "    <!-- layer {layer} -->").unwrap();
+            }
+            if let Some(effect) = self.effects.get(&layer) {
+                if pretty {
+                    writeln!(svg, "    <!-- {} effect -->", effect_name(effect)).unwrap();
+                }
+                write!(
+                    svg,
+                    "{}",
+                    effect_to_svg(effect, &elements, self.svg_precision)
+                )
+                .unwrap();
+            }
+            for element in &elements {
+                writeln!(svg, "    {}", element_to_svg(element, self.svg_precision)).unwrap();
+            }
+        }
+
+        // Everything but the defs is turned, mirrored and scaled as one.
+        if !export.is_identity() {
+            let body = svg.split_off(body_start);
+            let matrix = export.matrix(&viewport).map(fmt_num).join(" ");
+            writeln!(svg, r#"    <g transform="matrix({matrix})">"#).unwrap();
+            for line in body.lines() {
+                writeln!(svg, "    {line}").unwrap();
+            }
+            writeln!(svg, "    </g>").unwrap();
+        }
+
+        writeln!(svg, "</svg>").unwrap();
+        svg
+    }
+
+    /// Renders the `<defs>` section, containing a `<pattern>` for every
+    /// patterned fill and a `<clipPath>` for every clipping rectangle used on
+    /// the canvas.
+    fn defs_to_svg(&self) -> String {
+        let mut patterns: Vec<(FillPattern, Color)> = Vec::new();
+        let mut clips: Vec<Clip> = Vec::new();
+        for element in &self.elements {
+            if let Some(clip) = element.clip {
+                if !clips.contains(&clip) {
+                    clips.push(clip);
+                }
+            }
+
+            let mut colors = vec![element.style.color];
+            if let Some(effect) = self.effects.get(&element.layer) {
+                colors.push(effect_color(effect));
+            }
+
+            for color in colors {
+                let key = (element.style.fill, color);
+                if !matches!(key.0, FillPattern::None | FillPattern::Solid)
+                    && !patterns.contains(&key)
+                {
+                    patterns.push(key);
+                }
+            }
+        }
+
+        if patterns.is_empty() && clips.is_empty() {
+            return "    <defs/>\n".to_string();
+        }
+
+        let mut defs = String::from("    <defs>\n");
+        for (pattern, color) in patterns {
+            defs.push_str(&pattern_to_svg(pattern, color));
+        }
+        for clip in &clips {
+            defs.push_str(&clip_to_svg(clip));
+        }
+        defs.push_str("    </defs>\n");
+        defs
+    }
+}
+
+fn effect_name(effect: &Effect) -> &'static str {
+    match effect {
+        Effect::Shadow { .. } => "shadow",
+        Effect::Outline { .. } => "outline",
+    }
+}
+
+fn effect_color(effect: &Effect) -> Color {
+    match effect {
+        Effect::Shadow { color, .. } | Effect::Outline { color, .. } => *color,
+    }
+}
+
+/// Renders the recolored copy of a layer's elements that an effect draws
+/// beneath the layer.
+fn effect_to_svg(effect: &Effect, elements: &[Element], precision: Option<usize>) -> String {
+    let attrs = match effect {
+        Effect::Shadow { dx, dy, .. } => {
+            format!(
+                r#"transform="translate({} {})""#,
+                fmt_coord(*dx, precision),
+                fmt_coord(*dy, precision)
+            )
+        }
+        Effect::Outline { width, .. } => format!(
+            r#"stroke-width="{}" stroke-linejoin="round" stroke-linecap="round""#,
+            fmt_num(1.0 + 2.0 * width)
+        ),
+    };
+
+    let mut group = format!("    <g {attrs}>\n");
+    for element in elements {
+        let mut copy = element.clone();
+        copy.style.color = effect_color(effect);
+        // Strokes of the default width take the group's wider stroke, while
+        // wider ones set their own and are widened here instead.
+        if let Effect::Outline { width, .. } = effect {
+            if copy.style.width != 1.0 {
+                copy.style.width += 2.0 * width;
+            }
+        }
+        writeln!(group, "        {}", element_to_svg(&copy, precision)).unwrap();
+    }
+    group.push_str("    </g>\n");
+    group
+}
+
+/// The id of the `<clipPath>` for a clipping rectangle.
+fn clip_id(clip: &Clip) -> String {
+    format!(
+        "clip-{}-{}-{}-{}",
+        fmt_num(clip.x),
+        fmt_num(clip.y),
+        fmt_num(clip.width),
+        fmt_num(clip.height)
+    )
+}
+
+/// Renders the `<clipPath>` definition for a clipping rectangle.
+fn clip_to_svg(clip: &Clip) -> String {
+    format!(
+        "        <clipPath id=\"{}\">\n            <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>\n        </clipPath>\n",
+        clip_id(clip),
+        fmt_num(clip.x),
+        fmt_num(clip.y),
+        fmt_num(clip.width),
+        fmt_num(clip.height)
+    )
+}
+
+/// The id of the `<pattern>` used to fill with a given pattern and color.
+fn pattern_id(pattern: FillPattern, color: Color) -> String {
+    format!("{}-{}", pattern.name(), &fmt_color(color)[1..])
+}
+
+/// Renders the `<pattern>` definition for a patterned fill. Hatches are drawn
+/// on an 8px tile rotated by 45 degrees.
+fn pattern_to_svg(pattern: FillPattern, color: Color) -> String {
+    let id = pattern_id(pattern, color);
+    let color = fmt_color(color);
+
+    let (transform, content) = match pattern {
+        FillPattern::Hatch => (
+            r#" patternTransform="rotate(45)""#,
+            format!(r#"<path fill="none" stroke="{color}" d="M 0 0 L 0 8"/>"#),
+        ),
+        FillPattern::Crosshatch => (
+            r#" patternTransform="rotate(45)""#,
+            format!(r#"<path fill="none" stroke="{color}" d="M 0 0 L 0 8 M 0 0 L 8 0"/>"#),
+        ),
+        FillPattern::Dots => (
+            "",
+            format!(r#"<circle fill="{color}" stroke="none" cx="4" cy="4" r="1.5"/>"#),
+        ),
+        FillPattern::None | FillPattern::Solid => unreachable!(),
+    };
+
+    format!(
+        "        <pattern id=\"{id}\" width=\"8\" height=\"8\" patternUnits=\"userSpaceOnUse\"{transform}>\n            {content}\n        </pattern>\n"
+    )
+}
+
+/// The value of the `fill` attribute for an element drawn with `style`.
+fn fill_attr(style: &Style) -> String {
+    match style.fill {
+        FillPattern::None => "none".to_string(),
+        FillPattern::Solid => fmt_color(style.color),
+        pattern => format!("url(#{})", pattern_id(pattern, style.color)),
+    }
+}
+
+/// Renders a single element into an SVG tag, with its coordinates rounded to
+/// `precision` decimal places if given.
+fn element_to_svg(element: &Element, precision: Option<usize>) -> String {
+    let num = |num: f32| fmt_coord(num, precision);
+    let stroke = fmt_color(element.style.color);
+    let fill = fill_attr(&element.style);
+    let mut attrs = String::new();
+    if element.style.opacity < 1.0 {
+        write!(attrs, r#" opacity="{}""#, fmt_num(element.style.opacity)).unwrap();
+    }
+    if element.style.width != 1.0 && !matches!(element.shape, Shape::Text { .. }) {
+        write!(attrs, r#" stroke-width="{}""#, fmt_num(element.style.width)).unwrap();
+    }
+    if let Some((dash, gap)) = element.style.pattern.dashes(element.style.width) {
+        if !matches!(element.shape, Shape::Text { .. }) {
+            write!(
+                attrs,
+                r#" stroke-dasharray="{} {}""#,
+                fmt_num(dash),
+                fmt_num(gap)
+            )
+            .unwrap();
+        }
+    }
+    if element.style.reverse && element.clip.is_none() {
+        attrs.push_str(BLEND);
+    }
+
+    let tag = match &element.shape {
+        Shape::Line { from, to } => format!(
+            r#"<path fill="none" stroke="{stroke}"{attrs} d="M {} {} L {} {}"/>"#,
+            num(from.0),
+            num(from.1),
+            num(to.0),
+            num(to.1),
+        ),
+        Shape::Rect {
+            center,
+            width,
+            height,
+            rotation,
+        } => {
+            let mut rect = format!(
+                r#"<rect fill="{fill}" stroke="{stroke}"{attrs} x="{}" y="{}" width="{}" height="{}""#,
+                num(center.0 - width / 2.0),
+                num(center.1 - height / 2.0),
+                num(*width),
+                num(*height),
+            );
+            if *rotation != 0.0 {
+                write!(
+                    rect,
+                    r#" transform="rotate({} {} {})""#,
+                    num(*rotation),
+                    num(center.0),
+                    num(center.1),
+                )
+                .unwrap();
+            }
+            rect.push_str("/>");
+            rect
+        }
+        Shape::Polygon { points } => {
+            let points = points
+                .iter()
+                .map(|(x, y)| format!("{},{}", num(*x), num(*y)))
+                .collect::<Vec<String>>()
+                .join(" ");
+            format!(r#"<polygon fill="{fill}" stroke="{stroke}"{attrs} points="{points}"/>"#)
+        }
+        Shape::Circle { center, radius } => format!(
+            r#"<circle fill="{fill}" stroke="{stroke}"{attrs} cx="{}" cy="{}" r="{}"/>"#,
+            num(center.0),
+            num(center.1),
+            num(*radius),
+        ),
+        Shape::Ellipse {
+            center,
+            rx,
+            ry,
+            rotation,
+        } => {
+            let mut ellipse = format!(
+                r#"<ellipse fill="{fill}" stroke="{stroke}"{attrs} cx="{}" cy="{}" rx="{}" ry="{}""#,
+                num(center.0),
+                num(center.1),
+                num(*rx),
+                num(*ry),
+            );
+            if *rotation != 0.0 {
+                write!(
+                    ellipse,
+                    r#" transform="rotate({} {} {})""#,
+                    num(*rotation),
+                    num(center.0),
+                    num(center.1),
+                )
+                .unwrap();
+            }
+            ellipse.push_str("/>");
+            ellipse
+        }
+        Shape::Text {
+            at,
+            text,
+            size,
+            rotation,
+            font,
+            align,
+        } => {
+            let mut tag = format!(
+                r#"<text fill="{stroke}" stroke="none"{attrs} x="{}" y="{}" font-family="{}" font-size="{}""#,
+                num(at.0),
+                num(at.1),
+                escape_text(font).replace('"', "&quot;"),
+                fmt_num(*size),
+            );
+            if *align != TextAlign::Left {
+                write!(tag, r#" text-anchor="{}""#, align.anchor()).unwrap();
+            }
+            if *rotation != 0.0 {
+                write!(
+                    tag,
+                    r#" transform="rotate({} {} {})""#,
+                    num(*rotation),
+                    num(at.0),
+                    num(at.1),
+                )
+                .unwrap();
+            }
+            write!(tag, ">{}</text>", escape_text(text)).unwrap();
+            tag
+        }
+    };
+
+    match element.clip {
+        // The clip goes on a group around the element so that it isn't
+        // rotated along with it, and the group blends in the element's place.
+        Some(clip) => {
+            let blend = if element.style.reverse { BLEND } else { "" };
+            format!(
+                r#"<g clip-path="url(#{})"{blend}>{tag}</g>"#,
+                clip_id(&clip)
+            )
+        }
+        None => tag,
+    }
+}
+
+/// Escapes the characters that can't appear as they are in SVG text.
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use unsvg::COLORS;
+
+    use crate::canvas::{
+        export::{Export, Rotation},
+        units::parse_page_size,
+        PenPattern,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_fmt_num() {
+        assert_eq!(fmt_num(250.0), "250");
+        assert_eq!(fmt_num(-3.0), "-3");
+        assert_eq!(fmt_num(251.45314), "251.45314");
+    }
+
+    #[test]
+    fn test_fmt_coord() {
+        assert_eq!(fmt_coord(251.45314, None), "251.45314");
+        assert_eq!(fmt_coord(251.45314, Some(2)), "251.45");
+        assert_eq!(fmt_coord(250.0, Some(2)), "250");
+        assert_eq!(fmt_coord(12.5, Some(0)), "12");
+        assert_eq!(fmt_coord(-0.001, Some(1)), "0");
+    }
+
+    #[test]
+    fn test_fmt_color() {
+        assert_eq!(fmt_color(COLORS[0]), "#000000");
+        assert_eq!(fmt_color(COLORS[12]), "#fa8072");
+    }
+
+    #[test]
+    fn test_to_svg() {
+        let mut canvas = Canvas::new(500, 500);
+        canvas.draw_line(250.0, 250.0, 0.0, 50.0, Style::new(COLORS[7]));
+
+        assert_eq!(
+            canvas.to_svg(),
+            r##"<svg width="500" height="500" viewBox="0 0 500 500" xmlns="http://www.w3.org/2000/svg">
+    <defs/>
+    <path fill="#000000" stroke="none" d="M 0 0 L 500 0 L 500 500 L 0 500 Z"/>
+    <path fill="none" stroke="#ffffff" d="M 250 250 L 250 200"/>
+</svg>
+"##
+        );
+    }
+
+    #[test]
+    fn test_physical_size_to_svg() {
+        let mut canvas = Canvas::new(794, 1123);
+        canvas.physical_size = Some(parse_page_size("210mmx297mm").unwrap());
+
+        assert!(canvas.to_svg().starts_with(
+            r#"<svg width="210mm" height="297mm" viewBox="0 0 794 1123" xmlns="http://www.w3.org/2000/svg">"#
+        ));
+    }
+
+    #[test]
+    fn test_svg_style() {
+        let mut canvas = Canvas::new(10, 10);
+        canvas.draw_line(5.0, 5.0, 0.0, 2.0, Style::new(COLORS[7]));
+
+        canvas.svg_style = Some(SvgStyle::Compact);
+        assert_eq!(
+            canvas.to_svg(),
+            r##"<svg width="10" height="10" viewBox="0 0 10 10" xmlns="http://www.w3.org/2000/svg"><defs/><path fill="#000000" stroke="none" d="M 0 0 L 10 0 L 10 10 L 0 10 Z"/><path fill="none" stroke="#ffffff" d="M 5 5 L 5 3"/></svg>"##
+        );
+
+        canvas.svg_style = Some(SvgStyle::Pretty);
+        assert_eq!(
+            canvas.to_svg(),
+            r##"<svg width="10" height="10" viewBox="0 0 10 10" xmlns="http://www.w3.org/2000/svg">
+    <defs/>
+    <!-- background -->
+    <path fill="#000000" stroke="none" d="M 0 0 L 10 0 L 10 10 L 0 10 Z"/>
+    <!-- layer 0 -->
+    <path fill="none" stroke="#ffffff" d="M 5 5 L 5 3"/>
+</svg>
+"##
+        );
+    }
+
+    #[test]
+    fn test_split_svgs() {
+        let mut canvas = Canvas::new(10, 10);
+        canvas.set_layer(1);
+        canvas.draw_line(5.0, 5.0, 0.0, 2.0, Style::new(COLORS[4]));
+        canvas.set_layer(0);
+        canvas.draw_line(5.0, 5.0, 90.0, 2.0, Style::new(COLORS[7]));
+        canvas.draw_line(5.0, 5.0, 180.0, 2.0, Style::new(COLORS[4]));
+
+        let names = |svgs: Vec<(String, String)>| {
+            svgs.into_iter()
+                .map(|(name, svg)| (name, svg.matches("<path").count() - 1))
+                .collect::<Vec<(String, usize)>>()
+        };
+        assert_eq!(
+            names(canvas.split_svgs(SplitBy::Layer)),
+            vec![("layer-0".to_string(), 2), ("layer-1".to_string(), 1)]
+        );
+        assert_eq!(
+            names(canvas.split_svgs(SplitBy::Color)),
+            vec![("color-red".to_string(), 2), ("color-white".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_viewport() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.draw_line(50.0, 50.0, 0.0, 20.0, Style::new(COLORS[7]));
+        canvas.viewport = Some(Viewport {
+            x: 40.0,
+            y: 25.0,
+            width: 20.0,
+            height: 30.5,
+        });
+
+        assert_eq!(
+            canvas.to_svg(),
+            r##"<svg width="20" height="30.5" viewBox="40 25 20 30.5" xmlns="http://www.w3.org/2000/svg">
+    <defs/>
+    <path fill="#000000" stroke="none" d="M 40 25 L 60 25 L 60 55.5 L 40 55.5 Z"/>
+    <path fill="none" stroke="#ffffff" d="M 50 50 L 50 30"/>
+</svg>
+"##
+        );
+        assert_eq!(canvas.output_size(), (20, 31));
+    }
+
+    #[test]
+    fn test_grid_to_svg() {
+        let mut canvas = Canvas::new(40, 20);
+        canvas.grid = Some(10.0);
+        canvas.draw_line(20.0, 10.0, 90.0, 10.0, Style::new(COLORS[4]));
+        let svg = canvas.to_svg();
+
+        let lines: Vec<&str> = svg.lines().skip(3).collect();
+        assert_eq!(
+            lines,
+            vec![
+                r##"    <path fill="none" stroke="#333333" d="M 0 0 L 0 20"/>"##,
+                r##"    <path fill="none" stroke="#333333" d="M 10 0 L 10 20"/>"##,
+                r##"    <path fill="none" stroke="#333333" d="M 30 0 L 30 20"/>"##,
+                r##"    <path fill="none" stroke="#333333" d="M 40 0 L 40 20"/>"##,
+                r##"    <path fill="none" stroke="#333333" d="M 0 0 L 40 0"/>"##,
+                r##"    <path fill="none" stroke="#333333" d="M 0 20 L 40 20"/>"##,
+                r##"    <path fill="none" stroke="#808080" d="M 20 0 L 20 20"/>"##,
+                r##"    <path fill="none" stroke="#808080" d="M 0 10 L 40 10"/>"##,
+                r##"    <path fill="none" stroke="#ff0000" d="M 20 10 L 30 10"/>"##,
+                "</svg>",
+            ]
+        );
+        assert!(!canvas.split_svgs(SplitBy::Layer)[0].1.contains("#333333"));
+    }
+
+    #[test]
+    fn test_clip_to_svg() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.clip = Some(Clip {
+            x: 10.0,
+            y: 20.0,
+            width: 30.0,
+            height: 40.0,
+        });
+        canvas.draw_line(50.0, 50.0, 0.0, 10.0, Style::new(COLORS[4]));
+        canvas.clip = None;
+        canvas.draw_line(50.0, 50.0, 90.0, 10.0, Style::new(COLORS[4]));
+        let svg = canvas.to_svg();
+
+        assert!(svg.contains(
+            "    <defs>\n        <clipPath id=\"clip-10-20-30-40\">\n            <rect x=\"10\" y=\"20\" width=\"30\" height=\"40\"/>\n        </clipPath>\n    </defs>\n"
+        ));
+        assert!(svg.contains(
+            r##"    <g clip-path="url(#clip-10-20-30-40)"><path fill="none" stroke="#ff0000" d="M 50 50 L 50 40"/></g>"##
+        ));
+        assert!(svg.contains(r##"    <path fill="none" stroke="#ff0000" d="M 50 50 L 60 50"/>"##));
+    }
+
+    #[test]
+    fn test_export_to_svg() {
+        let mut canvas = Canvas::new(40, 20);
+        canvas.export = Export {
+            rotation: Rotation::Half,
+            flip: None,
+            scale: 0.5,
+        };
+        canvas.draw_line(20.0, 10.0, 90.0, 10.0, Style::new(COLORS[4]));
+
+        assert_eq!(canvas.output_size(), (20, 10));
+        assert_eq!(
+            canvas.to_svg(),
+            r##"<svg width="20" height="10" viewBox="0 0 20 10" xmlns="http://www.w3.org/2000/svg">
+    <defs/>
+    <g transform="matrix(-0.5 0 0 -0.5 20 10)">
+        <path fill="#000000" stroke="none" d="M 0 0 L 40 0 L 40 20 L 0 20 Z"/>
+        <path fill="none" stroke="#ff0000" d="M 20 10 L 30 10"/>
+    </g>
+</svg>
+"##
+        );
+    }
+
+    #[test]
+    fn test_rect_to_svg() {
+        let element = Element {
+            shape: Shape::Rect {
+                center: (50.0, 50.0),
+                width: 20.0,
+                height: 10.0,
+                rotation: 90.0,
+            },
+            style: Style::new(COLORS[4]),
+            layer: 0,
+            trail: None,
+            clip: None,
+        };
+
+        assert_eq!(
+            element_to_svg(&element, None),
+            r##"<rect fill="none" stroke="#ff0000" x="40" y="45" width="20" height="10" transform="rotate(90 50 50)"/>"##
+        );
+    }
+
+    #[test]
+    fn test_text_to_svg() {
+        let element = Element {
+            shape: Shape::Text {
+                at: (20.0, 30.0),
+                text: "A < B".to_string(),
+                size: 14.0,
+                rotation: -90.0,
+                font: "sans-serif".to_string(),
+                align: TextAlign::Left,
+            },
+            style: Style::new(COLORS[2]),
+            layer: 0,
+            trail: None,
+            clip: None,
+        };
+
+        assert_eq!(
+            element_to_svg(&element, None),
+            r##"<text fill="#00ffff" stroke="none" x="20" y="30" font-family="sans-serif" font-size="14" transform="rotate(-90 20 30)">A &lt; B</text>"##
+        );
+    }
+
+    #[test]
+    fn test_aligned_text_to_svg() {
+        let element = Element {
+            shape: Shape::Text {
+                at: (20.0, 30.0),
+                text: "Title".to_string(),
+                size: 20.0,
+                rotation: 0.0,
+                font: "Times \"New\" Roman".to_string(),
+                align: TextAlign::Center,
+            },
+            style: Style::new(COLORS[2]),
+            layer: 0,
+            trail: None,
+            clip: None,
+        };
+
+        assert_eq!(
+            element_to_svg(&element, None),
+            r##"<text fill="#00ffff" stroke="none" x="20" y="30" font-family="Times &quot;New&quot; Roman" font-size="20" text-anchor="middle">Title</text>"##
+        );
+    }
+
+    #[test]
+    fn test_polygon_to_svg() {
+        let element = Element {
+            shape: Shape::Polygon {
+                points: vec![(0.0, 0.0), (10.0, 0.0), (5.5, 8.25)],
+            },
+            style: Style::new(COLORS[1]),
+            layer: 0,
+            trail: None,
+            clip: None,
+        };
+
+        assert_eq!(
+            element_to_svg(&element, None),
+            r##"<polygon fill="none" stroke="#0000ff" points="0,0 10,0 5.5,8.25"/>"##
+        );
+    }
+
+    #[test]
+    fn test_circle_to_svg() {
+        let element = Element {
+            shape: Shape::Circle {
+                center: (50.0, 40.0),
+                radius: 12.5,
+            },
+            style: Style::new(COLORS[3]),
+            layer: 0,
+            trail: None,
+            clip: None,
+        };
+
+        assert_eq!(
+            element_to_svg(&element, None),
+            r##"<circle fill="none" stroke="#00ff00" cx="50" cy="40" r="12.5"/>"##
+        );
+    }
+
+    #[test]
+    fn test_ellipse_to_svg() {
+        let element = Element {
+            shape: Shape::Ellipse {
+                center: (50.0, 40.0),
+                rx: 20.0,
+                ry: 7.5,
+                rotation: 30.0,
+            },
+            style: Style::new(COLORS[3]),
+            layer: 0,
+            trail: None,
+            clip: None,
+        };
+
+        assert_eq!(
+            element_to_svg(&element, None),
+            r##"<ellipse fill="none" stroke="#00ff00" cx="50" cy="40" rx="20" ry="7.5" transform="rotate(30 50 40)"/>"##
+        );
+    }
+
+    #[test]
+    fn test_filled_polygon_to_svg() {
+        let mut canvas = Canvas::new(10, 10);
+        let points = vec![(0.0, 0.0), (10.0, 0.0), (5.0, 5.0)];
+        let style = Style {
+            color: COLORS[4],
+            fill: FillPattern::Hatch,
+            opacity: 1.0,
+            width: 1.0,
+            pattern: PenPattern::Solid,
+            reverse: false,
+        };
+        canvas.draw(Shape::Polygon { points }, style);
+
+        assert_eq!(
+            canvas.to_svg(),
+            r##"<svg width="10" height="10" viewBox="0 0 10 10" xmlns="http://www.w3.org/2000/svg">
+    <defs>
+        <pattern id="hatch-ff0000" width="8" height="8" patternUnits="userSpaceOnUse" patternTransform="rotate(45)">
+            <path fill="none" stroke="#ff0000" d="M 0 0 L 0 8"/>
+        </pattern>
+    </defs>
+    <path fill="#000000" stroke="none" d="M 0 0 L 10 0 L 10 10 L 0 10 Z"/>
+    <polygon fill="url(#hatch-ff0000)" stroke="#ff0000" points="0,0 10,0 5,5"/>
+</svg>
+"##
+        );
+    }
+
+    #[test]
+    fn test_fill_attr() {
+        let mut style = Style::new(COLORS[1]);
+        assert_eq!(fill_attr(&style), "none");
+
+        style.fill = FillPattern::Solid;
+        assert_eq!(fill_attr(&style), "#0000ff");
+
+        style.fill = FillPattern::Dots;
+        assert_eq!(fill_attr(&style), "url(#dots-0000ff)");
+    }
+
+    #[test]
+    fn test_layers_to_svg() {
+        let mut canvas = Canvas::new(10, 10);
+        canvas.set_layer(1);
+        canvas.draw_line(0.0, 0.0, 90.0, 5.0, Style::new(COLORS[1]));
+        canvas.set_effect(Some(Effect::Shadow {
+            dx: 2.0,
+            dy: 2.0,
+            color: COLORS[15],
+        }));
+        canvas.set_layer(0);
+        canvas.draw_line(0.0, 5.0, 90.0, 5.0, Style::new(COLORS[4]));
+
+        assert_eq!(
+            canvas.to_svg(),
+            r##"<svg width="10" height="10" viewBox="0 0 10 10" xmlns="http://www.w3.org/2000/svg">
+    <defs/>
+    <path fill="#000000" stroke="none" d="M 0 0 L 10 0 L 10 10 L 0 10 Z"/>
+    <path fill="none" stroke="#ff0000" d="M 0 5 L 5 5"/>
+    <g transform="translate(2 2)">
+        <path fill="none" stroke="#808080" d="M 0 0 L 5 0"/>
+    </g>
+    <path fill="none" stroke="#0000ff" d="M 0 0 L 5 0"/>
+</svg>
+"##
+        );
+    }
+
+    #[test]
+    fn test_dashed_to_svg() {
+        let element = Element {
+            shape: Shape::Line {
+                from: (0.0, 0.0),
+                to: (5.0, 0.0),
+            },
+            style: Style {
+                width: 2.0,
+                pattern: PenPattern::Dashed,
+                ..Style::new(COLORS[4])
+            },
+            layer: 0,
+            trail: None,
+            clip: None,
+        };
+
+        assert_eq!(
+            element_to_svg(&element, None),
+            r##"<path fill="none" stroke="#ff0000" stroke-width="2" stroke-dasharray="12 8" d="M 0 0 L 5 0"/>"##
+        );
+    }
+
+    #[test]
+    fn test_reverse_to_svg() {
+        let element = Element {
+            shape: Shape::Line {
+                from: (0.0, 0.0),
+                to: (5.0, 0.0),
+            },
+            style: Style {
+                reverse: true,
+                ..Style::new(COLORS[4])
+            },
+            layer: 0,
+            trail: None,
+            clip: None,
+        };
+
+        assert_eq!(
+            element_to_svg(&element, None),
+            r##"<path fill="none" stroke="#ff0000" style="mix-blend-mode:difference" d="M 0 0 L 5 0"/>"##
+        );
+    }
+
+    #[test]
+    fn test_outline_to_svg() {
+        let element = Element {
+            shape: Shape::Line {
+                from: (0.0, 0.0),
+                to: (5.0, 0.0),
+            },
+            style: Style::new(COLORS[1]),
+            layer: 0,
+            trail: None,
+            clip: None,
+        };
+        let effect = Effect::Outline {
+            width: 1.5,
+            color: COLORS[7],
+        };
+
+        assert_eq!(
+            effect_to_svg(&effect, &[element], None),
+            r##"    <g stroke-width="4" stroke-linejoin="round" stroke-linecap="round">
+        <path fill="none" stroke="#ffffff" d="M 0 0 L 5 0"/>
+    </g>
+"##
+        );
+    }
+
+    #[test]
+    fn test_trail_fade_to_svg() {
+        let mut canvas = Canvas::new(10, 10);
+        canvas.set_trail_fade(2);
+        canvas.draw_line(0.0, 0.0, 90.0, 5.0, Style::new(COLORS[1]));
+        canvas.draw_line(0.0, 5.0, 90.0, 5.0, Style::new(COLORS[1]));
+        canvas.draw_line(0.0, 9.0, 90.0, 5.0, Style::new(COLORS[1]));
+
+        assert_eq!(
+            canvas.to_svg(),
+            r##"<svg width="10" height="10" viewBox="0 0 10 10" xmlns="http://www.w3.org/2000/svg">
+    <defs/>
+    <path fill="#000000" stroke="none" d="M 0 0 L 10 0 L 10 10 L 0 10 Z"/>
+    <path fill="none" stroke="#0000ff" opacity="0.5" d="M 0 5 L 5 5"/>
+    <path fill="none" stroke="#0000ff" d="M 0 9 L 5 9"/>
+</svg>
+"##
+        );
+    }
+}
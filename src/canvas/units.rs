@@ -0,0 +1,133 @@
+//! Physical sizes for the canvas, given with `--size` and `--dpi`.
+//!
+//! A size such as `210mmx297mm` is converted into pixels at the given DPI,
+//! which is the coordinate space the turtle draws in. SVG output keeps the
+//! physical size in its `width` and `height` so it prints at the right size,
+//! while PNG output is simply that many pixels.
+
+use std::fmt;
+
+/// The DPI used when `--dpi` isn't given, which is the CSS pixel density.
+pub const DEFAULT_DPI: f32 = 96.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unit {
+    Px,
+    Mm,
+    Cm,
+    In,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Length {
+    pub value: f32,
+    pub unit: Unit,
+}
+
+impl Length {
+    /// Converts the length into pixels at `dpi` pixels per inch.
+    pub fn to_px(self, dpi: f32) -> f32 {
+        match self.unit {
+            Unit::Px => self.value,
+            Unit::Mm => self.value / 25.4 * dpi,
+            Unit::Cm => self.value / 2.54 * dpi,
+            Unit::In => self.value * dpi,
+        }
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let unit = match self.unit {
+            Unit::Px => "",
+            Unit::Mm => "mm",
+            Unit::Cm => "cm",
+            Unit::In => "in",
+        };
+        write!(f, "{}{}", self.value, unit)
+    }
+}
+
+/// Parses a length such as `210mm`, `8.5in` or `800`. A bare number is taken
+/// as pixels.
+pub fn parse_length(length: &str) -> Result<Length, String> {
+    let length = length.trim();
+    let (digits, unit) = if let Some(digits) = length.strip_suffix("mm") {
+        (digits, Unit::Mm)
+    } else if let Some(digits) = length.strip_suffix("cm") {
+        (digits, Unit::Cm)
+    } else if let Some(digits) = length.strip_suffix("in") {
+        (digits, Unit::In)
+    } else if let Some(digits) = length.strip_suffix("px") {
+        (digits, Unit::Px)
+    } else {
+        (length, Unit::Px)
+    };
+
+    match digits.parse::<f32>() {
+        Ok(value) if value > 0.0 => Ok(Length { value, unit }),
+        _ => Err(format!(
+            "Invalid length {:?}, expected e.g. 210mm, 21cm, 8.5in or 800",
+            length
+        )),
+    }
+}
+
+/// Parses a size of the form `WIDTHxHEIGHT`, such as `210mmx297mm`.
+pub fn parse_page_size(size: &str) -> Result<(Length, Length), String> {
+    let Some((width, height)) = size.split_once('x') else {
+        return Err(format!(
+            "Invalid size {:?}, expected WIDTHxHEIGHT such as 210mmx297mm",
+            size
+        ));
+    };
+    Ok((parse_length(width)?, parse_length(height)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_length() {
+        assert_eq!(
+            parse_length("210mm"),
+            Ok(Length {
+                value: 210.0,
+                unit: Unit::Mm
+            })
+        );
+        assert_eq!(
+            parse_length("8.5in"),
+            Ok(Length {
+                value: 8.5,
+                unit: Unit::In
+            })
+        );
+        assert_eq!(
+            parse_length("800"),
+            Ok(Length {
+                value: 800.0,
+                unit: Unit::Px
+            })
+        );
+        assert!(parse_length("tall").is_err());
+        assert!(parse_length("-3cm").is_err());
+    }
+
+    #[test]
+    fn test_parse_page_size() {
+        let (width, height) = parse_page_size("210mmx297mm").unwrap();
+        assert_eq!(width.to_string(), "210mm");
+        assert_eq!(height.to_string(), "297mm");
+        assert!(parse_page_size("210mm").is_err());
+    }
+
+    #[test]
+    fn test_to_px() {
+        let a4_width = parse_length("210mm").unwrap();
+        assert_eq!(a4_width.to_px(300.0).round(), 2480.0);
+        assert_eq!(parse_length("1in").unwrap().to_px(72.0), 72.0);
+        assert_eq!(parse_length("2.54cm").unwrap().to_px(96.0), 96.0);
+    }
+}
@@ -0,0 +1,74 @@
+//! The region of the drawing written out, given with `--viewport`.
+//!
+//! The viewport is in the same coordinates the turtle draws in, so the region
+//! around `XCOR` and `YCOR` can be picked out of a large drawing. The SVG's
+//! `viewBox` is set to the region and PNG output is cropped to it, so the
+//! image is as many pixels as the region is wide and tall.
+
+/// A rectangle of the canvas, from its top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    /// The size of the image in pixels, rounding partial pixels up.
+    pub fn size_px(&self) -> (u32, u32) {
+        (self.width.ceil() as u32, self.height.ceil() as u32)
+    }
+}
+
+/// Parses a viewport of the form `x,y,w,h`, such as `100,100,200,150`.
+pub fn parse_viewport(viewport: &str) -> Result<Viewport, String> {
+    let values = viewport
+        .split(',')
+        .map(|value| value.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>();
+
+    match values.as_deref() {
+        Ok(&[x, y, width, height])
+            if [x, y, width, height].iter().all(|value| value.is_finite())
+                && width > 0.0
+                && height > 0.0 =>
+        {
+            Ok(Viewport {
+                x,
+                y,
+                width,
+                height,
+            })
+        }
+        _ => Err(format!(
+            "Invalid viewport {:?}, expected X,Y,WIDTH,HEIGHT such as 100,100,200,150",
+            viewport
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_viewport() {
+        assert_eq!(
+            parse_viewport("100,-20, 200.5,150"),
+            Ok(Viewport {
+                x: 100.0,
+                y: -20.0,
+                width: 200.5,
+                height: 150.0,
+            })
+        );
+        assert_eq!(
+            parse_viewport("0,0,200.5,150").unwrap().size_px(),
+            (201, 150)
+        );
+        assert!(parse_viewport("0,0,200").is_err());
+        assert!(parse_viewport("0,0,0,150").is_err());
+        assert!(parse_viewport("0,0,wide,150").is_err());
+    }
+}
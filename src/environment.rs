@@ -0,0 +1,123 @@
+//! The environment shared between the parser and the interpreter.
+//!
+//! The parser records which variables and procedures have been declared so
+//! that it can validate references, while the interpreter uses the same
+//! environment to store variable values and look up procedure bodies.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{Expression, Procedure},
+    canvas::palette::Palette,
+    interpreter::{
+        angles::AngleUnit, coverage::Coverage, errors::ExecutionError, input::InputSource,
+        limits::Limits, log::ExecLog, precision::Precision, profile::Profile,
+        providers::QueryProvider, random::Rng,
+    },
+    parser::{dialect::Dialect, syntax::Syntax},
+};
+
+#[derive(Debug, Default)]
+pub struct Environment {
+    pub vars: HashMap<String, Expression>,
+    /// Variables declared with `CONST`, which may not be assigned again.
+    pub consts: HashSet<String>,
+    /// Procedures declared with `TO ... END`, keyed by name.
+    pub procedures: HashMap<String, Procedure>,
+    /// Property lists set with `PPROP`, keyed by the list's name and then
+    /// the property's.
+    pub props: HashMap<String, HashMap<String, Expression>>,
+    /// A frame for each procedure call and block in progress, innermost
+    /// last.
+    pub frames: Vec<CallFrame>,
+    /// Seed for `NOISE`, set with `--seed`.
+    pub seed: u64,
+    /// The generator for `RANDOM`, seeded with `--seed` or `RERANDOM`.
+    pub rng: Rng,
+    /// Whether `GETENV` is refused and the clock frozen, set with
+    /// `--deterministic`.
+    pub deterministic: bool,
+    /// The precision expressions are evaluated at, set with `--precision`.
+    pub precision: Precision,
+    /// The unit angles are given in, set with `--angles` or `SETANGLEUNIT`.
+    pub angle_unit: AngleUnit,
+    /// The colors palette indices refer to, set with `--palette`.
+    pub palette: Palette,
+    /// The syntax expressions are parsed in, set with `--syntax` or
+    /// `SETSYNTAX`.
+    pub syntax: Syntax,
+    /// The dialect scripts are parsed in, set with `--dialect` or
+    /// `SETDIALECT`.
+    pub dialect: Dialect,
+    /// Counters gathered while the script executes.
+    pub stats: Stats,
+    /// Where each executed command is logged, set with `--log`.
+    pub log: Option<ExecLog>,
+    /// Which lines of the script have run, kept with `--coverage`.
+    pub coverage: Option<Coverage>,
+    /// Time spent in each procedure call stack, kept with `--profile`.
+    pub profile: Option<Profile>,
+    /// Where `QUERY "name` looks up values, registered by the embedder.
+    pub providers: Vec<Box<dyn QueryProvider>>,
+    /// Where `READWORD` and `READNUMBER` read from, or standard input if
+    /// unset.
+    pub input: Option<Box<dyn InputSource>>,
+    pub limits: Limits,
+    /// Whether recoverable errors skip the statement instead of stopping the
+    /// script, set with `--keep-going`.
+    pub keep_going: bool,
+    /// The errors skipped over with `--keep-going`, in the order they
+    /// happened.
+    pub warnings: Vec<ExecutionError>,
+}
+
+/// The variables bound by a procedure call or inside a block, so that they
+/// can be put back the way they were when it ends.
+#[derive(Debug, Default)]
+pub struct CallFrame {
+    /// Each variable bound by the call, with its value from before the call,
+    /// or `None` if it didn't exist.
+    saved: Vec<(String, Option<Expression>)>,
+}
+
+impl Environment {
+    /// Starts a procedure call or block.
+    pub fn push_frame(&mut self) {
+        self.frames.push(CallFrame::default());
+    }
+
+    /// Sets `name` to `value` until the current procedure call or block
+    /// ends, or for good outside of one.
+    pub fn bind_local(&mut self, name: &str, value: Expression) {
+        let prev = self.vars.insert(name.to_string(), value);
+        if let Some(frame) = self.frames.last_mut() {
+            if !frame.saved.iter().any(|(saved, _)| saved == name) {
+                frame.saved.push((name.to_string(), prev));
+            }
+        }
+    }
+
+    /// Ends the current procedure call or block, restoring the variables it
+    /// bound.
+    pub fn pop_frame(&mut self) {
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        for (name, prev) in frame.saved.into_iter().rev() {
+            match prev {
+                Some(value) => self.vars.insert(name, value),
+                None => self.vars.remove(&name),
+            };
+        }
+    }
+}
+
+/// Counters gathered while executing a script, reported by `--emit-meta`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    /// Commands run, counting each run of a command inside a loop or
+    /// procedure separately.
+    pub commands: usize,
+    /// Procedure calls made.
+    pub calls: usize,
+}
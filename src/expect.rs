@@ -0,0 +1,238 @@
+//! The behavioural test runner behind `rslogo test`.
+//!
+//! A test is an ordinary Logo script with `EXPECT` directives in comments,
+//! so it still runs as a normal script:
+//!
+//! ```Logo
+//! // EXPECT Y 150
+//! // EXPECT HEADING 90
+//! // EXPECT SEGMENTS 1
+//! PENDOWN
+//! FORWARD "100
+//! TURN "90
+//! ```
+//!
+//! `X`, `Y` and `HEADING` check the turtle's final state and `SEGMENTS` the
+//! number of lines drawn, while `ERROR text` expects the script to fail with
+//! an error mentioning `text`. Without an `ERROR` directive the script must
+//! run without errors. Scripts run with `--deterministic`, so `GETENV` and the
+//! clock can't make a test flaky.
+
+use std::{
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    canvas::{Canvas, Shape},
+    environment::Environment,
+    interpreter::{execute::execute, turtle::Turtle},
+    parser::{parse::parse_tokens, tokenise::tokenize_script},
+};
+
+/// How far a final coordinate may be from the expected one.
+const TOLERANCE: f64 = 1e-3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expectation {
+    X(f64),
+    Y(f64),
+    Heading(f32),
+    Segments(usize),
+    Error(String),
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expectation::X(x) => write!(f, "X {}", x),
+            Expectation::Y(y) => write!(f, "Y {}", y),
+            Expectation::Heading(heading) => write!(f, "HEADING {}", heading),
+            Expectation::Segments(segments) => write!(f, "SEGMENTS {}", segments),
+            Expectation::Error(text) => write!(f, "ERROR {}", text),
+        }
+    }
+}
+
+/// Reads the `// EXPECT` directives from a script.
+///
+/// # Example
+///
+/// ```rust
+/// let expectations = parse_expectations("// EXPECT X 10\nSETX \"10\n").unwrap();
+/// assert_eq!(expectations, vec![Expectation::X(10.0)]);
+/// ```
+pub fn parse_expectations(script: &str) -> Result<Vec<Expectation>, String> {
+    script
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("//"))
+        .filter_map(|comment| comment.trim().strip_prefix("EXPECT "))
+        .map(|directive| {
+            let (kind, value) = directive.trim().split_once(' ').unwrap_or((directive, ""));
+            let value = value.trim();
+            let invalid = || format!("Invalid EXPECT directive: {:?}", directive);
+            match kind {
+                "X" => value.parse().map(Expectation::X).map_err(|_| invalid()),
+                "Y" => value.parse().map(Expectation::Y).map_err(|_| invalid()),
+                "HEADING" => value
+                    .parse()
+                    .map(Expectation::Heading)
+                    .map_err(|_| invalid()),
+                "SEGMENTS" => value
+                    .parse()
+                    .map(Expectation::Segments)
+                    .map_err(|_| invalid()),
+                "ERROR" => Ok(Expectation::Error(value.to_string())),
+                _ => Err(invalid()),
+            }
+        })
+        .collect()
+}
+
+/// Runs a test script on a canvas of the given size, returning a description
+/// of each expectation it didn't meet.
+pub fn check_script(script: &str, width: u32, height: u32) -> Result<Vec<String>, String> {
+    let expectations = parse_expectations(script)?;
+
+    let mut canvas = Canvas::new(width, height);
+    let mut turtle = Turtle::new(&mut canvas);
+    let mut env = Environment {
+        deterministic: true,
+        ..Default::default()
+    };
+    let res: Result<(), Box<dyn Error>> = parse_tokens(tokenize_script(script), &mut 0, &mut env)
+        .map_err(|e| e.into())
+        .and_then(|ast| {
+            execute(&ast, &mut turtle, &mut env)
+                .map(|_| ())
+                .map_err(|e| e.into())
+        });
+
+    let mut failures = Vec::new();
+    let expects_error = expectations
+        .iter()
+        .any(|expectation| matches!(expectation, Expectation::Error(_)));
+    if let (Err(e), false) = (&res, expects_error) {
+        failures.push(format!("unexpected error: {}", e));
+    }
+
+    let segments = turtle
+        .canvas
+        .elements
+        .iter()
+        .filter(|element| matches!(element.shape, Shape::Line { .. }))
+        .count();
+    for expectation in &expectations {
+        let actual = match expectation {
+            Expectation::X(x) if (turtle.xcor() - x).abs() > TOLERANCE => {
+                format!("X {}", turtle.xcor())
+            }
+            Expectation::Y(y) if (turtle.ycor() - y).abs() > TOLERANCE => {
+                format!("Y {}", turtle.ycor())
+            }
+            Expectation::Heading(heading)
+                if (turtle.heading - heading).abs() as f64 > TOLERANCE =>
+            {
+                format!("HEADING {}", turtle.heading)
+            }
+            Expectation::Segments(expected) if segments != *expected => {
+                format!("SEGMENTS {}", segments)
+            }
+            Expectation::Error(text) => match &res {
+                Ok(()) => "no error".to_string(),
+                Err(e) if !e.to_string().contains(text.as_str()) => format!("ERROR {}", e),
+                Err(_) => continue,
+            },
+            _ => continue,
+        };
+        failures.push(format!("expected {}, got {}", expectation, actual));
+    }
+
+    Ok(failures)
+}
+
+/// Finds the `.lg` scripts in `paths`, searching directories recursively.
+pub fn find_scripts(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut scripts = Vec::new();
+    for path in paths {
+        collect_scripts(path, &mut scripts)?;
+    }
+    Ok(scripts)
+}
+
+fn collect_scripts(path: &Path, scripts: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    if !path.is_dir() {
+        scripts.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<PathBuf>, std::io::Error>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() || entry.extension().is_some_and(|ext| ext == "lg") {
+            collect_scripts(&entry, scripts)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_expectations() {
+        let script = "// EXPECT X 10\n//EXPECT HEADING 90\n// EXPECT ERROR Division by zero\n\
+            // a comment\nPENDOWN\n";
+
+        assert_eq!(
+            parse_expectations(script),
+            Ok(vec![
+                Expectation::X(10.0),
+                Expectation::Heading(90.0),
+                Expectation::Error("Division by zero".to_string()),
+            ])
+        );
+        assert!(parse_expectations("// EXPECT SEGMENTS many").is_err());
+        assert!(parse_expectations("// EXPECT COLOR 1").is_err());
+    }
+
+    #[test]
+    fn test_check_script() {
+        let script = "// EXPECT Y 0\n// EXPECT HEADING 90\n// EXPECT SEGMENTS 1\n\
+            PENDOWN\nFORWARD \"100\nTURN \"90\n";
+        assert_eq!(check_script(script, 200, 200), Ok(vec![]));
+
+        let script = "// EXPECT X 0\n// EXPECT SEGMENTS 2\nPENDOWN\nFORWARD \"100\n";
+        assert_eq!(
+            check_script(script, 200, 200),
+            Ok(vec![
+                "expected X 0, got X 100".to_string(),
+                "expected SEGMENTS 2, got SEGMENTS 1".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_check_script_error() {
+        let script = "// EXPECT ERROR Division by zero\nFORWARD / \"1 \"0\n";
+        assert_eq!(check_script(script, 200, 200), Ok(vec![]));
+
+        let script = "FORWARD / \"1 \"0\n";
+        assert_eq!(
+            check_script(script, 200, 200),
+            Ok(vec!["unexpected error: Division by zero".to_string()])
+        );
+
+        let script = "// EXPECT ERROR Division by zero\nFORWARD \"1\n";
+        assert_eq!(
+            check_script(script, 200, 200),
+            Ok(vec![
+                "expected ERROR Division by zero, got no error".to_string()
+            ])
+        );
+    }
+}
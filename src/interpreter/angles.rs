@@ -0,0 +1,92 @@
+//! The unit angles are given in, set with `--angles` or `SETANGLEUNIT`.
+//!
+//! The turtle always keeps its heading in degrees, which needn't be whole.
+//! Angles passed to `TURN`, `SETHEADING` and `ROTATE` are converted from the
+//! current unit, and `HEADING` reports the heading back in it.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum AngleUnit {
+    #[default]
+    Degrees,
+    Radians,
+    Gradians,
+}
+
+impl AngleUnit {
+    /// The names accepted by `SETANGLEUNIT`.
+    pub const NAMES: [&'static str; 3] = ["degrees", "radians", "gradians"];
+
+    pub fn from_name(name: &str) -> Option<AngleUnit> {
+        match name.to_lowercase().as_str() {
+            "degrees" => Some(AngleUnit::Degrees),
+            "radians" => Some(AngleUnit::Radians),
+            "gradians" => Some(AngleUnit::Gradians),
+            _ => None,
+        }
+    }
+
+    /// Converts an angle in this unit into degrees.
+    pub fn to_degrees(self, angle: f64) -> f64 {
+        match self {
+            AngleUnit::Degrees => angle,
+            AngleUnit::Radians => angle.to_degrees(),
+            AngleUnit::Gradians => angle * 0.9,
+        }
+    }
+
+    /// Converts an angle in this unit into radians, without rounding, for
+    /// trigonometry.
+    pub fn to_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleUnit::Degrees => angle.to_radians(),
+            AngleUnit::Radians => angle,
+            AngleUnit::Gradians => (angle * 0.9).to_radians(),
+        }
+    }
+
+    /// Converts an angle in radians into this unit, without rounding.
+    pub fn radians_in_unit(self, radians: f64) -> f64 {
+        match self {
+            AngleUnit::Degrees => radians.to_degrees(),
+            AngleUnit::Radians => radians,
+            AngleUnit::Gradians => radians.to_degrees() / 0.9,
+        }
+    }
+
+    /// Converts an angle in degrees into this unit.
+    pub fn in_unit(self, degrees: f64) -> f64 {
+        match self {
+            AngleUnit::Degrees => degrees,
+            AngleUnit::Radians => degrees.to_radians(),
+            AngleUnit::Gradians => degrees / 0.9,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(AngleUnit::from_name("Radians"), Some(AngleUnit::Radians));
+        assert_eq!(AngleUnit::from_name("turns"), None);
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(AngleUnit::Degrees.to_degrees(1.5), 1.5);
+        assert_eq!(AngleUnit::Radians.to_degrees(std::f64::consts::PI), 180.0);
+        assert_eq!(AngleUnit::Gradians.to_degrees(100.0), 90.0);
+
+        assert_eq!(AngleUnit::Radians.in_unit(180.0), std::f64::consts::PI);
+        assert_eq!(AngleUnit::Gradians.in_unit(90.0), 100.0);
+
+        let quarter = std::f64::consts::FRAC_PI_2;
+        assert_eq!(AngleUnit::Degrees.to_radians(90.0), quarter);
+        assert_eq!(AngleUnit::Radians.to_radians(1.5), 1.5);
+        assert_eq!(AngleUnit::Gradians.to_radians(100.0), quarter);
+        assert_eq!(AngleUnit::Degrees.radians_in_unit(quarter), 90.0);
+        assert_eq!(AngleUnit::Gradians.radians_in_unit(quarter), 100.0);
+    }
+}
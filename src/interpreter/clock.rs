@@ -0,0 +1,95 @@
+//! The current date and time for the `YEAR`, `MONTH`, `DAY`, `HOUR`,
+//! `MINUTE` and `SECOND` queries.
+//!
+//! Times are in UTC. Under `--deterministic` the clock is frozen at the Unix
+//! epoch, so a script that reads it still draws the same thing on every run.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    /// The current time, or the Unix epoch if `frozen`.
+    pub fn now(frozen: bool) -> DateTime {
+        let secs = if frozen {
+            0
+        } else {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs())
+        };
+        DateTime::from_unix(secs)
+    }
+
+    /// Converts seconds since the Unix epoch into a calendar date and time,
+    /// using Howard Hinnant's `civil_from_days` algorithm.
+    pub fn from_unix(secs: u64) -> DateTime {
+        let days = (secs / 86400) as i64;
+        let time = (secs % 86400) as u32;
+
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: time / 3600,
+            minute: time / 60 % 60,
+            second: time % 60,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_unix() {
+        assert_eq!(
+            DateTime::from_unix(0),
+            DateTime {
+                year: 1970,
+                month: 1,
+                day: 1,
+                hour: 0,
+                minute: 0,
+                second: 0
+            }
+        );
+
+        // 2024-02-29 13:45:30 UTC
+        assert_eq!(
+            DateTime::from_unix(1709214330),
+            DateTime {
+                year: 2024,
+                month: 2,
+                day: 29,
+                hour: 13,
+                minute: 45,
+                second: 30
+            }
+        );
+    }
+
+    #[test]
+    fn test_now_frozen() {
+        assert_eq!(DateTime::now(true), DateTime::from_unix(0));
+    }
+}
@@ -3,43 +3,50 @@
 //! Responsible for evaluating conditions and executing the block if the
 //! condition is true.
 
-use std::collections::HashMap;
+use std::time::Instant;
 
-use crate::ast::{ASTNode, Condition, Expression};
+use crate::{
+    ast::{ASTNode, Condition, Expression},
+    environment::Environment,
+};
 
-use super::{errors::ExecutionError, execute::execute, matches::match_expressions, turtle::Turtle};
+use super::{
+    errors::{ExecutionError, ExecutionErrorKind},
+    execute::{execute, Flow},
+    matches::{list_items, match_expressions, match_value},
+    turtle::Turtle,
+};
 
 /// Compares two expressions using a given comparator.
 ///
 /// # Examples
 ///
 /// ```rust
-/// use std::collections::HashMap;
 /// use turtle::Turtle;
 /// use parser::ast::{Condition, Expression};
 /// use interpreter::errors::ExecutionError;
-/// use unsvg::Image;
+/// use crate::canvas::Canvas;
 ///
 ///
-/// let mut vars: HashMap<String, Expression> = HashMap::new();
-/// let mut image = Image::new(100, 100);
-/// let turtle = Turtle::new(&mut image);
+/// let env = Environment::default();
+/// let mut canvas = Canvas::new(100, 100);
+/// let turtle = Turtle::new(&mut canvas);
 ///
 /// let lhs = Expression::Float(8.0);
 /// let rhs = Expression::Float(10.0);
 ///
-/// let res = comparator(&lhs, &rhs, |a, b| a < b, &turtle, &vars).unwrap();
+/// let res = comparator(&lhs, &rhs, |a, b| a < b, &turtle, &env).unwrap();
 /// assert!(res);
 /// ```
 fn comparator(
     lhs: &Expression,
     rhs: &Expression,
-    comparator: fn(f32, f32) -> bool,
-    turtle: &Turtle,
-    vars: &HashMap<String, Expression>,
+    comparator: fn(f64, f64) -> bool,
+    turtle: &mut Turtle,
+    env: &mut Environment,
 ) -> Result<bool, ExecutionError> {
-    let lhs_val = match_expressions(lhs, vars, turtle)?;
-    let rhs_val = match_expressions(rhs, vars, turtle)?;
+    let lhs_val = match_expressions(lhs, env, turtle)?;
+    let rhs_val = match_expressions(rhs, env, turtle)?;
     Ok(comparator(lhs_val, rhs_val))
 }
 
@@ -52,11 +59,11 @@ fn comparator(
 /// use parser::ast::{ASTNode, Condition, Expression};
 /// use interpreter::control_flows::eval_exec_if;
 /// use interpreter::errors::ExecutionError;
-/// use unsvg::Image;
+/// use crate::canvas::Canvas;
 ///
-/// let mut vars: HashMap<String, Expression> = HashMap::new();
-/// let mut image = Image::new(100, 100);
-/// let mut turtle = Turtle::new(&mut image);
+/// let mut env = Environment::default();
+/// let mut canvas = Canvas::new(100, 100);
+/// let mut turtle = Turtle::new(&mut canvas);
 ///
 /// let condition = Condition::LessThan(
 ///   Expression::Float(8.0),
@@ -64,22 +71,22 @@ fn comparator(
 /// );
 ///
 /// let block = vec![ASTNode::Command(Command::Forward(Expression::Float(100.0)))];
-/// let res = eval_exec_if(&condition, &block, &mut turtle, &mut vars).unwrap();
+/// let res = eval_exec_if(&condition, &block, &mut turtle, &mut env).unwrap();
 /// assert!(res.is_ok());
 /// ```
 pub fn eval_exec_if(
     condition: &Condition,
     block: &Vec<ASTNode>,
     turtle: &mut Turtle,
-    vars: &mut HashMap<String, Expression>,
-) -> Result<(), ExecutionError> {
-    let exec = should_execute(condition, turtle, vars)?;
+    env: &mut Environment,
+) -> Result<Flow, ExecutionError> {
+    let exec = should_execute(condition, turtle, env)?;
 
     if exec {
-        execute(block, turtle, vars)?;
+        return execute_block(block, turtle, env);
     }
 
-    Ok(())
+    Ok(Flow::Next)
 }
 
 /// Evaluates the condition and executes a `WHILE` block if the condition is true.
@@ -92,33 +99,416 @@ pub fn eval_exec_if(
 /// use parser::ast::{ASTNode, Condition, Expression};
 /// use interpreter::errors::ExecutionError;
 ///
-/// let mut vars: HashMap<String, Expression> = HashMap::new();
-/// let mut image = Image::new(100, 100);
-/// let mut turtle = Turtle::new(&mut image);
+/// let mut env = Environment::default();
+/// let mut canvas = Canvas::new(100, 100);
+/// let mut turtle = Turtle::new(&mut canvas);
 /// let condition = Condition::LessThan(
 ///     Expression::Float(8.0),
 ///     Expression::Float(10.0),
 /// );
 ///
 /// let block = vec![ASTNode::Command(Command::Forward(Expression::Float(100.0)))];
-/// let res = eval_exec_while(&condition, &block, &mut turtle, &mut vars).unwrap();
+/// let res = eval_exec_while(&condition, &block, &mut turtle, &mut env).unwrap();
 /// assert!(res.is_ok());
 /// ```
 pub fn eval_exec_while(
     condition: &Condition,
     block: &Vec<ASTNode>,
     turtle: &mut Turtle,
-    vars: &mut HashMap<String, Expression>,
-) -> Result<(), ExecutionError> {
-    let mut exec = should_execute(condition, turtle, vars)?;
+    env: &mut Environment,
+) -> Result<Flow, ExecutionError> {
+    let mut exec = should_execute(condition, turtle, env)?;
 
     while exec {
-        execute(block, turtle, vars)?;
+        let flow = execute_block(block, turtle, env)?;
+        if flow != Flow::Next {
+            return Ok(flow);
+        }
+
+        exec = should_execute(condition, turtle, env)?;
+    }
+
+    Ok(Flow::Next)
+}
+
+/// Executes a `DO.WHILE` block once, then again for as long as the condition
+/// is true.
+///
+/// # Examples
+///
+/// ```rust
+/// let condition = Condition::LessThan(
+///     Expression::Query(Query::YCor),
+///     Expression::Float(10.0),
+/// );
+/// let block = vec![ASTNode::Command(Command::Forward(Expression::Float(10.0)))];
+/// eval_exec_do_while(&block, &condition, &mut turtle, &mut env).unwrap();
+///
+/// assert_eq!(turtle.y, 40.0);
+/// ```
+pub fn eval_exec_do_while(
+    block: &Vec<ASTNode>,
+    condition: &Condition,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<Flow, ExecutionError> {
+    let flow = execute_block(block, turtle, env)?;
+    if flow != Flow::Next {
+        return Ok(flow);
+    }
+    eval_exec_while(condition, block, turtle, env)
+}
+
+/// Executes an `UNTIL` block once, then again until the condition is true.
+///
+/// # Examples
+///
+/// ```rust
+/// let condition = Condition::LessThan(
+///     Expression::Query(Query::YCor),
+///     Expression::Float(10.0),
+/// );
+/// let block = vec![ASTNode::Command(Command::Forward(Expression::Float(10.0)))];
+/// eval_exec_until(&condition, &block, &mut turtle, &mut env).unwrap();
+///
+/// assert_eq!(turtle.y, 0.0);
+/// ```
+pub fn eval_exec_until(
+    condition: &Condition,
+    block: &Vec<ASTNode>,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<Flow, ExecutionError> {
+    loop {
+        let flow = execute_block(block, turtle, env)?;
+        if flow != Flow::Next {
+            return Ok(flow);
+        }
+        if should_execute(condition, turtle, env)? {
+            return Ok(Flow::Next);
+        }
+    }
+}
+
+/// Evaluates the count of a `REPEAT` once and executes its block that many
+/// times, rounding the count down.
+///
+/// # Examples
+///
+/// ```rust
+/// let count = Expression::Float(4.0);
+/// let block = vec![ASTNode::Command(Command::Forward(Expression::Float(10.0)))];
+/// eval_exec_repeat(&count, &block, &mut turtle, &mut env).unwrap();
+///
+/// assert_eq!(turtle.y, 10.0);
+/// ```
+pub fn eval_exec_repeat(
+    count: &Expression,
+    block: &Vec<ASTNode>,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<Flow, ExecutionError> {
+    let count = match_expressions(count, env, turtle)?;
+    if count < 0.0 {
+        return Err(ExecutionError {
+            kind: ExecutionErrorKind::InvalidArgument {
+                msg: "REPEAT needs a non-negative count".to_string(),
+            },
+        });
+    }
+
+    for _ in 0..count as usize {
+        let flow = execute_block(block, turtle, env)?;
+        if flow != Flow::Next {
+            return Ok(flow);
+        }
+    }
+
+    Ok(Flow::Next)
+}
+
+/// Executes the block of a `FOR` loop with `var` set to each value from the
+/// start to the end of `range`, inclusive, in steps of its step or 1 towards
+/// the end. The range is evaluated once, before the loop starts, and `var` is
+/// put back the way it was afterwards.
+///
+/// # Examples
+///
+/// ```rust
+/// let range = (&Expression::Float(1.0), &Expression::Float(3.0), None);
+/// let block = vec![ASTNode::Command(Command::Forward(Expression::Variable(
+///     "I".to_string(),
+/// )))];
+/// eval_exec_for("I", range, &block, &mut turtle, &mut env).unwrap();
+///
+/// assert_eq!(turtle.y, 44.0);
+/// ```
+pub fn eval_exec_for(
+    var: &str,
+    (start, end, step): (&Expression, &Expression, Option<&Expression>),
+    block: &Vec<ASTNode>,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<Flow, ExecutionError> {
+    let start = match_expressions(start, env, turtle)?;
+    let end = match_expressions(end, env, turtle)?;
+    let step = match step {
+        Some(step) => match_expressions(step, env, turtle)?,
+        None if start <= end => 1.0,
+        None => -1.0,
+    };
+    if step == 0.0 {
+        return Err(ExecutionError {
+            kind: ExecutionErrorKind::InvalidArgument {
+                msg: "FOR needs a non-zero step".to_string(),
+            },
+        });
+    }
+
+    let prev = env.vars.get(var).cloned();
+    let mut res = Ok(Flow::Next);
+    // Each value is computed from the start rather than by adding up steps,
+    // so fractional steps don't drift.
+    for i in 0.. {
+        let val = env.precision.round(start + i as f64 * step);
+        if (step > 0.0 && val > end) || (step < 0.0 && val < end) {
+            break;
+        }
+        env.vars.insert(var.to_string(), Expression::Float(val));
+        res = execute_block(block, turtle, env);
+        if !matches!(res, Ok(Flow::Next)) {
+            break;
+        }
+    }
+    match prev {
+        Some(prev) => env.vars.insert(var.to_string(), prev),
+        None => env.vars.remove(var),
+    };
+
+    res
+}
+
+/// Executes a `FOREACH` block once for each item of a list, or each
+/// character of a word, with `:?` set to the item.
+///
+/// # Examples
+///
+/// ```rust
+/// let list = Expression::List(vec![Expression::Float(10.0), Expression::Float(20.0)]);
+/// let block = vec![ASTNode::Command(Command::Forward(Expression::Variable("?".to_string())))];
+///
+/// eval_exec_foreach(&list, &block, &mut turtle, &mut env).unwrap();
+/// assert_eq!(turtle.y, 20.0);
+/// ```
+pub fn eval_exec_foreach(
+    list: &Expression,
+    block: &Vec<ASTNode>,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<Flow, ExecutionError> {
+    let list = match_value(list, env, turtle)?;
+    for item in list_items(&list) {
+        let res = with_item(item, env, |env| execute_block(block, turtle, env));
+        if !matches!(res, Ok(Flow::Next)) {
+            return res;
+        }
+    }
+
+    Ok(Flow::Next)
+}
+
+/// Executes the block of a `CATCH`, carrying on after it if a `THROW` of the
+/// same tag is reached inside. `CATCH "ERROR` also catches any error that
+/// `--keep-going` could skip, so the script can recover from it instead.
+///
+/// # Examples
+///
+/// ```rust
+/// let block = vec![ASTNode::ControlFlow(ControlFlow::Throw("DONE".to_string()))];
+///
+/// let flow = eval_exec_catch("DONE", &block, &mut turtle, &mut env).unwrap();
+/// assert_eq!(flow, Flow::Next);
+/// ```
+pub fn eval_exec_catch(
+    tag: &str,
+    block: &Vec<ASTNode>,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<Flow, ExecutionError> {
+    // Errors inside the block are caught here rather than skipped.
+    let keep_going = std::mem::replace(&mut env.keep_going, false);
+    let res = execute_block(block, turtle, env);
+    env.keep_going = keep_going;
 
-        exec = should_execute(condition, turtle, vars)?;
+    match res {
+        Err(ExecutionError {
+            kind: ExecutionErrorKind::Thrown { tag: thrown },
+        }) if thrown == tag => Ok(Flow::Next),
+        Err(ExecutionError {
+            kind: ExecutionErrorKind::Thrown { .. },
+        }) => res,
+        Err(e) if tag == "ERROR" && e.kind.is_recoverable() => Ok(Flow::Next),
+        res => res,
     }
+}
 
-    Ok(())
+/// Runs `run` with `:?` set to `item`, as for each item of a `FOREACH` or
+/// `MAP`, putting back any `?` variable from outside afterwards.
+pub fn with_item<T>(
+    item: Expression,
+    env: &mut Environment,
+    run: impl FnOnce(&mut Environment) -> Result<T, ExecutionError>,
+) -> Result<T, ExecutionError> {
+    let prev = env.vars.insert("?".to_string(), item);
+    let res = run(env);
+    match prev {
+        Some(prev) => env.vars.insert("?".to_string(), prev),
+        None => env.vars.remove("?"),
+    };
+    res
+}
+
+/// Calls a procedure as a statement, binding the evaluated arguments to its parameters
+/// before executing its body.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut env = Environment::default();
+/// env.procedures.insert(
+///     "STEP".to_string(),
+///     Procedure {
+///         name: "STEP".to_string(),
+///         params: vec!["SIZE".to_string()],
+///         block: vec![ASTNode::Command(Command::Forward(Expression::Variable(
+///             "SIZE".to_string(),
+///         )))],
+///     },
+/// );
+/// let mut canvas = Canvas::new(100, 100);
+/// let mut turtle = Turtle::new(&mut canvas);
+///
+/// let args = vec![Expression::Float(10.0)];
+/// let res = eval_exec_call("STEP", &args, &mut turtle, &mut env);
+/// assert!(res.is_ok());
+/// ```
+pub fn eval_exec_call(
+    name: &str,
+    args: &[Expression],
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<Flow, ExecutionError> {
+    // A `STOP` or `OUTPUT` only leaves the procedure it was reached in, and
+    // the value is dropped when the call is a statement.
+    call_procedure(name, args, turtle, env)?;
+    Ok(Flow::Next)
+}
+
+/// Calls a procedure as `eval_exec_call` does, returning the value it gave
+/// with `OUTPUT`, if any.
+pub fn call_procedure(
+    name: &str,
+    args: &[Expression],
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<Option<Expression>, ExecutionError> {
+    let procedure = env.procedures.get(name).cloned().ok_or(ExecutionError {
+        kind: ExecutionErrorKind::ProcedureNotFound {
+            name: name.to_string(),
+        },
+    })?;
+    env.stats.calls += 1;
+    let _span = tracing::info_span!("procedure", name).entered();
+
+    // Arguments are evaluated before any are bound, so that they are all
+    // evaluated in the caller's environment.
+    let vals = args
+        .iter()
+        .map(|arg| match_value(arg, env, turtle))
+        .collect::<Result<Vec<Expression>, ExecutionError>>()?;
+
+    // Parameters are only bound for the duration of the call.
+    env.push_frame();
+    for (param, val) in procedure.params.iter().zip(vals) {
+        env.bind_local(param, val);
+    }
+
+    if let Some(profile) = env.profile.as_mut() {
+        profile.enter(name, Instant::now());
+    }
+    let res = execute_block(&procedure.block, turtle, env);
+    if let Some(profile) = env.profile.as_mut() {
+        profile.exit(Instant::now());
+    }
+    env.pop_frame();
+    res.map(|flow| match flow {
+        Flow::Output(val) => Some(val),
+        Flow::Next | Flow::Stop => None,
+    })
+}
+
+/// Evaluates the condition of an `ASSERT`, failing with the message and the
+/// values of the variables the condition refers to if it is false.
+///
+/// # Examples
+///
+/// ```rust
+/// let condition = Condition::LessThan(
+///     Expression::Variable("X".to_string()),
+///     Expression::Float(100.0),
+/// );
+///
+/// let res = eval_exec_assert(&condition, "inside the box", &turtle, &env);
+/// assert!(res.is_ok());
+/// ```
+pub fn eval_exec_assert(
+    condition: &Condition,
+    msg: &str,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<(), ExecutionError> {
+    if should_execute(condition, turtle, env)? {
+        return Ok(());
+    }
+
+    let (lhs, rhs) = condition.operands();
+    let mut names = lhs.variables();
+    for name in rhs.variables() {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    let vars = names
+        .into_iter()
+        .map(|name| {
+            let val = match_value(&Expression::Variable(name.to_string()), env, turtle)?;
+            Ok((name.to_string(), val))
+        })
+        .collect::<Result<Vec<(String, Expression)>, ExecutionError>>()?;
+
+    Err(ExecutionError {
+        kind: ExecutionErrorKind::AssertionFailed {
+            msg: msg.to_string(),
+            vars,
+        },
+    })
+}
+
+/// Executes a nested block one level deeper than the current one, so that
+/// `--max-depth` can stop runaway nesting and recursion. Variables made with
+/// `LOCAL` inside the block are dropped when it ends.
+fn execute_block(
+    block: &Vec<ASTNode>,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<Flow, ExecutionError> {
+    env.limits.enter()?;
+    env.push_frame();
+    let res = execute(block, turtle, env);
+    env.pop_frame();
+    env.limits.exit();
+    res
 }
 
 /// Determines if the condition is true or not.
@@ -126,92 +516,98 @@ pub fn eval_exec_while(
 /// # Examples
 ///
 /// ```rust
-/// use std::collections::HashMap;
 /// use turtle::Turtle;
 /// use parser::ast::{Condition, Expression};
 /// use interpreter::control_flows::should_execute;
 /// use interpreter::errors::ExecutionError;
 ///
-/// let mut vars: HashMap<String, Expression> = HashMap::new();
-/// let mut image = Image::new(100, 100);
-/// let mut turtle = Turtle::new(&mut image);
+/// let env = Environment::default();
+/// let mut canvas = Canvas::new(100, 100);
+/// let mut turtle = Turtle::new(&mut canvas);
 /// let condition = Condition::LessThan(
 ///     Expression::Float(8.0),
 ///     Expression::Float(10.0),
 /// );
 ///
-/// let res = should_execute(&condition, &turtle, &vars).unwrap();
+/// let res = should_execute(&condition, &turtle, &env).unwrap();
 /// assert!(res);
 /// ```
 fn should_execute(
     condition: &Condition,
-    turtle: &Turtle,
-    vars: &HashMap<String, Expression>,
+    turtle: &mut Turtle,
+    env: &mut Environment,
 ) -> Result<bool, ExecutionError> {
     match condition {
-        Condition::Equals(lhs, rhs) => comparator(lhs, rhs, |a, b| a == b, turtle, vars),
-        Condition::LessThan(lhs, rhs) => comparator(lhs, rhs, |a, b| a < b, turtle, vars),
-        Condition::GreaterThan(lhs, rhs) => comparator(lhs, rhs, |a, b| a > b, turtle, vars),
-        Condition::And(lhs, rhs) => comparator(lhs, rhs, |a, b| a != 0.0 && b != 0.0, turtle, vars),
-        Condition::Or(lhs, rhs) => comparator(lhs, rhs, |a, b| a != 0.0 || b != 0.0, turtle, vars),
+        Condition::Equals(lhs, rhs) => comparator(lhs, rhs, |a, b| a == b, turtle, env),
+        Condition::LessThan(lhs, rhs) => comparator(lhs, rhs, |a, b| a < b, turtle, env),
+        Condition::GreaterThan(lhs, rhs) => comparator(lhs, rhs, |a, b| a > b, turtle, env),
+        Condition::LessEqual(lhs, rhs) => comparator(lhs, rhs, |a, b| a <= b, turtle, env),
+        Condition::GreaterEqual(lhs, rhs) => comparator(lhs, rhs, |a, b| a >= b, turtle, env),
+        Condition::And(lhs, rhs) => comparator(lhs, rhs, |a, b| a != 0.0 && b != 0.0, turtle, env),
+        Condition::Or(lhs, rhs) => comparator(lhs, rhs, |a, b| a != 0.0 || b != 0.0, turtle, env),
+        Condition::Xor(lhs, rhs) => {
+            comparator(lhs, rhs, |a, b| (a != 0.0) != (b != 0.0), turtle, env)
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use crate::canvas::Canvas;
 
-    use unsvg::Image;
-
-    use crate::ast::{ASTNode, Command, Condition, Expression};
+    use crate::ast::{
+        ASTNode, Command, Condition, ControlFlow, Expression, Function, ListOp, Math, Procedure,
+        Query,
+    };
 
     use super::*;
 
     #[test]
     fn test_comparator() {
-        let vars: HashMap<String, Expression> = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let lhs = Expression::Float(8.0);
         let rhs = Expression::Float(10.0);
 
-        let res = comparator(&lhs, &rhs, |a, b| a < b, &turtle, &vars).unwrap();
+        let res = comparator(&lhs, &rhs, |a, b| a < b, &mut turtle, &mut env).unwrap();
         assert!(res);
     }
 
     #[test]
     fn test_if_true() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let condition = Condition::Equals(Expression::Float(1.0), Expression::Float(1.0));
         let block = vec![ASTNode::Command(Command::PenDown)];
 
-        let res = eval_exec_if(&condition, &block, &mut turtle, &mut vars);
+        let res = eval_exec_if(&condition, &block, &mut turtle, &mut env);
         assert!(res.is_ok());
         assert!(turtle.pen_down);
     }
 
     #[test]
     fn test_if_false() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let condition = Condition::Equals(Expression::Float(1.0), Expression::Float(2.0));
         let block = vec![ASTNode::Command(Command::PenDown)];
 
-        let res = eval_exec_if(&condition, &block, &mut turtle, &mut vars);
+        let res = eval_exec_if(&condition, &block, &mut turtle, &mut env);
         assert!(res.is_ok());
         assert!(!turtle.pen_down);
     }
 
     #[test]
     fn test_while_executes_correctly() {
-        let mut vars = HashMap::new();
-        vars.insert("counter".to_string(), Expression::Float(0.0));
+        let mut env = Environment::default();
+        env.vars
+            .insert("counter".to_string(), Expression::Float(0.0));
 
         let condition = Condition::LessThan(
             Expression::Variable("counter".to_string()),
@@ -227,18 +623,18 @@ mod tests {
             )),
         ];
 
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
         turtle.pen_down = true;
 
-        let result = eval_exec_while(&condition, &block, &mut turtle, &mut vars);
+        let result = eval_exec_while(&condition, &block, &mut turtle, &mut env);
         assert!(result.is_ok());
 
         // Check if turtle has moved correctly and counter variable has increased
         assert_eq!(turtle.y, 20.0);
         assert_eq!(turtle.x, 80.0);
 
-        match vars.get("counter") {
+        match env.vars.get("counter") {
             Some(Expression::Float(val)) => assert_eq!(*val, 3.0),
             _ => panic!("Counter variable was not incremented correctly"),
         }
@@ -246,8 +642,9 @@ mod tests {
 
     #[test]
     fn test_while_does_not_execute() {
-        let mut vars = HashMap::new();
-        vars.insert("counter".to_string(), Expression::Float(3.0));
+        let mut env = Environment::default();
+        env.vars
+            .insert("counter".to_string(), Expression::Float(3.0));
 
         let condition = Condition::LessThan(
             Expression::Variable("counter".to_string()),
@@ -263,55 +660,506 @@ mod tests {
             )),
         ];
 
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
         turtle.pen_down = true;
 
-        let result = eval_exec_while(&condition, &block, &mut turtle, &mut vars);
+        let result = eval_exec_while(&condition, &block, &mut turtle, &mut env);
         assert!(result.is_ok());
 
         // Check if turtle has moved correctly and counter variable has increased
         assert_eq!(turtle.y, 50.0);
         assert_eq!(turtle.x, 50.0);
 
-        match vars.get("counter") {
+        match env.vars.get("counter") {
             Some(Expression::Float(val)) => assert_eq!(*val, 3.0),
             _ => panic!("Counter variable was not incremented correctly"),
         }
     }
 
+    #[test]
+    fn test_do_while_and_until() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let condition =
+            Condition::LessThan(Expression::Query(Query::YCor), Expression::Float(10.0));
+        let block = vec![ASTNode::Command(Command::Forward(Expression::Float(10.0)))];
+
+        eval_exec_do_while(&block, &condition, &mut turtle, &mut env).unwrap();
+        assert_eq!(turtle.y, 40.0);
+
+        eval_exec_until(&condition, &block, &mut turtle, &mut env).unwrap();
+        assert_eq!(turtle.y, 0.0);
+
+        // Both run the block at least once, even when the loop would end
+        // straight away.
+        eval_exec_until(&condition, &block, &mut turtle, &mut env).unwrap();
+        assert_eq!(turtle.y, -10.0);
+    }
+
+    #[test]
+    fn test_repeat() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        let block = vec![
+            ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+            ASTNode::Command(Command::Turn(Expression::Number(90))),
+        ];
+        eval_exec_repeat(&Expression::Float(3.9), &block, &mut turtle, &mut env).unwrap();
+
+        assert_eq!((turtle.x, turtle.y, turtle.heading), (60.0, 50.0, 270.0));
+        assert_eq!(env.limits.depth, 0);
+
+        let res = eval_exec_repeat(&Expression::Float(-1.0), &block, &mut turtle, &mut env);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_for() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let block = vec![ASTNode::Command(Command::Forward(Expression::Variable(
+            "I".to_string(),
+        )))];
+
+        let range = (&Expression::Float(1.0), &Expression::Float(3.0), None);
+        eval_exec_for("I", range, &block, &mut turtle, &mut env).unwrap();
+        assert_eq!(turtle.y, 44.0);
+        assert_eq!(env.vars.get("I"), None);
+
+        env.vars.insert("I".to_string(), Expression::Float(7.0));
+        let step = Expression::Float(-0.5);
+        let range = (
+            &Expression::Float(2.0),
+            &Expression::Float(1.0),
+            Some(&step),
+        );
+        eval_exec_for("I", range, &block, &mut turtle, &mut env).unwrap();
+        assert_eq!(turtle.y, 39.5);
+        assert_eq!(env.vars.get("I"), Some(&Expression::Float(7.0)));
+
+        let step = Expression::Float(0.0);
+        let range = (
+            &Expression::Float(1.0),
+            &Expression::Float(2.0),
+            Some(&step),
+        );
+        assert!(eval_exec_for("I", range, &block, &mut turtle, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_foreach() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let block = vec![ASTNode::Command(Command::Forward(Expression::Variable(
+            "?".to_string(),
+        )))];
+
+        let list = Expression::List(vec![Expression::Float(1.0), Expression::Float(2.0)]);
+        eval_exec_foreach(&list, &block, &mut turtle, &mut env).unwrap();
+        assert_eq!(turtle.y, 47.0);
+        assert_eq!(env.vars.get("?"), None);
+
+        // Words are gone through a character at a time.
+        env.vars.insert("?".to_string(), Expression::Float(7.0));
+        let word = Expression::Word("34".to_string());
+        eval_exec_foreach(&word, &block, &mut turtle, &mut env).unwrap();
+        assert_eq!(turtle.y, 40.0);
+        assert_eq!(env.vars.get("?"), Some(&Expression::Float(7.0)));
+
+        let stop = vec![ASTNode::ControlFlow(ControlFlow::Stop)];
+        let flow = eval_exec_foreach(&list, &stop, &mut turtle, &mut env).unwrap();
+        assert_eq!(flow, Flow::Stop);
+    }
+
+    #[test]
+    fn test_catch() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let forward = ASTNode::Command(Command::Forward(Expression::Float(10.0)));
+        let throw = |tag: &str| ASTNode::ControlFlow(ControlFlow::Throw(tag.to_string()));
+
+        // The block stops at the THROW, and the script carries on after it.
+        let block = vec![forward.clone(), throw("DONE"), forward.clone()];
+        let flow = eval_exec_catch("DONE", &block, &mut turtle, &mut env).unwrap();
+        assert_eq!(flow, Flow::Next);
+        assert_eq!(turtle.y, 40.0);
+
+        let res = eval_exec_catch("ERROR", &block, &mut turtle, &mut env);
+        assert!(matches!(
+            res.unwrap_err().kind,
+            ExecutionErrorKind::Thrown { tag } if tag == "DONE"
+        ));
+
+        let divide = ASTNode::Command(Command::Forward(Expression::Math(Box::new(Math::Div(
+            Expression::Float(1.0),
+            Expression::Float(0.0),
+        )))));
+        let block = vec![divide.clone(), forward.clone()];
+        env.keep_going = true;
+        let flow = eval_exec_catch("ERROR", &block, &mut turtle, &mut env).unwrap();
+        assert_eq!(flow, Flow::Next);
+        assert!(env.keep_going);
+        assert!(env.warnings.is_empty());
+        assert_eq!(turtle.y, 30.0);
+
+        let res = eval_exec_catch("DONE", &block, &mut turtle, &mut env);
+        assert!(matches!(
+            res.unwrap_err().kind,
+            ExecutionErrorKind::DivisionByZero
+        ));
+    }
+
+    #[test]
+    fn test_call() {
+        let mut env = Environment::default();
+        env.procedures.insert(
+            "STEP".to_string(),
+            Procedure {
+                name: "STEP".to_string(),
+                params: vec!["SIZE".to_string()],
+                block: vec![ASTNode::Command(Command::Forward(Expression::Variable(
+                    "SIZE".to_string(),
+                )))],
+            },
+        );
+
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        let args = vec![Expression::Float(10.0)];
+        let res = eval_exec_call("STEP", &args, &mut turtle, &mut env);
+        assert!(res.is_ok());
+        assert_eq!(turtle.y, 40.0);
+    }
+
+    #[test]
+    fn test_call_binds_params_for_call() {
+        let mut env = Environment::default();
+        let n = || Expression::Variable("N".to_string());
+        // TO NEST "N  IF GT :N "0 [ NEST - :N "1 ]  FORWARD :N  END
+        env.procedures.insert(
+            "NEST".to_string(),
+            Procedure {
+                name: "NEST".to_string(),
+                params: vec!["N".to_string()],
+                block: vec![
+                    ASTNode::ControlFlow(ControlFlow::If {
+                        condition: Condition::GreaterThan(n(), Expression::Float(0.0)),
+                        block: vec![ASTNode::ControlFlow(ControlFlow::Call {
+                            name: "NEST".to_string(),
+                            args: vec![Expression::Math(Box::new(Math::Sub(
+                                n(),
+                                Expression::Float(1.0),
+                            )))],
+                        })],
+                    }),
+                    ASTNode::Command(Command::Forward(n())),
+                ],
+            },
+        );
+        env.vars.insert("N".to_string(), Expression::Float(99.0));
+
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        eval_exec_call("NEST", &[Expression::Float(3.0)], &mut turtle, &mut env).unwrap();
+
+        // Each call moves by its own N once the calls inside it return.
+        assert_eq!(turtle.y, 44.0);
+        assert_eq!(env.vars.get("N"), Some(&Expression::Float(99.0)));
+        assert!(env.frames.is_empty());
+
+        env.vars.remove("N");
+        eval_exec_call("NEST", &[Expression::Float(0.0)], &mut turtle, &mut env).unwrap();
+        assert_eq!(env.vars.get("N"), None);
+    }
+
+    #[test]
+    fn test_stop() {
+        let mut env = Environment::default();
+        // TO WALK  REPEAT "10 [ FORWARD "5  IF GT :STEPS "1 [ STOP ]  ADDASSIGN "STEPS "1 ]  END
+        env.procedures.insert(
+            "WALK".to_string(),
+            Procedure {
+                name: "WALK".to_string(),
+                params: vec![],
+                block: vec![ASTNode::ControlFlow(ControlFlow::Repeat {
+                    count: Expression::Float(10.0),
+                    block: vec![
+                        ASTNode::Command(Command::Forward(Expression::Float(5.0))),
+                        ASTNode::ControlFlow(ControlFlow::If {
+                            condition: Condition::GreaterThan(
+                                Expression::Variable("STEPS".to_string()),
+                                Expression::Float(1.0),
+                            ),
+                            block: vec![ASTNode::ControlFlow(ControlFlow::Stop)],
+                        }),
+                        ASTNode::Command(Command::AddAssign(
+                            "STEPS".to_string(),
+                            Expression::Float(1.0),
+                        )),
+                    ],
+                })],
+            },
+        );
+        env.vars.insert("STEPS".to_string(), Expression::Float(0.0));
+
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        // The STOP leaves the loop and the procedure, but not the caller.
+        let flow = eval_exec_call("WALK", &[], &mut turtle, &mut env).unwrap();
+        assert_eq!(flow, Flow::Next);
+        assert_eq!(turtle.y, 35.0);
+
+        let block = vec![
+            ASTNode::ControlFlow(ControlFlow::Stop),
+            ASTNode::Command(Command::Forward(Expression::Float(5.0))),
+        ];
+        let flow = eval_exec_repeat(&Expression::Float(3.0), &block, &mut turtle, &mut env);
+        assert_eq!(flow.unwrap(), Flow::Stop);
+        assert_eq!(turtle.y, 35.0);
+    }
+
+    #[test]
+    fn test_output() {
+        let mut env = Environment::default();
+        let n = || Expression::Variable("N".to_string());
+        // TO FACT "N  IF LT :N "2 [ OUTPUT "1 ]  OUTPUT * :N FACT - :N "1  END
+        env.procedures.insert(
+            "FACT".to_string(),
+            Procedure {
+                name: "FACT".to_string(),
+                params: vec!["N".to_string()],
+                block: vec![
+                    ASTNode::ControlFlow(ControlFlow::If {
+                        condition: Condition::LessThan(n(), Expression::Float(2.0)),
+                        block: vec![ASTNode::ControlFlow(ControlFlow::Output(
+                            Expression::Float(1.0),
+                        ))],
+                    }),
+                    ASTNode::ControlFlow(ControlFlow::Output(Expression::Math(Box::new(
+                        Math::Mul(
+                            n(),
+                            Expression::Call(
+                                "FACT".to_string(),
+                                vec![Expression::Math(Box::new(Math::Sub(
+                                    n(),
+                                    Expression::Float(1.0),
+                                )))],
+                            ),
+                        ),
+                    )))),
+                ],
+            },
+        );
+        env.procedures.insert(
+            "NOTHING".to_string(),
+            Procedure {
+                name: "NOTHING".to_string(),
+                params: vec![],
+                block: vec![ASTNode::ControlFlow(ControlFlow::Stop)],
+            },
+        );
+
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        let fact = Expression::Call("FACT".to_string(), vec![Expression::Float(4.0)]);
+        assert_eq!(
+            match_expressions(&fact, &mut env, &mut turtle).unwrap(),
+            24.0
+        );
+        assert!(env.frames.is_empty());
+
+        // As a statement, the value is dropped.
+        let res = eval_exec_call("FACT", &[Expression::Float(3.0)], &mut turtle, &mut env);
+        assert_eq!(res.unwrap(), Flow::Next);
+
+        let nothing = Expression::Call("NOTHING".to_string(), vec![]);
+        let err = match_expressions(&nothing, &mut env, &mut turtle).unwrap_err();
+        assert!(matches!(err.kind, ExecutionErrorKind::NoOutput { .. }));
+    }
+
+    #[test]
+    fn test_call_not_found() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        let res = eval_exec_call("MISSING", &[], &mut turtle, &mut env);
+        assert!(res.is_err());
+    }
+
     #[test]
     fn test_should_execute_gt() {
-        let vars: HashMap<String, Expression> = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let condition = Condition::GreaterThan(Expression::Float(8.0), Expression::Float(10.0));
-        let res = should_execute(&condition, &turtle, &vars).unwrap();
+        let res = should_execute(&condition, &mut turtle, &mut env).unwrap();
         assert!(!res);
     }
 
+    #[test]
+    fn test_should_execute_le_ge() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        let condition = Condition::LessEqual(Expression::Float(10.0), Expression::Float(10.0));
+        assert!(should_execute(&condition, &mut turtle, &mut env).unwrap());
+
+        let condition = Condition::GreaterEqual(Expression::Float(8.0), Expression::Float(10.0));
+        assert!(!should_execute(&condition, &mut turtle, &mut env).unwrap());
+    }
+
     #[test]
     fn test_should_execute_and() {
-        let vars: HashMap<String, Expression> = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let condition = Condition::And(Expression::Float(1.0), Expression::Float(0.0));
 
-        let res = should_execute(&condition, &turtle, &vars).unwrap();
+        let res = should_execute(&condition, &mut turtle, &mut env).unwrap();
         assert!(!res);
     }
 
     #[test]
     fn test_should_execute_or() {
-        let vars: HashMap<String, Expression> = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let condition = Condition::Or(Expression::Float(1.0), Expression::Float(0.0));
 
-        let res = should_execute(&condition, &turtle, &vars).unwrap();
+        let res = should_execute(&condition, &mut turtle, &mut env).unwrap();
         assert!(res);
     }
+
+    #[test]
+    fn test_should_execute_xor() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        let condition = Condition::Xor(Expression::Float(1.0), Expression::Float(1.0));
+        assert!(!should_execute(&condition, &mut turtle, &mut env).unwrap());
+
+        let condition = Condition::Xor(Expression::Float(0.0), Expression::Float(1.0));
+        assert!(should_execute(&condition, &mut turtle, &mut env).unwrap());
+    }
+
+    #[test]
+    fn test_call_max_depth() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        env.limits.max_depth = Some(5);
+
+        // A procedure that calls itself forever.
+        env.procedures.insert(
+            "LOOP".to_string(),
+            Procedure {
+                name: "LOOP".to_string(),
+                params: vec![],
+                block: vec![ASTNode::ControlFlow(ControlFlow::Call {
+                    name: "LOOP".to_string(),
+                    args: vec![],
+                })],
+            },
+        );
+
+        let res = eval_exec_call("LOOP", &[], &mut turtle, &mut env);
+
+        assert!(matches!(
+            res,
+            Err(ExecutionError {
+                kind: ExecutionErrorKind::DepthLimit { depth: 6, limit: 5 }
+            })
+        ));
+        assert_eq!(env.limits.depth, 0);
+    }
+
+    #[test]
+    fn test_eval_exec_assert() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        env.vars.insert("X".to_string(), Expression::Float(120.0));
+        env.vars
+            .insert("LIMIT".to_string(), Expression::Float(100.0));
+
+        let condition = Condition::LessThan(
+            Expression::Variable("X".to_string()),
+            Expression::Math(Box::new(Math::Add(
+                Expression::Variable("LIMIT".to_string()),
+                Expression::Variable("X".to_string()),
+            ))),
+        );
+        assert!(eval_exec_assert(&condition, "ok", &mut turtle, &mut env).is_ok());
+
+        let condition = Condition::LessThan(
+            Expression::Variable("X".to_string()),
+            Expression::Variable("LIMIT".to_string()),
+        );
+        let res = eval_exec_assert(&condition, "inside the box", &mut turtle, &mut env);
+        let Err(ExecutionError {
+            kind: ExecutionErrorKind::AssertionFailed { msg, vars },
+        }) = res
+        else {
+            panic!("expected an assertion failure, got {:?}", res);
+        };
+        assert_eq!(msg, "inside the box");
+        assert_eq!(
+            vars,
+            vec![
+                ("X".to_string(), Expression::Float(120.0)),
+                ("LIMIT".to_string(), Expression::Float(100.0))
+            ]
+        );
+
+        // Variables inside functions and calls are reported too.
+        let condition = Condition::LessThan(
+            Expression::Function(Box::new(Function::Abs(Expression::Variable(
+                "X".to_string(),
+            )))),
+            Expression::Float(3.0),
+        );
+        let res = eval_exec_assert(&condition, "too big", &mut turtle, &mut env);
+        let Err(ExecutionError {
+            kind: ExecutionErrorKind::AssertionFailed { vars, .. },
+        }) = res
+        else {
+            panic!("expected an assertion failure, got {:?}", res);
+        };
+        assert_eq!(vars, vec![("X".to_string(), Expression::Float(120.0))]);
+
+        // Lists are reported as they are rather than failing to convert.
+        let list = Expression::List(vec![Expression::Word("a".to_string())]);
+        env.vars.insert("L".to_string(), list.clone());
+        let condition = Condition::Equals(
+            Expression::ListOp(Box::new(ListOp::Count(Expression::Variable(
+                "L".to_string(),
+            )))),
+            Expression::Float(3.0),
+        );
+        let res = eval_exec_assert(&condition, "three items", &mut turtle, &mut env);
+        let Err(ExecutionError {
+            kind: ExecutionErrorKind::AssertionFailed { vars, .. },
+        }) = res
+        else {
+            panic!("expected an assertion failure, got {:?}", res);
+        };
+        assert_eq!(vars, vec![("L".to_string(), list)]);
+    }
 }
@@ -0,0 +1,161 @@
+//! Line coverage recorded with `--coverage`.
+//!
+//! While coverage is on, the parser places an `ASTNode::Line` marker before
+//! each statement, naming the script and line it started on, and the
+//! interpreter counts the markers it passes. Tokens are slices of the script
+//! they came from, so the line of a statement is found from where its first
+//! token sits in the script, without the tokeniser having to track lines.
+//!
+//! Lines without a statement, such as blank lines, comments and `TO` and
+//! `END`, aren't counted, and neither are statements from `INCLUDE`d
+//! libraries.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+struct CoveredScript {
+    path: PathBuf,
+    /// The address of the script's text while it was being parsed.
+    start: usize,
+    len: usize,
+    /// The byte offset each line starts at.
+    line_starts: Vec<usize>,
+    lines: Vec<String>,
+    /// How many times statements on each line ran, for lines with statements.
+    hits: BTreeMap<usize, usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct Coverage {
+    scripts: Vec<CoveredScript>,
+}
+
+impl Coverage {
+    /// Adds a script that is about to be parsed.
+    pub fn add_script(&mut self, path: &Path, source: &str) {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        self.scripts.push(CoveredScript {
+            path: path.to_path_buf(),
+            start: source.as_ptr() as usize,
+            len: source.len(),
+            line_starts,
+            lines: source.lines().map(str::to_string).collect(),
+            hits: BTreeMap::new(),
+        });
+    }
+
+    /// Finds the script and 1-based line a token came from, and marks the line
+    /// as having a statement. Returns `None` for tokens from anywhere else,
+    /// such as a library.
+    pub fn locate(&mut self, token: &str) -> Option<(usize, usize)> {
+        let addr = token.as_ptr() as usize;
+        // Scripts are searched newest first, as an earlier script's text may
+        // have been freed and its memory reused for the one being parsed.
+        let (index, script) = self
+            .scripts
+            .iter_mut()
+            .enumerate()
+            .rev()
+            .find(|(_, script)| (script.start..script.start + script.len).contains(&addr))?;
+
+        let offset = addr - script.start;
+        let line = script.line_starts.partition_point(|&start| start <= offset);
+        script.hits.entry(line).or_insert(0);
+        Some((index, line))
+    }
+
+    /// Counts a statement on the given line running.
+    pub fn hit(&mut self, script: usize, line: usize) {
+        if let Some(hits) = self
+            .scripts
+            .get_mut(script)
+            .and_then(|script| script.hits.get_mut(&line))
+        {
+            *hits += 1;
+        }
+    }
+
+    /// The coverage as an lcov tracefile, as read by `genhtml` and most
+    /// coverage viewers.
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::new();
+        for script in &self.scripts {
+            writeln!(lcov, "SF:{}", script.path.display()).unwrap();
+            for (line, hits) in &script.hits {
+                writeln!(lcov, "DA:{},{}", line, hits).unwrap();
+            }
+            let hit = script.hits.values().filter(|hits| **hits > 0).count();
+            writeln!(lcov, "LH:{}", hit).unwrap();
+            writeln!(lcov, "LF:{}", script.hits.len()).unwrap();
+            writeln!(lcov, "end_of_record").unwrap();
+        }
+        lcov
+    }
+
+    /// The scripts annotated with how many times each line ran, in the style
+    /// of `gcov`: `-` marks lines without statements and `#####` lines with
+    /// statements that never ran.
+    pub fn to_listing(&self) -> String {
+        let mut listing = String::new();
+        for script in &self.scripts {
+            writeln!(
+                listing,
+                "{:>9}:{:>5}:Source:{}",
+                "-",
+                0,
+                script.path.display()
+            )
+            .unwrap();
+            for (i, text) in script.lines.iter().enumerate() {
+                let count = match script.hits.get(&(i + 1)) {
+                    None => "-".to_string(),
+                    Some(0) => "#####".to_string(),
+                    Some(hits) => hits.to_string(),
+                };
+                writeln!(listing, "{:>9}:{:>5}:{}", count, i + 1, text).unwrap();
+            }
+        }
+        listing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        let source = "PENDOWN\n\nIF EQ \"1 \"0 [\n  FORWARD \"10\n]\n";
+        let mut coverage = Coverage::default();
+        coverage.add_script(Path::new("a.lg"), source);
+
+        assert_eq!(coverage.locate(&source[0..7]), Some((0, 1)));
+        assert_eq!(coverage.locate(&source[9..11]), Some((0, 3)));
+        assert_eq!(coverage.locate(&source[25..32]), Some((0, 4)));
+        assert_eq!(coverage.locate("PENDOWN"), None);
+
+        coverage.hit(0, 1);
+        coverage.hit(0, 3);
+        coverage.hit(0, 3);
+
+        assert_eq!(
+            coverage.to_lcov(),
+            "SF:a.lg\nDA:1,1\nDA:3,2\nDA:4,0\nLH:2\nLF:3\nend_of_record\n"
+        );
+        assert_eq!(
+            coverage.to_listing(),
+            "        -:    0:Source:a.lg\n\
+             \x20       1:    1:PENDOWN\n\
+             \x20       -:    2:\n\
+             \x20       2:    3:IF EQ \"1 \"0 [\n\
+             \x20   #####:    4:  FORWARD \"10\n\
+             \x20       -:    5:]\n"
+        );
+    }
+}
@@ -1,8 +1,91 @@
+use crate::ast::Expression;
+
 #[derive(Debug)]
 pub enum ExecutionErrorKind {
     DivisionByZero,
-    VariableNotFound { var: String },
-    TypeError { expected: String },
+    VariableNotFound {
+        var: String,
+    },
+    TypeError {
+        expected: String,
+    },
+    ProcedureNotFound {
+        name: String,
+    },
+    InvalidArgument {
+        msg: String,
+    },
+    /// No query provider answered `QUERY "name`.
+    QueryNotFound {
+        name: String,
+    },
+    /// `JUMPTO` named a mark that was never set with `MARK`.
+    MarkNotFound {
+        name: String,
+    },
+    /// A procedure used as a value finished without an `OUTPUT`.
+    NoOutput {
+        name: String,
+    },
+    Io {
+        msg: String,
+    },
+    MemoryLimit {
+        limit: usize,
+        ast_nodes: usize,
+        vars: usize,
+        elements: usize,
+    },
+    DepthLimit {
+        depth: usize,
+        limit: usize,
+    },
+    /// An expression evaluated to NaN or an infinity.
+    NonFinite {
+        expr: String,
+        value: f64,
+    },
+    /// An `ASSERT` condition was false. Holds the values of the variables the
+    /// condition referred to.
+    AssertionFailed {
+        msg: String,
+        vars: Vec<(String, Expression)>,
+    },
+    /// A `THROW "tag` with no `CATCH "tag` around it.
+    Thrown {
+        tag: String,
+    },
+    /// A command would have moved the turtle off the canvas in fence mode.
+    OutOfBounds {
+        command: String,
+        x: f64,
+        y: f64,
+    },
+}
+
+impl ExecutionErrorKind {
+    /// Whether `--keep-going` can skip the statement that caused the error
+    /// and carry on. Exceeded limits, I/O errors and failed assertions always
+    /// stop the script.
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            ExecutionErrorKind::DivisionByZero
+            | ExecutionErrorKind::VariableNotFound { .. }
+            | ExecutionErrorKind::TypeError { .. }
+            | ExecutionErrorKind::ProcedureNotFound { .. }
+            | ExecutionErrorKind::InvalidArgument { .. }
+            | ExecutionErrorKind::QueryNotFound { .. }
+            | ExecutionErrorKind::MarkNotFound { .. }
+            | ExecutionErrorKind::NoOutput { .. }
+            | ExecutionErrorKind::NonFinite { .. }
+            | ExecutionErrorKind::Thrown { .. }
+            | ExecutionErrorKind::OutOfBounds { .. } => true,
+            ExecutionErrorKind::Io { .. }
+            | ExecutionErrorKind::MemoryLimit { .. }
+            | ExecutionErrorKind::DepthLimit { .. }
+            | ExecutionErrorKind::AssertionFailed { .. } => false,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -24,6 +107,71 @@ impl std::fmt::Display for ExecutionError {
             ExecutionErrorKind::TypeError { expected } => {
                 write!(f, "Type error: expected '{}'", expected)
             }
+            ExecutionErrorKind::ProcedureNotFound { name } => {
+                write!(f, "Procedure not found: '{}'", name)
+            }
+            ExecutionErrorKind::InvalidArgument { msg } => {
+                write!(f, "Invalid argument: {}", msg)
+            }
+            ExecutionErrorKind::QueryNotFound { name } => {
+                write!(f, "Query not found: '{}'", name)
+            }
+            ExecutionErrorKind::MarkNotFound { name } => {
+                write!(f, "Mark not found: '{}'", name)
+            }
+            ExecutionErrorKind::NoOutput { name } => {
+                write!(f, "Procedure '{}' did not OUTPUT a value", name)
+            }
+            ExecutionErrorKind::Io { msg } => {
+                write!(f, "I/O error: {}", msg)
+            }
+            ExecutionErrorKind::MemoryLimit {
+                limit,
+                ast_nodes,
+                vars,
+                elements,
+            } => {
+                write!(
+                    f,
+                    "Memory limit of {} bytes exceeded with {} AST nodes, {} variables and {} shapes",
+                    limit, ast_nodes, vars, elements
+                )
+            }
+            ExecutionErrorKind::DepthLimit { depth, limit } => {
+                write!(
+                    f,
+                    "Maximum depth of {} exceeded: blocks and procedure calls nested {} deep",
+                    limit, depth
+                )
+            }
+            ExecutionErrorKind::NonFinite { expr, value } => {
+                write!(f, "Expression '{}' evaluated to {}", expr, value)
+            }
+            ExecutionErrorKind::AssertionFailed { msg, vars } => {
+                write!(f, "Assertion failed: {}", msg)?;
+                if !vars.is_empty() {
+                    let vars = vars
+                        .iter()
+                        .map(|(var, val)| match val {
+                            // Numbers are written without a word's quote.
+                            Expression::Float(val) => format!(":{} = {}", var, val),
+                            _ => format!(":{} = {}", var, val),
+                        })
+                        .collect::<Vec<String>>();
+                    write!(f, " ({})", vars.join(", "))?;
+                }
+                Ok(())
+            }
+            ExecutionErrorKind::Thrown { tag } => {
+                write!(f, "THROW \"{} was not caught", tag)
+            }
+            ExecutionErrorKind::OutOfBounds { command, x, y } => {
+                write!(
+                    f,
+                    "Turtle out of bounds: {} would move it to ({}, {}), outside the fence",
+                    command, x, y
+                )
+            }
         }
     }
 }
@@ -52,5 +200,103 @@ mod tests {
             },
         };
         assert_eq!(error.to_string(), "Type error: expected 'number'");
+
+        let error = ExecutionError {
+            kind: ExecutionErrorKind::ProcedureNotFound {
+                name: "BOX".to_string(),
+            },
+        };
+        assert_eq!(error.to_string(), "Procedure not found: 'BOX'");
+
+        let error = ExecutionError {
+            kind: ExecutionErrorKind::InvalidArgument {
+                msg: "POLYGON needs at least 3 sides".to_string(),
+            },
+        };
+        assert_eq!(
+            error.to_string(),
+            "Invalid argument: POLYGON needs at least 3 sides"
+        );
+
+        let error = ExecutionError {
+            kind: ExecutionErrorKind::QueryNotFound {
+                name: "mousex".to_string(),
+            },
+        };
+        assert_eq!(error.to_string(), "Query not found: 'mousex'");
+
+        let error = ExecutionError {
+            kind: ExecutionErrorKind::MarkNotFound {
+                name: "corner".to_string(),
+            },
+        };
+        assert_eq!(error.to_string(), "Mark not found: 'corner'");
+
+        let error = ExecutionError {
+            kind: ExecutionErrorKind::NoOutput {
+                name: "SQUARE".to_string(),
+            },
+        };
+        assert_eq!(
+            error.to_string(),
+            "Procedure 'SQUARE' did not OUTPUT a value"
+        );
+
+        let error = ExecutionError {
+            kind: ExecutionErrorKind::Io {
+                msg: "disk full".to_string(),
+            },
+        };
+        assert_eq!(error.to_string(), "I/O error: disk full");
+
+        let error = ExecutionError {
+            kind: ExecutionErrorKind::MemoryLimit {
+                limit: 1024,
+                ast_nodes: 3,
+                vars: 1,
+                elements: 20,
+            },
+        };
+        assert_eq!(
+            error.to_string(),
+            "Memory limit of 1024 bytes exceeded with 3 AST nodes, 1 variables and 20 shapes"
+        );
+
+        let error = ExecutionError {
+            kind: ExecutionErrorKind::DepthLimit {
+                depth: 101,
+                limit: 100,
+            },
+        };
+        assert_eq!(
+            error.to_string(),
+            "Maximum depth of 100 exceeded: blocks and procedure calls nested 101 deep"
+        );
+
+        let error = ExecutionError {
+            kind: ExecutionErrorKind::NonFinite {
+                expr: "/ :x \"0.5".to_string(),
+                value: f64::INFINITY,
+            },
+        };
+        assert_eq!(
+            error.to_string(),
+            "Expression '/ :x \"0.5' evaluated to inf"
+        );
+
+        let error = ExecutionError {
+            kind: ExecutionErrorKind::AssertionFailed {
+                msg: "inside the box".to_string(),
+                vars: vec![
+                    ("X".to_string(), Expression::Float(120.0)),
+                    ("SIZE".to_string(), Expression::Float(2.5)),
+                    ("NAME".to_string(), Expression::Word("box".to_string())),
+                ],
+            },
+        };
+        assert_eq!(
+            error.to_string(),
+            "Assertion failed: inside the box (:X = 120, :SIZE = 2.5, :NAME = \"box)"
+        );
     }
 }
@@ -1,313 +1,1078 @@
 //! Handles the execution of the parsed AST and draws the image using the
 //! turtle.
 
-use std::collections::HashMap;
+use unsvg::Color;
 
-use crate::ast::{ASTNode, Command, ControlFlow, Expression, Query};
+use crate::{
+    ast::{ASTNode, Command, ControlFlow, Expression, Query},
+    canvas::{hsv_to_color, Clip, Effect},
+    environment::Environment,
+};
 
 use super::{
-    control_flows::{eval_exec_if, eval_exec_while},
+    control_flows::{
+        eval_exec_assert, eval_exec_call, eval_exec_catch, eval_exec_do_while, eval_exec_for,
+        eval_exec_foreach, eval_exec_if, eval_exec_repeat, eval_exec_until, eval_exec_while,
+    },
     errors::{ExecutionError, ExecutionErrorKind},
-    matches::match_expressions,
+    log::TurtleState,
+    matches::{match_expressions, match_value, match_word, pen_color_value},
+    random::Rng,
+    screen::ScreenMode,
     turtle::Turtle,
 };
 
-/// Executes the parsed AST and draws on the image using the turtle.
+/// Whether execution carries on after a statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Flow {
+    /// Carry on with the next statement.
+    Next,
+    /// A `STOP` was reached, so the blocks up to the enclosing procedure
+    /// call are abandoned.
+    Stop,
+    /// An `OUTPUT` was reached, leaving the procedure like `STOP` but with a
+    /// value for the caller, which may be a word or a list as well as a number.
+    Output(Expression),
+}
+
+/// Executes the parsed AST and draws on the image using the turtle, returning
+/// how it was cut short if a `STOP` or `OUTPUT` was reached.
 ///
 /// # Examples
 ///
 /// ```rust
-/// use std::collections::HashMap;
-/// use unsvg::Image;
+/// use crate::canvas::Canvas;
 /// use super::*;
 ///
-/// let mut image = Image::new(100, 100);
-/// let mut turtle = Turtle::new(&mut image);
-/// let mut vars = HashMap::new();
+/// let mut canvas = Canvas::new(100, 100);
+/// let mut turtle = Turtle::new(&mut canvas);
+/// let mut env = Environment::default();
 ///
 /// let ast = vec![ASTNode::Command(Command::PenDown)];
-/// let res = execute(&ast, &mut turtle, &mut vars).unwrap();
+/// let flow = execute(&ast, &mut turtle, &mut env).unwrap();
 ///
 /// assert!(turte.pen_down);
-/// assert!(res.is_ok());
+/// assert_eq!(flow, Flow::Next);
 /// ```
 pub fn execute(
     ast: &Vec<ASTNode>,
     turtle: &mut Turtle,
-    vars: &mut HashMap<String, Expression>,
-) -> Result<(), ExecutionError> {
+    env: &mut Environment,
+) -> Result<Flow, ExecutionError> {
     for node in ast {
-        match node {
-            ASTNode::Command(command) => match command {
-                Command::PenDown => turtle.pen_down(),
-                Command::PenUp => turtle.pen_up(),
-                Command::Forward(expr) => {
-                    let dist = match_expressions(expr, vars, turtle)?;
-                    turtle.forward(dist);
-                }
-                Command::Back(expr) => {
-                    let dist = match_expressions(expr, vars, turtle)?;
-                    turtle.back(dist);
-                }
-                Command::Left(expr) => {
-                    let dist = match_expressions(expr, vars, turtle)?;
-                    turtle.left(dist);
-                }
-                Command::Right(expr) => {
-                    let dist = match_expressions(expr, vars, turtle)?;
-                    turtle.right(dist);
-                }
-                Command::SetPenColor(expr) => {
-                    let color = match_expressions(expr, vars, turtle)?;
-                    turtle.set_pen_color(color as usize)
-                }
-                Command::Turn(expr) => {
-                    let degs = match_expressions(expr, vars, turtle)?;
-                    turtle.turn(degs as i32);
+        match execute_node(node, turtle, env) {
+            Ok(Flow::Next) => {}
+            Ok(flow) => return Ok(flow),
+            // With `--keep-going`, the statement is skipped and the error
+            // reported once the script has finished.
+            Err(e) if env.keep_going && e.kind.is_recoverable() => env.warnings.push(e),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(Flow::Next)
+}
+
+/// Executes a single node of the AST.
+fn execute_node(
+    node: &ASTNode,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<Flow, ExecutionError> {
+    match node {
+        ASTNode::Command(command) => {
+            env.stats.commands += 1;
+
+            let before = env.log.is_some().then(|| {
+                let args = command
+                    .args()
+                    .into_iter()
+                    .map(|arg| {
+                        // Calls and random numbers are left out rather than
+                        // evaluated twice.
+                        if arg.has_side_effects() {
+                            return None;
+                        }
+                        match_expressions(arg, env, turtle)
+                            .ok()
+                            .map(|val| val as f32)
+                    })
+                    .collect::<Vec<Option<f32>>>();
+                (TurtleState::from(&*turtle), args)
+            });
+
+            execute_command(command, turtle, env)?;
+            if let Some((x, y)) = turtle.out_of_bounds.take() {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::OutOfBounds {
+                        command: command.keyword().to_string(),
+                        x,
+                        y,
+                    },
+                });
+            }
+            env.limits
+                .check_memory(env.vars.len(), turtle.canvas.elements.len())?;
+
+            if let (Some((before, args)), Some(log)) = (before, env.log.as_mut()) {
+                let after = TurtleState::from(&*turtle);
+                log.record(command, &args, &before, &after, &env.palette)
+                    .map_err(|e| ExecutionError {
+                        kind: ExecutionErrorKind::Io { msg: e.to_string() },
+                    })?;
+            }
+        }
+        ASTNode::ControlFlow(control_flow) => {
+            return match control_flow {
+                ControlFlow::If { condition, block } => eval_exec_if(condition, block, turtle, env),
+                ControlFlow::While { condition, block } => {
+                    eval_exec_while(condition, block, turtle, env)
                 }
-                Command::SetHeading(expr) => {
-                    let degs = match_expressions(expr, vars, turtle)?;
-                    turtle.set_heading(degs as i32);
+                ControlFlow::DoWhile { block, condition } => {
+                    eval_exec_do_while(block, condition, turtle, env)
                 }
-                Command::SetX(expr) => {
-                    let x = match_expressions(expr, vars, turtle)?;
-                    turtle.set_x(x);
+                ControlFlow::Until { condition, block } => {
+                    eval_exec_until(condition, block, turtle, env)
                 }
-                Command::SetY(expr) => {
-                    let y = match_expressions(expr, vars, turtle)?;
-                    turtle.set_y(y);
+                ControlFlow::For {
+                    var,
+                    start,
+                    end,
+                    step,
+                    block,
+                } => eval_exec_for(var, (start, end, step.as_ref()), block, turtle, env),
+                ControlFlow::Repeat { count, block } => eval_exec_repeat(count, block, turtle, env),
+                ControlFlow::ForEach { list, block } => eval_exec_foreach(list, block, turtle, env),
+                ControlFlow::Catch { tag, block } => eval_exec_catch(tag, block, turtle, env),
+                ControlFlow::Throw(tag) => Err(ExecutionError {
+                    kind: ExecutionErrorKind::Thrown { tag: tag.clone() },
+                }),
+                ControlFlow::Output(expr) => match_value(expr, env, turtle).map(Flow::Output),
+                ControlFlow::Stop => Ok(Flow::Stop),
+                ControlFlow::Call { name, args } => eval_exec_call(name, args, turtle, env),
+            };
+        }
+        ASTNode::Line { script, line } => {
+            if let Some(coverage) = env.coverage.as_mut() {
+                coverage.hit(*script, *line);
+            }
+        }
+    }
+
+    Ok(Flow::Next)
+}
+
+/// Executes a single command.
+fn execute_command(
+    command: &Command,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<(), ExecutionError> {
+    match command {
+        Command::PenDown => turtle.pen_down(),
+        Command::PenUp => turtle.pen_up(),
+        Command::PenPaint => turtle.set_pen_reverse(false),
+        Command::PenReverse => turtle.set_pen_reverse(true),
+        Command::ShowTurtle => turtle.set_visible(true),
+        Command::HideTurtle => turtle.set_visible(false),
+        Command::Home => turtle.home(),
+        Command::Wrap => turtle.set_screen_mode(ScreenMode::Wrap),
+        Command::Fence => turtle.set_screen_mode(ScreenMode::Fence),
+        Command::Window => turtle.set_screen_mode(ScreenMode::Window),
+        Command::Forward(expr) => {
+            let dist = match_expressions(expr, env, turtle)?;
+            turtle.forward(dist);
+        }
+        Command::Back(expr) => {
+            let dist = match_expressions(expr, env, turtle)?;
+            turtle.back(dist);
+        }
+        Command::Left(expr) => {
+            let dist = match_expressions(expr, env, turtle)?;
+            turtle.left(dist);
+        }
+        Command::Right(expr) => {
+            let dist = match_expressions(expr, env, turtle)?;
+            turtle.right(dist);
+        }
+        Command::SetPenColor(expr) => {
+            let color = match match_value(expr, env, turtle)? {
+                Expression::List(items) | Expression::Array(items) if items.len() == 3 => {
+                    rgb_color(&items[0], &items[1], &items[2], turtle, env)?
                 }
-                Command::Make(var, expr) => {
-                    // TODO: I hate this, need to refactor.
-                    let var = var.to_string();
-                    if let Expression::Query(query) = expr {
-                        match query {
-                            Query::XCor => {
-                                vars.insert(var, Expression::Float(turtle.x));
-                            }
-                            Query::YCor => {
-                                vars.insert(var, Expression::Float(turtle.y));
-                            }
-                            Query::Heading => {
-                                vars.insert(var, Expression::Number(turtle.heading));
-                            }
-                            Query::Color => {
-                                vars.insert(var, Expression::Usize(turtle.pen_color));
-                            }
+                value => {
+                    let index = match_expressions(&value, env, turtle)?;
+                    match palette_color(index, env) {
+                        Some(color) => color,
+                        None => {
+                            return Err(ExecutionError {
+                                kind: ExecutionErrorKind::InvalidArgument {
+                                    msg: format!(
+                                        "Colour index must be between 0 and {} inclusive",
+                                        env.palette.len() - 1
+                                    ),
+                                },
+                            })
                         }
-                    } else if let Expression::Float(_) = expr {
-                        vars.insert(var.clone(), expr.clone());
-                    } else if let Expression::Number(_) = expr {
-                        vars.insert(var.clone(), expr.clone());
-                    } else if let Expression::Usize(_) = expr {
-                        vars.insert(var.clone(), expr.clone());
-                    } else if let Expression::Math(_) = expr {
-                        let val = match_expressions(expr, vars, turtle)?;
-                        vars.insert(var.clone(), Expression::Float(val));
-                    } else {
-                        return Err(ExecutionError {
-                            kind: ExecutionErrorKind::TypeError {
-                                expected: "float, number, usize, query, or mathematical expression"
-                                    .to_string(),
-                            },
-                        });
                     }
                 }
-                Command::AddAssign(var, expr) => {
-                    let val = match_expressions(expr, vars, turtle)?;
-
-                    if let Some(Expression::Float(curr_val)) = vars.get(var) {
-                        vars.insert(var.to_string(), Expression::Float(curr_val + val));
-                    } else {
-                        return Err(ExecutionError {
-                            kind: ExecutionErrorKind::VariableNotFound {
-                                var: var.to_string(),
-                            },
-                        });
-                    }
-                }
-            },
-            ASTNode::ControlFlow(control_flow) => match control_flow {
-                ControlFlow::If { condition, block } => {
-                    eval_exec_if(condition, block, turtle, vars)?;
+            };
+            turtle.set_pen_color(color)
+        }
+        Command::SetPenRgb(red, green, blue) => {
+            let color = rgb_color(red, green, blue, turtle, env)?;
+            turtle.set_pen_color(color)
+        }
+        Command::SetPenHsv(hue, saturation, value) => {
+            let hue = match_expressions(hue, env, turtle)?;
+            let saturation = match_expressions(saturation, env, turtle)?;
+            let value = match_expressions(value, env, turtle)?;
+            if !(0.0..=1.0).contains(&saturation) || !(0.0..=1.0).contains(&value) {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: "Saturation and value must be between 0 and 1".to_string(),
+                    },
+                });
+            }
+            turtle.set_pen_color(hsv_to_color(hue, saturation, value))
+        }
+        Command::SetPenSize(expr) => {
+            let size = match_expressions(expr, env, turtle)?;
+            if size <= 0.0 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: format!("SETPENSIZE needs a width above 0, not {}", size),
+                    },
+                });
+            }
+            turtle.set_pen_size(size as f32);
+        }
+        Command::SetPenAlpha(expr) => {
+            let alpha = match_expressions(expr, env, turtle)?;
+            if !(0.0..=1.0).contains(&alpha) {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: format!("SETPENALPHA needs an alpha from 0 to 1, not {}", alpha),
+                    },
+                });
+            }
+            turtle.set_pen_alpha(alpha as f32);
+        }
+        Command::Turn(expr) => {
+            let angle = match_expressions(expr, env, turtle)?;
+            turtle.turn(env.angle_unit.to_degrees(angle) as f32);
+        }
+        Command::TurnLeft(expr) => {
+            let angle = match_expressions(expr, env, turtle)?;
+            turtle.turn(-env.angle_unit.to_degrees(angle) as f32);
+        }
+        Command::TurnRight(expr) => {
+            let angle = match_expressions(expr, env, turtle)?;
+            turtle.turn(env.angle_unit.to_degrees(angle) as f32);
+        }
+        Command::SetHeading(expr) => {
+            let angle = match_expressions(expr, env, turtle)?;
+            turtle.set_heading(env.angle_unit.to_degrees(angle) as f32);
+        }
+        Command::SetX(expr) => {
+            let x = match_expressions(expr, env, turtle)?;
+            turtle.set_x(x);
+        }
+        Command::SetPos(x, y) => {
+            let x = match_expressions(x, env, turtle)?;
+            let y = match_expressions(y, env, turtle)?;
+            turtle.set_pos(x, y);
+        }
+        Command::SetY(expr) => {
+            let y = match_expressions(expr, env, turtle)?;
+            turtle.set_y(y);
+        }
+        Command::Rect(width, height) => {
+            let width = match_expressions(width, env, turtle)?;
+            let height = match_expressions(height, env, turtle)?;
+            turtle.rect(width as f32, height as f32);
+        }
+        Command::Polygon(sides, radius) => {
+            let sides = match_expressions(sides, env, turtle)?;
+            let radius = match_expressions(radius, env, turtle)?;
+            let sides = shape_count(sides, 3, "POLYGON", "sides")?;
+            turtle.polygon(sides, radius as f32);
+        }
+        Command::Star(points, radius) => {
+            let points = match_expressions(points, env, turtle)?;
+            let radius = match_expressions(radius, env, turtle)?;
+            let points = shape_count(points, 2, "STAR", "points")?;
+            turtle.star(points, radius as f32);
+        }
+        Command::Circle(radius) => {
+            let radius = match_expressions(radius, env, turtle)?;
+            if radius < 0.0 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: format!("CIRCLE needs a radius of at least 0, not {}", radius),
+                    },
+                });
+            }
+            turtle.circle(radius as f32);
+        }
+        Command::Ellipse(rx, ry) => {
+            let rx = match_expressions(rx, env, turtle)?;
+            let ry = match_expressions(ry, env, turtle)?;
+            if rx < 0.0 || ry < 0.0 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: format!("ELLIPSE needs radii of at least 0, not {} and {}", rx, ry),
+                    },
+                });
+            }
+            turtle.ellipse(rx as f32, ry as f32);
+        }
+        Command::SetFillPattern(pattern) => turtle.set_fill_pattern(*pattern),
+        Command::SetPenPattern(pattern) => turtle.set_pen_pattern(*pattern),
+        Command::SetLabelFont(font) => turtle.label_font = font.clone(),
+        Command::SetLabelHeight(expr) => {
+            let size = match_expressions(expr, env, turtle)?;
+            if size <= 0.0 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: format!("SETLABELHEIGHT needs a height above 0, not {}", size),
+                    },
+                });
+            }
+            turtle.label_size = size as f32;
+        }
+        Command::SetLabelAlign(align) => turtle.label_align = *align,
+        Command::SetLayer(expr) => {
+            let layer = match_expressions(expr, env, turtle)?;
+            if layer < 0.0 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: "SETLAYER needs a non-negative layer".to_string(),
+                    },
+                });
+            }
+            turtle.set_layer(layer as usize);
+        }
+        Command::SetShadow(dx, dy, color) => {
+            let dx = match_expressions(dx, env, turtle)? as f32;
+            let dy = match_expressions(dy, env, turtle)? as f32;
+            let color = effect_color(color, turtle, env)?;
+            turtle.set_effect(Some(Effect::Shadow { dx, dy, color }));
+        }
+        Command::SetOutline(width, color) => {
+            let width = match_expressions(width, env, turtle)? as f32;
+            let color = effect_color(color, turtle, env)?;
+            turtle.set_effect(Some(Effect::Outline { width, color }));
+        }
+        Command::ClearEffect => turtle.set_effect(None),
+        Command::EraseProcedure(name) => {
+            if env.procedures.remove(name).is_none() {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::ProcedureNotFound { name: name.clone() },
+                });
+            }
+        }
+        Command::EraseName(var) => {
+            if env.vars.remove(var).is_none() {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::VariableNotFound { var: var.clone() },
+                });
+            }
+        }
+        Command::Erase(expr) => {
+            let count = match_expressions(expr, env, turtle)?;
+            if count < 0.0 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: "ERASE needs a non-negative number of strokes".to_string(),
+                    },
+                });
+            }
+            turtle.canvas.erase(count as usize);
+        }
+        Command::SetTrailFade(expr) => {
+            let length = match_expressions(expr, env, turtle)?;
+            if length < 0.0 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: "SETTRAILFADE needs a non-negative length".to_string(),
+                    },
+                });
+            }
+            turtle.set_trail_fade(length as usize);
+        }
+        Command::SetPosRel(expr) => {
+            let relative = match_expressions(expr, env, turtle)?;
+            turtle.set_relative(relative != 0.0);
+        }
+        Command::Mark(name) => turtle.mark(name),
+        Command::PProp(list, prop, expr) => {
+            let value = match_value(expr, env, turtle)?;
+            env.props
+                .entry(list.clone())
+                .or_default()
+                .insert(prop.clone(), value);
+        }
+        Command::SetItem(index, var, expr) => {
+            let index = match_expressions(index, env, turtle)?;
+            let value = match_value(expr, env, turtle)?;
+            let Some(Expression::Array(items)) = env.vars.get_mut(var) else {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::TypeError {
+                        expected: format!("an array in :{}", var),
+                    },
+                });
+            };
+            if index < 1.0 || index.fract() != 0.0 || index > items.len() as f64 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: format!(
+                            "SETITEM {} is out of range for an array of {} items",
+                            index,
+                            items.len()
+                        ),
+                    },
+                });
+            }
+            items[index as usize - 1] = value;
+        }
+        Command::RemProp(list, prop) => {
+            if let Some(props) = env.props.get_mut(list) {
+                props.remove(prop);
+                if props.is_empty() {
+                    env.props.remove(list);
                 }
-                ControlFlow::While { condition, block } => {
-                    eval_exec_while(condition, block, turtle, vars)?;
+            }
+        }
+        Command::Label(text) => {
+            let text = match_word(text, env, turtle)?;
+            turtle.label(&text);
+        }
+        Command::JumpTo(name) => {
+            if !turtle.jump_to(name) {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::MarkNotFound { name: name.clone() },
+                });
+            }
+        }
+        Command::SetAngleUnit(unit) => env.angle_unit = *unit,
+        Command::Rerandom(expr) => {
+            let seed = match_expressions(expr, env, turtle)?;
+            env.rng = Rng::new(seed as u64);
+        }
+        Command::Assert(condition, msg) => eval_exec_assert(condition, msg, turtle, env)?,
+        Command::PushTransform => turtle.push_transform(),
+        Command::PopTransform => {
+            if !turtle.pop_transform() {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: "POPTRANSFORM without a matching PUSHTRANSFORM".to_string(),
+                    },
+                });
+            }
+        }
+        Command::PushState => turtle.push_state(),
+        Command::PopState => {
+            if !turtle.pop_state() {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: "POPSTATE without a matching PUSHSTATE".to_string(),
+                    },
+                });
+            }
+        }
+        Command::Scale(expr) => {
+            let factor = match_expressions(expr, env, turtle)?;
+            if factor == 0.0 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: "SCALE cannot be 0".to_string(),
+                    },
+                });
+            }
+            turtle.transform = turtle.transform.scaled(factor as f32);
+        }
+        Command::Rotate(expr) => {
+            let angle = match_expressions(expr, env, turtle)?;
+            let degrees = env.angle_unit.to_degrees(angle);
+            turtle.transform = turtle.transform.rotated(degrees as f32);
+        }
+        Command::Translate(dx, dy) => {
+            let dx = match_expressions(dx, env, turtle)? as f32;
+            let dy = match_expressions(dy, env, turtle)? as f32;
+            turtle.transform = turtle.transform.translated(dx, dy, turtle.precision);
+        }
+        Command::SetClip(x, y, width, height) => {
+            let x = match_expressions(x, env, turtle)?;
+            let y = match_expressions(y, env, turtle)?;
+            let width = match_expressions(width, env, turtle)?;
+            let height = match_expressions(height, env, turtle)?;
+            if width < 0.0 || height < 0.0 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: format!(
+                            "SETCLIP needs a width and height of at least 0, not {} and {}",
+                            width, height
+                        ),
+                    },
+                });
+            }
+            turtle.set_clip(Some(Clip {
+                x: x as f32,
+                y: y as f32,
+                width: width as f32,
+                height: height as f32,
+            }));
+        }
+        Command::ClearClip => turtle.set_clip(None),
+        Command::SetSymmetry(expr) | Command::SetKaleidoscope(expr) => {
+            let ways = match_expressions(expr, env, turtle)?;
+            if ways < 1.0 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: format!("{} needs at least 1 way", command.keyword()),
+                    },
+                });
+            }
+            if let Command::SetKaleidoscope(_) = command {
+                turtle.set_kaleidoscope(ways as usize);
+            } else {
+                turtle.set_symmetry(ways as usize);
+            }
+        }
+        Command::Make(var, expr) => {
+            let value = match expr {
+                Expression::Float(_) | Expression::Number(_) | Expression::Usize(_) => expr.clone(),
+                Expression::Query(Query::Color) => match pen_color_value(turtle, env) {
+                    Expression::Float(index) => Expression::Usize(index as usize),
+                    value => value,
+                },
+                _ => match_value(expr, env, turtle)?,
+            };
+            env.vars.insert(var.to_string(), value);
+        }
+        Command::Local(var) => env.bind_local(var, Expression::Float(0.0)),
+        Command::LocalMake(var, expr) if expr.is_word() => {
+            let value = match_value(expr, env, turtle)?;
+            env.bind_local(var, value);
+        }
+        Command::LocalMake(var, expr) => {
+            let val = match_expressions(expr, env, turtle)?;
+            env.bind_local(var, Expression::Float(val));
+        }
+        Command::AddAssign(var, expr) => {
+            let val = match_expressions(expr, env, turtle)?;
+
+            if let Some(Expression::Float(curr_val)) = env.vars.get(var) {
+                let sum = env.precision.round(curr_val + val);
+                if !sum.is_finite() {
+                    return Err(ExecutionError {
+                        kind: ExecutionErrorKind::NonFinite {
+                            expr: format!("+ :{} {}", var, expr),
+                            value: sum,
+                        },
+                    });
                 }
-            },
+                env.vars.insert(var.to_string(), Expression::Float(sum));
+            } else {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::VariableNotFound {
+                        var: var.to_string(),
+                    },
+                });
+            }
         }
     }
 
     Ok(())
 }
 
+/// The most sides a `POLYGON`, or points a `STAR`, can have. Far more would
+/// look no different from a circle, and would only use up memory.
+const MAX_SHAPE_COUNT: usize = 10_000;
+
+/// Checks the number of sides or points of a shape is a whole number from
+/// `min` to `MAX_SHAPE_COUNT`.
+fn shape_count(count: f64, min: usize, keyword: &str, what: &str) -> Result<usize, ExecutionError> {
+    if count.fract() != 0.0 || !(min as f64..=MAX_SHAPE_COUNT as f64).contains(&count) {
+        return Err(ExecutionError {
+            kind: ExecutionErrorKind::InvalidArgument {
+                msg: format!(
+                    "{} needs a whole number of {} from {} to {}, not {}",
+                    keyword, what, min, MAX_SHAPE_COUNT, count
+                ),
+            },
+        });
+    }
+    Ok(count as usize)
+}
+
+/// Evaluates the red, green and blue of a color, each from 0 to 255.
+fn rgb_color(
+    red: &Expression,
+    green: &Expression,
+    blue: &Expression,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<Color, ExecutionError> {
+    let mut channels = [0; 3];
+    for (channel, expr) in channels.iter_mut().zip([red, green, blue]) {
+        let value = match_expressions(expr, env, turtle)?;
+        if !(0.0..=255.0).contains(&value) {
+            return Err(ExecutionError {
+                kind: ExecutionErrorKind::InvalidArgument {
+                    msg: format!(
+                        "Red, green and blue must be between 0 and 255, got {}",
+                        value
+                    ),
+                },
+            });
+        }
+        *channel = value.round() as u8;
+    }
+    let [red, green, blue] = channels;
+    Ok(Color { red, green, blue })
+}
+
+/// The color at a palette index, if the index is in the palette.
+fn palette_color(index: f64, env: &Environment) -> Option<Color> {
+    if index < 0.0 {
+        return None;
+    }
+    env.palette.get(index as usize)
+}
+
+/// Evaluates the palette index of an effect's color.
+fn effect_color(
+    expr: &Expression,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<Color, ExecutionError> {
+    let index = match_expressions(expr, env, turtle)?;
+    palette_color(index, env).ok_or_else(|| ExecutionError {
+        kind: ExecutionErrorKind::InvalidArgument {
+            msg: format!(
+                "Effect color must be between 0 and {}, got {}",
+                env.palette.len() - 1,
+                index
+            ),
+        },
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use unsvg::COLORS;
 
-    use unsvg::Image;
+    use crate::canvas::{Canvas, Shape, TextAlign};
+    use crate::interpreter::{angles::AngleUnit, input::Lines, limits::DEFAULT_MAX_DEPTH};
+    use crate::parser::{parse::parse_tokens, tokenise::tokenize_script};
 
-    use crate::ast::{Command, Condition, Expression, Math, Query};
+    use crate::ast::{Command, Condition, Expression, Function, Math, Procedure, Query};
 
     use super::*;
 
+    #[test]
+    fn test_execute_keep_going() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        let ast = vec![
+            ASTNode::Command(Command::Forward(Expression::Variable(
+                "missing".to_string(),
+            ))),
+            ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+        ];
+
+        assert!(execute(&ast, &mut turtle, &mut env).is_err());
+        assert_eq!(turtle.y, 50.0);
+
+        env.keep_going = true;
+        execute(&ast, &mut turtle, &mut env).unwrap();
+        assert_eq!(turtle.y, 40.0);
+
+        let ast = vec![ASTNode::Command(Command::SetPenColor(Expression::Float(
+            99.0,
+        )))];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+        assert_eq!(turtle.pen_color, COLORS[7]);
+
+        let warnings: Vec<String> = env.warnings.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "Variable not found: 'missing'",
+                "Invalid argument: Colour index must be between 0 and 15 inclusive"
+            ]
+        );
+    }
+
     #[test]
     fn test_execute_pen_down() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![ASTNode::Command(Command::PenDown)];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
         assert!(turtle.pen_down);
     }
 
     #[test]
     fn test_execute_pen_up() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![
             ASTNode::Command(Command::PenDown),
             ASTNode::Command(Command::PenUp),
         ];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
         assert!(!turtle.pen_down);
     }
 
     #[test]
     fn test_execute_forward() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![ASTNode::Command(Command::Forward(Expression::Float(30.0)))];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
         assert_eq!(turtle.y, 20.0);
     }
 
     #[test]
     fn test_execute_back() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![ASTNode::Command(Command::Back(Expression::Float(30.0)))];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
         assert_eq!(turtle.y, 80.0);
     }
 
     #[test]
     fn test_execute_left() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![ASTNode::Command(Command::Left(Expression::Float(30.0)))];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
         assert_eq!(turtle.x, 20.0);
     }
 
     #[test]
     fn test_execute_right() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![ASTNode::Command(Command::Right(Expression::Float(30.0)))];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
         assert_eq!(turtle.x, 80.0);
     }
 
     #[test]
     fn test_execute_set_pen_color() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![ASTNode::Command(Command::SetPenColor(Expression::Usize(1)))];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
-        assert_eq!(turtle.pen_color, 1);
+        assert_eq!(turtle.pen_color, COLORS[1]);
+    }
+
+    #[test]
+    fn test_execute_set_pen_rgb() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        let orange = Color {
+            red: 255,
+            green: 100,
+            blue: 0,
+        };
+
+        let ast = vec![
+            ASTNode::Command(Command::SetPenRgb(
+                Expression::Float(255.0),
+                Expression::Float(100.0),
+                Expression::Float(0.0),
+            )),
+            ASTNode::Command(Command::Make(
+                "saved".to_string(),
+                Expression::Query(Query::Color),
+            )),
+            ASTNode::Command(Command::SetPenColor(Expression::Usize(1))),
+            ASTNode::Command(Command::SetPenColor(Expression::Variable(
+                "saved".to_string(),
+            ))),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(turtle.pen_color, orange);
+        assert_eq!(
+            env.vars.get("saved").unwrap(),
+            &Expression::List(vec![
+                Expression::Float(255.0),
+                Expression::Float(100.0),
+                Expression::Float(0.0),
+            ])
+        );
+
+        let ast = vec![ASTNode::Command(Command::SetPenRgb(
+            Expression::Float(256.0),
+            Expression::Float(0.0),
+            Expression::Float(0.0),
+        ))];
+        assert!(matches!(
+            execute(&ast, &mut turtle, &mut env),
+            Err(ExecutionError {
+                kind: ExecutionErrorKind::InvalidArgument { .. }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_execute_set_pen_hsv() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![ASTNode::Command(Command::SetPenHsv(
+            Expression::Float(240.0),
+            Expression::Float(1.0),
+            Expression::Float(1.0),
+        ))];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+        assert_eq!(
+            turtle.pen_color,
+            Color {
+                red: 0,
+                green: 0,
+                blue: 255
+            }
+        );
+
+        let ast = vec![ASTNode::Command(Command::SetPenHsv(
+            Expression::Float(0.0),
+            Expression::Float(2.0),
+            Expression::Float(1.0),
+        ))];
+        assert!(matches!(
+            execute(&ast, &mut turtle, &mut env),
+            Err(ExecutionError {
+                kind: ExecutionErrorKind::InvalidArgument { .. }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_execute_fence() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::Fence),
+            ASTNode::Command(Command::Forward(Expression::Float(60.0))),
+        ];
+        let err = execute(&ast, &mut turtle, &mut env).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            ExecutionErrorKind::OutOfBounds { ref command, x, y }
+                if command == "FORWARD" && (x, y) == (50.0, -10.0)
+        ));
+        assert_eq!((turtle.x, turtle.y), (50.0, 50.0));
+
+        let ast = vec![
+            ASTNode::ControlFlow(ControlFlow::Catch {
+                tag: "ERROR".to_string(),
+                block: vec![ASTNode::Command(Command::Back(Expression::Float(60.0)))],
+            }),
+            ASTNode::Command(Command::Back(Expression::Float(10.0))),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+        assert_eq!((turtle.x, turtle.y), (50.0, 60.0));
+    }
+
+    #[test]
+    fn test_execute_set_pen_size() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::SetPenSize(Expression::Float(4.0))),
+            ASTNode::Command(Command::PenDown),
+            ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(turtle.canvas.elements[0].style.width, 4.0);
+        assert!(turtle.canvas.to_svg().contains(r#"stroke-width="4""#));
+
+        let ast = vec![ASTNode::Command(Command::SetPenSize(Expression::Float(
+            0.0,
+        )))];
+        assert!(execute(&ast, &mut turtle, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_execute_set_pen_alpha() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::SetPenAlpha(Expression::Float(0.25))),
+            ASTNode::Command(Command::PenDown),
+            ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(turtle.canvas.elements[0].style.opacity, 0.25);
+        assert!(turtle.canvas.to_svg().contains(r#"opacity="0.25""#));
+
+        let ast = vec![ASTNode::Command(Command::SetPenAlpha(Expression::Float(
+            1.5,
+        )))];
+        assert!(execute(&ast, &mut turtle, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_execute_label_font() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::SetLabelFont("monospace".to_string())),
+            ASTNode::Command(Command::SetLabelHeight(Expression::Float(24.0))),
+            ASTNode::Command(Command::SetLabelAlign(TextAlign::Center)),
+            ASTNode::Command(Command::Label(Expression::Word("hi".to_string()))),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        let svg = turtle.canvas.to_svg();
+        assert!(svg.contains(r#"font-family="monospace" font-size="24" text-anchor="middle""#));
+
+        let ast = vec![ASTNode::Command(Command::SetLabelHeight(
+            Expression::Float(0.0),
+        ))];
+        assert!(execute(&ast, &mut turtle, &mut env).is_err());
     }
 
     #[test]
     fn test_execute_turn() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![ASTNode::Command(Command::Turn(Expression::Number(30)))];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(turtle.heading, 30.0);
+
+        let ast = vec![
+            ASTNode::Command(Command::Turn(Expression::Float(22.5))),
+            ASTNode::Command(Command::Make(
+                "heading".to_string(),
+                Expression::Query(Query::Heading),
+            )),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(turtle.heading, 52.5);
+        assert_eq!(env.vars.get("heading"), Some(&Expression::Float(52.5)));
+    }
+
+    #[test]
+    fn test_execute_turn_left_right() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
-        assert_eq!(turtle.heading, 30);
+        let ast = vec![
+            ASTNode::Command(Command::TurnLeft(Expression::Float(90.0))),
+            ASTNode::Command(Command::TurnRight(Expression::Float(30.0))),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(turtle.heading, 300.0);
+        assert_eq!((turtle.x, turtle.y), (50.0, 50.0));
     }
 
     #[test]
     fn test_execute_set_heading() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![ASTNode::Command(Command::SetHeading(Expression::Number(
             30,
         )))];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
-        assert_eq!(turtle.heading, 30);
+        assert_eq!(turtle.heading, 30.0);
     }
 
     #[test]
     fn test_execute_set_x() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         turtle.set_y(50.0);
 
         let ast = vec![ASTNode::Command(Command::SetX(Expression::Float(30.0)))];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
         assert_eq!(turtle.x, 30.0);
     }
 
     #[test]
     fn test_execute_set_y() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         turtle.set_x(50.0);
 
         let ast = vec![ASTNode::Command(Command::SetY(Expression::Float(30.0)))];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
         assert_eq!(turtle.y, 30.0);
     }
 
     #[test]
     fn test_execute_make_queries() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![
             ASTNode::Command(Command::Make(
@@ -328,19 +1093,19 @@ mod tests {
             )),
         ];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
-        assert_eq!(vars.get("x").unwrap(), &Expression::Float(50.0));
-        assert_eq!(vars.get("y").unwrap(), &Expression::Float(50.0));
-        assert_eq!(vars.get("heading").unwrap(), &Expression::Number(0));
-        assert_eq!(vars.get("color").unwrap(), &Expression::Usize(7));
+        assert_eq!(env.vars.get("x").unwrap(), &Expression::Float(50.0));
+        assert_eq!(env.vars.get("y").unwrap(), &Expression::Float(50.0));
+        assert_eq!(env.vars.get("heading").unwrap(), &Expression::Float(0.0));
+        assert_eq!(env.vars.get("color").unwrap(), &Expression::Usize(7));
     }
 
     #[test]
     fn test_execute_make_other() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![
             ASTNode::Command(Command::Make("float".to_string(), Expression::Float(30.0))),
@@ -355,71 +1120,237 @@ mod tests {
             )),
         ];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(env.vars.get("float").unwrap(), &Expression::Float(30.0));
+        assert_eq!(env.vars.get("number").unwrap(), &Expression::Number(30));
+        assert_eq!(env.vars.get("usize").unwrap(), &Expression::Usize(1));
+        assert_eq!(env.vars.get("math").unwrap(), &Expression::Float(20.0));
+    }
+
+    #[test]
+    fn test_execute_read() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment {
+            input: Some(Box::new(Lines::new(["tulip", "6", "six"]))),
+            ..Default::default()
+        };
+
+        let ast = vec![
+            ASTNode::Command(Command::Make("name".to_string(), Expression::ReadWord)),
+            ASTNode::Command(Command::Make(
+                "petals".to_string(),
+                Expression::Query(Query::ReadNumber),
+            )),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(
+            env.vars.get("name").unwrap(),
+            &Expression::Word("tulip".to_string())
+        );
+        assert_eq!(env.vars.get("petals").unwrap(), &Expression::Float(6.0));
+
+        let ast = vec![ASTNode::Command(Command::Forward(Expression::Query(
+            Query::ReadNumber,
+        )))];
+        assert!(matches!(
+            execute(&ast, &mut turtle, &mut env),
+            Err(ExecutionError {
+                kind: ExecutionErrorKind::TypeError { .. }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_execute_make_word() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        let word = |word: &str| Expression::Word(word.to_string());
+        let concat = |lhs, rhs| Expression::Concat(Box::new((lhs, rhs)));
+
+        let ast = vec![
+            ASTNode::Command(Command::Make(
+                "name".to_string(),
+                concat(word("turtle"), Expression::Float(2.0)),
+            )),
+            ASTNode::Command(Command::Label(Expression::Variable("name".to_string()))),
+            ASTNode::Command(Command::Forward(concat(word("1"), word("0")))),
+        ];
+
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(env.vars.get("name").unwrap(), &word("turtle2"));
+        assert!(matches!(
+            &turtle.canvas.elements[0].shape,
+            Shape::Text { text, .. } if text == "turtle2"
+        ));
+        assert_eq!(turtle.ycor(), 40.0);
+
+        let ast = vec![ASTNode::Command(Command::Forward(Expression::Variable(
+            "name".to_string(),
+        )))];
+        let result = execute(&ast, &mut turtle, &mut env);
+
+        assert!(matches!(
+            result.unwrap_err().kind,
+            ExecutionErrorKind::TypeError { expected } if expected == "a number, not the word \"turtle2"
+        ));
+    }
+
+    #[test]
+    fn test_execute_erase_names() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        env.vars.insert("X".to_string(), Expression::Float(1.0));
+        env.procedures.insert(
+            "STEP".to_string(),
+            Procedure {
+                name: "STEP".to_string(),
+                params: vec![],
+                block: vec![],
+            },
+        );
+
+        let ast = vec![
+            ASTNode::Command(Command::EraseName("X".to_string())),
+            ASTNode::Command(Command::EraseProcedure("STEP".to_string())),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+        assert!(env.vars.is_empty());
+        assert!(env.procedures.is_empty());
+
+        // Neither can be erased twice.
+        for command in ast {
+            assert!(execute(&vec![command], &mut turtle, &mut env).is_err());
+        }
+    }
+
+    #[test]
+    fn test_execute_arrays() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        let set_item = |index: f64, value: f64| {
+            ASTNode::Command(Command::SetItem(
+                Expression::Float(index),
+                "GRID".to_string(),
+                Expression::Float(value),
+            ))
+        };
+
+        let ast = vec![
+            ASTNode::Command(Command::Make(
+                "GRID".to_string(),
+                Expression::MakeArray(Box::new(Expression::Float(3.0))),
+            )),
+            set_item(2.0, 5.0),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+        assert_eq!(
+            env.vars.get("GRID").unwrap(),
+            &Expression::Array(vec![
+                Expression::Float(0.0),
+                Expression::Float(5.0),
+                Expression::Float(0.0),
+            ])
+        );
+
+        for ast in [vec![set_item(4.0, 1.0)], vec![set_item(0.0, 1.0)]] {
+            assert!(execute(&ast, &mut turtle, &mut env).is_err());
+        }
+    }
+
+    #[test]
+    fn test_execute_props() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        let size = || Expression::GetProp("SQUARE".to_string(), "SIZE".to_string());
+
+        let ast = vec![
+            ASTNode::Command(Command::PProp(
+                "SQUARE".to_string(),
+                "SIZE".to_string(),
+                Expression::Float(20.0),
+            )),
+            ASTNode::Command(Command::Forward(size())),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+        assert_eq!(turtle.y, 30.0);
 
-        assert_eq!(vars.get("float").unwrap(), &Expression::Float(30.0));
-        assert_eq!(vars.get("number").unwrap(), &Expression::Number(30));
-        assert_eq!(vars.get("usize").unwrap(), &Expression::Usize(1));
-        assert_eq!(vars.get("math").unwrap(), &Expression::Float(20.0));
+        let ast = vec![ASTNode::Command(Command::RemProp(
+            "SQUARE".to_string(),
+            "SIZE".to_string(),
+        ))];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+        assert!(env.props.is_empty());
+        assert_eq!(
+            match_value(&size(), &mut env, &mut turtle).unwrap(),
+            Expression::List(vec![])
+        );
     }
 
     #[test]
     fn test_execute_make_err() {
         // Only one case where there will be an error is when the expression is
         // a variable.
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![ASTNode::Command(Command::Make(
             "x".to_string(),
             Expression::Variable("y".to_string()),
         ))];
 
-        let result = execute(&ast, &mut turtle, &mut vars);
+        let result = execute(&ast, &mut turtle, &mut env);
 
         assert!(result.is_err());
     }
 
     #[test]
     fn test_execute_add_assign() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
-        vars.insert("x".to_string(), Expression::Float(10.0));
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        env.vars.insert("x".to_string(), Expression::Float(10.0));
 
         let ast = vec![ASTNode::Command(Command::AddAssign(
             "x".to_string(),
             Expression::Float(10.0),
         ))];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
-        assert_eq!(vars.get("x").unwrap(), &Expression::Float(20.0));
+        assert_eq!(env.vars.get("x").unwrap(), &Expression::Float(20.0));
     }
 
     #[test]
     fn test_execute_add_assign_err() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
 
         let ast = vec![ASTNode::Command(Command::AddAssign(
             "x".to_string(),
             Expression::Float(10.0),
         ))];
 
-        let result = execute(&ast, &mut turtle, &mut vars);
+        let result = execute(&ast, &mut turtle, &mut env);
 
         assert!(result.is_err());
     }
 
     #[test]
     fn test_execute_if() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
-        vars.insert("x".to_string(), Expression::Float(10.0));
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        env.vars.insert("x".to_string(), Expression::Float(10.0));
 
         let ast = vec![ASTNode::ControlFlow(ControlFlow::If {
             condition: Condition::Equals(
@@ -435,17 +1366,17 @@ mod tests {
             ))],
         })];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
-        assert_eq!(vars.get("x").unwrap(), &Expression::Float(20.0));
+        assert_eq!(env.vars.get("x").unwrap(), &Expression::Float(20.0));
     }
 
     #[test]
     fn test_execute_while() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
-        let mut vars = HashMap::new();
-        vars.insert("x".to_string(), Expression::Float(10.0));
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        env.vars.insert("x".to_string(), Expression::Float(10.0));
 
         let ast = vec![ASTNode::ControlFlow(ControlFlow::While {
             condition: Condition::LessThan(
@@ -458,8 +1389,538 @@ mod tests {
             ))],
         })];
 
-        execute(&ast, &mut turtle, &mut vars).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(env.vars.get("x").unwrap(), &Expression::Float(20.0));
+    }
+
+    #[test]
+    fn test_execute_random() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        let random = || Expression::Function(Box::new(Function::Random(Expression::Float(100.0))));
+
+        let ast = vec![
+            ASTNode::Command(Command::Rerandom(Expression::Float(3.0))),
+            ASTNode::Command(Command::Make("A".to_string(), random())),
+            ASTNode::Command(Command::Make("B".to_string(), random())),
+            ASTNode::Command(Command::Rerandom(Expression::Float(3.0))),
+            ASTNode::Command(Command::Make("C".to_string(), random())),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        // Reseeding starts the same numbers over.
+        let a = env.vars["A"].clone();
+        assert_ne!(a, env.vars["B"]);
+        assert_eq!(a, env.vars["C"]);
+
+        let ast = vec![ASTNode::Command(Command::Forward(Expression::Function(
+            Box::new(Function::Random(Expression::Float(0.0))),
+        )))];
+        assert!(execute(&ast, &mut turtle, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_execute_local() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        let x = || "X".to_string();
+        // TO SHADOW  LOCAL "X  MAKE "X "5  FORWARD :X  END
+        env.procedures.insert(
+            "SHADOW".to_string(),
+            Procedure {
+                name: "SHADOW".to_string(),
+                params: vec![],
+                block: vec![
+                    ASTNode::Command(Command::Local(x())),
+                    ASTNode::Command(Command::Make(x(), Expression::Float(5.0))),
+                    ASTNode::Command(Command::Forward(Expression::Variable(x()))),
+                ],
+            },
+        );
+
+        let ast = vec![
+            ASTNode::Command(Command::Make(x(), Expression::Float(1.0))),
+            ASTNode::ControlFlow(ControlFlow::Call {
+                name: "SHADOW".to_string(),
+                args: vec![],
+            }),
+            ASTNode::ControlFlow(ControlFlow::If {
+                condition: Condition::Equals(Expression::Float(1.0), Expression::Float(1.0)),
+                block: vec![
+                    ASTNode::Command(Command::LocalMake("Y".to_string(), Expression::Float(3.0))),
+                    ASTNode::Command(Command::Forward(Expression::Variable("Y".to_string()))),
+                ],
+            }),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        // The locals were used while they were in scope, then dropped.
+        assert_eq!(turtle.y, 42.0);
+        assert_eq!(env.vars.get("X"), Some(&Expression::Float(1.0)));
+        assert_eq!(env.vars.get("Y"), None);
+        assert!(env.frames.is_empty());
+    }
+
+    #[test]
+    fn test_execute_local_parsed() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        // A local is only known inside the procedure or block making it,
+        // even when that procedure is never called.
+        for script in [
+            "TO Q LOCALMAKE \"Z \"30 END PENDOWN FORWARD :Z",
+            "IF EQ \"1 \"1 [ LOCALMAKE \"Z \"3 ] FORWARD :Z",
+        ] {
+            let err = parse_tokens(tokenize_script(script), &mut 0, &mut env).unwrap_err();
+            assert_eq!(err.to_string(), "Variable not found: 'Z'.");
+        }
+
+        let script = "MAKE \"Z \"5 TO Q LOCALMAKE \"Z \"30 FORWARD :Z END Q FORWARD :Z";
+        let ast = parse_tokens(tokenize_script(script), &mut 0, &mut env).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(turtle.y, 15.0);
+        assert_eq!(env.vars.get("Z"), Some(&Expression::Float(5.0)));
+    }
+
+    #[test]
+    fn test_execute_output_word() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let script = "TO NAME OUTPUT \"hi END TO PAIR OUTPUT [ 1 2 ] END \
+                      MAKE \"W NAME MAKE \"P PAIR LABEL NAME";
+        let ast = parse_tokens(tokenize_script(script), &mut 0, &mut env).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(env.vars.get("W"), Some(&Expression::Word("hi".to_string())));
+        assert_eq!(
+            env.vars.get("P"),
+            Some(&Expression::List(vec![
+                Expression::Float(1.0),
+                Expression::Float(2.0)
+            ]))
+        );
+        assert_eq!(turtle.canvas.elements.len(), 1);
+    }
+
+    #[test]
+    fn test_execute_call_with_list() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let script = "TO SHAPE \"PTS FOREACH :PTS [ FORWARD :? ] END \
+                      MAKE \"L [ 10 20 ] SHAPE :L SHAPE [ 5 ]";
+        let ast = parse_tokens(tokenize_script(script), &mut 0, &mut env).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(turtle.y, 15.0);
+    }
+
+    #[test]
+    fn test_execute_make_count() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let script = "MAKE \"L [ 10 20 30 ] MAKE \"C COUNT :L";
+        let ast = parse_tokens(tokenize_script(script), &mut 0, &mut env).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(env.vars.get("C"), Some(&Expression::Float(3.0)));
+    }
+
+    #[test]
+    fn test_execute_make_variable() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let script = "MAKE \"W WORD \"a \"b MAKE \"V :W MAKE \"N \"2 MAKE \"M :N";
+        let ast = parse_tokens(tokenize_script(script), &mut 0, &mut env).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(env.vars.get("V"), Some(&Expression::Word("ab".to_string())));
+        assert_eq!(env.vars.get("M"), Some(&Expression::Float(2.0)));
+    }
+
+    #[test]
+    fn test_execute_call() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        env.procedures.insert(
+            "STEP".to_string(),
+            Procedure {
+                name: "STEP".to_string(),
+                params: vec!["SIZE".to_string()],
+                block: vec![ASTNode::Command(Command::Forward(Expression::Variable(
+                    "SIZE".to_string(),
+                )))],
+            },
+        );
+
+        let ast = vec![ASTNode::ControlFlow(ControlFlow::Call {
+            name: "STEP".to_string(),
+            args: vec![Expression::Float(30.0)],
+        })];
+
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(turtle.y, 20.0);
+    }
+
+    #[test]
+    fn test_execute_shapes() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::PenDown),
+            ASTNode::Command(Command::Rect(
+                Expression::Float(20.0),
+                Expression::Float(10.0),
+            )),
+            ASTNode::Command(Command::Polygon(
+                Expression::Float(6.0),
+                Expression::Float(10.0),
+            )),
+            ASTNode::Command(Command::Star(
+                Expression::Float(5.0),
+                Expression::Float(10.0),
+            )),
+        ];
+
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(turtle.canvas.elements.len(), 3);
+    }
+
+    #[test]
+    fn test_execute_polygon_err() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![ASTNode::Command(Command::Polygon(
+            Expression::Float(2.0),
+            Expression::Float(10.0),
+        ))];
+
+        let result = execute(&ast, &mut turtle, &mut env);
+
+        assert!(result.is_err());
+
+        for (points, radius) in [(1e12, 10.0), (5.5, 10.0), (f64::NAN, 10.0)] {
+            let ast = vec![ASTNode::Command(Command::Star(
+                Expression::Float(points),
+                Expression::Float(radius),
+            ))];
+            assert!(execute(&ast, &mut turtle, &mut env).is_err());
+        }
+        assert!(turtle.canvas.elements.is_empty());
+    }
+
+    #[test]
+    fn test_execute_effects() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::SetLayer(Expression::Float(2.0))),
+            ASTNode::Command(Command::SetShadow(
+                Expression::Float(4.0),
+                Expression::Float(-4.0),
+                Expression::Usize(15),
+            )),
+        ];
+
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(canvas.layer, 2);
+        assert_eq!(
+            canvas.effects.get(&2),
+            Some(&Effect::Shadow {
+                dx: 4.0,
+                dy: -4.0,
+                color: COLORS[15]
+            })
+        );
+    }
+
+    #[test]
+    fn test_execute_effect_color_err() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![ASTNode::Command(Command::SetOutline(
+            Expression::Float(1.0),
+            Expression::Float(16.0),
+        ))];
+
+        let result = execute(&ast, &mut turtle, &mut env);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_execute_symmetry() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::SetSymmetry(Expression::Float(6.0))),
+            ASTNode::Command(Command::PenDown),
+            ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+        ];
+
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(canvas.elements.len(), 6);
+    }
+
+    #[test]
+    fn test_execute_clip() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::SetClip(
+                Expression::Float(0.0),
+                Expression::Float(0.0),
+                Expression::Float(100.0),
+                Expression::Float(45.0),
+            )),
+            ASTNode::Command(Command::PenDown),
+            ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+            ASTNode::Command(Command::ClearClip),
+            ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        // Only the part of the first line above y = 45 is drawn.
+        assert_eq!(turtle.canvas.color_at(50.0, 48.0), None);
+        assert_eq!(turtle.canvas.color_at(50.0, 42.0), Some(COLORS[7]));
+        assert_eq!(turtle.canvas.elements[1].clip, None);
+
+        let ast = vec![ASTNode::Command(Command::SetClip(
+            Expression::Float(0.0),
+            Expression::Float(0.0),
+            Expression::Float(-1.0),
+            Expression::Float(45.0),
+        ))];
+        assert!(execute(&ast, &mut turtle, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_execute_kaleidoscope() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::SetKaleidoscope(Expression::Float(6.0))),
+            ASTNode::Command(Command::PenDown),
+            ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+        ];
+
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(canvas.elements.len(), 12);
+    }
+
+    #[test]
+    fn test_execute_symmetry_err() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![ASTNode::Command(Command::SetSymmetry(Expression::Float(
+            0.0,
+        )))];
+
+        assert!(execute(&ast, &mut turtle, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_execute_transforms() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::PushTransform),
+            ASTNode::Command(Command::Scale(Expression::Float(2.0))),
+            ASTNode::Command(Command::Rotate(Expression::Float(90.0))),
+            ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+            ASTNode::Command(Command::PopTransform),
+            ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+        ];
+
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!((turtle.x, turtle.y), (70.0, 40.0));
+        assert!(turtle.transforms.is_empty());
+    }
+
+    #[test]
+    fn test_execute_pop_transform_err() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![ASTNode::Command(Command::PopTransform)];
+
+        assert!(execute(&ast, &mut turtle, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_execute_push_and_pop_state() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::PenDown),
+            ASTNode::Command(Command::PushState),
+            ASTNode::Command(Command::Turn(Expression::Float(30.0))),
+            ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+            ASTNode::Command(Command::PopState),
+            ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!((turtle.x, turtle.y, turtle.heading), (50.0, 40.0, 0.0));
+        assert_eq!(turtle.canvas.elements.len(), 2);
+
+        let ast = vec![ASTNode::Command(Command::PopState)];
+        assert!(execute(&ast, &mut turtle, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_execute_set_pos_rel() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::SetPosRel(Expression::Float(1.0))),
+            ASTNode::Command(Command::SetX(Expression::Float(5.0))),
+            ASTNode::Command(Command::Make(
+                "dx".to_string(),
+                Expression::Query(Query::DeltaX),
+            )),
+            ASTNode::Command(Command::SetPosRel(Expression::Float(0.0))),
+            ASTNode::Command(Command::SetY(Expression::Float(5.0))),
+        ];
+
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!((turtle.x, turtle.y), (55.0, 5.0));
+        assert_eq!(env.vars.get("dx"), Some(&Expression::Float(5.0)));
+    }
+
+    #[test]
+    fn test_execute_memory_limit() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        env.limits.max_memory = Some(1);
+
+        let ast = vec![ASTNode::Command(Command::Make(
+            "x".to_string(),
+            Expression::Float(1.0),
+        ))];
+
+        let result = execute(&ast, &mut turtle, &mut env);
+
+        assert!(matches!(
+            result,
+            Err(ExecutionError {
+                kind: ExecutionErrorKind::MemoryLimit { vars: 1, .. }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_execute_make_getenv_word() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        std::env::set_var("RSLOGO_TEST_MAKE_GETENV", "red");
+
+        let script = "MAKE \"C GETENV \"RSLOGO_TEST_MAKE_GETENV";
+        let ast = parse_tokens(tokenize_script(script), &mut 0, &mut env).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        assert_eq!(
+            env.vars.get("C"),
+            Some(&Expression::Word("red".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_execute_default_depth_limit() {
+        // Runs on a thread with the main thread's usual stack, since test
+        // threads get a smaller one.
+        let handle = std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(|| {
+                let mut canvas = Canvas::new(100, 100);
+                let mut turtle = Turtle::new(&mut canvas);
+                let mut env = Environment::default();
+
+                let script = "TO R \"N R + :N \"1 END R \"1";
+                let ast = parse_tokens(tokenize_script(script), &mut 0, &mut env).unwrap();
+                execute(&ast, &mut turtle, &mut env)
+            })
+            .unwrap();
+
+        assert!(matches!(
+            handle.join().unwrap(),
+            Err(ExecutionError {
+                kind: ExecutionErrorKind::DepthLimit {
+                    limit: DEFAULT_MAX_DEPTH,
+                    ..
+                }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_execute_angle_unit() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+
+        let ast = vec![
+            ASTNode::Command(Command::SetAngleUnit(AngleUnit::Radians)),
+            ASTNode::Command(Command::Turn(Expression::Float(
+                std::f64::consts::FRAC_PI_2,
+            ))),
+            ASTNode::Command(Command::Make(
+                "h".to_string(),
+                Expression::Query(Query::Heading),
+            )),
+        ];
+        execute(&ast, &mut turtle, &mut env).unwrap();
 
-        assert_eq!(vars.get("x").unwrap(), &Expression::Float(20.0));
+        assert_eq!(turtle.heading, 90.0);
+        assert_eq!(
+            env.vars.get("h"),
+            Some(&Expression::Float(std::f32::consts::FRAC_PI_2 as f64))
+        );
     }
 }
@@ -0,0 +1,241 @@
+//! Fixed-point arithmetic for `--precision fixed`.
+//!
+//! Values are whole numbers of 1/65536ths (Q16.16) held in an `i64`. The
+//! turtle's moves, the distances it travels and the sines, cosines and
+//! arctangents they need are worked out with integer operations alone.
+//! Trig comes from CORDIC, which only adds, subtracts and shifts, using a
+//! table of constants rather than the platform's maths library, so every
+//! platform gets exactly the same bits.
+
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Bits after the point.
+const FRAC_BITS: u32 = 16;
+
+/// One, in fixed point.
+const ONE: i64 = 1 << FRAC_BITS;
+
+/// Bits after the point in CORDIC's working values, which carry more than
+/// the result keeps so that the error of each step is rounded away.
+const CORDIC_BITS: u32 = 30;
+
+/// `atan(2^-i)` in degrees, in units of 2^-30 degrees.
+const ATANS: [i64; 30] = [
+    48318382080,
+    28524006506,
+    15071301663,
+    7650428050,
+    3840059795,
+    1921901881,
+    961185452,
+    480622056,
+    240314695,
+    120157806,
+    60078960,
+    30039487,
+    15019745,
+    7509872,
+    3754936,
+    1877468,
+    938734,
+    469367,
+    234684,
+    117342,
+    58671,
+    29335,
+    14668,
+    7334,
+    3667,
+    1833,
+    917,
+    458,
+    229,
+    115,
+];
+
+/// How much the CORDIC rotations shrink a vector by, in units of 2^-30.
+const GAIN: i64 = 652032874;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fixed(i64);
+
+impl Fixed {
+    /// The nearest fixed-point value, rounding halves away from zero.
+    pub fn from_f64(value: f64) -> Fixed {
+        // Multiplying by a power of two is exact, so this rounds the same
+        // everywhere.
+        Fixed((value * ONE as f64).round() as i64)
+    }
+
+    /// The value as an `f64`, which holds every fixed-point value exactly.
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / ONE as f64
+    }
+
+    /// Rounds a CORDIC working value to fixed point.
+    fn from_cordic(value: i64) -> Fixed {
+        let shift = CORDIC_BITS - FRAC_BITS;
+        Fixed((value + (1 << (shift - 1))) >> shift)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    fn add(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+
+    fn sub(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+
+    /// Multiplies, rounding the product to the nearest 1/65536.
+    fn mul(self, rhs: Fixed) -> Fixed {
+        let product = self.0 as i128 * rhs.0 as i128 + (1 << (FRAC_BITS - 1));
+        Fixed((product >> FRAC_BITS).clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+
+    fn neg(self) -> Fixed {
+        Fixed(self.0.saturating_neg())
+    }
+}
+
+/// The sine and cosine of an angle in degrees.
+pub fn sin_cos(degrees: Fixed) -> (Fixed, Fixed) {
+    // Only angles from 0 up to 90 degrees are rotated through, and the
+    // quadrant swaps and negates the results.
+    let quarter = 90 * ONE;
+    let angle = degrees.0.rem_euclid(4 * quarter);
+    let (quadrant, angle) = (angle / quarter, angle % quarter);
+
+    let (mut x, mut y) = (GAIN, 0);
+    let mut z = angle << (CORDIC_BITS - FRAC_BITS);
+    for (i, atan) in ATANS.iter().enumerate() {
+        let (dx, dy) = (y >> i, x >> i);
+        if z >= 0 {
+            (x, y, z) = (x - dx, y + dy, z - atan);
+        } else {
+            (x, y, z) = (x + dx, y - dy, z + atan);
+        }
+    }
+
+    let (sin, cos) = (Fixed::from_cordic(y), Fixed::from_cordic(x));
+    match quadrant {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    }
+}
+
+/// The angle in degrees, above -180 and up to 180, from the positive x axis
+/// to the point `(x, y)`, as `f64::atan2` gives in radians.
+pub fn atan2(y: Fixed, x: Fixed) -> Fixed {
+    if x.0 == 0 && y.0 == 0 {
+        return Fixed(0);
+    }
+
+    // Points left of the y axis are turned half way round first, since the
+    // rotations only reach 90 degrees either way.
+    let (mut x, mut y, offset) = match (x.0 < 0, y.0 < 0) {
+        (false, _) => (x.0, y.0, 0),
+        (true, false) => (-x.0, -y.0, 180),
+        (true, true) => (-x.0, -y.0, -180),
+    };
+    // Scales the point to about 2^40 so the shifts keep enough bits.
+    let bits = 64 - x.max(y.abs()).leading_zeros() as i32;
+    let shift = 40 - bits;
+    if shift >= 0 {
+        (x, y) = (x << shift, y << shift);
+    } else {
+        (x, y) = (x >> -shift, y >> -shift);
+    }
+
+    let mut z = 0;
+    for (i, atan) in ATANS.iter().enumerate() {
+        let (dx, dy) = (y >> i, x >> i);
+        if y > 0 {
+            (x, y, z) = (x + dx, y - dy, z + atan);
+        } else {
+            (x, y, z) = (x - dx, y + dy, z - atan);
+        }
+    }
+
+    Fixed(offset * ONE) + Fixed::from_cordic(z)
+}
+
+/// The length of the vector `(x, y)`, rounded down.
+pub fn hypot(x: Fixed, y: Fixed) -> Fixed {
+    // The square root of a square with 32 bits after the point has 16.
+    let square = (x.0 as i128).pow(2) as u128 + (y.0 as i128).pow(2) as u128;
+    Fixed(square.isqrt().min(i64::MAX as u128) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed(value: f64) -> Fixed {
+        Fixed::from_f64(value)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(fixed(0.1), Fixed(6554));
+        assert_eq!(fixed(-2.5).to_f64(), -2.5);
+        assert_eq!(fixed(1.5) + fixed(2.25), fixed(3.75));
+        assert_eq!(fixed(1.5) - fixed(2.25), fixed(-0.75));
+        assert_eq!(fixed(1.5) * fixed(-2.25), fixed(-3.375));
+        // 0.1 * 0.1 is 42949636 / 2^32, which rounds to 655 / 2^16.
+        assert_eq!(fixed(0.1) * fixed(0.1), Fixed(655));
+    }
+
+    #[test]
+    fn test_sin_cos() {
+        assert_eq!(sin_cos(fixed(0.0)), (fixed(0.0), fixed(1.0)));
+        assert_eq!(sin_cos(fixed(30.0)).0, fixed(0.5));
+        assert_eq!(sin_cos(fixed(60.0)).1, fixed(0.5));
+        assert_eq!(sin_cos(fixed(90.0)), (fixed(1.0), fixed(0.0)));
+        assert_eq!(sin_cos(fixed(180.0)), (fixed(0.0), fixed(-1.0)));
+        assert_eq!(sin_cos(fixed(-90.0)), (fixed(-1.0), fixed(0.0)));
+        assert_eq!(sin_cos(fixed(405.0)), sin_cos(fixed(45.0)));
+
+        // Every whole degree is within a step of the true value.
+        for degrees in -360..=360 {
+            let (sin, cos) = sin_cos(fixed(degrees as f64));
+            let (true_sin, true_cos) = (degrees as f64).to_radians().sin_cos();
+            assert!((sin.to_f64() - true_sin).abs() <= 1.0 / 65536.0);
+            assert!((cos.to_f64() - true_cos).abs() <= 1.0 / 65536.0);
+        }
+    }
+
+    #[test]
+    fn test_atan2() {
+        assert_eq!(atan2(fixed(0.0), fixed(0.0)), fixed(0.0));
+        assert_eq!(atan2(fixed(0.0), fixed(5.0)), fixed(0.0));
+        assert_eq!(atan2(fixed(5.0), fixed(5.0)), fixed(45.0));
+        assert_eq!(atan2(fixed(5.0), fixed(0.0)), fixed(90.0));
+        assert_eq!(atan2(fixed(0.0), fixed(-5.0)), fixed(180.0));
+        assert_eq!(atan2(fixed(-5.0), fixed(-5.0)), fixed(-135.0));
+        assert_eq!(atan2(fixed(-5000.0), fixed(0.0)), fixed(-90.0));
+    }
+
+    #[test]
+    fn test_hypot() {
+        assert_eq!(hypot(fixed(3.0), fixed(-4.0)), fixed(5.0));
+        assert_eq!(hypot(fixed(0.0), fixed(0.0)), fixed(0.0));
+    }
+}
@@ -0,0 +1,97 @@
+//! Where `READWORD` and `READNUMBER` read their input.
+//!
+//! Input is read a line at a time from standard input, unless the embedder
+//! sets `env.input` to another source, such as the lines of a test.
+//!
+//! # Example
+//!
+//! ```rust
+//! env.input = Some(Box::new(Lines::new(["6"])));
+//! // MAKE "petals READNUMBER
+//! ```
+
+use std::{collections::VecDeque, fmt::Debug, io::BufRead};
+
+use crate::environment::Environment;
+
+use super::errors::{ExecutionError, ExecutionErrorKind};
+
+pub trait InputSource: Debug {
+    /// The next line of input without its line ending, or `None` once the
+    /// input has run out.
+    fn read_line(&mut self) -> std::io::Result<Option<String>>;
+}
+
+/// Reads from the process's standard input.
+#[derive(Debug)]
+pub struct Stdin;
+
+impl InputSource for Stdin {
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        let mut line = String::new();
+        if std::io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+}
+
+/// Input given up front, one line at a time.
+#[derive(Debug, Default)]
+pub struct Lines(VecDeque<String>);
+
+impl Lines {
+    pub fn new<S: Into<String>>(lines: impl IntoIterator<Item = S>) -> Lines {
+        Lines(lines.into_iter().map(Into::into).collect())
+    }
+}
+
+impl InputSource for Lines {
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        Ok(self.0.pop_front())
+    }
+}
+
+/// Reads the next line of input for `keyword`, trimmed of surrounding
+/// whitespace, from `env.input` or else standard input.
+pub fn read_input(env: &mut Environment, keyword: &str) -> Result<String, ExecutionError> {
+    let line = match env.input.as_mut() {
+        Some(input) => input.read_line(),
+        None => Stdin.read_line(),
+    };
+    match line {
+        Ok(Some(line)) => Ok(line.trim().to_string()),
+        Ok(None) => Err(ExecutionError {
+            kind: ExecutionErrorKind::Io {
+                msg: format!("{} reached the end of the input", keyword),
+            },
+        }),
+        Err(e) => Err(ExecutionError {
+            kind: ExecutionErrorKind::Io {
+                msg: format!("{} could not read the input: {}", keyword, e),
+            },
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_input() {
+        let mut env = Environment {
+            input: Some(Box::new(Lines::new(["  hello ", "6"]))),
+            ..Default::default()
+        };
+
+        assert_eq!(read_input(&mut env, "READWORD").unwrap(), "hello");
+        assert_eq!(read_input(&mut env, "READWORD").unwrap(), "6");
+        assert!(matches!(
+            read_input(&mut env, "READWORD"),
+            Err(ExecutionError {
+                kind: ExecutionErrorKind::Io { .. }
+            })
+        ));
+    }
+}
@@ -0,0 +1,190 @@
+//! Resource limits enforced while a script runs.
+//!
+//! `--max-memory` bounds the memory held by the parsed script, its variables
+//! and the shapes drawn so far. The usage is an estimate from the size of each
+//! item rather than a measurement of the heap, but it grows with exactly the
+//! things a runaway loop piles up, so the script stops with a clear error long
+//! before the process runs out of memory.
+//!
+//! `--max-depth` separately bounds how deeply blocks and procedure calls may
+//! nest, which catches runaway recursion before it overflows the stack. It
+//! defaults to `DEFAULT_MAX_DEPTH`, which leaves room to spare in the main
+//! thread's usual 8 MiB stack even in debug builds.
+
+use std::mem::size_of;
+
+use crate::{
+    ast::{ASTNode, ControlFlow, Expression},
+    canvas::Element,
+};
+
+use super::errors::{ExecutionError, ExecutionErrorKind};
+
+/// How deeply blocks and procedure calls may nest unless `--max-depth` says
+/// otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Bytes the script may use, or `None` for no limit.
+    pub max_memory: Option<usize>,
+    /// Nodes in the parsed script, including procedure bodies.
+    pub ast_nodes: usize,
+    /// How deeply blocks and procedure calls may nest, or `None` for no limit.
+    pub max_depth: Option<usize>,
+    /// How deeply blocks and procedure calls are currently nested.
+    pub depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_memory: None,
+            ast_nodes: 0,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            depth: 0,
+        }
+    }
+}
+
+/// Parses a size such as `512K`, `64M` or `1G` into bytes. A bare number is
+/// taken as bytes.
+pub fn parse_size(size: &str) -> Result<usize, String> {
+    let size = size.trim();
+    let (digits, multiplier) = match size.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&size[..size.len() - 1], 1 << 10),
+        Some('M') => (&size[..size.len() - 1], 1 << 20),
+        Some('G') => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("Invalid size {:?}, expected e.g. 512K, 64M or 1G", size))
+}
+
+/// Counts the nodes in an AST, including those inside blocks.
+pub fn count_nodes(ast: &[ASTNode]) -> usize {
+    ast.iter()
+        .map(|node| match node {
+            ASTNode::ControlFlow(ControlFlow::If { block, .. })
+            | ASTNode::ControlFlow(ControlFlow::While { block, .. })
+            | ASTNode::ControlFlow(ControlFlow::DoWhile { block, .. })
+            | ASTNode::ControlFlow(ControlFlow::Until { block, .. })
+            | ASTNode::ControlFlow(ControlFlow::Repeat { block, .. })
+            | ASTNode::ControlFlow(ControlFlow::ForEach { block, .. })
+            | ASTNode::ControlFlow(ControlFlow::Catch { block, .. })
+            | ASTNode::ControlFlow(ControlFlow::For { block, .. }) => 1 + count_nodes(block),
+            _ => 1,
+        })
+        .sum()
+}
+
+impl Limits {
+    /// Estimates the bytes used by the script, its variables and its shapes.
+    pub fn estimate(&self, vars: usize, elements: usize) -> usize {
+        self.ast_nodes * size_of::<ASTNode>()
+            + vars * (size_of::<String>() + size_of::<Expression>())
+            + elements * size_of::<Element>()
+    }
+
+    /// Returns an error if the estimated usage is over the limit.
+    pub fn check_memory(&self, vars: usize, elements: usize) -> Result<(), ExecutionError> {
+        match self.max_memory {
+            Some(limit) if self.estimate(vars, elements) > limit => Err(ExecutionError {
+                kind: ExecutionErrorKind::MemoryLimit {
+                    limit,
+                    ast_nodes: self.ast_nodes,
+                    vars,
+                    elements,
+                },
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Enters a nested block, returning an error if that is deeper than
+    /// allowed.
+    pub fn enter(&mut self) -> Result<(), ExecutionError> {
+        self.depth += 1;
+        match self.max_depth {
+            Some(limit) if self.depth > limit => {
+                let depth = self.depth;
+                self.depth -= 1;
+                Err(ExecutionError {
+                    kind: ExecutionErrorKind::DepthLimit { depth, limit },
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Leaves a block entered with `enter`.
+    pub fn exit(&mut self) {
+        self.depth -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Command, Condition};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("100"), Ok(100));
+        assert_eq!(parse_size("2k"), Ok(2048));
+        assert_eq!(parse_size("64M"), Ok(64 << 20));
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("99999999999999999999G").is_err());
+        assert!(parse_size(&format!("{}G", usize::MAX >> 20)).is_err());
+    }
+
+    #[test]
+    fn test_count_nodes() {
+        let ast = vec![
+            ASTNode::Command(Command::PenDown),
+            ASTNode::ControlFlow(ControlFlow::While {
+                condition: Condition::Equals(Expression::Float(1.0), Expression::Float(1.0)),
+                block: vec![
+                    ASTNode::Command(Command::Forward(Expression::Float(1.0))),
+                    ASTNode::Command(Command::Turn(Expression::Float(1.0))),
+                ],
+            }),
+        ];
+
+        assert_eq!(count_nodes(&ast), 4);
+    }
+
+    #[test]
+    fn test_check_memory() {
+        let limits = Limits {
+            max_memory: Some(10 * size_of::<Element>()),
+            ..Default::default()
+        };
+
+        assert!(limits.check_memory(0, 10).is_ok());
+        assert!(limits.check_memory(0, 11).is_err());
+        assert!(Limits::default().check_memory(1000, 1000).is_ok());
+    }
+
+    #[test]
+    fn test_enter() {
+        let mut limits = Limits {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+
+        assert!(limits.enter().is_ok());
+        assert!(limits.enter().is_ok());
+        assert!(limits.enter().is_err());
+        assert_eq!(limits.depth, 2);
+
+        limits.exit();
+        assert!(limits.enter().is_ok());
+    }
+}
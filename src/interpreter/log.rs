@@ -0,0 +1,190 @@
+//! The per-command execution log written by `--log`.
+//!
+//! Each executed command appends one JSON object on its own line, holding the
+//! step index, the time since execution started, the command, its evaluated
+//! arguments and whichever parts of the turtle's state it changed.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    time::Instant,
+};
+
+use serde_json::{json, Map, Value};
+use unsvg::Color;
+
+use crate::{
+    ast::Command,
+    canvas::{fmt_color, palette::Palette},
+};
+
+use super::turtle::Turtle;
+
+/// The parts of the turtle's state recorded in the log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TurtleState {
+    pub x: f32,
+    pub y: f32,
+    pub heading: f32,
+    pub pen_down: bool,
+    pub pen_color: Color,
+}
+
+impl From<&Turtle<'_>> for TurtleState {
+    fn from(turtle: &Turtle) -> Self {
+        TurtleState {
+            x: turtle.x as f32,
+            y: turtle.y as f32,
+            heading: turtle.heading,
+            pen_down: turtle.pen_down,
+            pen_color: turtle.pen_color,
+        }
+    }
+}
+
+impl TurtleState {
+    /// The fields of `after` that differ from `self`, as a JSON object.
+    fn delta(&self, after: &TurtleState, palette: &Palette) -> Map<String, Value> {
+        let mut delta = Map::new();
+        if self.x != after.x {
+            delta.insert("x".to_string(), json!(after.x));
+        }
+        if self.y != after.y {
+            delta.insert("y".to_string(), json!(after.y));
+        }
+        if self.heading != after.heading {
+            delta.insert("heading".to_string(), json!(after.heading));
+        }
+        if self.pen_down != after.pen_down {
+            delta.insert("pen_down".to_string(), json!(after.pen_down));
+        }
+        if self.pen_color != after.pen_color {
+            delta.insert(
+                "pen_color".to_string(),
+                color_to_json(after.pen_color, palette),
+            );
+        }
+        delta
+    }
+}
+
+/// A color as it is logged: its palette index, or its hex code if it isn't in
+/// the palette.
+pub fn color_to_json(color: Color, palette: &Palette) -> Value {
+    match palette.index_of(color) {
+        Some(index) => json!(index),
+        None => json!(fmt_color(color)),
+    }
+}
+
+pub struct ExecLog {
+    writer: Box<dyn Write>,
+    step: usize,
+    start: Instant,
+}
+
+impl fmt::Debug for ExecLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExecLog").field("step", &self.step).finish()
+    }
+}
+
+impl ExecLog {
+    pub fn new(writer: Box<dyn Write>) -> ExecLog {
+        ExecLog {
+            writer,
+            step: 0,
+            start: Instant::now(),
+        }
+    }
+
+    /// Appends a line for a command that has just executed. Arguments that
+    /// could not be evaluated are written as `null`.
+    pub fn record(
+        &mut self,
+        command: &Command,
+        args: &[Option<f32>],
+        before: &TurtleState,
+        after: &TurtleState,
+        palette: &Palette,
+    ) -> io::Result<()> {
+        let entry = json!({
+            "step": self.step,
+            "t_us": self.start.elapsed().as_micros() as u64,
+            "command": command.keyword(),
+            "args": args,
+            "delta": before.delta(after, palette),
+        });
+        self.step += 1;
+
+        writeln!(self.writer, "{}", entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use unsvg::COLORS;
+
+    use crate::ast::Expression;
+
+    use super::*;
+
+    /// A writer that can be read back after the log has taken ownership.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let buf = SharedBuf::default();
+        let mut log = ExecLog::new(Box::new(buf.clone()));
+
+        let before = TurtleState {
+            x: 50.0,
+            y: 50.0,
+            heading: 0.0,
+            pen_down: true,
+            pen_color: COLORS[7],
+        };
+        let after = TurtleState {
+            y: 40.0,
+            ..before.clone()
+        };
+        let command = Command::Forward(Expression::Float(10.0));
+        log.record(
+            &command,
+            &[Some(10.0)],
+            &before,
+            &after,
+            &Palette::default(),
+        )
+        .unwrap();
+        log.record(&Command::PenUp, &[], &after, &after, &Palette::default())
+            .unwrap();
+
+        let output = String::from_utf8(buf.0.borrow().clone()).unwrap();
+        let lines: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["step"], json!(0));
+        assert_eq!(lines[0]["command"], json!("FORWARD"));
+        assert_eq!(lines[0]["args"], json!([10.0]));
+        assert_eq!(lines[0]["delta"], json!({ "y": 40.0 }));
+        assert_eq!(lines[1]["step"], json!(1));
+        assert_eq!(lines[1]["delta"], json!({}));
+    }
+}
@@ -1,13 +1,20 @@
 //! Contains helper functions to match expressions to their values.
-//! Defaults to a f32 value and returns an ExecutionError if
+//! Defaults to a f64 value and returns an ExecutionError if
 //! the expression is not parsable as a float.
 
-use std::collections::HashMap;
-
-use crate::ast::{Expression, Math, Query};
+use crate::{
+    ast::{Expression, Function, ListOp, Math, Query},
+    environment::Environment,
+};
 
 use super::{
+    clock::DateTime,
+    control_flows::{call_procedure, with_item},
     errors::{ExecutionError, ExecutionErrorKind},
+    input::read_input,
+    noise::noise,
+    precision::Precision,
+    providers::resolve,
     turtle::Turtle,
 };
 
@@ -16,23 +23,118 @@ use super::{
 /// # Example
 ///
 /// ```rust
-/// let mut image = Image::new(100, 100);
-/// let turtle = Turtle::new(&mut image);
+/// let mut canvas = Canvas::new(100, 100);
+/// let turtle = Turtle::new(&mut canvas);
 ///
-/// let res = match_queries(&Query::XCor, &turtle);
+/// let res = match_queries(&Query::XCor, &Environment::default(), &turtle).unwrap();
 /// assert_eq!(res, 50.0);
 /// ```
-fn match_queries(query: &Query, turtle: &Turtle) -> f32 {
+fn match_queries(
+    query: &Query,
+    env: &mut Environment,
+    turtle: &mut Turtle,
+) -> Result<f64, ExecutionError> {
     match query {
-        Query::XCor => turtle.x,
-        Query::YCor => turtle.y,
-        Query::Heading => turtle.heading as f32,
-        Query::Color => turtle.pen_color as f32,
+        Query::XCor => Ok(turtle.xcor()),
+        Query::YCor => Ok(turtle.ycor()),
+        Query::Heading => {
+            let heading = env.angle_unit.in_unit(turtle.heading as f64);
+            Ok(env.precision.round(heading))
+        }
+        Query::Color => match pen_color_value(turtle, env) {
+            Expression::Float(index) => Ok(index),
+            value => Err(not_a_number(&value)),
+        },
+        Query::DeltaX => Ok(turtle.delta_x()),
+        Query::DeltaY => Ok(turtle.delta_y()),
+        Query::Odometer => Ok(env.precision.round(turtle.odometer)),
+        Query::Tripmeter => Ok(env.precision.round(turtle.tripmeter)),
+        Query::MinX => Ok(bound(turtle, |(min_x, _, _, _)| min_x)),
+        Query::MaxX => Ok(bound(turtle, |(_, _, max_x, _)| max_x)),
+        Query::MinY => Ok(bound(turtle, |(_, min_y, _, _)| min_y)),
+        Query::MaxY => Ok(bound(turtle, |(_, _, _, max_y)| max_y)),
+        Query::GetEnv(name) => match get_env(name, env)? {
+            Expression::Float(value) => Ok(value),
+            Expression::Word(word) => word_to_number(&word),
+            value => Err(not_a_number(&value)),
+        },
+        Query::Provided(name) => match resolve(&env.providers, name) {
+            Some(value) => Ok(env.precision.round(value)),
+            None => Err(ExecutionError {
+                kind: ExecutionErrorKind::QueryNotFound { name: name.clone() },
+            }),
+        },
+        Query::ReadNumber => {
+            let line = read_input(env, "READNUMBER")?;
+            line.parse::<f64>()
+                .map(|value| env.precision.round(value))
+                .map_err(|_| ExecutionError {
+                    kind: ExecutionErrorKind::TypeError {
+                        expected: format!("a number for READNUMBER, not {:?}", line),
+                    },
+                })
+        }
+        Query::Year => Ok(DateTime::now(env.deterministic).year as f64),
+        Query::Month => Ok(DateTime::now(env.deterministic).month as f64),
+        Query::Day => Ok(DateTime::now(env.deterministic).day as f64),
+        Query::Hour => Ok(DateTime::now(env.deterministic).hour as f64),
+        Query::Minute => Ok(DateTime::now(env.deterministic).minute as f64),
+        Query::Second => Ok(DateTime::now(env.deterministic).second as f64),
+        Query::Touching(args) => {
+            let x = match_expressions(&args.0, env, turtle)?;
+            let y = match_expressions(&args.1, env, turtle)?;
+            let radius = match_expressions(&args.2, env, turtle)?;
+            let touching = turtle.canvas.touching(x as f32, y as f32, radius as f32);
+            Ok(if touching { 1.0 } else { 0.0 })
+        }
+        Query::Intersects(distance) => {
+            let distance = match_expressions(distance, env, turtle)?;
+            let (x, y) = turtle.forward_end(distance);
+            let from = (turtle.x as f32, turtle.y as f32);
+            let intersects = turtle.canvas.intersects(from, (x as f32, y as f32));
+            Ok(if intersects { 1.0 } else { 0.0 })
+        }
+        Query::Towards(point) => {
+            let x = match_expressions(&point.0, env, turtle)?;
+            let y = match_expressions(&point.1, env, turtle)?;
+            let heading = env.angle_unit.in_unit(turtle.towards(x, y));
+            Ok(env.precision.round(heading))
+        }
+        Query::Distance(point) => {
+            let x = match_expressions(&point.0, env, turtle)?;
+            let y = match_expressions(&point.1, env, turtle)?;
+            Ok(env.precision.round(turtle.distance(x, y)))
+        }
     }
 }
 
+/// Reads an environment variable for `GETENV`, as a number if it is one and
+/// as a word otherwise.
+///
+/// This is refused under `--deterministic` and on WASM, where the same script
+/// must always produce the same drawing.
+fn get_env(name: &str, env: &Environment) -> Result<Expression, ExecutionError> {
+    if env.deterministic || cfg!(target_family = "wasm") {
+        return Err(ExecutionError {
+            kind: ExecutionErrorKind::InvalidArgument {
+                msg: format!("GETENV {:?} is disabled in deterministic mode", name),
+            },
+        });
+    }
+
+    let value = std::env::var(name).map_err(|_| ExecutionError {
+        kind: ExecutionErrorKind::VariableNotFound {
+            var: name.to_string(),
+        },
+    })?;
+    Ok(match value.trim().parse::<f64>() {
+        Ok(number) => Expression::Float(env.precision.round(number)),
+        Err(_) => Expression::Word(value),
+    })
+}
+
 /// Helper function to match expressions to their values. This defaults for
-/// f32 values. We return an ExecutionError if the expression is not parsable
+/// f64 values, rounded to the precision set in the environment. We return an ExecutionError if the expression is not parsable
 /// as a float.
 ///
 /// # Example
@@ -40,55 +142,300 @@ fn match_queries(query: &Query, turtle: &Turtle) -> f32 {
 /// ```rust
 /// let expr = Expression::Float(1.0);
 ///
-/// let res = match_expressions(&expr, &HashMap::new(), &Turtle::new()).unwrap();
+/// let res = match_expressions(&expr, &Environment::default(), &Turtle::new()).unwrap();
 /// assert_eq!(res, 1.0);
 /// ```
 pub fn match_expressions(
     expr: &Expression,
-    variables: &HashMap<String, Expression>,
-    turtle: &Turtle,
-) -> Result<f32, ExecutionError> {
+    env: &mut Environment,
+    turtle: &mut Turtle,
+) -> Result<f64, ExecutionError> {
+    let val = match expr {
+        Expression::Float(val) => Ok(env.precision.round(*val)),
+        // NOTE: What is the point of this is we are just casting it to f64?
+        Expression::Number(val) => Ok(*val as f64),
+        Expression::Usize(val) => Ok(*val as f64),
+        Expression::Query(query) => match_queries(query, env, turtle),
+        Expression::Variable(var) => get_var_val(var, env, turtle),
+        Expression::Math(expr) => Ok(eval_math(expr, env, turtle)?),
+        Expression::Function(function) => eval_function(function, env, turtle),
+        Expression::Word(word) => word_to_number(word),
+        Expression::Concat(_) => word_to_number(&match_word(expr, env, turtle)?),
+        Expression::List(_)
+        | Expression::ListOp(_)
+        | Expression::Call(..)
+        | Expression::GetProp(..)
+        | Expression::Array(_)
+        | Expression::MakeArray(_)
+        | Expression::ReadWord => match match_value(expr, env, turtle)? {
+            Expression::Float(val) => Ok(val),
+            Expression::Word(word) => word_to_number(&word),
+            value => Err(not_a_number(&value)),
+        },
+    }?;
+
+    // Catch overflow and the like here, where we still know which
+    // expression went wrong, rather than drawing garbage coordinates.
+    if !val.is_finite() {
+        return Err(ExecutionError {
+            kind: ExecutionErrorKind::NonFinite {
+                expr: expr.to_string(),
+                value: val,
+            },
+        });
+    }
+    Ok(val)
+}
+
+/// Evaluates an expression as a word, such as for `WORD` and `LABEL`.
+/// Numbers are written the way Logo prints them, so `"10` gives `10`.
+///
+/// # Example
+///
+/// ```rust
+/// let words = Expression::Concat(Box::new((
+///     Expression::Word("turtle".to_string()),
+///     Expression::Float(2.0),
+/// )));
+///
+/// let res = match_word(&words, &mut Environment::default(), &mut turtle).unwrap();
+/// assert_eq!(res, "turtle2");
+/// ```
+pub fn match_word(
+    expr: &Expression,
+    env: &mut Environment,
+    turtle: &mut Turtle,
+) -> Result<String, ExecutionError> {
     match expr {
-        Expression::Float(val) => Ok(*val),
-        // NOTE: What is the point of this is we are just casting it to f32?
-        Expression::Number(val) => Ok(*val as f32),
-        Expression::Usize(val) => Ok(*val as f32),
-        Expression::Query(query) => Ok(match_queries(query, turtle)),
-        Expression::Variable(var) => get_var_val(var, variables, turtle),
-        Expression::Math(expr) => Ok(eval_math(expr, variables, turtle)?),
+        Expression::Concat(words) => {
+            let lhs = match_word(&words.0, env, turtle)?;
+            let rhs = match_word(&words.1, env, turtle)?;
+            Ok(lhs + &rhs)
+        }
+        _ => Ok(value_to_word(&match_value(expr, env, turtle)?)),
+    }
+}
+
+/// Evaluates an expression that may give a word or a list as well as a
+/// number, giving back a `Word`, `List` or `Float`.
+///
+/// # Example
+///
+/// ```rust
+/// let list = Expression::List(vec![Expression::Float(90.0), Expression::Float(45.0)]);
+/// let first = Expression::ListOp(Box::new(ListOp::First(list)));
+///
+/// let res = match_value(&first, &mut Environment::default(), &mut turtle).unwrap();
+/// assert_eq!(res, Expression::Float(90.0));
+/// ```
+pub fn match_value(
+    expr: &Expression,
+    env: &mut Environment,
+    turtle: &mut Turtle,
+) -> Result<Expression, ExecutionError> {
+    match expr {
+        Expression::Word(_) | Expression::List(_) | Expression::Array(_) => Ok(expr.clone()),
+        Expression::Concat(_) => Ok(Expression::Word(match_word(expr, env, turtle)?)),
+        Expression::Variable(var) => match env.vars.get(var) {
+            Some(value @ (Expression::Word(_) | Expression::List(_) | Expression::Array(_))) => {
+                Ok(value.clone())
+            }
+            _ => Ok(Expression::Float(match_expressions(expr, env, turtle)?)),
+        },
+        Expression::ListOp(op) => eval_list_op(op, env, turtle),
+        Expression::Call(name, args) => {
+            call_procedure(name, args, turtle, env)?.ok_or_else(|| ExecutionError {
+                kind: ExecutionErrorKind::NoOutput { name: name.clone() },
+            })
+        }
+        Expression::MakeArray(size) => {
+            let size = match_expressions(size, env, turtle)?;
+            if size < 0.0 || size.fract() != 0.0 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: format!("ARRAY needs a whole number of items, not {}", size),
+                    },
+                });
+            }
+            Ok(Expression::Array(vec![
+                Expression::Float(0.0);
+                size as usize
+            ]))
+        }
+        Expression::ReadWord => Ok(Expression::Word(read_input(env, "READWORD")?)),
+        Expression::Query(Query::Color) => Ok(pen_color_value(turtle, env)),
+        Expression::Query(Query::GetEnv(name)) => get_env(name, env),
+        Expression::GetProp(list, prop) => Ok(env
+            .props
+            .get(list)
+            .and_then(|props| props.get(prop))
+            .cloned()
+            .unwrap_or(Expression::List(vec![]))),
+        _ => Ok(Expression::Float(match_expressions(expr, env, turtle)?)),
+    }
+}
+
+/// The pen color as `COLOR` gives it: its palette index, or a list of its
+/// red, green and blue if it isn't in the palette.
+pub fn pen_color_value(turtle: &Turtle, env: &Environment) -> Expression {
+    let color = turtle.pen_color;
+    match env.palette.index_of(color) {
+        Some(index) => Expression::Float(index as f64),
+        None => Expression::List(
+            [color.red, color.green, color.blue]
+                .into_iter()
+                .map(|channel| Expression::Float(channel as f64))
+                .collect(),
+        ),
+    }
+}
+
+/// Evaluates a function taking apart a list. Words and numbers are taken
+/// apart as lists of their characters, so `FIRST "turtle` is `t`.
+fn eval_list_op(
+    op: &ListOp,
+    env: &mut Environment,
+    turtle: &mut Turtle,
+) -> Result<Expression, ExecutionError> {
+    let value = match_value(op.list(), env, turtle)?;
+    let items = list_items(&value);
+
+    let empty = || ExecutionError {
+        kind: ExecutionErrorKind::InvalidArgument {
+            msg: format!("{} needs a list or word that isn't empty", op.keyword()),
+        },
+    };
+    let item = match op {
+        ListOp::First(_) => items.into_iter().next().ok_or_else(empty)?,
+        ListOp::ButFirst(_) if items.is_empty() => return Err(empty()),
+        ListOp::ButFirst(_) => match value {
+            Expression::List(_) | Expression::Array(_) => Expression::List(items[1..].to_vec()),
+            _ => Expression::Word(value_to_word(&value).chars().skip(1).collect()),
+        },
+        ListOp::Item(index, _) => {
+            let index = match_expressions(index, env, turtle)?;
+            if index < 1.0 || index.fract() != 0.0 || index > items.len() as f64 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::InvalidArgument {
+                        msg: format!(
+                            "ITEM {} is out of range for a list of {} items",
+                            index,
+                            items.len()
+                        ),
+                    },
+                });
+            }
+            items[index as usize - 1].clone()
+        }
+        ListOp::Count(_) => Expression::Float(items.len() as f64),
+        ListOp::Map(template, _) => {
+            let mut mapped = Vec::with_capacity(items.len());
+            for item in items {
+                mapped.push(with_item(item, env, |env| {
+                    match_value(template, env, turtle)
+                })?);
+            }
+            Expression::List(mapped)
+        }
+    };
+
+    // A single character such as the `1` of `FIRST "12` is still a number.
+    Ok(match item {
+        Expression::Word(word) => match word.parse::<f64>() {
+            Ok(val) if val.is_finite() => Expression::Float(val),
+            _ => Expression::Word(word),
+        },
+        item => item,
+    })
+}
+
+/// The items of a list or array, or the characters of a word or number.
+pub fn list_items(value: &Expression) -> Vec<Expression> {
+    match value {
+        Expression::List(items) | Expression::Array(items) => items.clone(),
+        _ => value_to_word(value)
+            .chars()
+            .map(|c| Expression::Word(c.to_string()))
+            .collect(),
+    }
+}
+
+/// Writes a value the way Logo prints it: numbers such as `10` without a
+/// fraction and lists as their items separated by spaces, with any lists in
+/// them in brackets and arrays in braces.
+fn value_to_word(value: &Expression) -> String {
+    match value {
+        Expression::Word(word) => word.clone(),
+        Expression::Float(val) => val.to_string(),
+        Expression::List(items) | Expression::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                Expression::List(_) => format!("[{}]", value_to_word(item)),
+                Expression::Array(_) => format!("{{{}}}", value_to_word(item)),
+                _ => value_to_word(item),
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => value.to_string(),
     }
 }
 
+/// The error for a list or array used where a number is expected.
+fn not_a_number(value: &Expression) -> ExecutionError {
+    let value = match value {
+        Expression::Array(_) => "an array",
+        _ => "a list",
+    };
+    ExecutionError {
+        kind: ExecutionErrorKind::TypeError {
+            expected: format!("a number, not {}", value),
+        },
+    }
+}
+
+/// Reads a word as a number, for words such as `"1` joined with `WORD`.
+fn word_to_number(word: &str) -> Result<f64, ExecutionError> {
+    word.parse::<f64>().map_err(|_| ExecutionError {
+        kind: ExecutionErrorKind::TypeError {
+            expected: format!("a number, not the word \"{}", word),
+        },
+    })
+}
+
 /// Gets the value of a variable from the variables hashmap.
 ///
 /// # Example
 ///
 /// ```rust
-/// let mut variables = HashMap::new();
-/// variables.insert("x".to_string(), Expression::Float(1.0));
+/// let mut env = Environment::default();
+/// env.vars.insert("x".to_string(), Expression::Float(1.0));
 ///
-/// let image = Image::new(100, 100);
-/// let turtle = Turtle::new(&mut image);
+/// let canvas = Canvas::new(100, 100);
+/// let turtle = Turtle::new(&mut canvas);
 ///
-/// let res = get_var_val("x", &variables, &turtle).unwrap();
+/// let res = get_var_val("x", &env, &turtle).unwrap();
 /// assert_eq!(res, 1.0);
 /// ```
 fn get_var_val(
     var: &str,
-    variables: &HashMap<String, Expression>,
-    turtle: &Turtle,
-) -> Result<f32, ExecutionError> {
+    env: &mut Environment,
+    turtle: &mut Turtle,
+) -> Result<f64, ExecutionError> {
     // TODO: Hate this, refactor.
-    if let Some(Expression::Float(val)) = variables.get(var) {
+    if let Some(Expression::Float(val)) = env.vars.get(var) {
         Ok(*val)
-    } else if let Some(Expression::Number(val)) = variables.get(var) {
-        Ok(*val as f32)
-    } else if let Some(Expression::Usize(val)) = variables.get(var) {
-        Ok(*val as f32)
-    } else if let Some(Expression::Query(query)) = variables.get(var) {
-        Ok(match_queries(query, turtle))
-    } else if let Some(Expression::Math(expr)) = variables.get(var) {
-        Ok(eval_math(expr, variables, turtle)?)
+    } else if let Some(Expression::Number(val)) = env.vars.get(var) {
+        Ok(*val as f64)
+    } else if let Some(Expression::Usize(val)) = env.vars.get(var) {
+        Ok(*val as f64)
+    } else if let Some(Expression::Query(query)) = env.vars.get(var).cloned() {
+        match_queries(&query, env, turtle)
+    } else if let Some(Expression::Math(expr)) = env.vars.get(var).cloned() {
+        Ok(eval_math(&expr, env, turtle)?)
+    } else if let Some(Expression::Word(word)) = env.vars.get(var) {
+        word_to_number(word)
+    } else if let Some(value @ (Expression::List(_) | Expression::Array(_))) = env.vars.get(var) {
+        Err(not_a_number(value))
     } else {
         Err(ExecutionError {
             kind: ExecutionErrorKind::VariableNotFound {
@@ -106,19 +453,19 @@ fn get_var_val(
 /// let lhs = Expression::Float(1.0);
 /// let rhs = Expression::Float(2.0);
 ///
-/// let res = eval_binary_op(&lhs, &rhs, &HashMap::new(), &Turtle::new(), |a, b| a + b).unwrap();
+/// let res = eval_binary_op(&lhs, &rhs, &Environment::default(), &Turtle::new(), |a, b| a + b).unwrap();
 /// assert_eq!(res, 3.0);
 /// ```
 fn eval_binary_op(
     lhs: &Expression,
     rhs: &Expression,
-    variables: &HashMap<String, Expression>,
-    turtle: &Turtle,
-    op: fn(f32, f32) -> f32,
-) -> Result<f32, ExecutionError> {
-    let lhs_val = match_expressions(lhs, variables, turtle)?;
-    let rhs_val = match_expressions(rhs, variables, turtle)?;
-    Ok(op(lhs_val, rhs_val))
+    env: &mut Environment,
+    turtle: &mut Turtle,
+    op: fn(f64, f64) -> f64,
+) -> Result<f64, ExecutionError> {
+    let lhs_val = match_expressions(lhs, env, turtle)?;
+    let rhs_val = match_expressions(rhs, env, turtle)?;
+    Ok(env.precision.round(op(lhs_val, rhs_val)))
 }
 
 /// Evaluates a logical operation and returns the result.
@@ -129,18 +476,18 @@ fn eval_binary_op(
 /// let lhs = Expression::Float(1.0);
 /// let rhs = Expression::Float(2.0);
 ///
-/// let res = eval_logical_op(&lhs, &rhs, &HashMap::new(), &Turtle::new(), |a, b| a + b);
+/// let res = eval_logical_op(&lhs, &rhs, &Environment::default(), &Turtle::new(), |a, b| a + b);
 /// assert_eq!(res, Ok(1.0));
 /// ```
 fn eval_logical_op(
     lhs: &Expression,
     rhs: &Expression,
-    variables: &HashMap<String, Expression>,
-    turtle: &Turtle,
-    op: fn(f32, f32) -> f32,
-) -> Result<f32, ExecutionError> {
-    let lhs_val = match_expressions(lhs, variables, turtle)?;
-    let rhs_val = match_expressions(rhs, variables, turtle)?;
+    env: &mut Environment,
+    turtle: &mut Turtle,
+    op: fn(f64, f64) -> f64,
+) -> Result<f64, ExecutionError> {
+    let lhs_val = match_expressions(lhs, env, turtle)?;
+    let rhs_val = match_expressions(rhs, env, turtle)?;
     if op(lhs_val, rhs_val) != 0.0 {
         Ok(1.0)
     } else {
@@ -156,120 +503,292 @@ fn eval_logical_op(
 /// ```rust
 /// let expr = Math::Add(Expression::Float(1.0), Expression::Float(2.0));
 ///
-/// let res = eval_math(&expr, &HashMap::new(), &Turtle::new()).unwrap();
+/// let res = eval_math(&expr, &Environment::default(), &Turtle::new()).unwrap();
 /// assert_eq!(res, 3.0);
 /// ```
 fn eval_math(
     expr: &Math,
-    variables: &HashMap<String, Expression>,
-    turtle: &Turtle,
-) -> Result<f32, ExecutionError> {
+    env: &mut Environment,
+    turtle: &mut Turtle,
+) -> Result<f64, ExecutionError> {
     match expr {
-        Math::Add(lhs, rhs) => eval_binary_op(lhs, rhs, variables, turtle, |a, b| a + b),
-        Math::Sub(lhs, rhs) => eval_binary_op(lhs, rhs, variables, turtle, |a, b| a - b),
-        Math::Mul(lhs, rhs) => eval_binary_op(lhs, rhs, variables, turtle, |a, b| a * b),
+        Math::Add(lhs, rhs) => eval_binary_op(lhs, rhs, env, turtle, |a, b| a + b),
+        Math::Sub(lhs, rhs) => eval_binary_op(lhs, rhs, env, turtle, |a, b| a - b),
+        Math::Mul(lhs, rhs) => eval_binary_op(lhs, rhs, env, turtle, |a, b| a * b),
         Math::Div(lhs, rhs) => {
-            let rhs_val = match_expressions(rhs, variables, turtle)?;
+            let rhs_val = match_expressions(rhs, env, turtle)?;
             if rhs_val == 0.0 {
                 return Err(ExecutionError {
                     kind: ExecutionErrorKind::DivisionByZero,
                 });
             }
-            Ok(eval_binary_op(lhs, rhs, variables, turtle, |a, b| a / b)?)
+            Ok(eval_binary_op(lhs, rhs, env, turtle, |a, b| a / b)?)
         }
+        Math::Mod(lhs, rhs) | Math::Remainder(lhs, rhs) => {
+            let rhs_val = match_expressions(rhs, env, turtle)?;
+            if rhs_val == 0.0 {
+                return Err(ExecutionError {
+                    kind: ExecutionErrorKind::DivisionByZero,
+                });
+            }
+            if let Math::Mod(..) = expr {
+                eval_binary_op(lhs, rhs, env, turtle, |a, b| a - b * (a / b).floor())
+            } else {
+                eval_binary_op(lhs, rhs, env, turtle, |a, b| a % b)
+            }
+        }
+        Math::Min(lhs, rhs) => eval_binary_op(lhs, rhs, env, turtle, f64::min),
+        Math::Max(lhs, rhs) => eval_binary_op(lhs, rhs, env, turtle, f64::max),
         Math::Eq(lhs, rhs) => {
-            eval_logical_op(
-                lhs,
-                rhs,
-                variables,
-                turtle,
-                |a, b| if a == b { 1.0 } else { 0.0 },
-            )
+            eval_logical_op(lhs, rhs, env, turtle, |a, b| if a == b { 1.0 } else { 0.0 })
         }
         Math::Lt(lhs, rhs) => {
-            eval_logical_op(
-                lhs,
-                rhs,
-                variables,
-                turtle,
-                |a, b| if a < b { 1.0 } else { 0.0 },
-            )
+            eval_logical_op(lhs, rhs, env, turtle, |a, b| if a < b { 1.0 } else { 0.0 })
         }
         Math::Gt(lhs, rhs) => {
-            eval_logical_op(
-                lhs,
-                rhs,
-                variables,
-                turtle,
-                |a, b| if a > b { 1.0 } else { 0.0 },
-            )
+            eval_logical_op(lhs, rhs, env, turtle, |a, b| if a > b { 1.0 } else { 0.0 })
         }
         Math::Ne(lhs, rhs) => {
+            eval_logical_op(lhs, rhs, env, turtle, |a, b| if a != b { 1.0 } else { 0.0 })
+        }
+        Math::Le(lhs, rhs) => {
+            eval_logical_op(lhs, rhs, env, turtle, |a, b| if a <= b { 1.0 } else { 0.0 })
+        }
+        Math::Ge(lhs, rhs) => {
+            eval_logical_op(lhs, rhs, env, turtle, |a, b| if a >= b { 1.0 } else { 0.0 })
+        }
+        Math::And(lhs, rhs) => eval_logical_op(lhs, rhs, env, turtle, |a, b| a * b),
+        Math::Or(lhs, rhs) => {
             eval_logical_op(
                 lhs,
                 rhs,
-                variables,
+                env,
                 turtle,
-                |a, b| if a != b { 1.0 } else { 0.0 },
+                |a, b| {
+                    if a + b > 0.0 {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                },
             )
         }
-        Math::And(lhs, rhs) => eval_logical_op(lhs, rhs, variables, turtle, |a, b| a * b),
-        Math::Or(lhs, rhs) => eval_logical_op(lhs, rhs, variables, turtle, |a, b| {
-            if a + b > 0.0 {
+        Math::Xor(lhs, rhs) => eval_logical_op(lhs, rhs, env, turtle, |a, b| {
+            if (a != 0.0) != (b != 0.0) {
                 1.0
             } else {
                 0.0
             }
         }),
+        Math::Noise(x, y) => {
+            let x = match_expressions(x, env, turtle)?;
+            let y = match_expressions(y, env, turtle)?;
+            Ok(noise(x as f32, y as f32, env.seed) as f64)
+        }
+        Math::Pixel(x, y) => {
+            let x = match_expressions(x, env, turtle)?;
+            let y = match_expressions(y, env, turtle)?;
+            Ok(pixel(x as f32, y as f32, turtle, env))
+        }
+    }
+}
+
+/// Evaluates a function of a single value, taking and giving angles in the
+/// current angle unit. `RANDOM` takes the next number from the environment's
+/// generator.
+///
+/// # Example
+///
+/// ```rust
+/// let function = Function::Cos(Expression::Float(60.0));
+///
+/// let res = eval_function(&function, &mut Environment::default(), &mut turtle).unwrap();
+/// assert!((res - 0.5).abs() < 1e-6);
+/// ```
+fn eval_function(
+    function: &Function,
+    env: &mut Environment,
+    turtle: &mut Turtle,
+) -> Result<f64, ExecutionError> {
+    let val = match_expressions(function.operand(), env, turtle)?;
+    let res = match function {
+        // Fixed point works out trig in degrees without the maths library.
+        Function::Sin(_) | Function::Cos(_) | Function::Tan(_)
+            if env.precision == Precision::Fixed =>
+        {
+            let (sin, cos) = env.precision.sin_cos(env.angle_unit.to_degrees(val));
+            match function {
+                Function::Sin(_) => sin,
+                Function::Cos(_) => cos,
+                _ => sin / cos,
+            }
+        }
+        Function::ArcTan(_) if env.precision == Precision::Fixed => {
+            env.angle_unit.in_unit(env.precision.atan2(val, 1.0))
+        }
+        Function::Sin(_) => env.angle_unit.to_radians(val).sin(),
+        Function::Cos(_) => env.angle_unit.to_radians(val).cos(),
+        Function::Tan(_) => env.angle_unit.to_radians(val).tan(),
+        Function::ArcTan(_) => env.angle_unit.radians_in_unit(val.atan()),
+        Function::Round(_) => val.round(),
+        Function::Int(_) => val.trunc(),
+        Function::Abs(_) => val.abs(),
+        Function::Random(_) if val > 0.0 => env.rng.below(val),
+        Function::Random(_) => {
+            return Err(ExecutionError {
+                kind: ExecutionErrorKind::InvalidArgument {
+                    msg: "RANDOM needs a positive number".to_string(),
+                },
+            })
+        }
+    };
+    Ok(env.precision.round(res))
+}
+
+/// The palette index of the color drawn at a point, 0 (black) where only the
+/// background shows, or -1 for a color outside the palette.
+fn pixel(x: f32, y: f32, turtle: &Turtle, env: &Environment) -> f64 {
+    match turtle.canvas.color_at(x, y) {
+        Some(color) => env
+            .palette
+            .index_of(color)
+            .map_or(-1.0, |index| index as f64),
+        None => 0.0,
     }
 }
 
+/// One edge of the box around everything drawn so far, picked out of
+/// `(min_x, min_y, max_x, max_y)`, or 0 if nothing has been drawn.
+fn bound(turtle: &Turtle, edge: fn((f32, f32, f32, f32)) -> f32) -> f64 {
+    turtle
+        .canvas
+        .bounding_box()
+        .map_or(0.0, |bbox| edge(bbox) as f64)
+}
+
 #[cfg(test)]
 mod tests {
-    use unsvg::Image;
+    use unsvg::COLORS;
+
+    use crate::canvas::Canvas;
 
     use super::*;
     use crate::ast::Query;
+    use crate::interpreter::{angles::AngleUnit, providers::QueryProvider};
 
     #[test]
     fn test_match_queries() {
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
-        let res = match_queries(&Query::XCor, &turtle);
+        let res = match_queries(&Query::XCor, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 50.0);
 
-        let res = match_queries(&Query::YCor, &turtle);
+        let res = match_queries(&Query::YCor, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 50.0);
 
-        let res = match_queries(&Query::Heading, &turtle);
+        let res = match_queries(&Query::Heading, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 0.0);
+        turtle.turn(-30.0);
+        let res = match_queries(&Query::Heading, &mut env, &mut turtle).unwrap();
+        assert_eq!(res, 330.0);
+        turtle.turn(30.0);
 
-        let res = match_queries(&Query::Color, &turtle);
+        let res = match_queries(&Query::Color, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 7.0);
+
+        let res = match_queries(&Query::Odometer, &mut env, &mut turtle).unwrap();
+        assert_eq!(res, 0.0);
+
+        let towards = Query::Towards(Box::new((Expression::Float(40.0), Expression::Float(50.0))));
+        let res = match_queries(&towards, &mut env, &mut turtle).unwrap();
+        assert_eq!(res, 270.0);
+        env.angle_unit = AngleUnit::Gradians;
+        let res = match_queries(&towards, &mut env, &mut turtle).unwrap();
+        assert_eq!(res, 300.0);
+
+        let distance =
+            Query::Distance(Box::new((Expression::Float(53.0), Expression::Float(54.0))));
+        let res = match_queries(&distance, &mut env, &mut turtle).unwrap();
+        assert_eq!(res, 5.0);
+
+        let mut env = Environment {
+            deterministic: true,
+            ..Default::default()
+        };
+        let res = match_queries(&Query::Year, &mut env, &mut turtle).unwrap();
+        assert_eq!(res, 1970.0);
+    }
+
+    #[test]
+    fn test_match_provided_query() {
+        #[derive(Debug)]
+        struct MouseX;
+
+        impl QueryProvider for MouseX {
+            fn query(&self, name: &str) -> Option<f64> {
+                (name == "mousex").then_some(12.5)
+            }
+        }
+
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        let query = Query::Provided("mousex".to_string());
+        assert!(match_queries(&query, &mut env, &mut turtle).is_err());
+
+        env.providers.push(Box::new(MouseX));
+        assert_eq!(match_queries(&query, &mut env, &mut turtle).unwrap(), 12.5);
+    }
+
+    #[test]
+    fn test_get_env() {
+        let mut env = Environment::default();
+        std::env::set_var("RSLOGO_TEST_GETENV", " 42 ");
+        std::env::set_var("RSLOGO_TEST_GETENV_WORD", "forty-two");
+
+        assert_eq!(
+            get_env("RSLOGO_TEST_GETENV", &env).unwrap(),
+            Expression::Float(42.0)
+        );
+        assert_eq!(
+            get_env("RSLOGO_TEST_GETENV_WORD", &env).unwrap(),
+            Expression::Word("forty-two".to_string())
+        );
+        assert!(get_env("RSLOGO_TEST_GETENV_UNSET", &env).is_err());
+
+        env.deterministic = true;
+        assert!(get_env("RSLOGO_TEST_GETENV", &env).is_err());
     }
 
     #[test]
     fn test_match_expressions() {
-        let mut variables = HashMap::new();
-        variables.insert("x".to_string(), Expression::Float(1.0));
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        env.vars.insert("x".to_string(), Expression::Float(1.0));
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
-        let res = match_expressions(&Expression::Float(1.0), &variables, &turtle).unwrap();
+        let res = match_expressions(&Expression::Float(1.0), &mut env, &mut turtle).unwrap();
         assert_eq!(res, 1.0);
 
-        let res = match_expressions(&Expression::Number(1), &variables, &turtle).unwrap();
+        let res = match_expressions(&Expression::Number(1), &mut env, &mut turtle).unwrap();
         assert_eq!(res, 1.0);
 
-        let res = match_expressions(&Expression::Usize(1), &variables, &turtle).unwrap();
+        let res = match_expressions(&Expression::Usize(1), &mut env, &mut turtle).unwrap();
         assert_eq!(res, 1.0);
 
-        let res = match_expressions(&Expression::Query(Query::XCor), &variables, &turtle).unwrap();
+        let res =
+            match_expressions(&Expression::Query(Query::XCor), &mut env, &mut turtle).unwrap();
         assert_eq!(res, 50.0);
 
-        let res =
-            match_expressions(&Expression::Variable("x".to_string()), &variables, &turtle).unwrap();
+        let res = match_expressions(
+            &Expression::Variable("x".to_string()),
+            &mut env,
+            &mut turtle,
+        )
+        .unwrap();
         assert_eq!(res, 1.0);
 
         let res = match_expressions(
@@ -277,8 +796,8 @@ mod tests {
                 Expression::Float(1.0),
                 Expression::Float(2.0),
             ))),
-            &variables,
-            &turtle,
+            &mut env,
+            &mut turtle,
         )
         .unwrap();
         assert_eq!(res, 3.0);
@@ -286,13 +805,14 @@ mod tests {
 
     #[test]
     fn test_get_var_val() {
-        let mut variables = HashMap::new();
-
-        variables.insert("float".to_string(), Expression::Float(1.0));
-        variables.insert("number".to_string(), Expression::Number(1));
-        variables.insert("usize".to_string(), Expression::Usize(1));
-        variables.insert("query".to_string(), Expression::Query(Query::XCor));
-        variables.insert(
+        let mut env = Environment::default();
+
+        env.vars.insert("float".to_string(), Expression::Float(1.0));
+        env.vars.insert("number".to_string(), Expression::Number(1));
+        env.vars.insert("usize".to_string(), Expression::Usize(1));
+        env.vars
+            .insert("query".to_string(), Expression::Query(Query::XCor));
+        env.vars.insert(
             "math".to_string(),
             Expression::Math(Box::new(Math::Add(
                 Expression::Float(1.0),
@@ -300,58 +820,58 @@ mod tests {
             ))),
         );
 
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
-        let res = get_var_val("float", &variables, &turtle).unwrap();
+        let res = get_var_val("float", &mut env, &mut turtle).unwrap();
         assert_eq!(res, 1.0);
 
-        let res = get_var_val("number", &variables, &turtle).unwrap();
+        let res = get_var_val("number", &mut env, &mut turtle).unwrap();
         assert_eq!(res, 1.0);
 
-        let res = get_var_val("usize", &variables, &turtle).unwrap();
+        let res = get_var_val("usize", &mut env, &mut turtle).unwrap();
         assert_eq!(res, 1.0);
 
-        let res = get_var_val("query", &variables, &turtle).unwrap();
+        let res = get_var_val("query", &mut env, &mut turtle).unwrap();
         assert_eq!(res, 50.0);
 
-        let res = get_var_val("math", &variables, &turtle).unwrap();
+        let res = get_var_val("math", &mut env, &mut turtle).unwrap();
         assert_eq!(res, 3.0);
     }
 
     #[test]
     fn test_get_var_val_error() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
-        let res = get_var_val("x", &variables, &turtle);
+        let res = get_var_val("x", &mut env, &mut turtle);
         assert!(res.is_err());
     }
 
     #[test]
     fn test_eval_binary_op() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let lhs = Expression::Float(1.0);
         let rhs = Expression::Float(2.0);
 
-        let res = eval_binary_op(&lhs, &rhs, &variables, &turtle, |a, b| a + b).unwrap();
+        let res = eval_binary_op(&lhs, &rhs, &mut env, &mut turtle, |a, b| a + b).unwrap();
         assert_eq!(res, 3.0);
     }
 
     #[test]
     fn test_eval_logical_op() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let lhs = Expression::Float(1.0);
         let rhs = Expression::Float(2.0);
 
-        let res = eval_logical_op(&lhs, &rhs, &variables, &turtle, |a, b| {
+        let res = eval_logical_op(&lhs, &rhs, &mut env, &mut turtle, |a, b| {
             if a < b {
                 1.0
             } else {
@@ -361,7 +881,7 @@ mod tests {
         .unwrap();
         assert_eq!(res, 1.0);
 
-        let res = eval_logical_op(&lhs, &rhs, &variables, &turtle, |a, b| {
+        let res = eval_logical_op(&lhs, &rhs, &mut env, &mut turtle, |a, b| {
             if a > b {
                 1.0
             } else {
@@ -374,145 +894,484 @@ mod tests {
 
     #[test]
     fn test_eval_math_add() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let expr = Math::Add(Expression::Float(1.0), Expression::Float(2.0));
 
-        let res = eval_math(&expr, &variables, &turtle).unwrap();
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 3.0);
     }
 
+    #[test]
+    fn test_eval_math_precision() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        let expr = Math::Add(Expression::Float(0.1), Expression::Float(0.2));
+
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
+        assert_eq!(res, (0.1f32 + 0.2f32) as f64);
+
+        env.precision = Precision::F64;
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
+        assert_eq!(res, 0.1 + 0.2);
+    }
+
+    #[test]
+    fn test_match_expressions_non_finite() {
+        let mut env = Environment::default();
+        env.vars.insert("BIG".to_string(), Expression::Float(1e30));
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        // Overflows f32.
+        let expr = Expression::Math(Box::new(Math::Mul(
+            Expression::Variable("BIG".to_string()),
+            Expression::Variable("BIG".to_string()),
+        )));
+        let res = match_expressions(&expr, &mut env, &mut turtle);
+
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "Expression '* :BIG :BIG' evaluated to inf"
+        );
+    }
+
     #[test]
     fn test_eval_math_sub() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let expr = Math::Sub(Expression::Float(1.0), Expression::Float(2.0));
 
-        let res = eval_math(&expr, &variables, &turtle).unwrap();
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
         assert_eq!(res, -1.0);
     }
 
     #[test]
     fn test_eval_math_mul() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let expr = Math::Mul(Expression::Float(1.0), Expression::Float(2.0));
 
-        let res = eval_math(&expr, &variables, &turtle).unwrap();
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 2.0);
     }
 
     #[test]
     fn test_eval_math_div() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let expr = Math::Div(Expression::Float(1.0), Expression::Float(2.0));
 
-        let res = eval_math(&expr, &variables, &turtle).unwrap();
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 0.5);
     }
 
     #[test]
     fn test_eval_math_div_by_zero() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let expr = Math::Div(Expression::Float(1.0), Expression::Float(0.0));
 
-        let res = eval_math(&expr, &variables, &turtle);
+        let res = eval_math(&expr, &mut env, &mut turtle);
         assert!(res.is_err());
     }
 
+    #[test]
+    fn test_eval_math_mod() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        for (a, b, modulo, remainder) in [
+            (370.0, 360.0, 10.0, 10.0),
+            (-30.0, 360.0, 330.0, -30.0),
+            (7.0, -2.0, -1.0, 1.0),
+            (7.5, 2.0, 1.5, 1.5),
+        ] {
+            let expr = Math::Mod(Expression::Float(a), Expression::Float(b));
+            assert_eq!(eval_math(&expr, &mut env, &mut turtle).unwrap(), modulo);
+            let expr = Math::Remainder(Expression::Float(a), Expression::Float(b));
+            assert_eq!(eval_math(&expr, &mut env, &mut turtle).unwrap(), remainder);
+        }
+
+        let expr = Math::Mod(Expression::Float(1.0), Expression::Float(0.0));
+        assert!(eval_math(&expr, &mut env, &mut turtle).is_err());
+    }
+
+    #[test]
+    fn test_eval_function() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+
+        let sin = Function::Sin(Expression::Float(30.0));
+        assert!(close(
+            eval_function(&sin, &mut env, &mut turtle).unwrap(),
+            0.5
+        ));
+        let cos = Function::Cos(Expression::Float(180.0));
+        assert!(close(
+            eval_function(&cos, &mut env, &mut turtle).unwrap(),
+            -1.0
+        ));
+        let tan = Function::Tan(Expression::Float(45.0));
+        assert!(close(
+            eval_function(&tan, &mut env, &mut turtle).unwrap(),
+            1.0
+        ));
+        let arctan = Function::ArcTan(Expression::Float(1.0));
+        assert!(close(
+            eval_function(&arctan, &mut env, &mut turtle).unwrap(),
+            45.0
+        ));
+
+        env.angle_unit = AngleUnit::Radians;
+        let sin = Function::Sin(Expression::Float(std::f64::consts::FRAC_PI_2));
+        assert!(close(
+            eval_function(&sin, &mut env, &mut turtle).unwrap(),
+            1.0
+        ));
+        assert!(close(
+            eval_function(&arctan, &mut env, &mut turtle).unwrap(),
+            std::f64::consts::FRAC_PI_4
+        ));
+    }
+
+    #[test]
+    fn test_eval_rounding_functions() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        for (val, round, int, abs) in [
+            (2.5, 3.0, 2.0, 2.5),
+            (-2.5, -3.0, -2.0, 2.5),
+            (-0.75, -1.0, 0.0, 0.75),
+            (4.0, 4.0, 4.0, 4.0),
+        ] {
+            let val = || Expression::Float(val);
+            let res = eval_function(&Function::Round(val()), &mut env, &mut turtle);
+            assert_eq!(res.unwrap(), round);
+            let res = eval_function(&Function::Int(val()), &mut env, &mut turtle);
+            assert_eq!(res.unwrap(), int);
+            let res = eval_function(&Function::Abs(val()), &mut env, &mut turtle);
+            assert_eq!(res.unwrap(), abs);
+        }
+    }
+
+    #[test]
+    fn test_eval_math_min_max() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        let expr = Math::Min(Expression::Float(3.0), Expression::Float(-2.0));
+        assert_eq!(eval_math(&expr, &mut env, &mut turtle).unwrap(), -2.0);
+        let expr = Math::Max(Expression::Float(3.0), Expression::Float(-2.0));
+        assert_eq!(eval_math(&expr, &mut env, &mut turtle).unwrap(), 3.0);
+    }
+
     #[test]
     fn test_eval_math_eq() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let expr = Math::Eq(Expression::Float(1.0), Expression::Float(1.0));
 
-        let res = eval_math(&expr, &variables, &turtle).unwrap();
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 1.0);
     }
 
     #[test]
     fn test_eval_math_lt() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let expr = Math::Lt(Expression::Float(1.0), Expression::Float(2.0));
 
-        let res = eval_math(&expr, &variables, &turtle).unwrap();
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 1.0);
     }
 
     #[test]
     fn test_eval_math_gt() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let expr = Math::Gt(Expression::Float(1.0), Expression::Float(2.0));
 
-        let res = eval_math(&expr, &variables, &turtle).unwrap();
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 0.0);
     }
 
+    #[test]
+    fn test_eval_math_le_ge() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        let expr = Math::Le(Expression::Float(2.0), Expression::Float(2.0));
+        assert_eq!(eval_math(&expr, &mut env, &mut turtle).unwrap(), 1.0);
+
+        let expr = Math::Ge(Expression::Float(1.0), Expression::Float(2.0));
+        assert_eq!(eval_math(&expr, &mut env, &mut turtle).unwrap(), 0.0);
+    }
+
     #[test]
     fn test_eval_math_ne() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let expr = Math::Ne(Expression::Float(1.0), Expression::Float(2.0));
 
-        let res = eval_math(&expr, &variables, &turtle).unwrap();
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 1.0);
     }
 
     #[test]
     fn test_eval_math_and() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let expr = Math::And(Expression::Float(1.0), Expression::Float(2.0));
 
-        let res = eval_math(&expr, &variables, &turtle).unwrap();
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 1.0);
     }
 
     #[test]
     fn test_eval_math_or() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let expr = Math::Or(Expression::Float(1.0), Expression::Float(0.0));
 
-        let res = eval_math(&expr, &variables, &turtle).unwrap();
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 1.0);
     }
 
     #[test]
     fn test_eval_math_or_false() {
-        let variables = HashMap::new();
-        let mut image = Image::new(100, 100);
-        let turtle = Turtle::new(&mut image);
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         let expr = Math::Or(Expression::Float(0.0), Expression::Float(0.0));
 
-        let res = eval_math(&expr, &variables, &turtle).unwrap();
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
         assert_eq!(res, 0.0);
     }
+
+    #[test]
+    fn test_eval_math_xor() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        let expr = Math::Xor(Expression::Float(1.0), Expression::Float(0.0));
+        assert_eq!(eval_math(&expr, &mut env, &mut turtle).unwrap(), 1.0);
+
+        let expr = Math::Xor(Expression::Float(1.0), Expression::Float(1.0));
+        assert_eq!(eval_math(&expr, &mut env, &mut turtle).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_eval_math_noise() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        let expr = Math::Noise(Expression::Float(1.5), Expression::Float(2.5));
+
+        let res = eval_math(&expr, &mut env, &mut turtle).unwrap();
+        assert!((0.0..=1.0).contains(&res));
+        assert_eq!(res, eval_math(&expr, &mut env, &mut turtle).unwrap());
+
+        env.seed = 1;
+        assert_ne!(res, eval_math(&expr, &mut env, &mut turtle).unwrap());
+    }
+
+    #[test]
+    fn test_match_collision_queries() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        turtle.pen_down();
+        turtle.forward(20.0);
+        turtle.turn(90.0);
+        turtle.forward(10.0);
+        turtle.turn(90.0);
+        turtle.forward(10.0);
+        turtle.turn(90.0);
+
+        let touching = |x, y, r| {
+            Query::Touching(Box::new((
+                Expression::Float(x),
+                Expression::Float(y),
+                Expression::Float(r),
+            )))
+        };
+        assert_eq!(
+            match_queries(&touching(50.0, 35.0, 1.0), &mut env, &mut turtle).unwrap(),
+            1.0
+        );
+        assert_eq!(
+            match_queries(&touching(55.0, 35.0, 1.0), &mut env, &mut turtle).unwrap(),
+            0.0
+        );
+
+        // Heading back towards the first line.
+        let intersects = |d| Query::Intersects(Box::new(Expression::Float(d)));
+        assert_eq!(
+            match_queries(&intersects(5.0), &mut env, &mut turtle).unwrap(),
+            0.0
+        );
+        assert_eq!(
+            match_queries(&intersects(15.0), &mut env, &mut turtle).unwrap(),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_eval_math_pixel() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        turtle.pen_down();
+        turtle.set_pen_color(COLORS[4]);
+        turtle.forward(10.0);
+
+        let expr = Math::Pixel(Expression::Float(50.0), Expression::Float(45.0));
+        assert_eq!(eval_math(&expr, &mut env, &mut turtle).unwrap(), 4.0);
+
+        let expr = Math::Pixel(Expression::Float(60.0), Expression::Float(45.0));
+        assert_eq!(eval_math(&expr, &mut env, &mut turtle).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_match_bounding_box_queries() {
+        let mut env = Environment::default();
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        assert_eq!(
+            match_queries(&Query::MaxX, &mut env, &mut turtle).unwrap(),
+            0.0
+        );
+
+        turtle.pen_down();
+        turtle.forward(10.0);
+        turtle.pen_up();
+        turtle.right(30.0);
+        turtle.pen_down();
+        turtle.back(20.0);
+
+        assert_eq!(
+            match_queries(&Query::MinX, &mut env, &mut turtle).unwrap(),
+            50.0
+        );
+        assert_eq!(
+            match_queries(&Query::MaxX, &mut env, &mut turtle).unwrap(),
+            80.0
+        );
+        assert_eq!(
+            match_queries(&Query::MinY, &mut env, &mut turtle).unwrap(),
+            40.0
+        );
+        assert_eq!(
+            match_queries(&Query::MaxY, &mut env, &mut turtle).unwrap(),
+            60.0
+        );
+    }
+
+    #[test]
+    fn test_match_list_ops() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        let word = |word: &str| Expression::Word(word.to_string());
+        let angles = Expression::List(vec![
+            Expression::Float(90.0),
+            word("left"),
+            Expression::List(vec![Expression::Float(1.0), Expression::Float(2.0)]),
+        ]);
+        env.vars.insert("ANGLES".to_string(), angles.clone());
+        let op = |op| Expression::ListOp(Box::new(op));
+        let angles = || Expression::Variable("ANGLES".to_string());
+
+        let first = op(ListOp::First(angles()));
+        assert_eq!(
+            match_expressions(&first, &mut env, &mut turtle).unwrap(),
+            90.0
+        );
+        let second = op(ListOp::Item(Expression::Float(2.0), angles()));
+        assert_eq!(
+            match_value(&second, &mut env, &mut turtle).unwrap(),
+            word("left")
+        );
+        let count = op(ListOp::Count(op(ListOp::ButFirst(angles()))));
+        assert_eq!(
+            match_expressions(&count, &mut env, &mut turtle).unwrap(),
+            2.0
+        );
+        assert_eq!(
+            match_word(&angles(), &mut env, &mut turtle).unwrap(),
+            "90 left [1 2]"
+        );
+
+        // Words are taken apart by character.
+        let rest = op(ListOp::ButFirst(word("turtle")));
+        assert_eq!(match_word(&rest, &mut env, &mut turtle).unwrap(), "urtle");
+        let digit = op(ListOp::First(Expression::Float(42.0)));
+        assert_eq!(
+            match_value(&digit, &mut env, &mut turtle).unwrap(),
+            Expression::Float(4.0)
+        );
+
+        for bad in [
+            op(ListOp::Item(Expression::Float(4.0), angles())),
+            op(ListOp::First(Expression::List(vec![]))),
+            angles(),
+        ] {
+            assert!(match_expressions(&bad, &mut env, &mut turtle).is_err());
+        }
+    }
+
+    #[test]
+    fn test_match_map() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        let list = Expression::List(vec![Expression::Float(1.0), Expression::Float(2.0)]);
+        let double = Expression::Math(Box::new(Math::Mul(
+            Expression::Variable("?".to_string()),
+            Expression::Float(2.0),
+        )));
+        let map = Expression::ListOp(Box::new(ListOp::Map(double, list)));
+
+        assert_eq!(
+            match_value(&map, &mut env, &mut turtle).unwrap(),
+            Expression::List(vec![Expression::Float(2.0), Expression::Float(4.0)])
+        );
+        assert_eq!(env.vars.get("?"), None);
+    }
 }
@@ -1,5 +1,19 @@
+pub mod angles;
+mod clock;
 mod control_flows;
-mod errors;
+pub mod coverage;
+pub mod errors;
 pub mod execute;
+mod fixed;
+pub mod input;
+pub mod limits;
+pub mod log;
 mod matches;
+mod noise;
+pub mod precision;
+pub mod profile;
+pub mod providers;
+pub mod random;
+pub mod screen;
+mod transform;
 pub mod turtle;
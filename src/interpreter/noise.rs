@@ -0,0 +1,78 @@
+//! Seeded 2D value noise, used by the `NOISE` expression.
+//!
+//! Each integer lattice point is given a pseudo-random value derived from its
+//! coordinates and the seed, and points in between are smoothly interpolated
+//! from the four surrounding lattice points. The same seed always gives the
+//! same noise, so drawings are reproducible.
+
+/// Hashes a lattice point into a value in `[0, 1)`.
+fn lattice(x: i32, y: i32, seed: u64) -> f32 {
+    let mut h = seed
+        ^ (x as u32 as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (y as u32 as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    // splitmix64 finaliser
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^= h >> 31;
+
+    (h >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// Smoothstep, so that the noise has no visible creases at lattice lines.
+fn fade(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Samples the noise at (x, y), returning a value between 0 and 1.
+///
+/// # Example
+///
+/// ```rust
+/// let n = noise(1.5, 2.25, 0);
+/// assert!((0.0..=1.0).contains(&n));
+/// ```
+pub fn noise(x: f32, y: f32, seed: u64) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (fade(x - x0), fade(y - y0));
+    let (ix, iy) = (x0 as i32, y0 as i32);
+
+    let top = lerp(lattice(ix, iy, seed), lattice(ix + 1, iy, seed), tx);
+    let bottom = lerp(lattice(ix, iy + 1, seed), lattice(ix + 1, iy + 1, seed), tx);
+    lerp(top, bottom, ty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_range() {
+        for i in 0..100 {
+            let n = noise(i as f32 * 0.37, i as f32 * -1.13, 42);
+            assert!((0.0..=1.0).contains(&n));
+        }
+    }
+
+    #[test]
+    fn test_noise_deterministic() {
+        assert_eq!(noise(3.3, 4.4, 7), noise(3.3, 4.4, 7));
+        assert_ne!(noise(3.3, 4.4, 7), noise(3.3, 4.4, 8));
+    }
+
+    #[test]
+    fn test_noise_lattice() {
+        // At lattice points the noise is exactly the lattice value.
+        assert_eq!(noise(2.0, 5.0, 1), lattice(2, 5, 1));
+    }
+
+    #[test]
+    fn test_noise_continuous() {
+        let a = noise(0.999, 0.5, 3);
+        let b = noise(1.001, 0.5, 3);
+        assert!((a - b).abs() < 0.01);
+    }
+}
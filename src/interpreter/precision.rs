@@ -0,0 +1,85 @@
+//! The precision numbers are evaluated at, set with `--precision`.
+//!
+//! Numbers are always held as `f64`, but by default every result is rounded
+//! to `f32` as it is produced, which gives exactly the same drawings as when
+//! the interpreter used `f32` throughout. With `f64`, variables and the
+//! turtle's position keep full precision, so constructions built from
+//! thousands of small steps don't drift. Positions are only rounded when they
+//! are written to the image.
+//!
+//! `fixed` rounds every result to a multiple of 1/65536, and moves the turtle
+//! with the integer arithmetic of the `fixed` module, trig included. Adding,
+//! multiplying and dividing `f64`s is rounded the same way on every platform;
+//! only the maths library's trig differs, and `fixed` never uses it, so golden
+//! images come out bit-identical wherever they are rendered.
+
+use super::fixed::{self, Fixed};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Precision {
+    #[default]
+    F32,
+    F64,
+    /// Fixed point with 16 fractional bits.
+    Fixed,
+}
+
+impl Precision {
+    /// Rounds a freshly computed value to this precision.
+    pub fn round(self, value: f64) -> f64 {
+        match self {
+            Precision::F32 => value as f32 as f64,
+            Precision::F64 => value,
+            Precision::Fixed => Fixed::from_f64(value).to_f64(),
+        }
+    }
+
+    /// The sine and cosine of an angle in degrees.
+    pub fn sin_cos(self, degrees: f64) -> (f64, f64) {
+        match self {
+            Precision::F32 | Precision::F64 => degrees.to_radians().sin_cos(),
+            Precision::Fixed => {
+                let (sin, cos) = fixed::sin_cos(Fixed::from_f64(degrees));
+                (sin.to_f64(), cos.to_f64())
+            }
+        }
+    }
+
+    /// The angle in degrees from the positive x axis to the point `(x, y)`.
+    pub fn atan2(self, y: f64, x: f64) -> f64 {
+        match self {
+            Precision::F32 | Precision::F64 => y.atan2(x).to_degrees(),
+            Precision::Fixed => fixed::atan2(Fixed::from_f64(y), Fixed::from_f64(x)).to_f64(),
+        }
+    }
+
+    /// The length of the vector `(x, y)`.
+    pub fn hypot(self, x: f64, y: f64) -> f64 {
+        match self {
+            Precision::F32 | Precision::F64 => x.hypot(y),
+            Precision::Fixed => fixed::hypot(Fixed::from_f64(x), Fixed::from_f64(y)).to_f64(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round() {
+        assert_eq!(Precision::F32.round(0.1), 0.1f32 as f64);
+        assert_eq!(Precision::F64.round(0.1), 0.1);
+        assert_eq!(Precision::F32.round(2.5), 2.5);
+        assert_eq!(Precision::Fixed.round(0.1), 6554.0 / 65536.0);
+        assert_eq!(Precision::Fixed.round(-2.5), -2.5);
+    }
+
+    #[test]
+    fn test_trig() {
+        assert_eq!(Precision::Fixed.sin_cos(30.0).0, 0.5);
+        assert_eq!(Precision::Fixed.atan2(1.0, 1.0), 45.0);
+        assert_eq!(Precision::Fixed.hypot(3.0, 4.0), 5.0);
+        assert_eq!(Precision::F64.sin_cos(0.0), (0.0, 1.0));
+    }
+}
@@ -0,0 +1,104 @@
+//! The procedure profile written by `--profile`.
+//!
+//! Time is attributed to the procedure call stack it was spent in, and written
+//! in the folded-stacks format read by `flamegraph.pl` and `inferno`: one line
+//! per distinct stack, such as `main;TREE;BRANCH 1520`, giving the
+//! microseconds spent in `BRANCH` itself when called from `TREE`. Time spent
+//! outside any procedure is counted against `main`.
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+struct Frame {
+    /// The stack down to and including this call, joined with `;`.
+    path: String,
+    start: Instant,
+    /// Time spent in the calls this one made.
+    children: Duration,
+}
+
+#[derive(Debug)]
+pub struct Profile {
+    stack: Vec<Frame>,
+    /// Time spent in each stack, not counting the calls it made.
+    totals: BTreeMap<String, Duration>,
+}
+
+impl Profile {
+    pub fn new(now: Instant) -> Profile {
+        Profile {
+            stack: vec![Frame {
+                path: "main".to_string(),
+                start: now,
+                children: Duration::ZERO,
+            }],
+            totals: BTreeMap::new(),
+        }
+    }
+
+    /// Records a call to the procedure `name` starting.
+    pub fn enter(&mut self, name: &str, now: Instant) {
+        let path = match self.stack.last() {
+            Some(caller) => format!("{};{}", caller.path, name),
+            None => name.to_string(),
+        };
+        self.stack.push(Frame {
+            path,
+            start: now,
+            children: Duration::ZERO,
+        });
+    }
+
+    /// Records the innermost call returning.
+    pub fn exit(&mut self, now: Instant) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        let elapsed = now.duration_since(frame.start);
+        *self.totals.entry(frame.path).or_default() += elapsed.saturating_sub(frame.children);
+        if let Some(caller) = self.stack.last_mut() {
+            caller.children += elapsed;
+        }
+    }
+
+    /// Ends every call still running, including `main`, and returns the
+    /// profile in the folded-stacks format.
+    pub fn finish(&mut self, now: Instant) -> String {
+        while !self.stack.is_empty() {
+            self.exit(now);
+        }
+
+        self.totals
+            .iter()
+            .map(|(path, time)| format!("{} {}\n", path, time.as_micros()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let start = Instant::now();
+        let at = |micros| start + Duration::from_micros(micros);
+
+        let mut profile = Profile::new(start);
+        profile.enter("TREE", at(10));
+        profile.enter("BRANCH", at(20));
+        profile.exit(at(50));
+        profile.enter("BRANCH", at(60));
+        profile.exit(at(70));
+        profile.exit(at(100));
+        profile.enter("BRANCH", at(100));
+
+        assert_eq!(
+            profile.finish(at(105)),
+            "main 10\nmain;BRANCH 5\nmain;TREE 50\nmain;TREE;BRANCH 40\n"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Queries answered by the program embedding the interpreter.
+//!
+//! `QUERY "name` asks each registered provider in turn for the value called
+//! `name`, so an embedder can feed in sensor readings, the mouse position or
+//! a data series without the parser or interpreter knowing about them.
+//!
+//! # Example
+//!
+//! ```rust
+//! #[derive(Debug)]
+//! struct Temperature;
+//!
+//! impl QueryProvider for Temperature {
+//!     fn query(&self, name: &str) -> Option<f64> {
+//!         (name == "celsius").then_some(21.5)
+//!     }
+//! }
+//!
+//! env.providers.push(Box::new(Temperature));
+//! // FORWARD QUERY "celsius
+//! ```
+
+use std::fmt::Debug;
+
+pub trait QueryProvider: Debug {
+    /// The current value of the query called `name`, or `None` if this
+    /// provider doesn't answer it.
+    fn query(&self, name: &str) -> Option<f64>;
+}
+
+/// Asks each provider in turn for the value of `name`, returning the first
+/// answer.
+pub fn resolve(providers: &[Box<dyn QueryProvider>], name: &str) -> Option<f64> {
+    providers.iter().find_map(|provider| provider.query(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Series(HashMap<&'static str, f64>);
+
+    impl QueryProvider for Series {
+        fn query(&self, name: &str) -> Option<f64> {
+            self.0.get(name).copied()
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let providers: Vec<Box<dyn QueryProvider>> = vec![
+            Box::new(Series(HashMap::from([("a", 1.0)]))),
+            Box::new(Series(HashMap::from([("a", 2.0), ("b", 3.0)]))),
+        ];
+
+        assert_eq!(resolve(&providers, "a"), Some(1.0));
+        assert_eq!(resolve(&providers, "b"), Some(3.0));
+        assert_eq!(resolve(&providers, "c"), None);
+    }
+}
@@ -0,0 +1,65 @@
+//! The random numbers given by `RANDOM`.
+//!
+//! The generator is seeded from `--seed` and can be reseeded from the script
+//! with `RERANDOM`, so a generative drawing comes out the same every time it
+//! is run with the same seed.
+
+/// A small, fast generator (splitmix64). It is not suitable for anything
+/// that needs to be unpredictable.
+#[derive(Debug, Default, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A whole number from 0 up to but not including `n`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let mut rng = Rng::new(7);
+    /// let roll = rng.below(6.0);
+    /// assert!((0.0..6.0).contains(&roll));
+    /// ```
+    pub fn below(&mut self, n: f64) -> f64 {
+        // The top 53 bits give every f64 in [0, 1) an equal chance.
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        (unit * n).floor()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_below() {
+        let mut rng = Rng::new(42);
+        let rolls: Vec<f64> = (0..1000).map(|_| rng.below(6.0)).collect();
+
+        assert!(rolls.iter().all(|roll| (0.0..6.0).contains(roll)));
+        assert!(rolls.iter().all(|roll| roll.fract() == 0.0));
+        for face in 0..6 {
+            assert!(rolls.contains(&(face as f64)));
+        }
+
+        // The same seed gives the same numbers.
+        let mut again = Rng::new(42);
+        assert_eq!(
+            (0..1000).map(|_| again.below(6.0)).collect::<Vec<_>>(),
+            rolls
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! What happens when the turtle reaches the edge of the canvas, set with
+//! `WRAP`, `FENCE` or `WINDOW`.
+//!
+//! By default the turtle roams freely and whatever it draws off the canvas is
+//! cut off. In wrap mode the canvas is a torus: a line leaving one edge
+//! carries on from the opposite edge, split into one piece per crossing. In
+//! fence mode any move that would leave the canvas is an error instead, and
+//! the turtle stays where it was. In window mode the turtle roams freely too,
+//! but the image is widened when it's saved to take in everything drawn.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ScreenMode {
+    #[default]
+    Free,
+    Wrap,
+    Fence,
+    Window,
+}
+
+/// Whether a point lies on a `width` by `height` canvas, edges included.
+pub fn on_canvas((x, y): (f64, f64), (width, height): (f64, f64)) -> bool {
+    (0.0..=width).contains(&x) && (0.0..=height).contains(&y)
+}
+
+/// Wraps a point back onto a `width` by `height` canvas.
+pub fn wrap_point((x, y): (f64, f64), (width, height): (f64, f64)) -> (f64, f64) {
+    (x.rem_euclid(width), y.rem_euclid(height))
+}
+
+/// The most pieces a line is split into on a wrapping canvas. A line long
+/// enough to cross the edges more often than this has long since covered the
+/// canvas, so the rest of it is left out rather than piling up shapes.
+const MAX_WRAP_SEGMENTS: usize = 4096;
+
+/// Splits the line from `from` to `to` into the pieces drawn on a wrapping
+/// `width` by `height` canvas, with each piece running up to an edge and the
+/// next starting from the opposite edge. `from` is wrapped onto the canvas
+/// first, and at most `MAX_WRAP_SEGMENTS` pieces are given.
+pub fn wrap_segments(
+    from: (f64, f64),
+    to: (f64, f64),
+    (width, height): (f64, f64),
+) -> Vec<((f64, f64), (f64, f64))> {
+    let start = wrap_point(from, (width, height));
+    let (mut from, mut to) = (start, (to.0 - from.0 + start.0, to.1 - from.1 + start.1));
+    let mut segments = Vec::new();
+    loop {
+        // The fraction of the way to `to` at which the line leaves the canvas
+        // through a vertical edge, and likewise a horizontal one.
+        let exit = |from: f64, to: f64, size: f64| {
+            if to > size {
+                Some(((size - from) / (to - from), -size))
+            } else if to < 0.0 {
+                Some((from / (from - to), size))
+            } else {
+                None
+            }
+        };
+        let crossing = match (exit(from.0, to.0, width), exit(from.1, to.1, height)) {
+            (None, None) => {
+                segments.push((from, to));
+                return segments;
+            }
+            (Some((t, shift)), None) => (t, (shift, 0.0)),
+            (None, Some((t, shift))) => (t, (0.0, shift)),
+            (Some((tx, x_shift)), Some((ty, _))) if tx < ty => (tx, (x_shift, 0.0)),
+            (Some((tx, _)), Some((ty, y_shift))) if ty < tx => (ty, (0.0, y_shift)),
+            // Through a corner, so it wraps both ways at once.
+            (Some((t, x_shift)), Some((_, y_shift))) => (t, (x_shift, y_shift)),
+        };
+        let (t, shift) = crossing;
+        let edge = (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+        // A line starting on the edge it leaves through has nothing to draw
+        // before wrapping.
+        if edge != from {
+            segments.push((from, edge));
+        }
+        if segments.len() == MAX_WRAP_SEGMENTS {
+            return segments;
+        }
+        from = (edge.0 + shift.0, edge.1 + shift.1);
+        to = (to.0 + shift.0, to.1 + shift.1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_on_canvas() {
+        assert!(on_canvas((0.0, 50.0), (100.0, 50.0)));
+        assert!(!on_canvas((-0.5, 10.0), (100.0, 50.0)));
+        assert!(!on_canvas((10.0, 50.5), (100.0, 50.0)));
+    }
+
+    #[test]
+    fn test_wrap_point() {
+        assert_eq!(wrap_point((110.0, -10.0), (100.0, 50.0)), (10.0, 40.0));
+        assert_eq!(wrap_point((30.0, 20.0), (100.0, 50.0)), (30.0, 20.0));
+    }
+
+    #[test]
+    fn test_wrap_segments_inside() {
+        let segments = wrap_segments((10.0, 10.0), (20.0, 30.0), (100.0, 100.0));
+        assert_eq!(segments, vec![((10.0, 10.0), (20.0, 30.0))]);
+    }
+
+    #[test]
+    fn test_wrap_segments_across_edge() {
+        let segments = wrap_segments((50.0, 10.0), (50.0, -20.0), (100.0, 100.0));
+        assert_eq!(
+            segments,
+            vec![((50.0, 10.0), (50.0, 0.0)), ((50.0, 100.0), (50.0, 80.0))]
+        );
+
+        let segments = wrap_segments((90.0, 50.0), (130.0, 70.0), (100.0, 100.0));
+        assert_eq!(
+            segments,
+            vec![((90.0, 50.0), (100.0, 55.0)), ((0.0, 55.0), (30.0, 70.0))]
+        );
+    }
+
+    #[test]
+    fn test_wrap_segments_many_times() {
+        let segments = wrap_segments((50.0, 50.0), (50.0, 320.0), (100.0, 100.0));
+        assert_eq!(
+            segments,
+            vec![
+                ((50.0, 50.0), (50.0, 100.0)),
+                ((50.0, 0.0), (50.0, 100.0)),
+                ((50.0, 0.0), (50.0, 100.0)),
+                ((50.0, 0.0), (50.0, 20.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wrap_segments_bounded() {
+        let segments = wrap_segments((50.0, 50.0), (1e12, 3e11), (100.0, 100.0));
+        assert_eq!(segments.len(), MAX_WRAP_SEGMENTS);
+        assert_eq!(segments[0].0, (50.0, 50.0));
+    }
+
+    #[test]
+    fn test_wrap_segments_from_edge() {
+        let segments = wrap_segments((0.0, 50.0), (-10.0, 50.0), (100.0, 100.0));
+        assert_eq!(segments, vec![((100.0, 50.0), (90.0, 50.0))]);
+    }
+
+    #[test]
+    fn test_wrap_segments_corner() {
+        let segments = wrap_segments((90.0, 90.0), (110.0, 110.0), (100.0, 100.0));
+        assert_eq!(
+            segments,
+            vec![((90.0, 90.0), (100.0, 100.0)), ((0.0, 0.0), (10.0, 10.0))]
+        );
+    }
+}
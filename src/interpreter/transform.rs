@@ -0,0 +1,119 @@
+//! Coordinate transforms applied to turtle movement.
+//!
+//! A transform maps the turtle's local coordinates onto the canvas by scaling,
+//! then rotating, then offsetting them. `SCALE`, `ROTATE` and `TRANSLATE` each
+//! compose a new step onto the current transform, so a procedure drawn after
+//! `SCALE "2` draws twice as large without knowing it has been scaled.
+
+use super::precision::Precision;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub scale: f32,
+    /// Degrees clockwise.
+    pub rotation: f32,
+    /// Where the local origin lies on the canvas.
+    pub offset: (f32, f32),
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            scale: 1.0,
+            rotation: 0.0,
+            offset: (0.0, 0.0),
+        }
+    }
+}
+
+impl Transform {
+    /// Maps a point in local coordinates onto the canvas, rotating it with
+    /// the trig of `precision`.
+    pub fn apply(&self, (x, y): (f64, f64), precision: Precision) -> (f64, f64) {
+        let (sin, cos) = precision.sin_cos(self.rotation as f64);
+        let scale = self.scale as f64;
+        let (x, y) = (x * scale, y * scale);
+        (
+            self.offset.0 as f64 + x * cos - y * sin,
+            self.offset.1 as f64 + x * sin + y * cos,
+        )
+    }
+
+    /// Maps a point on the canvas back into local coordinates.
+    pub fn invert(&self, (x, y): (f64, f64), precision: Precision) -> (f64, f64) {
+        let (sin, cos) = precision.sin_cos(self.rotation as f64);
+        let scale = self.scale as f64;
+        let (x, y) = (x - self.offset.0 as f64, y - self.offset.1 as f64);
+        ((x * cos + y * sin) / scale, (-x * sin + y * cos) / scale)
+    }
+
+    pub fn scaled(self, factor: f32) -> Transform {
+        Transform {
+            scale: self.scale * factor,
+            ..self
+        }
+    }
+
+    pub fn rotated(self, degrees: f32) -> Transform {
+        Transform {
+            rotation: self.rotation + degrees,
+            ..self
+        }
+    }
+
+    /// Moves the local origin by (dx, dy), measured in local coordinates.
+    pub fn translated(self, dx: f32, dy: f32, precision: Precision) -> Transform {
+        let origin = self.apply((dx as f64, dy as f64), precision);
+        Transform {
+            offset: (origin.0 as f32, origin.1 as f32),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Precision::{Fixed, F32};
+
+    fn assert_close(a: (f64, f64), b: (f64, f64)) {
+        assert!(
+            (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn test_identity() {
+        let transform = Transform::default();
+        assert_eq!(transform.apply((3.5, -2.0), F32), (3.5, -2.0));
+        assert_eq!(transform.invert((3.5, -2.0), F32), (3.5, -2.0));
+    }
+
+    #[test]
+    fn test_compose() {
+        let transform = Transform::default()
+            .translated(10.0, 20.0, F32)
+            .scaled(2.0)
+            .rotated(90.0);
+
+        assert_close(transform.apply((0.0, 0.0), F32), (10.0, 20.0));
+        assert_close(transform.apply((1.0, 0.0), F32), (10.0, 22.0));
+    }
+
+    #[test]
+    fn test_invert() {
+        let transform = Transform::default()
+            .rotated(30.0)
+            .translated(5.0, 5.0, F32)
+            .scaled(3.0);
+
+        let point = (7.0, -4.0);
+        for precision in [F32, Fixed] {
+            let applied = transform.apply(point, precision);
+            assert_close(transform.invert(applied, precision), point);
+        }
+    }
+}
@@ -8,39 +8,154 @@
 //! The turtle follows the below default state:
 //!
 //! ```rust
-//! use unsvg::Image;
+//! use crate::canvas::Canvas;
 //!
-//! let width: f32 = 100.0;
-//! let height: f32 = 100.0;
+//! let width: u32 = 100;
+//! let height: u32 = 100;
 //!
-//! let mut image = Image::new(width, height);
+//! let mut canvas = Canvas::new(width, height);
 //!
-//! let turtle = Turtle::new(&mut image);
+//! let turtle = Turtle::new(&mut canvas);
 //! ```
 
-use unsvg::{Image, COLORS};
+use std::collections::HashMap;
+
+use unsvg::{Color, COLORS};
+
+use crate::canvas::{
+    end_coordinates, quantize, Canvas, Clip, Effect, FillPattern, PenPattern, Shape, Style,
+    TextAlign,
+};
+
+use super::{
+    fixed::{self, Fixed},
+    precision::Precision,
+    screen::{on_canvas, wrap_point, wrap_segments, ScreenMode},
+    transform::Transform,
+};
+
+/// The height of the text written by `LABEL` until `SETLABELHEIGHT`, in
+/// pixels.
+const LABEL_SIZE: f32 = 14.0;
+
+/// The distance from the turtle to the tip of its sprite, in pixels.
+const SPRITE_SIZE: f32 = 10.0;
 
 pub struct Turtle<'a> {
-    pub x: f32,
-    pub y: f32,
-    /// Degrees, where 0 is Up/North
-    pub heading: i32,
+    pub x: f64,
+    pub y: f64,
+    /// Degrees clockwise from Up/North, not necessarily whole, and always in
+    /// `[0, 360)`.
+    pub heading: f32,
+    pub pen_down: bool,
+    /// Whether the pen draws in reverse, set with `PENREVERSE` and cleared
+    /// with `PENPAINT`.
+    pub pen_reverse: bool,
+    /// What happens at the edges of the canvas, set with `WRAP`, `FENCE` or
+    /// `WINDOW`.
+    pub screen_mode: ScreenMode,
+    /// Where the turtle last tried to leave the canvas in fence mode, until
+    /// the interpreter reports it as an error.
+    pub out_of_bounds: Option<(f64, f64)>,
+    /// Whether the turtle is drawn at its final position, set with
+    /// `SHOWTURTLE` and `HIDETURTLE`.
+    pub visible: bool,
+    /// The color lines and shapes are drawn in, which starts as white from
+    /// the palette but may be any RGB color.
+    pub pen_color: Color,
+    /// The width of the lines drawn, in pixels.
+    pub pen_size: f32,
+    /// How opaque everything drawn is, from 0 to 1.
+    pub pen_alpha: f32,
+    /// Whether lines and outlines are dashed or dotted.
+    pub pen_pattern: PenPattern,
+    /// How closed shapes (RECT, POLYGON, STAR) are filled.
+    pub fill_pattern: FillPattern,
+    /// The font family labels are written in, set with `SETLABELFONT`.
+    pub label_font: String,
+    /// The height of labels in pixels, set with `SETLABELHEIGHT`.
+    pub label_size: f32,
+    /// Where labels are written relative to the turtle, set with
+    /// `SETLABELALIGN`.
+    pub label_align: TextAlign,
+    /// Maps the turtle's movement onto the canvas. `x` and `y` are always
+    /// canvas coordinates.
+    pub transform: Transform,
+    /// Transforms saved by `PUSHTRANSFORM`.
+    pub transforms: Vec<Transform>,
+    /// Whether SETX and SETY move relative to the current position.
+    pub relative: bool,
+    /// Local coordinates of the turtle before it last moved.
+    pub last_position: (f64, f64),
+    /// The precision the turtle's position is kept at.
+    pub precision: Precision,
+    /// The total distance the turtle has travelled on the canvas, whether
+    /// drawing or not.
+    pub odometer: f64,
+    /// The distance the turtle has travelled on the canvas with the pen down.
+    pub tripmeter: f64,
+    /// Positions and headings remembered with `MARK`, keyed by name.
+    pub marks: HashMap<String, Mark>,
+    /// States saved by `PUSHSTATE`.
+    pub states: Vec<SavedState>,
+    pub canvas: &'a mut Canvas,
+}
+
+/// A position and heading remembered with `MARK`. The position is on the
+/// canvas, so `JUMPTO` returns to the same spot whatever transform is in
+/// effect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mark {
+    pub x: f64,
+    pub y: f64,
+    pub heading: f32,
+}
+
+/// The position, heading and pen saved by `PUSHSTATE`. Like a mark, the
+/// position is on the canvas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SavedState {
+    pub x: f64,
+    pub y: f64,
+    pub heading: f32,
     pub pen_down: bool,
-    /// Indexed into a unsvg::COLORS array.
-    pub pen_color: usize,
-    pub image: &'a mut Image,
+    pub pen_reverse: bool,
+    pub pen_color: Color,
+    pub pen_size: f32,
+    pub pen_alpha: f32,
+    pub pen_pattern: PenPattern,
 }
 
 impl Turtle<'_> {
-    pub fn new(image: &mut Image) -> Turtle {
-        let (width, height) = image.get_dimensions();
+    pub fn new(canvas: &mut Canvas) -> Turtle<'_> {
+        let (width, height) = canvas.get_dimensions();
         Turtle {
-            x: (width / 2) as f32,
-            y: (height / 2) as f32,
-            heading: 0,
+            x: (width / 2) as f64,
+            y: (height / 2) as f64,
+            heading: 0.0,
             pen_down: false,
-            pen_color: 7,
-            image,
+            pen_reverse: false,
+            visible: false,
+            screen_mode: ScreenMode::Free,
+            out_of_bounds: None,
+            pen_color: COLORS[7],
+            pen_size: 1.0,
+            pen_alpha: 1.0,
+            pen_pattern: PenPattern::Solid,
+            fill_pattern: FillPattern::None,
+            label_font: "sans-serif".to_string(),
+            label_size: LABEL_SIZE,
+            label_align: TextAlign::Left,
+            transform: Transform::default(),
+            transforms: Vec::new(),
+            relative: false,
+            last_position: ((width / 2) as f64, (height / 2) as f64),
+            precision: Precision::default(),
+            odometer: 0.0,
+            tripmeter: 0.0,
+            marks: HashMap::new(),
+            states: Vec::new(),
+            canvas,
         }
     }
 
@@ -52,96 +167,608 @@ impl Turtle<'_> {
         self.pen_down = false;
     }
 
-    pub fn set_pen_color(&mut self, color: usize) {
+    /// Puts the pen down, drawing in reverse if `reverse` and painting
+    /// otherwise.
+    pub fn set_pen_reverse(&mut self, reverse: bool) {
+        self.pen_down = true;
+        self.pen_reverse = reverse;
+    }
+
+    /// Sets the screen mode, wrapping the turtle back onto the canvas if
+    /// it has wandered off and the mode is `Wrap`. Fencing a turtle that is
+    /// already off the canvas counts as leaving it.
+    pub fn set_screen_mode(&mut self, mode: ScreenMode) {
+        self.screen_mode = mode;
+        (self.x, self.y) = self.bounded((self.x, self.y));
+        self.within_fence((self.x, self.y));
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn set_pen_color(&mut self, color: Color) {
         self.pen_color = color;
     }
 
-    /// Degrees are not normalised.
-    pub fn turn(&mut self, degrees: i32) {
-        self.heading += degrees;
+    pub fn set_pen_size(&mut self, size: f32) {
+        self.pen_size = size;
+    }
+
+    pub fn set_fill_pattern(&mut self, pattern: FillPattern) {
+        self.fill_pattern = pattern;
+    }
+
+    pub fn set_pen_alpha(&mut self, alpha: f32) {
+        self.pen_alpha = alpha;
+    }
+
+    pub fn set_pen_pattern(&mut self, pattern: PenPattern) {
+        self.pen_pattern = pattern;
+    }
+
+    pub fn set_layer(&mut self, layer: usize) {
+        self.canvas.set_layer(layer);
+    }
+
+    pub fn set_effect(&mut self, effect: Option<Effect>) {
+        self.canvas.set_effect(effect);
+    }
+
+    pub fn set_clip(&mut self, clip: Option<Clip>) {
+        self.canvas.clip = clip;
+    }
+
+    pub fn set_trail_fade(&mut self, length: usize) {
+        self.canvas.set_trail_fade(length);
+    }
+
+    pub fn set_symmetry(&mut self, ways: usize) {
+        self.canvas.set_symmetry(ways);
+    }
+
+    pub fn set_kaleidoscope(&mut self, ways: usize) {
+        self.canvas.set_kaleidoscope(ways);
+    }
+
+    /// The style the turtle currently draws with.
+    pub fn style(&self) -> Style {
+        Style {
+            color: self.pen_color,
+            fill: self.fill_pattern,
+            opacity: self.pen_alpha,
+            width: self.pen_size,
+            pattern: self.pen_pattern,
+            reverse: self.pen_reverse,
+        }
+    }
+
+    /// The style lines are drawn with, which are never filled.
+    fn line_style(&self) -> Style {
+        Style {
+            opacity: self.pen_alpha,
+            width: self.pen_size,
+            pattern: self.pen_pattern,
+            reverse: self.pen_reverse,
+            ..Style::new(self.pen_color)
+        }
+    }
+
+    /// The heading is normalised into `[0, 360)`.
+    pub fn turn(&mut self, degrees: f32) {
+        self.heading = normalize_heading(self.heading + degrees);
+    }
+
+    /// The heading is normalised into `[0, 360)`.
+    pub fn set_heading(&mut self, degrees: f32) {
+        self.heading = normalize_heading(degrees);
+    }
+
+    /// Save the current transform so `pop_transform` can restore it.
+    pub fn push_transform(&mut self) {
+        self.transforms.push(self.transform);
+    }
+
+    /// Restore the last saved transform, returning false if none was saved.
+    pub fn pop_transform(&mut self) -> bool {
+        match self.transforms.pop() {
+            Some(transform) => {
+                self.transform = transform;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The x coordinate of the turtle in its local coordinates.
+    pub fn xcor(&self) -> f64 {
+        self.precision
+            .round(self.transform.invert((self.x, self.y), self.precision).0)
+    }
+
+    /// The y coordinate of the turtle in its local coordinates.
+    pub fn ycor(&self) -> f64 {
+        self.precision
+            .round(self.transform.invert((self.x, self.y), self.precision).1)
+    }
+
+    /// The heading, in degrees from 0 up to 360, that would point the turtle
+    /// at `(x, y)` in its local coordinates. The turtle's own position gives
+    /// 0.
+    pub fn towards(&self, x: f64, y: f64) -> f64 {
+        let (dx, dy) = (x - self.xcor(), y - self.ycor());
+        if dx == 0.0 && dy == 0.0 {
+            return 0.0;
+        }
+        // Headings run clockwise from up, where y decreases.
+        self.precision.atan2(dx, -dy).rem_euclid(360.0)
+    }
+
+    /// The straight line distance from the turtle to `(x, y)` in its local
+    /// coordinates.
+    pub fn distance(&self, x: f64, y: f64) -> f64 {
+        self.precision.hypot(x - self.xcor(), y - self.ycor())
+    }
+
+    /// How far the turtle moved along x the last time it moved.
+    pub fn delta_x(&self) -> f64 {
+        self.precision.round(self.xcor() - self.last_position.0)
+    }
+
+    /// How far the turtle moved along y the last time it moved.
+    pub fn delta_y(&self) -> f64 {
+        self.precision.round(self.ycor() - self.last_position.1)
+    }
+
+    pub fn set_relative(&mut self, relative: bool) {
+        self.relative = relative;
+    }
+
+    /// Set the x coordinate of the turtle, or move it along x by `x` in
+    /// relative mode. Note that even if the pen is down, the turtle will not
+    /// draw a line to the new position.
+    pub fn set_x(&mut self, x: f64) {
+        let (curr_x, curr_y) = (self.xcor(), self.ycor());
+        let x = if self.relative { curr_x + x } else { x };
+
+        self.last_position = (curr_x, curr_y);
+        self.move_to(self.transform.apply((x, curr_y), self.precision));
+    }
+
+    /// Set the y coordinate of the turtle, or move it along y by `y` in
+    /// relative mode. Note that even if the pen is down, the turtle will not
+    /// draw a line to the new position.
+    pub fn set_y(&mut self, y: f64) {
+        let (curr_x, curr_y) = (self.xcor(), self.ycor());
+        let y = if self.relative { curr_y + y } else { y };
+
+        self.last_position = (curr_x, curr_y);
+        self.move_to(self.transform.apply((curr_x, y), self.precision));
+    }
+
+    /// Moves the turtle to `(x, y)`, or by `(x, y)` in relative mode. Unlike
+    /// SETX and SETY, this draws a line to the new position if the pen is
+    /// down.
+    pub fn set_pos(&mut self, x: f64, y: f64) {
+        let (curr_x, curr_y) = (self.xcor(), self.ycor());
+        let (x, y) = if self.relative {
+            (curr_x + x, curr_y + y)
+        } else {
+            (x, y)
+        };
+
+        self.last_position = (curr_x, curr_y);
+        self.line_to(self.transform.apply((x, y), self.precision));
+    }
+
+    /// Remembers the turtle's position and heading as `name`, replacing any
+    /// mark of the same name.
+    pub fn mark(&mut self, name: &str) {
+        let mark = Mark {
+            x: self.x,
+            y: self.y,
+            heading: self.heading,
+        };
+        self.marks.insert(name.to_string(), mark);
+    }
+
+    /// Returns the turtle to the position and heading marked as `name`
+    /// without drawing, returning false if there is no such mark.
+    pub fn jump_to(&mut self, name: &str) -> bool {
+        let Some(mark) = self.marks.get(name).copied() else {
+            return false;
+        };
+        self.last_position = (self.xcor(), self.ycor());
+        self.move_to((mark.x, mark.y));
+        self.heading = mark.heading;
+        true
     }
 
-    /// Degrees are not normalised.
-    pub fn set_heading(&mut self, degrees: i32) {
-        self.heading = degrees;
+    /// Save the turtle's position, heading and pen so `pop_state` can restore
+    /// them.
+    pub fn push_state(&mut self) {
+        self.states.push(SavedState {
+            x: self.x,
+            y: self.y,
+            heading: self.heading,
+            pen_down: self.pen_down,
+            pen_reverse: self.pen_reverse,
+            pen_color: self.pen_color,
+            pen_size: self.pen_size,
+            pen_alpha: self.pen_alpha,
+            pen_pattern: self.pen_pattern,
+        });
     }
 
-    /// Set the x coordinate of the turtle. Note that even if the pen is down,
-    /// the turtle will not draw a line to the new position.
-    pub fn set_x(&mut self, x: f32) {
-        self.x = x
+    /// Restore the last saved state without drawing, returning false if none
+    /// was saved.
+    pub fn pop_state(&mut self) -> bool {
+        let Some(state) = self.states.pop() else {
+            return false;
+        };
+        self.last_position = (self.xcor(), self.ycor());
+        self.move_to((state.x, state.y));
+        self.heading = state.heading;
+        self.pen_down = state.pen_down;
+        self.pen_reverse = state.pen_reverse;
+        self.pen_color = state.pen_color;
+        self.pen_size = state.pen_size;
+        self.pen_alpha = state.pen_alpha;
+        self.pen_pattern = state.pen_pattern;
+        true
     }
 
-    /// Set the y coordinate of the turtle. Note that even if the pen is down,
-    /// the turtle will not draw a line to the new position.
-    pub fn set_y(&mut self, y: f32) {
-        self.y = y
+    /// Returns the turtle to the centre of the canvas facing up, drawing a
+    /// line there if the pen is down.
+    pub fn home(&mut self) {
+        let (width, height) = self.canvas.get_dimensions();
+        self.last_position = (self.xcor(), self.ycor());
+        self.line_to(((width / 2) as f64, (height / 2) as f64));
+        self.heading = 0.0;
+    }
+
+    /// Moves the turtle to a point on the canvas, drawing a line there if the
+    /// pen is down.
+    fn line_to(&mut self, to: (f64, f64)) {
+        if !self.within_fence(to) {
+            return;
+        }
+        if self.pen_down {
+            self.tripmeter += self.precision.hypot(to.0 - self.x, to.1 - self.y);
+            self.draw_path((self.x, self.y), to);
+        }
+        self.move_to(to);
+    }
+
+    /// Draws a line between two points on the canvas, split into pieces
+    /// where it wraps around the edges in wrap mode. Lines too short to see
+    /// are skipped.
+    fn draw_path(&mut self, from: (f64, f64), to: (f64, f64)) {
+        let segments = match self.screen_mode {
+            ScreenMode::Free | ScreenMode::Fence | ScreenMode::Window => vec![(from, to)],
+            ScreenMode::Wrap => wrap_segments(from, to, self.canvas_size()),
+        };
+        for (from, to) in segments {
+            let from = (quantize(from.0 as f32), quantize(from.1 as f32));
+            let to = (quantize(to.0 as f32), quantize(to.1 as f32));
+            if from != to {
+                let style = self.line_style();
+                self.canvas.draw(Shape::Line { from, to }, style);
+            }
+        }
+    }
+
+    /// Moves the turtle to a point on the canvas without drawing, rounding it
+    /// to the turtle's precision.
+    fn move_to(&mut self, (x, y): (f64, f64)) {
+        if !self.within_fence((x, y)) {
+            return;
+        }
+        self.odometer += self.precision.hypot(x - self.x, y - self.y);
+        let (x, y) = self.bounded((x, y));
+        self.x = self.precision.round(x);
+        self.y = self.precision.round(y);
+    }
+
+    /// Where a point on the canvas puts the turtle under the screen mode.
+    fn bounded(&self, point: (f64, f64)) -> (f64, f64) {
+        match self.screen_mode {
+            ScreenMode::Free | ScreenMode::Fence | ScreenMode::Window => point,
+            ScreenMode::Wrap => wrap_point(point, self.canvas_size()),
+        }
+    }
+
+    /// Whether the turtle may move to a point on the canvas. In fence mode a
+    /// point off the canvas is refused and kept in `out_of_bounds`.
+    fn within_fence(&mut self, point: (f64, f64)) -> bool {
+        if self.screen_mode != ScreenMode::Fence || on_canvas(point, self.canvas_size()) {
+            return true;
+        }
+        self.out_of_bounds = Some(point);
+        false
+    }
+
+    fn canvas_size(&self) -> (f64, f64) {
+        let (width, height) = self.canvas.get_dimensions();
+        (width as f64, height as f64)
     }
 
     /// Turtle controls for going forwards
-    pub fn forward(&mut self, distance: f32) {
+    pub fn forward(&mut self, distance: f64) {
         self.move_turtle(self.heading, distance);
     }
 
+    /// Where moving forward by `distance` would take the turtle, in canvas
+    /// coordinates, without moving it.
+    pub fn forward_end(&self, distance: f64) -> (f64, f64) {
+        let heading = (self.heading + self.transform.rotation) as f64;
+        let distance = distance * self.transform.scale as f64;
+        let (sin, cos) = self.precision.sin_cos(heading - 90.0);
+        (self.x + cos * distance, self.y + sin * distance)
+    }
+
     /// Turtle controls for going backwards
-    pub fn back(&mut self, distance: f32) {
-        self.move_turtle((self.heading + 180) % 360, distance);
+    pub fn back(&mut self, distance: f64) {
+        self.move_turtle((self.heading + 180.0) % 360.0, distance);
     }
 
     /// Turtle controls for going left
-    pub fn left(&mut self, distance: f32) {
-        self.move_turtle((self.heading - 90) % 360, distance);
+    pub fn left(&mut self, distance: f64) {
+        self.move_turtle((self.heading - 90.0) % 360.0, distance);
     }
 
     /// Turtle controls for going right
-    pub fn right(&mut self, distance: f32) {
-        self.move_turtle((self.heading + 90) % 360, distance);
+    pub fn right(&mut self, distance: f64) {
+        self.move_turtle((self.heading + 90.0) % 360.0, distance);
+    }
+
+    /// Draws a rectangle centred on the turtle, rotated to match its heading.
+    /// The turtle does not move.
+    pub fn rect(&mut self, width: f32, height: f32) {
+        if self.pen_down {
+            let shape = Shape::Rect {
+                center: (self.x as f32, self.y as f32),
+                width: width * self.transform.scale,
+                height: height * self.transform.scale,
+                rotation: self.heading + self.transform.rotation,
+            };
+            self.canvas.draw(shape, self.style());
+        }
+    }
+
+    /// Draws a circle of `radius` centred on the turtle. The turtle does not
+    /// move.
+    pub fn circle(&mut self, radius: f32) {
+        if self.pen_down {
+            let shape = Shape::Circle {
+                center: (self.x as f32, self.y as f32),
+                radius: radius * self.transform.scale,
+            };
+            self.canvas.draw(shape, self.style());
+        }
+    }
+
+    /// Draws an ellipse centred on the turtle, `rx` across and `ry` along its
+    /// heading. The turtle does not move.
+    pub fn ellipse(&mut self, rx: f32, ry: f32) {
+        if self.pen_down {
+            let shape = Shape::Ellipse {
+                center: (self.x as f32, self.y as f32),
+                rx: rx * self.transform.scale,
+                ry: ry * self.transform.scale,
+                rotation: self.heading + self.transform.rotation,
+            };
+            self.canvas.draw(shape, self.style());
+        }
+    }
+
+    /// Draws a regular polygon centred on the turtle, with its first vertex
+    /// `radius` away in the direction of the turtle's heading. The turtle does
+    /// not move.
+    pub fn polygon(&mut self, sides: usize, radius: f32) {
+        let points = (0..sides)
+            .map(|i| self.point_at(i as f32 * 360.0 / sides as f32, radius))
+            .collect();
+        self.draw_polygon(points);
     }
 
-    fn move_turtle(&mut self, heading: i32, distance: f32) {
-        let color = COLORS[self.pen_color];
+    /// Draws a star centred on the turtle, with its first point `radius` away
+    /// in the direction of the turtle's heading. The turtle does not move.
+    pub fn star(&mut self, points: usize, radius: f32) {
+        // The inner radius that makes each edge line up with the edge two
+        // points along, like a pentagram. Stars with fewer than five points
+        // don't have such a radius, so we fall back to half the outer radius.
+        let inner_radius = if points >= 5 && self.precision == Precision::Fixed {
+            let angle = 180.0 / points as f64;
+            let (_, cos) = self.precision.sin_cos(angle);
+            let (_, double_cos) = self.precision.sin_cos(2.0 * angle);
+            radius * (double_cos / cos) as f32
+        } else if points >= 5 {
+            let angle = std::f32::consts::PI / points as f32;
+            radius * (2.0 * angle).cos() / angle.cos()
+        } else {
+            radius / 2.0
+        };
+
+        let step = 180.0 / points as f32;
+        let vertices = (0..points * 2)
+            .map(|i| {
+                let dist = if i % 2 == 0 { radius } else { inner_radius };
+                self.point_at(i as f32 * step, dist)
+            })
+            .collect();
+        self.draw_polygon(vertices);
+    }
+
+    /// Writes `text` at the turtle in its pen color and label font, running
+    /// in the direction of its heading. Labels are written whether or not the
+    /// pen is down, and the turtle does not move.
+    pub fn label(&mut self, text: &str) {
+        let shape = Shape::Text {
+            at: (quantize(self.x as f32), quantize(self.y as f32)),
+            text: text.to_string(),
+            size: self.label_size * self.transform.scale,
+            rotation: self.heading + self.transform.rotation - 90.0,
+            font: self.label_font.clone(),
+            align: self.label_align,
+        };
+        self.canvas.draw(shape, Style::new(self.pen_color));
+    }
+
+    /// Draws the turtle as a filled triangle pointing along its heading, if
+    /// it is visible. The sprite goes on top of everything, without
+    /// symmetric copies or fading, so it should only be drawn once the
+    /// script has finished.
+    pub fn draw_sprite(&mut self) {
+        if !self.visible {
+            return;
+        }
+        let points = vec![
+            self.point_at(0.0, SPRITE_SIZE),
+            self.point_at(140.0, SPRITE_SIZE * 0.8),
+            self.point_at(220.0, SPRITE_SIZE * 0.8),
+        ];
+        let style = Style {
+            fill: FillPattern::Solid,
+            ..Style::new(self.pen_color)
+        };
+        self.canvas.symmetry = 1;
+        self.canvas.mirror = false;
+        self.canvas.clip = None;
+        self.canvas.trail_fade = 0;
+        self.canvas.layer = self.canvas.layers().last().copied().unwrap_or(0);
+        self.canvas.draw(Shape::Polygon { points }, style);
+    }
+
+    fn draw_polygon(&mut self, points: Vec<(f32, f32)>) {
         if self.pen_down {
-            match self
-                .image
-                .draw_simple_line(self.x, self.y, heading, distance, color)
-            {
-                Ok((x, y)) => {
-                    self.x = x;
-                    self.y = y;
+            self.canvas.draw(Shape::Polygon { points }, self.style());
+        }
+    }
+
+    /// The point `distance` away from the turtle, `angle` degrees clockwise
+    /// from its heading.
+    fn point_at(&self, angle: f32, distance: f32) -> (f32, f32) {
+        let direction = self.heading + self.transform.rotation + angle - 90.0;
+        let distance = distance * self.transform.scale;
+        if self.precision == Precision::Fixed {
+            let (x, y) = fixed_step((self.x, self.y), direction as f64, distance as f64);
+            return (quantize(x as f32), quantize(y as f32));
+        }
+        let direction = direction.to_radians();
+        (
+            quantize(self.x as f32 + direction.cos() * distance),
+            quantize(self.y as f32 + direction.sin() * distance),
+        )
+    }
+
+    fn move_turtle(&mut self, heading: f32, distance: f64) {
+        let heading = heading + self.transform.rotation;
+        let distance = distance * self.transform.scale as f64;
+        let end = self.move_end(heading, distance);
+        if !self.within_fence(end) {
+            return;
+        }
+        self.last_position = (self.xcor(), self.ycor());
+        self.odometer += distance.abs();
+        if self.pen_down {
+            self.tripmeter += distance.abs();
+        }
+
+        if self.pen_down {
+            match (self.screen_mode, self.precision) {
+                (ScreenMode::Wrap, _) => self.draw_path((self.x, self.y), end),
+                (_, Precision::F32) => {
+                    let style = self.line_style();
+                    let (x, y) = (self.x as f32, self.y as f32);
+                    self.canvas.draw_line(x, y, heading, distance as f32, style);
+                }
+                (_, Precision::F64 | Precision::Fixed) => {
+                    let shape = Shape::Line {
+                        from: (quantize(self.x as f32), quantize(self.y as f32)),
+                        to: (quantize(end.0 as f32), quantize(end.1 as f32)),
+                    };
+                    self.canvas.draw(shape, self.line_style());
                 }
-                Err(e) => panic!("Error drawing line: {:?}", e),
             }
-        } else {
-            let (end_x, end_y) = unsvg::get_end_coordinates(self.x, self.y, heading, distance);
-            self.x = end_x;
-            self.y = end_y;
         }
+        (self.x, self.y) = self.bounded(end);
+    }
+
+    /// Where moving `distance` along `heading` takes the turtle on the
+    /// canvas, before any wrapping.
+    fn move_end(&self, heading: f32, distance: f64) -> (f64, f64) {
+        match self.precision {
+            // Every step starts from the rounded end of the last one, exactly
+            // as `unsvg` draws lines.
+            Precision::F32 => {
+                let (x, y) = if self.pen_down {
+                    (quantize(self.x as f32), quantize(self.y as f32))
+                } else {
+                    (self.x as f32, self.y as f32)
+                };
+                let (end_x, end_y) = end_coordinates(x, y, heading, distance as f32);
+                (end_x as f64, end_y as f64)
+            }
+            // The position is kept at full or fixed precision and only
+            // rounded for drawing, so rounding errors don't build up from one
+            // step to the next.
+            Precision::F64 => {
+                let (sin, cos) = self.precision.sin_cos(heading as f64 - 90.0);
+                (self.x + cos * distance, self.y + sin * distance)
+            }
+            Precision::Fixed => fixed_step((self.x, self.y), heading as f64 - 90.0, distance),
+        }
+    }
+}
+
+/// The point `distance` from `from` in the direction `degrees` clockwise from
+/// the positive x axis, worked out in fixed point.
+fn fixed_step(from: (f64, f64), degrees: f64, distance: f64) -> (f64, f64) {
+    let (sin, cos) = fixed::sin_cos(Fixed::from_f64(degrees));
+    let distance = Fixed::from_f64(distance);
+    (
+        (Fixed::from_f64(from.0) + cos * distance).to_f64(),
+        (Fixed::from_f64(from.1) + sin * distance).to_f64(),
+    )
+}
+
+/// Brings a heading in degrees into `[0, 360)`.
+fn normalize_heading(degrees: f32) -> f32 {
+    let heading = degrees.rem_euclid(360.0);
+    // A tiny negative heading rounds up to a whole turn.
+    if heading >= 360.0 {
+        0.0
+    } else {
+        heading
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::canvas::Element;
+
     use super::*;
 
     #[test]
     fn test_new_turtle() {
         let width: u32 = 100;
         let height: u32 = 100;
-        let mut image = Image::new(width, height);
+        let mut canvas = Canvas::new(width, height);
 
-        let turtle = Turtle::new(&mut image);
+        let turtle = Turtle::new(&mut canvas);
 
-        assert_eq!(turtle.x, width as f32 / 2.0);
-        assert_eq!(turtle.y, height as f32 / 2.0);
-        assert_eq!(turtle.heading, 0);
+        assert_eq!(turtle.x, width as f64 / 2.0);
+        assert_eq!(turtle.y, height as f64 / 2.0);
+        assert_eq!(turtle.heading, 0.0);
         assert!(!turtle.pen_down);
-        assert_eq!(turtle.pen_color, 7);
+        assert_eq!(turtle.pen_color, COLORS[7]);
     }
 
     #[test]
     fn test_pen_down() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         assert!(!turtle.pen_down);
         turtle.pen_down();
@@ -150,8 +777,8 @@ mod tests {
 
     #[test]
     fn test_pen_up() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         assert!(!turtle.pen_down);
         turtle.pen_down();
@@ -160,50 +787,191 @@ mod tests {
         assert!(!turtle.pen_down);
     }
 
+    #[test]
+    fn test_set_pen_reverse() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.set_pen_reverse(true);
+        turtle.forward(10.0);
+        turtle.set_pen_reverse(false);
+        turtle.forward(10.0);
+
+        assert!(turtle.pen_down);
+        assert!(turtle.canvas.elements[0].style.reverse);
+        assert!(!turtle.canvas.elements[1].style.reverse);
+    }
+
     #[test]
     fn test_set_pen_color() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
-        assert_eq!(turtle.pen_color, 7);
-        turtle.set_pen_color(0);
-        assert_eq!(turtle.pen_color, 0);
+        assert_eq!(turtle.pen_color, COLORS[7]);
+        turtle.set_pen_color(COLORS[0]);
+        assert_eq!(turtle.pen_color, COLORS[0]);
+    }
+
+    #[test]
+    fn test_style() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.set_pen_color(COLORS[4]);
+        turtle.set_fill_pattern(FillPattern::Dots);
+        turtle.set_pen_size(3.0);
+        turtle.set_pen_pattern(PenPattern::Dotted);
+        assert_eq!(
+            turtle.style(),
+            Style {
+                color: COLORS[4],
+                fill: FillPattern::Dots,
+                opacity: 1.0,
+                width: 3.0,
+                pattern: PenPattern::Dotted,
+                reverse: false,
+            }
+        );
     }
 
     #[test]
     fn test_turn() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
-        assert_eq!(turtle.heading, 0);
-        turtle.turn(90);
-        assert_eq!(turtle.heading, 90);
+        assert_eq!(turtle.heading, 0.0);
+        turtle.turn(90.0);
+        assert_eq!(turtle.heading, 90.0);
+    }
+
+    #[test]
+    fn test_normalize_heading() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.turn(-90.0);
+        assert_eq!(turtle.heading, 270.0);
+        turtle.turn(450.0);
+        assert_eq!(turtle.heading, 0.0);
+        turtle.set_heading(-720.5);
+        assert_eq!(turtle.heading, 359.5);
+        turtle.set_heading(1000.0);
+        assert_eq!(turtle.heading, 280.0);
+        assert_eq!(normalize_heading(-1e-6), 0.0);
+    }
+
+    #[test]
+    fn test_fractional_turn() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.pen_down();
+        turtle.turn(22.5);
+        turtle.forward(10.0);
+        assert_eq!(turtle.heading, 22.5);
+        assert!((turtle.x - 53.8268).abs() < 0.01);
+        assert!((turtle.y - 40.7612).abs() < 0.01);
+
+        // A 16 sided polygon closes up rather than drifting off.
+        for _ in 1..16 {
+            turtle.turn(22.5);
+            turtle.forward(10.0);
+        }
+        assert_eq!(turtle.heading, 0.0);
+        assert!((turtle.x - 50.0).abs() < 0.01);
+        assert!((turtle.y - 50.0).abs() < 0.01);
     }
 
     #[test]
     fn test_set_heading() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
-        assert_eq!(turtle.heading, 0);
-        turtle.set_heading(90);
-        assert_eq!(turtle.heading, 90);
+        assert_eq!(turtle.heading, 0.0);
+        turtle.set_heading(90.0);
+        assert_eq!(turtle.heading, 90.0);
     }
 
     #[test]
     fn test_set_x() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         assert_eq!(turtle.x, 50.0);
         turtle.set_x(10.0);
         assert_eq!(turtle.x, 10.0);
     }
 
+    #[test]
+    fn test_set_x_transformed() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.transform = Transform::default()
+            .translated(10.0, 0.0, Precision::F32)
+            .scaled(2.0);
+        assert_eq!(turtle.xcor(), 20.0);
+
+        turtle.set_x(5.0);
+        assert_eq!((turtle.x, turtle.y), (20.0, 50.0));
+        assert_eq!(turtle.xcor(), 5.0);
+    }
+
+    #[test]
+    fn test_relative() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.set_relative(true);
+        turtle.set_x(10.0);
+        turtle.set_y(-5.0);
+        assert_eq!((turtle.x, turtle.y), (60.0, 45.0));
+        assert_eq!((turtle.delta_x(), turtle.delta_y()), (0.0, -5.0));
+
+        turtle.forward(20.0);
+        assert_eq!((turtle.delta_x(), turtle.delta_y()), (0.0, -20.0));
+    }
+
+    #[test]
+    fn test_odometer() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.forward(10.0);
+        turtle.pen_down();
+        turtle.back(20.0);
+        turtle.transform = turtle.transform.scaled(2.0);
+        turtle.right(5.0);
+        turtle.set_x(turtle.xcor() + 3.0);
+
+        assert_eq!(turtle.odometer, 46.0);
+        assert_eq!(turtle.tripmeter, 30.0);
+    }
+
+    #[test]
+    fn test_mark_and_jump_to() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        assert!(!turtle.jump_to("start"));
+        turtle.turn(45.0);
+        turtle.mark("start");
+        turtle.pen_down();
+        turtle.forward(20.0);
+        turtle.set_heading(180.0);
+        turtle.push_transform();
+        turtle.transform = turtle.transform.translated(10.0, 10.0, Precision::F32);
+
+        assert!(turtle.jump_to("start"));
+        assert_eq!((turtle.x, turtle.y, turtle.heading), (50.0, 50.0, 45.0));
+        drop(turtle);
+        assert_eq!(canvas.elements.len(), 1);
+    }
+
     #[test]
     fn test_set_y() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         assert_eq!(turtle.y, 50.0);
         turtle.set_y(10.0);
@@ -212,8 +980,8 @@ mod tests {
 
     #[test]
     fn test_forward() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         assert_eq!(turtle.x, 50.0);
         assert_eq!(turtle.y, 50.0);
@@ -224,8 +992,8 @@ mod tests {
 
     #[test]
     fn test_back() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         assert_eq!(turtle.x, 50.0);
         assert_eq!(turtle.y, 50.0);
@@ -236,8 +1004,8 @@ mod tests {
 
     #[test]
     fn test_left() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         assert_eq!(turtle.x, 50.0);
         assert_eq!(turtle.y, 50.0);
@@ -248,8 +1016,8 @@ mod tests {
 
     #[test]
     fn test_right() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         assert_eq!(turtle.x, 50.0);
         assert_eq!(turtle.y, 50.0);
@@ -260,13 +1028,367 @@ mod tests {
 
     #[test]
     fn test_move_turtle() {
-        let mut image = Image::new(100, 100);
-        let mut turtle = Turtle::new(&mut image);
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
 
         assert_eq!(turtle.x, 50.0);
         assert_eq!(turtle.y, 50.0);
-        turtle.move_turtle(0, 10.0);
+        turtle.move_turtle(0.0, 10.0);
         assert_eq!(turtle.x, 50.0);
         assert_eq!(turtle.y, 40.0);
     }
+
+    #[test]
+    fn test_rect() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.rect(20.0, 10.0);
+        assert!(turtle.canvas.elements.is_empty());
+
+        turtle.pen_down();
+        turtle.turn(45.0);
+        turtle.rect(20.0, 10.0);
+        assert_eq!(
+            turtle.canvas.elements[0].shape,
+            Shape::Rect {
+                center: (50.0, 50.0),
+                width: 20.0,
+                height: 10.0,
+                rotation: 45.0,
+            }
+        );
+        assert_eq!((turtle.x, turtle.y), (50.0, 50.0));
+    }
+
+    #[test]
+    fn test_polygon() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.pen_down();
+        turtle.polygon(4, 10.0);
+        assert_eq!(
+            turtle.canvas.elements[0].shape,
+            Shape::Polygon {
+                points: vec![(50.0, 40.0), (60.0, 50.0), (50.0, 60.0), (40.0, 50.0)]
+            }
+        );
+    }
+
+    #[test]
+    fn test_circle() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.pen_down();
+        turtle.circle(10.0);
+        assert_eq!(
+            turtle.canvas.elements[0].shape,
+            Shape::Circle {
+                center: (50.0, 50.0),
+                radius: 10.0,
+            }
+        );
+        assert_eq!((turtle.x, turtle.y), (50.0, 50.0));
+    }
+
+    #[test]
+    fn test_ellipse() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.pen_down();
+        turtle.turn(30.0);
+        turtle.ellipse(20.0, 10.0);
+        assert_eq!(
+            turtle.canvas.elements[0].shape,
+            Shape::Ellipse {
+                center: (50.0, 50.0),
+                rx: 20.0,
+                ry: 10.0,
+                rotation: 30.0,
+            }
+        );
+        assert_eq!((turtle.x, turtle.y), (50.0, 50.0));
+    }
+
+    #[test]
+    fn test_star() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.pen_down();
+        turtle.star(4, 10.0);
+        assert_eq!(
+            turtle.canvas.elements[0].shape,
+            Shape::Polygon {
+                points: vec![
+                    (50.0, 40.0),
+                    (53.535156, 46.464844),
+                    (60.0, 50.0),
+                    (53.535156, 53.535156),
+                    (50.0, 60.0),
+                    (46.464844, 53.535156),
+                    (40.0, 50.0),
+                    (46.464844, 46.464844),
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn test_towards() {
+        let mut canvas = Canvas::new(100, 100);
+        let turtle = Turtle::new(&mut canvas);
+
+        assert_eq!(turtle.towards(50.0, 40.0), 0.0);
+        assert_eq!(turtle.towards(60.0, 50.0), 90.0);
+        assert_eq!(turtle.towards(50.0, 60.0), 180.0);
+        assert_eq!(turtle.towards(40.0, 50.0), 270.0);
+        assert_eq!(turtle.towards(60.0, 40.0), 45.0);
+        assert_eq!(turtle.towards(50.0, 50.0), 0.0);
+    }
+
+    #[test]
+    fn test_set_pos() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.set_pos(10.0, 20.0);
+        assert_eq!((turtle.x, turtle.y), (10.0, 20.0));
+        assert!(turtle.canvas.elements.is_empty());
+
+        turtle.pen_down();
+        turtle.set_relative(true);
+        turtle.set_pos(30.0, 40.0);
+        assert_eq!((turtle.x, turtle.y), (40.0, 60.0));
+        assert_eq!(turtle.last_position, (10.0, 20.0));
+        assert_eq!(turtle.tripmeter, 50.0);
+        assert_eq!(
+            turtle.canvas.elements[0].shape,
+            Shape::Line {
+                from: (10.0, 20.0),
+                to: (40.0, 60.0)
+            }
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.forward(60.0);
+        turtle.set_screen_mode(ScreenMode::Wrap);
+        assert_eq!((turtle.x, turtle.y), (50.0, 90.0));
+
+        turtle.pen_down();
+        turtle.forward(20.0);
+        assert_eq!((turtle.x, turtle.y), (50.0, 70.0));
+        turtle.turn(90.0);
+        turtle.forward(70.0);
+        assert_eq!((turtle.x, turtle.y), (20.0, 70.0));
+        assert_eq!(turtle.odometer, 150.0);
+        assert_eq!(turtle.tripmeter, 90.0);
+
+        let lines: Vec<Shape> = turtle
+            .canvas
+            .elements
+            .iter()
+            .map(|element| element.shape.clone())
+            .collect();
+        let line = |from, to| Shape::Line { from, to };
+        assert_eq!(
+            lines,
+            vec![
+                line((50.0, 90.0), (50.0, 70.0)),
+                line((50.0, 70.0), (100.0, 70.0)),
+                line((0.0, 70.0), (20.0, 70.0)),
+            ]
+        );
+
+        turtle.set_pos(-10.0, 70.0);
+        assert_eq!((turtle.x, turtle.y), (90.0, 70.0));
+        assert_eq!(turtle.canvas.elements.len(), 5);
+    }
+
+    #[test]
+    fn test_fence() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.set_screen_mode(ScreenMode::Fence);
+        turtle.pen_down();
+        turtle.forward(50.0);
+        assert_eq!((turtle.x, turtle.y), (50.0, 0.0));
+        assert_eq!(turtle.out_of_bounds, None);
+
+        turtle.forward(1.0);
+        assert_eq!((turtle.x, turtle.y), (50.0, 0.0));
+        assert_eq!(turtle.out_of_bounds, Some((50.0, -1.0)));
+        assert_eq!(turtle.canvas.elements.len(), 1);
+        assert_eq!(turtle.odometer, 50.0);
+
+        turtle.out_of_bounds = None;
+        turtle.set_pos(120.0, 0.0);
+        assert_eq!(turtle.out_of_bounds, Some((120.0, 0.0)));
+        assert_eq!(turtle.canvas.elements.len(), 1);
+    }
+
+    #[test]
+    fn test_push_and_pop_state() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.pen_down();
+        turtle.turn(45.0);
+        turtle.push_state();
+        turtle.pen_up();
+        turtle.set_pen_color(COLORS[4]);
+        turtle.set_pen_size(3.0);
+        turtle.forward(20.0);
+        turtle.turn(90.0);
+
+        assert!(turtle.pop_state());
+        assert_eq!((turtle.x, turtle.y, turtle.heading), (50.0, 50.0, 45.0));
+        assert!(turtle.pen_down);
+        assert_eq!((turtle.pen_color, turtle.pen_size), (COLORS[7], 1.0));
+        // Returning doesn't draw.
+        assert!(turtle.canvas.elements.is_empty());
+        assert!(!turtle.pop_state());
+    }
+
+    #[test]
+    fn test_home() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.turn(90.0);
+        turtle.forward(10.0);
+        turtle.home();
+        assert_eq!((turtle.x, turtle.y, turtle.heading), (50.0, 50.0, 0.0));
+        assert!(turtle.canvas.elements.is_empty());
+
+        turtle.forward(10.0);
+        turtle.pen_down();
+        turtle.home();
+        turtle.home();
+        assert_eq!((turtle.x, turtle.y), (50.0, 50.0));
+        assert_eq!(turtle.tripmeter, 10.0);
+        assert_eq!(
+            turtle.canvas.elements[..],
+            [Element {
+                shape: Shape::Line {
+                    from: (50.0, 40.0),
+                    to: (50.0, 50.0)
+                },
+                style: Style::new(COLORS[7]),
+                layer: 0,
+                trail: None,
+                clip: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_draw_sprite() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.draw_sprite();
+        assert!(turtle.canvas.elements.is_empty());
+
+        turtle.set_symmetry(4);
+        turtle.set_visible(true);
+        turtle.turn(90.0);
+        turtle.draw_sprite();
+        assert_eq!(turtle.canvas.elements.len(), 1);
+        let element = &turtle.canvas.elements[0];
+        assert_eq!(element.style.fill, FillPattern::Solid);
+        let Shape::Polygon { points } = &element.shape else {
+            panic!("expected a polygon, got {:?}", element.shape);
+        };
+        assert_eq!(points[0], (60.0, 50.0));
+    }
+
+    #[test]
+    fn test_label() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+
+        turtle.set_pen_color(COLORS[4]);
+        turtle.turn(90.0);
+        turtle.label("hello");
+        assert_eq!(
+            turtle.canvas.elements[0].shape,
+            Shape::Text {
+                at: (50.0, 50.0),
+                text: "hello".to_string(),
+                size: 14.0,
+                rotation: 0.0,
+                font: "sans-serif".to_string(),
+                align: TextAlign::Left,
+            }
+        );
+        assert_eq!(turtle.canvas.elements[0].style.color, COLORS[4]);
+
+        turtle.label_font = "serif".to_string();
+        turtle.label_size = 20.0;
+        turtle.label_align = TextAlign::Right;
+        turtle.label("bye");
+        let Shape::Text {
+            size, font, align, ..
+        } = &turtle.canvas.elements[1].shape
+        else {
+            panic!("expected text, got {:?}", turtle.canvas.elements[1].shape);
+        };
+        assert_eq!(
+            (*size, font.as_str(), *align),
+            (20.0, "serif", TextAlign::Right)
+        );
+    }
+
+    #[test]
+    fn test_f64_precision_does_not_drift() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        turtle.precision = Precision::F64;
+
+        // A full circle of small steps ends exactly where it started.
+        turtle.pen_down();
+        for _ in 0..360 {
+            turtle.forward(0.3);
+            turtle.turn(1.0);
+        }
+
+        assert!((turtle.x - 50.0).abs() < 1e-9);
+        assert!((turtle.y - 50.0).abs() < 1e-9);
+        assert_eq!(turtle.canvas.elements.len(), 360);
+    }
+
+    #[test]
+    fn test_fixed_precision() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        turtle.precision = Precision::Fixed;
+
+        turtle.turn(30.0);
+        turtle.forward(10.0);
+
+        // Both coordinates land on the 1/65536 grid.
+        assert_eq!((turtle.x * 65536.0).fract(), 0.0);
+        assert_eq!((turtle.y * 65536.0).fract(), 0.0);
+        assert_eq!(turtle.x, 55.0);
+
+        // The cosine of 45 degrees is 46341 / 65536 in fixed point, and 100
+        // steps along it move exactly 4634100 / 65536 on every platform.
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        turtle.precision = Precision::Fixed;
+        turtle.turn(45.0);
+        turtle.forward(100.0);
+        assert_eq!(turtle.x, 50.0 + 4634100.0 / 65536.0);
+        assert_eq!(turtle.y, 50.0 - 4634100.0 / 65536.0);
+    }
 }
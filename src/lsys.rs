@@ -0,0 +1,154 @@
+//! Expands L-systems into Logo programs, for the `lsys` subcommand.
+//!
+//! An L-system starts from an axiom and repeatedly rewrites every symbol
+//! using its rule. The expanded string is then read as turtle instructions:
+//!
+//! - `F` and `G` move forward drawing a line.
+//! - `f` moves forward without drawing.
+//! - `+` turns left and `-` turns right by the angle.
+//! - `|` turns around.
+//! - `[` saves the turtle's position and heading, and `]` restores them.
+//!
+//! Any other symbol only takes part in rewriting.
+//!
+//! # Example
+//!
+//! ```rust
+//! let lsys = LSystem {
+//!     axiom: "F".to_string(),
+//!     rules: HashMap::from([('F', "F+F--F+F".to_string())]),
+//!     angle: 60.0,
+//!     step: 10.0,
+//! };
+//!
+//! let program = lsys.to_logo(&lsys.expand(4))?;
+//! ```
+
+use std::{collections::HashMap, fmt::Write};
+
+pub struct LSystem {
+    pub axiom: String,
+    pub rules: HashMap<char, String>,
+    /// Degrees turned by `+` and `-`.
+    pub angle: f32,
+    /// Distance moved by `F`, `G` and `f`.
+    pub step: f32,
+}
+
+/// Parses a rule of the form `F=F+F--F+F`.
+pub fn parse_rule(rule: &str) -> Result<(char, String), String> {
+    let Some((symbol, replacement)) = rule.split_once('=') else {
+        return Err(format!(
+            "Invalid rule {:?}, expected SYMBOL=REPLACEMENT",
+            rule
+        ));
+    };
+
+    let mut chars = symbol.trim().chars();
+    match (chars.next(), chars.next()) {
+        (Some(symbol), None) => Ok((symbol, replacement.trim().to_string())),
+        _ => Err(format!(
+            "Invalid rule {:?}, the left hand side must be a single symbol",
+            rule
+        )),
+    }
+}
+
+impl LSystem {
+    /// Applies the rules to the axiom `iterations` times.
+    pub fn expand(&self, iterations: u32) -> String {
+        let mut current = self.axiom.clone();
+        for _ in 0..iterations {
+            current = current
+                .chars()
+                .map(|symbol| match self.rules.get(&symbol) {
+                    Some(replacement) => replacement.clone(),
+                    None => symbol.to_string(),
+                })
+                .collect();
+        }
+        current
+    }
+
+    /// Translates an expanded L-system into a Logo program, with each `[`
+    /// becoming `PUSHSTATE` and each `]` `POPSTATE`.
+    pub fn to_logo(&self, expanded: &str) -> Result<String, String> {
+        let step = self.step;
+        let angle = self.angle;
+        let mut program = String::from("PENDOWN\n");
+        let mut depth = 0;
+
+        for symbol in expanded.chars() {
+            match symbol {
+                'F' | 'G' => writeln!(program, "FORWARD \"{step}"),
+                'f' => writeln!(program, "PENUP\nFORWARD \"{step}\nPENDOWN"),
+                '+' => writeln!(program, "TURN \"{}", -angle),
+                '-' => writeln!(program, "TURN \"{angle}"),
+                '|' => writeln!(program, "TURN \"180"),
+                '[' => {
+                    depth += 1;
+                    writeln!(program, "PUSHSTATE")
+                }
+                ']' => {
+                    if depth == 0 {
+                        return Err("Unmatched ']' in the expanded L-system".to_string());
+                    }
+                    depth -= 1;
+                    writeln!(program, "POPSTATE")
+                }
+                _ => Ok(()),
+            }
+            .unwrap();
+        }
+
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn koch() -> LSystem {
+        LSystem {
+            axiom: "F".to_string(),
+            rules: HashMap::from([('F', "F+F--F+F".to_string())]),
+            angle: 60.0,
+            step: 5.0,
+        }
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        assert_eq!(parse_rule("F=F+F"), Ok(('F', "F+F".to_string())));
+        assert_eq!(parse_rule(" X = F[+X] "), Ok(('X', "F[+X]".to_string())));
+        assert!(parse_rule("F").is_err());
+        assert!(parse_rule("FF=F").is_err());
+    }
+
+    #[test]
+    fn test_expand() {
+        let lsys = koch();
+        assert_eq!(lsys.expand(0), "F");
+        assert_eq!(lsys.expand(1), "F+F--F+F");
+        assert_eq!(lsys.expand(2).matches('F').count(), 16);
+    }
+
+    #[test]
+    fn test_to_logo() {
+        let lsys = koch();
+        assert_eq!(
+            lsys.to_logo("F+f-|").unwrap(),
+            "PENDOWN\nFORWARD \"5\nTURN \"-60\nPENUP\nFORWARD \"5\nPENDOWN\nTURN \"60\nTURN \"180\n"
+        );
+    }
+
+    #[test]
+    fn test_to_logo_brackets() {
+        let lsys = koch();
+        let program = lsys.to_logo("[F]").unwrap();
+
+        assert_eq!(program, "PENDOWN\nPUSHSTATE\nFORWARD \"5\nPOPSTATE\n");
+        assert!(lsys.to_logo("F]").is_err());
+    }
+}
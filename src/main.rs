@@ -10,21 +10,106 @@
 //! ```
 //! This will run the program with the file `examples/flower.lg` and output
 //! the image to `examples/flower.svg` with a height and width of 1000.
+//!
+//! `rslogo run`, `repl`, `test` and `lsys` run several scripts as one
+//! program, run statements as they are typed, check scripts' `// EXPECT`
+//! directives and render L-systems. The README describes these, every
+//! option and the language itself.
 
 pub mod ast;
+mod canvas;
+mod environment;
+mod expect;
 mod interpreter;
+mod lsys;
+mod meta;
 mod parser;
+mod repl;
+mod source;
+mod stream;
 
 use ast::Expression;
-use interpreter::{execute::execute, turtle::Turtle};
-use parser::{parse::parse_tokens, tokenise::tokenize_script};
-use std::{collections::HashMap, error::Error, fs::File, io::Read};
+use environment::Environment;
+use expect::{check_script, find_scripts};
+use interpreter::{
+    angles::AngleUnit,
+    coverage::Coverage,
+    execute::execute,
+    input::Lines,
+    limits::{count_nodes, parse_size, DEFAULT_MAX_DEPTH},
+    log::ExecLog,
+    precision::Precision,
+    profile::Profile,
+    random::Rng,
+    screen::ScreenMode,
+    turtle::Turtle,
+};
+use lsys::{parse_rule, LSystem};
+use meta::render_meta;
+use parser::{
+    dialect::Dialect, parse::parse_tokens, stdlib::parse_library, syntax::Syntax,
+    tokenise::tokenize_script,
+};
+use repl::repl;
+use source::Source;
+use std::{
+    error::Error,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use stream::stream_script;
 
-use clap::Parser;
-use unsvg::Image;
+use canvas::{
+    export::{parse_scale, Export, Flip, Rotation},
+    grid::parse_spacing,
+    palette::Palette,
+    units::{parse_page_size, Length, DEFAULT_DPI},
+    viewport::{parse_viewport, Viewport},
+    Antialias, Canvas, SplitBy, SvgStyle,
+};
+use clap::{Parser, Subcommand};
+use tracing::info_span;
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::{fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt};
 
-/// A simple program to parse four arguments using clap.
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    #[command(flatten)]
+    args: Option<Args>,
+
+    #[command(flatten)]
+    options: Options,
+
+    /// Print how long each phase and procedure call took to stderr
+    #[arg(long, global = true)]
+    trace: bool,
+
+    /// Write a Chrome trace of each phase and procedure call to this path
+    #[arg(long, global = true, value_name = "PATH")]
+    trace_chrome: Option<std::path::PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Run several scripts as one program, such as a library then a main
+    /// script
+    Run(Box<RunArgs>),
+    /// Run statements typed on stdin, saving the drawing after each one
+    Repl(ReplArgs),
+    /// Run scripts with EXPECT directives and report which pass
+    Test(TestArgs),
+    /// Expand an L-system into a Logo program, or render it directly
+    Lsys(LsysArgs),
+}
+
+/// A simple program to parse four arguments using clap.
+#[derive(clap::Args)]
 struct Args {
     /// Path to a file
     file_path: std::path::PathBuf,
@@ -32,6 +117,186 @@ struct Args {
     /// Path to an svg or png image
     image_path: std::path::PathBuf,
 
+    /// Height
+    #[arg(required_unless_present = "size", conflicts_with = "size")]
+    height: Option<u32>,
+
+    /// Width
+    #[arg(required_unless_present = "size", conflicts_with = "size")]
+    width: Option<u32>,
+}
+
+#[derive(clap::Args)]
+struct RunArgs {
+    /// The scripts to run in order, then the svg or png image path, then the
+    /// height and width unless --size is given
+    #[arg(required = true, num_args = 2.., value_name = "PATHS")]
+    paths: Vec<String>,
+
+    #[command(flatten)]
+    options: Options,
+}
+
+/// Options shared by the default command and `run`.
+#[derive(clap::Args)]
+struct Options {
+    /// Physical canvas size as WIDTHxHEIGHT, e.g. 210mmx297mm, in place of
+    /// the height and width in pixels
+    #[arg(long, value_name = "SIZE", value_parser = parse_page_size)]
+    size: Option<(Length, Length)>,
+
+    /// Pixels per inch used to convert --size into pixels
+    #[arg(long, default_value_t = DEFAULT_DPI, requires = "size")]
+    dpi: f32,
+
+    /// Load the bundled standard library of shape procedures
+    #[arg(long)]
+    stdlib: bool,
+
+    /// Seed for the NOISE and RANDOM expressions
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Precision numbers and the turtle's position are kept at
+    #[arg(long, value_enum, default_value_t = Precision::F32)]
+    precision: Precision,
+
+    /// Unit for angles given to TURN, SETHEADING and ROTATE, and reported by
+    /// HEADING
+    #[arg(long, value_enum, default_value_t = AngleUnit::Degrees)]
+    angles: AngleUnit,
+
+    /// Syntax arithmetic is written in, which scripts can change with
+    /// SETSYNTAX
+    #[arg(long, value_enum, default_value_t = Syntax::Prefix)]
+    syntax: Syntax,
+
+    /// Dialect scripts are written in, where `classic` makes LEFT and RIGHT
+    /// turn the turtle, which scripts can change with SETDIALECT
+    #[arg(long, value_enum, default_value_t = Dialect::Rslogo)]
+    dialect: Dialect,
+
+    /// Repeat everything drawn this many times around the centre of the
+    /// canvas, as with SETSYMMETRY
+    #[arg(long, value_name = "WAYS", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    symmetry: u16,
+
+    /// Mirror every symmetric copy as well, as with SETKALEIDOSCOPE
+    #[arg(long)]
+    mirror: bool,
+
+    /// Refuse GETENV and freeze the clock queries at the Unix epoch
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Read the lines for READWORD and READNUMBER from this path instead of
+    /// standard input
+    #[arg(long, value_name = "PATH")]
+    input: Option<std::path::PathBuf>,
+
+    /// Replace the 16 built-in colors with a GIMP palette or a list of hex
+    /// colors
+    #[arg(long, value_name = "PATH")]
+    palette: Option<std::path::PathBuf>,
+
+    /// Append a JSON line for every executed command to this path
+    #[arg(long, value_name = "PATH")]
+    log: Option<std::path::PathBuf>,
+
+    /// Decimal places to write SVG coordinates with
+    #[arg(long, value_name = "DIGITS")]
+    svg_precision: Option<usize>,
+
+    /// Save the drawing in this format instead of the one the image path's
+    /// extension names
+    #[arg(long, value_enum)]
+    format: Option<ImageFormat>,
+
+    /// Write only the region X,Y,WIDTH,HEIGHT of the drawing, in the
+    /// coordinates the turtle draws in
+    #[arg(long, value_name = "X,Y,W,H", value_parser = parse_viewport)]
+    viewport: Option<Viewport>,
+
+    /// Rotate the saved image clockwise by this many degrees
+    #[arg(long, value_enum, value_name = "DEGREES", default_value_t = Rotation::None)]
+    rotate_output: Rotation,
+
+    /// Mirror the saved image, before rotating it
+    #[arg(long, value_enum)]
+    flip: Option<Flip>,
+
+    /// Save the image this many times larger, such as 2 or 0.5
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0, value_parser = parse_scale)]
+    scale_output: f32,
+
+    /// Draw a coordinate grid with lines this many pixels apart beneath the
+    /// drawing, with axes through the centre of the canvas
+    #[arg(long, value_name = "SPACING", value_parser = parse_spacing)]
+    grid: Option<f32>,
+
+    /// Also write one SVG per layer, or per pen color with `=color`, next to
+    /// the image
+    #[arg(
+        long,
+        value_enum,
+        value_name = "BY",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "layer"
+    )]
+    split_layers: Option<SplitBy>,
+
+    /// How edges are smoothed in PNG output
+    #[arg(long, value_enum, default_value_t = Antialias::Coverage)]
+    antialias: Antialias,
+
+    /// Lay the SVG out indented with comments, or on a single line
+    #[arg(long, value_enum)]
+    svg_style: Option<SvgStyle>,
+
+    /// Stop with an error once the script, its variables and its shapes use
+    /// roughly this much memory, e.g. 64M
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_memory: Option<usize>,
+
+    /// Stop with an error once blocks and procedure calls nest deeper than this
+    #[arg(long, value_name = "DEPTH", default_value_t = DEFAULT_MAX_DEPTH)]
+    max_depth: usize,
+
+    /// Parse and execute the script a statement at a time instead of all at
+    /// once, for very large generated scripts
+    #[arg(long)]
+    stream: bool,
+
+    /// Write which lines of the scripts ran to this path, as an lcov
+    /// tracefile if it ends in .info and as an annotated listing otherwise
+    #[arg(long, value_name = "PATH", conflicts_with = "stream")]
+    coverage: Option<std::path::PathBuf>,
+
+    /// Write the time spent in each procedure call stack to this path, in the
+    /// folded-stacks format used to draw flamegraphs
+    #[arg(long, value_name = "PATH")]
+    profile: Option<std::path::PathBuf>,
+
+    /// Skip statements that fail with a recoverable error, such as an unknown
+    /// variable, and list the errors once the script has finished
+    #[arg(long)]
+    keep_going: bool,
+
+    /// Write a JSON file describing the render to this path
+    #[arg(long, value_name = "PATH")]
+    emit_meta: Option<std::path::PathBuf>,
+
+    /// Values given to the scripts as :ARG1, :ARG2 and so on, after `--`
+    #[arg(last = true, value_name = "ARGS")]
+    script_args: Vec<String>,
+}
+
+#[derive(clap::Args)]
+struct ReplArgs {
+    /// Path to an svg or png image
+    image_path: std::path::PathBuf,
+
     /// Height
     height: u32,
 
@@ -39,45 +304,584 @@ struct Args {
     width: u32,
 }
 
+#[derive(clap::Args)]
+struct TestArgs {
+    /// Scripts to test, or directories to search for .lg scripts
+    #[arg(required = true)]
+    paths: Vec<std::path::PathBuf>,
+
+    /// Height of the canvas the scripts run on
+    #[arg(long, default_value_t = 500)]
+    height: u32,
+
+    /// Width of the canvas the scripts run on
+    #[arg(long, default_value_t = 500)]
+    width: u32,
+}
+
+#[derive(clap::Args)]
+struct LsysArgs {
+    /// The starting string
+    #[arg(long)]
+    axiom: String,
+
+    /// A rewriting rule such as "F=F+F--F+F", can be given more than once
+    #[arg(long = "rule", value_parser = parse_rule)]
+    rules: Vec<(char, String)>,
+
+    /// How many times to apply the rules
+    #[arg(long, default_value_t = 4)]
+    iterations: u32,
+
+    /// Degrees turned by + and -
+    #[arg(long, default_value_t = 90.0)]
+    angle: f32,
+
+    /// Distance moved by F, G and f
+    #[arg(long, default_value_t = 10.0)]
+    step: f32,
+
+    /// Render to this svg or png image instead of printing the Logo program
+    image_path: Option<std::path::PathBuf>,
+
+    /// Height of the rendered image
+    #[arg(long, default_value_t = 500)]
+    height: u32,
+
+    /// Width of the rendered image
+    #[arg(long, default_value_t = 500)]
+    width: u32,
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let args: Args = Args::parse();
+    let cli = Cli::parse();
+    let _guard = init_tracing(cli.trace, cli.trace_chrome);
+
+    match (cli.command, cli.args) {
+        (Some(Commands::Run(args)), _) => {
+            let (scripts, image_path, dims) = args.split_paths()?;
+            run(&scripts, &image_path, dims, args.options)
+        }
+        (Some(Commands::Repl(args)), _) => run_repl(args),
+        (Some(Commands::Test(args)), _) => run_tests(args),
+        (Some(Commands::Lsys(args)), _) => run_lsys(args),
+        (None, Some(args)) => run(
+            &[args.file_path],
+            &args.image_path,
+            args.height.zip(args.width),
+            cli.options,
+        ),
+        (None, None) => unreachable!("clap requires the render arguments"),
+    }
+}
 
-    // Access the parsed arguments
-    let file_path = args.file_path;
-    let image_path = args.image_path;
-    let height = args.height;
-    let width = args.width;
+/// Installs a subscriber for the spans around each phase and procedure call,
+/// if either kind of tracing was asked for. The returned guard must be held
+/// until the program exits so that the Chrome trace is flushed.
+fn init_tracing(console: bool, chrome: Option<PathBuf>) -> Option<FlushGuard> {
+    if !console && chrome.is_none() {
+        return None;
+    }
+
+    let console = console.then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_writer(std::io::stderr)
+            .with_span_events(FmtSpan::CLOSE)
+    });
+    let (chrome, guard) = match chrome {
+        Some(path) => {
+            let (layer, guard) = ChromeLayerBuilder::new().file(path).build();
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
 
-    let mut image = Image::new(width, height);
+    tracing_subscriber::registry()
+        .with(console)
+        .with(chrome)
+        .init();
+    guard
+}
 
-    let mut file = File::open(file_path)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
+/// A canvas height and width in pixels.
+type Dims = (u32, u32);
 
-    let mut turtle = Turtle::new(&mut image);
+impl RunArgs {
+    /// Splits the paths into the scripts, the image path and, unless `--size`
+    /// was given, the height and width.
+    fn split_paths(&self) -> Result<(Vec<PathBuf>, PathBuf, Option<Dims>), String> {
+        let mut paths = self.paths.clone();
+        let dims = match self.options.size {
+            Some(_) => None,
+            None if paths.len() < 4 => {
+                return Err(
+                    "Expected one or more scripts, an image path, a height and a width".into(),
+                )
+            }
+            None => {
+                let width = paths.pop().unwrap();
+                let height = paths.pop().unwrap();
+                Some((
+                    height
+                        .parse()
+                        .map_err(|_| format!("Invalid height: {height:?}"))?,
+                    width
+                        .parse()
+                        .map_err(|_| format!("Invalid width: {width:?}"))?,
+                ))
+            }
+        };
+
+        let image_path = PathBuf::from(paths.pop().unwrap());
+        Ok((
+            paths.into_iter().map(PathBuf::from).collect(),
+            image_path,
+            dims,
+        ))
+    }
+}
+
+/// Gives the values after `--` to the script as `:ARG1`, `:ARG2` and so on,
+/// as numbers where they parse as one and as words otherwise.
+fn bind_script_args(script_args: &[String], env: &mut Environment) {
+    for (i, value) in script_args.iter().enumerate() {
+        let value = match value.parse::<f64>() {
+            Ok(val) => Expression::Float(val),
+            Err(_) => Expression::Word(value.clone()),
+        };
+        env.vars.insert(format!("ARG{}", i + 1), value);
+    }
+}
+
+/// Runs the scripts in order as one program, sharing their variables and
+/// procedures, and saves the drawing to `image_path`.
+fn run(
+    scripts: &[PathBuf],
+    image_path: &Path,
+    dims: Option<Dims>,
+    args: Options,
+) -> Result<(), Box<dyn Error>> {
+    let mut canvas = match args.size {
+        Some((width, height)) => {
+            let mut canvas = Canvas::new(
+                width.to_px(args.dpi).round() as u32,
+                height.to_px(args.dpi).round() as u32,
+            );
+            canvas.physical_size = Some((width, height));
+            canvas
+        }
+        None => {
+            let (height, width) = dims.expect("clap requires the height and width");
+            Canvas::new(width, height)
+        }
+    };
+
+    canvas.svg_precision = args.svg_precision;
+    canvas.svg_style = args.svg_style;
+    canvas.viewport = args.viewport;
+    canvas.antialias = args.antialias;
+    canvas.grid = args.grid;
+    canvas.export = Export {
+        rotation: args.rotate_output,
+        flip: args.flip,
+        scale: args.scale_output,
+    };
+    if args.mirror {
+        canvas.set_kaleidoscope(args.symmetry as usize);
+    } else {
+        canvas.set_symmetry(args.symmetry as usize);
+    }
 
-    let mut vars: HashMap<String, Expression> = HashMap::new();
-    let tokens = tokenize_script(&contents);
-    let ast = parse_tokens(tokens, &mut 0, &mut vars)?;
-    execute(&ast, &mut turtle, &mut vars)?;
+    let _span = info_span!("run", scripts = scripts.len()).entered();
 
-    match image_path.extension().and_then(|s| s.to_str()) {
-        Some("svg") => {
-            let res = image.save_svg(&image_path);
+    let mut turtle = Turtle::new(&mut canvas);
+    turtle.precision = args.precision;
+
+    let log = match args.log {
+        Some(log_path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_path)?;
+            Some(ExecLog::new(Box::new(std::io::BufWriter::new(file))))
+        }
+        None => None,
+    };
+
+    let palette = match &args.palette {
+        Some(palette_path) => Palette::parse(&std::fs::read_to_string(palette_path)?)
+            .map_err(|e| format!("{}: {}", palette_path.display(), e))?,
+        None => Palette::default(),
+    };
+
+    let mut env = Environment {
+        palette,
+        seed: args.seed,
+        rng: Rng::new(args.seed),
+        deterministic: args.deterministic,
+        precision: args.precision,
+        angle_unit: args.angles,
+        syntax: args.syntax,
+        log,
+        keep_going: args.keep_going,
+        ..Default::default()
+    };
+    if args.stdlib {
+        parse_library("std", &mut env)?;
+    }
+    bind_script_args(&args.script_args, &mut env);
+    if let Some(input_path) = args.input {
+        let input = std::fs::read_to_string(input_path)?;
+        env.input = Some(Box::new(Lines::new(input.lines())));
+    }
+
+    if args.coverage.is_some() {
+        env.coverage = Some(Coverage::default());
+    }
+    if args.profile.is_some() {
+        env.profile = Some(Profile::new(Instant::now()));
+    }
+
+    env.limits.max_memory = args.max_memory;
+    env.limits.max_depth = Some(args.max_depth);
+
+    let start = if args.stream {
+        let start = Instant::now();
+        for file_path in scripts {
+            env.syntax = args.syntax;
+            env.dialect = args.dialect;
+            let reader = BufReader::new(File::open(file_path)?);
+            info_span!("stream", file = %file_path.display())
+                .in_scope(|| stream_script(reader, &mut turtle, &mut env))?;
+        }
+        start
+    } else {
+        let mut ast = Vec::new();
+        for file_path in scripts {
+            env.syntax = args.syntax;
+            env.dialect = args.dialect;
+            let source = Source::open(file_path)?;
+            if let Some(coverage) = env.coverage.as_mut() {
+                coverage.add_script(file_path, source.as_str());
+            }
+
+            let tokens = info_span!("tokenise", file = %file_path.display())
+                .in_scope(|| tokenize_script(source.as_str()));
+            ast.extend(
+                info_span!("parse", file = %file_path.display())
+                    .in_scope(|| parse_tokens(tokens, &mut 0, &mut env))?,
+            );
+        }
+
+        env.limits.ast_nodes = count_nodes(&ast)
+            + env
+                .procedures
+                .values()
+                .map(|procedure| count_nodes(&procedure.block))
+                .sum::<usize>();
+        env.limits.check_memory(env.vars.len(), 0)?;
+
+        let start = Instant::now();
+        info_span!("execute").in_scope(|| execute(&ast, &mut turtle, &mut env))?;
+        start
+    };
+
+    turtle.draw_sprite();
+    if turtle.screen_mode == ScreenMode::Window {
+        turtle.canvas.fit_viewport();
+    }
+
+    for warning in &env.warnings {
+        eprintln!("Warning: {warning}");
+    }
+
+    if let (Some(coverage_path), Some(coverage)) = (args.coverage, env.coverage.as_ref()) {
+        let report = match coverage_path.extension().and_then(|s| s.to_str()) {
+            Some("info") => coverage.to_lcov(),
+            _ => coverage.to_listing(),
+        };
+        std::fs::write(coverage_path, report)?;
+    }
+
+    if let (Some(profile_path), Some(profile)) = (args.profile, env.profile.as_mut()) {
+        std::fs::write(profile_path, profile.finish(Instant::now()))?;
+    }
+
+    if let Some(meta_path) = args.emit_meta {
+        let meta = render_meta(&turtle, &env, start.elapsed());
+        std::fs::write(meta_path, serde_json::to_string_pretty(&meta)?)?;
+    }
+
+    save_image_as(&canvas, image_path, args.format)?;
+    if let Some(by) = args.split_layers {
+        save_split(&canvas, image_path, by)?;
+    }
+
+    Ok(())
+}
+
+/// Saves each layer or pen color as its own SVG next to `image_path`, such
+/// as `out-layer-1.svg` or `out-color-red.svg` for `out.svg`.
+fn save_split(canvas: &Canvas, image_path: &Path, by: SplitBy) -> Result<(), Box<dyn Error>> {
+    let stem = image_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("image");
+    for (name, svg) in canvas.split_svgs(by) {
+        let path = image_path.with_file_name(format!("{stem}-{name}.svg"));
+        if let Err(e) = std::fs::write(&path, svg) {
+            return Err(format!("Error saving svg: {e}").into());
+        }
+    }
+
+    Ok(())
+}
+
+fn run_repl(args: ReplArgs) -> Result<(), Box<dyn Error>> {
+    let mut canvas = Canvas::new(args.width, args.height);
+    let mut turtle = Turtle::new(&mut canvas);
+    let mut env = Environment::default();
+
+    repl(
+        std::io::stdin().lock(),
+        &mut turtle,
+        &mut env,
+        &args.image_path,
+    )
+}
+
+fn run_tests(args: TestArgs) -> Result<(), Box<dyn Error>> {
+    let scripts = find_scripts(&args.paths)?;
+    let mut failed = 0;
+
+    for path in &scripts {
+        let script = std::fs::read_to_string(path)?;
+        match check_script(&script, args.width, args.height) {
+            Ok(failures) if failures.is_empty() => println!("PASS {}", path.display()),
+            Ok(failures) => {
+                failed += 1;
+                println!("FAIL {}", path.display());
+                for failure in failures {
+                    println!("    {failure}");
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {}\n    {e}", path.display());
+            }
+        }
+    }
+
+    println!("{} passed, {} failed", scripts.len() - failed, failed);
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{failed} of {} scripts failed", scripts.len()).into()),
+    }
+}
+
+fn run_lsys(args: LsysArgs) -> Result<(), Box<dyn Error>> {
+    let lsys = LSystem {
+        axiom: args.axiom,
+        rules: args.rules.into_iter().collect(),
+        angle: args.angle,
+        step: args.step,
+    };
+    let program = lsys.to_logo(&lsys.expand(args.iterations))?;
+
+    let Some(image_path) = args.image_path else {
+        print!("{program}");
+        return Ok(());
+    };
+
+    let mut canvas = Canvas::new(args.width, args.height);
+    let mut turtle = Turtle::new(&mut canvas);
+    let mut env = Environment::default();
+
+    let tokens = info_span!("tokenise").in_scope(|| tokenize_script(&program));
+    let ast = info_span!("parse").in_scope(|| parse_tokens(tokens, &mut 0, &mut env))?;
+    info_span!("execute").in_scope(|| execute(&ast, &mut turtle, &mut env))?;
+
+    save_image(&canvas, &image_path)
+}
+
+/// The kinds of file the drawing can be saved as.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum ImageFormat {
+    Svg,
+    Png,
+    /// A web page that replays the drawing, with play/pause and scrubbing.
+    Html,
+}
+
+fn save_image(canvas: &Canvas, image_path: &Path) -> Result<(), Box<dyn Error>> {
+    save_image_as(canvas, image_path, None)
+}
+
+/// Saves the drawing in `format`, or the format `image_path`'s extension
+/// names if it isn't given.
+fn save_image_as(
+    canvas: &Canvas,
+    image_path: &Path,
+    format: Option<ImageFormat>,
+) -> Result<(), Box<dyn Error>> {
+    let _span = info_span!("save", path = %image_path.display()).entered();
+    let format = match (format, image_path.extension().and_then(|s| s.to_str())) {
+        (Some(format), _) => format,
+        (None, Some("svg")) => ImageFormat::Svg,
+        (None, Some("png")) => ImageFormat::Png,
+        (None, Some("html")) => ImageFormat::Html,
+        _ => {
+            return Err("Invalid file extension. Please use .svg, .png or .html".into());
+        }
+    };
+
+    match format {
+        ImageFormat::Svg => {
+            let res = canvas.save_svg(image_path);
             if let Err(e) = res {
                 return Err(format!("Error saving svg: {e}").into());
             }
         }
-        Some("png") => {
-            let res = image.save_png(&image_path);
+        ImageFormat::Png => {
+            let res = canvas.save_png(image_path);
             if let Err(e) = res {
                 return Err(format!("Error saving png: {e}").into());
             }
         }
-        _ => {
-            return Err("Invalid file extension. Please use .svg or .png".into());
+        ImageFormat::Html => {
+            let res = canvas.save_html(image_path);
+            if let Err(e) = res {
+                return Err(format!("Error saving html: {e}").into());
+            }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_script_args() {
+        let mut env = Environment::default();
+        bind_script_args(&["10".to_string(), "foo".to_string()], &mut env);
+
+        assert_eq!(env.vars.get("ARG1"), Some(&Expression::Float(10.0)));
+        assert_eq!(
+            env.vars.get("ARG2"),
+            Some(&Expression::Word("foo".to_string()))
+        );
+        assert!(!env.vars.contains_key("ARG3"));
+    }
+
+    /// Parses the arguments of `rslogo run`.
+    fn run_args(args: &[&str]) -> RunArgs {
+        let cli = Cli::try_parse_from(["rslogo", "run"].iter().chain(args)).unwrap();
+        match cli.command {
+            Some(Commands::Run(args)) => *args,
+            _ => panic!("expected the run command"),
+        }
+    }
+
+    #[test]
+    fn test_split_paths() {
+        let args = run_args(&["lib.lg", "main.lg", "out.svg", "200", "300"]);
+
+        assert_eq!(
+            args.split_paths(),
+            Ok((
+                vec![PathBuf::from("lib.lg"), PathBuf::from("main.lg")],
+                PathBuf::from("out.svg"),
+                Some((200, 300)),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_split_paths_with_size() {
+        let args = run_args(&["main.lg", "out.svg", "--size", "210mmx297mm"]);
+
+        assert_eq!(
+            args.split_paths(),
+            Ok((
+                vec![PathBuf::from("main.lg")],
+                PathBuf::from("out.svg"),
+                None
+            ))
+        );
+    }
+
+    #[test]
+    fn test_split_paths_err() {
+        assert!(run_args(&["main.lg", "out.svg", "200"])
+            .split_paths()
+            .is_err());
+        assert!(run_args(&["main.lg", "out.svg", "tall", "300"])
+            .split_paths()
+            .is_err());
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let args = run_args(&["main.lg", "out.svg"]);
+        assert_eq!(args.options.max_depth, DEFAULT_MAX_DEPTH);
+
+        let args = run_args(&["main.lg", "out.svg", "--max-depth", "2000"]);
+        assert_eq!(args.options.max_depth, 2000);
+    }
+
+    #[test]
+    fn test_trace_spans() {
+        // A directory of its own, so concurrent runs don't share files.
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!(
+            "rslogo_test_trace_{}_{}",
+            std::process::id(),
+            nanos
+        ));
+        std::fs::create_dir(&dir).unwrap();
+        let script = dir.join("trace.lg");
+        let image = dir.join("trace.svg");
+        let trace = dir.join("trace.json");
+        std::fs::write(&script, "TO STEP FORWARD \"10 END STEP STEP").unwrap();
+
+        let (layer, guard) = ChromeLayerBuilder::new()
+            .file(&trace)
+            .include_args(true)
+            .build();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        let args = run_args(&[
+            script.to_str().unwrap(),
+            image.to_str().unwrap(),
+            "50",
+            "50",
+        ]);
+        let (scripts, image_path, dims) = args.split_paths().unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            run(&scripts, &image_path, dims, args.options)
+        })
+        .unwrap();
+        drop(guard);
+
+        let events: Vec<serde_json::Value> =
+            serde_json::from_str(&std::fs::read_to_string(&trace).unwrap()).unwrap();
+        let spans: Vec<&serde_json::Value> =
+            events.iter().filter(|event| event["ph"] == "B").collect();
+        for phase in ["run", "tokenise", "parse", "execute"] {
+            assert!(spans.iter().any(|span| span["name"] == phase));
+        }
+        // The procedure's name is recorded with its `Debug` quotes.
+        let calls = spans
+            .iter()
+            .filter(|span| span["name"] == "procedure" && span["args"]["name"] == "\"STEP\"")
+            .count();
+        assert_eq!(calls, 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
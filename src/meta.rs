@@ -0,0 +1,125 @@
+//! Builds the sidecar metadata written by `--emit-meta`.
+//!
+//! The metadata describes a finished render: the canvas, the palette, where
+//! the turtle ended up, the extent of the drawing and how much work the
+//! interpreter did to produce it.
+
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::{
+    canvas::{fmt_color, Shape},
+    environment::Environment,
+    interpreter::{log::color_to_json, turtle::Turtle},
+};
+
+/// Collects the metadata for a render once the script has finished executing.
+///
+/// # Example
+///
+/// ```rust
+/// let meta = render_meta(&turtle, &env, start.elapsed());
+/// std::fs::write("out.meta.json", serde_json::to_string_pretty(&meta)?)?;
+/// ```
+pub fn render_meta(turtle: &Turtle, env: &Environment, elapsed: Duration) -> Value {
+    let canvas = &turtle.canvas;
+    let (width, height) = canvas.get_dimensions();
+
+    let palette: Vec<Value> = env
+        .palette
+        .entries()
+        .enumerate()
+        .map(|(index, (color, name))| {
+            json!({ "index": index, "name": name, "color": fmt_color(color) })
+        })
+        .collect();
+
+    let bounding_box = match canvas.bounding_box() {
+        Some((min_x, min_y, max_x, max_y)) => {
+            json!({ "min_x": min_x, "min_y": min_y, "max_x": max_x, "max_y": max_y })
+        }
+        None => Value::Null,
+    };
+
+    let segments = canvas
+        .elements
+        .iter()
+        .filter(|element| matches!(element.shape, Shape::Line { .. }))
+        .count();
+
+    json!({
+        "canvas": { "width": width, "height": height },
+        "palette": palette,
+        "turtle": {
+            "x": turtle.x as f32,
+            "y": turtle.y as f32,
+            "heading": turtle.heading,
+            "pen_down": turtle.pen_down,
+            "pen_color": color_to_json(turtle.pen_color, &env.palette),
+            "pen_alpha": turtle.pen_alpha,
+            "pen_pattern": turtle.pen_pattern.name(),
+            "fill_pattern": turtle.fill_pattern.name(),
+        },
+        "bounding_box": bounding_box,
+        "segments": segments,
+        "elements": canvas.elements.len(),
+        "stats": {
+            "commands": env.stats.commands,
+            "calls": env.stats.calls,
+            "odometer": turtle.odometer,
+            "tripmeter": turtle.tripmeter,
+            "elapsed_ms": elapsed.as_secs_f64() * 1000.0,
+        },
+        "seed": env.seed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::canvas::Canvas;
+
+    use super::*;
+
+    #[test]
+    fn test_render_meta() {
+        let mut canvas = Canvas::new(200, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        env.stats.commands = 3;
+
+        turtle.pen_down();
+        turtle.forward(20.0);
+
+        let meta = render_meta(&turtle, &env, Duration::from_millis(5));
+
+        assert_eq!(meta["canvas"], json!({ "width": 200, "height": 100 }));
+        assert_eq!(meta["palette"].as_array().unwrap().len(), 16);
+        assert_eq!(
+            meta["palette"][4],
+            json!({ "index": 4, "name": "red", "color": "#ff0000" })
+        );
+        assert_eq!(meta["turtle"]["y"], json!(30.0));
+        assert_eq!(
+            meta["bounding_box"],
+            json!({ "min_x": 100.0, "min_y": 30.0, "max_x": 100.0, "max_y": 50.0 })
+        );
+        assert_eq!(meta["segments"], json!(1));
+        assert_eq!(meta["stats"]["commands"], json!(3));
+        assert_eq!(meta["stats"]["tripmeter"], json!(20.0));
+        assert_eq!(meta["stats"]["elapsed_ms"], json!(5.0));
+        assert_eq!(meta["seed"], json!(0));
+    }
+
+    #[test]
+    fn test_render_meta_empty() {
+        let mut canvas = Canvas::new(10, 10);
+        let turtle = Turtle::new(&mut canvas);
+        let env = Environment::default();
+
+        let meta = render_meta(&turtle, &env, Duration::ZERO);
+
+        assert_eq!(meta["bounding_box"], Value::Null);
+        assert_eq!(meta["segments"], json!(0));
+    }
+}
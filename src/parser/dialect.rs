@@ -0,0 +1,39 @@
+//! Which dialect of Logo a script is written in, set with `--dialect` or
+//! `SETDIALECT`.
+//!
+//! In rslogo's own dialect `LEFT` and `RIGHT` move the turtle sideways without
+//! turning it. In the classic dialect of other Logos they turn it on the spot
+//! instead, so `RIGHT "90` is `TURN "90` and `LEFT "90` is `TURN "-90`, and
+//! programs written for those Logos draw as they were meant to. Like the
+//! syntax, the dialect only changes how a script is parsed.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Dialect {
+    #[default]
+    Rslogo,
+    Classic,
+}
+
+impl Dialect {
+    /// The names accepted by `SETDIALECT`.
+    pub const NAMES: [&'static str; 2] = ["rslogo", "classic"];
+
+    pub fn from_name(name: &str) -> Option<Dialect> {
+        match name.to_lowercase().as_str() {
+            "rslogo" => Some(Dialect::Rslogo),
+            "classic" => Some(Dialect::Classic),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Dialect::from_name("Classic"), Some(Dialect::Classic));
+        assert_eq!(Dialect::from_name("ucb"), None);
+    }
+}
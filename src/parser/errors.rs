@@ -5,6 +5,7 @@ pub enum ParseErrorKind {
     UnexpectedToken { token: String },
     InvalidSyntax { msg: String },
     VariableNotFound { var: String },
+    ConstantReassigned { var: String },
 }
 
 #[derive(Debug, PartialEq)]
@@ -26,6 +27,9 @@ impl std::fmt::Display for ParseError {
             ParseErrorKind::VariableNotFound { var } => {
                 write!(f, "Variable not found: '{}'.", var)
             }
+            ParseErrorKind::ConstantReassigned { var } => {
+                write!(f, "Cannot assign to constant: '{}'.", var)
+            }
         }
     }
 }
@@ -56,5 +60,12 @@ mod tests {
             },
         };
         assert_eq!(err.to_string(), "Variable not found: 'foo'.");
+
+        let err = ParseError {
+            kind: ParseErrorKind::ConstantReassigned {
+                var: "foo".to_string(),
+            },
+        };
+        assert_eq!(err.to_string(), "Cannot assign to constant: 'foo'.");
     }
 }
@@ -3,14 +3,16 @@
 //! Contains the bulk of the parsing functionality and how each `Expression`
 //! is parsed.
 
-use std::collections::HashMap;
-
-use crate::ast::{ASTNode, Condition, Expression, Math, Query};
+use crate::{
+    ast::{ASTNode, Condition, ControlFlow, Expression, Function, ListOp, Math, Procedure, Query},
+    environment::Environment,
+};
 
 use super::{
     errors::ParseError,
     errors::ParseErrorKind::{self, VariableNotFound},
-    parse::parse_tokens,
+    parse::{parse_block, BUILTINS},
+    syntax::{parse_infix, Syntax},
 };
 
 /// Matches and parses a token into an `Expression`.
@@ -18,26 +20,50 @@ use super::{
 /// # Example
 ///
 /// ```rust
-/// use std::collections::HashMap;
-///
-/// let mut vars: HashMap<String, Expression> = HashMap::new();
+/// let mut env = Environment::default();
 /// let tokens = vec!["\"100"];
-/// let expr = match_parse(&tokens, &mut 0, &mut vars).unwrap();
+/// let expr = match_parse(&tokens, &mut 0, &mut env).unwrap();
 ///
 /// assert_eq!(expr, Expression::Float(100.0));
 /// ```
 pub fn match_parse(
     tokens: &[&str],
     pos: &mut usize,
-    vars: &mut HashMap<String, Expression>,
+    env: &mut Environment,
+) -> Result<Expression, ParseError> {
+    match env.syntax {
+        Syntax::Prefix => parse_prefix(tokens, pos, env),
+        Syntax::Infix => parse_infix(tokens, pos, env),
+    }
+}
+
+/// Parses an expression in Logo's own prefix syntax, such as `+ :X "1`,
+/// leaving the position at its last token.
+pub fn parse_prefix(
+    tokens: &[&str],
+    pos: &mut usize,
+    env: &mut Environment,
 ) -> Result<Expression, ParseError> {
     if tokens[*pos].starts_with('"') {
         // Normal expressions
         parse_expression(tokens, *pos).map(Expression::Float)
+    } else if tokens[*pos] == "(" {
+        // Grouping, leaving the position at the closing parenthesis
+        *pos += 1;
+        let expr = parse_word(tokens, pos, env)?;
+        *pos += 1;
+        if tokens.get(*pos) != Some(&")") {
+            return Err(ParseError {
+                kind: ParseErrorKind::InvalidSyntax {
+                    msg: "Expected ')' to close '('".to_string(),
+                },
+            });
+        }
+        Ok(expr)
     } else if tokens[*pos].starts_with(':') {
         // Variables
         let token = tokens[*pos].trim_start_matches(':');
-        if vars.contains_key(token) {
+        if env.vars.contains_key(token) {
             Ok(Expression::Variable(token.to_string()))
         } else {
             Err(ParseError {
@@ -48,17 +74,201 @@ pub fn match_parse(
         }
     } else if matches!(
         tokens[*pos],
-        "+" | "-" | "*" | "/" | "EQ" | "LT" | "GT" | "NE" | "AND" | "OR"
+        "+" | "-"
+            | "*"
+            | "/"
+            | "MOD"
+            | "REMAINDER"
+            | "MIN"
+            | "MAX"
+            | "EQ"
+            | "LT"
+            | "GT"
+            | "NE"
+            | "LE"
+            | "GE"
+            | "AND"
+            | "OR"
+            | "XOR"
+            | "NOISE"
+            | "PIXEL"
+    ) {
+        parse_maths(tokens, pos, env)
+    } else if matches!(
+        tokens[*pos],
+        "SIN" | "COS" | "TAN" | "ARCTAN" | "RANDOM" | "ROUND" | "INT" | "ABS"
     ) {
-        parse_maths(tokens, pos, vars)
+        parse_function(tokens, pos, env)
+    } else if tokens[*pos] == "TOUCHING" {
+        *pos += 1;
+        let x = match_parse(tokens, pos, env)?;
+        *pos += 1;
+        let y = match_parse(tokens, pos, env)?;
+        *pos += 1;
+        let radius = match_parse(tokens, pos, env)?;
+        Ok(Expression::Query(Query::Touching(Box::new((x, y, radius)))))
+    } else if tokens[*pos] == "INTERSECTS" {
+        *pos += 1;
+        let distance = match_parse(tokens, pos, env)?;
+        Ok(Expression::Query(Query::Intersects(Box::new(distance))))
+    } else if matches!(tokens[*pos], "TOWARDS" | "DISTANCE") {
+        let keyword = tokens[*pos];
+        *pos += 1;
+        let x = match_parse(tokens, pos, env)?;
+        *pos += 1;
+        let y = match_parse(tokens, pos, env)?;
+        let point = Box::new((x, y));
+        Ok(Expression::Query(if keyword == "TOWARDS" {
+            Query::Towards(point)
+        } else {
+            Query::Distance(point)
+        }))
+    } else if tokens[*pos] == "WORD" {
+        *pos += 1;
+        let lhs = parse_word(tokens, pos, env)?;
+        *pos += 1;
+        let rhs = parse_word(tokens, pos, env)?;
+        Ok(Expression::Concat(Box::new((lhs, rhs))))
+    } else if matches!(tokens[*pos], "FIRST" | "BUTFIRST" | "COUNT") {
+        let keyword = tokens[*pos];
+        *pos += 1;
+        let list = parse_word(tokens, pos, env)?;
+        let op = match keyword {
+            "FIRST" => ListOp::First(list),
+            "BUTFIRST" => ListOp::ButFirst(list),
+            _ => ListOp::Count(list),
+        };
+        Ok(Expression::ListOp(Box::new(op)))
+    } else if tokens[*pos] == "MAP" {
+        *pos += 1;
+        if tokens.get(*pos) != Some(&"[") {
+            return Err(ParseError {
+                kind: ParseErrorKind::InvalidSyntax {
+                    msg: "Expected '[' before the template of MAP".to_string(),
+                },
+            });
+        }
+        *pos += 1;
+        let template = with_item_var(env, |env| parse_word(tokens, pos, env))?;
+        *pos += 1;
+        if tokens.get(*pos) != Some(&"]") {
+            return Err(ParseError {
+                kind: ParseErrorKind::InvalidSyntax {
+                    msg: "Expected ']' after the template of MAP".to_string(),
+                },
+            });
+        }
+        *pos += 1;
+        let list = parse_word(tokens, pos, env)?;
+        Ok(Expression::ListOp(Box::new(ListOp::Map(template, list))))
+    } else if tokens[*pos] == "ITEM" {
+        *pos += 1;
+        let index = match_parse(tokens, pos, env)?;
+        *pos += 1;
+        let list = parse_word(tokens, pos, env)?;
+        Ok(Expression::ListOp(Box::new(ListOp::Item(index, list))))
+    } else if tokens[*pos] == "ARRAY" {
+        *pos += 1;
+        let size = match_parse(tokens, pos, env)?;
+        Ok(Expression::MakeArray(Box::new(size)))
+    } else if tokens[*pos] == "GPROP" {
+        *pos += 1;
+        let list = tokens[*pos].trim_start_matches('"').to_string();
+        *pos += 1;
+        let prop = tokens[*pos].trim_start_matches('"').to_string();
+        Ok(Expression::GetProp(list, prop))
+    } else if tokens[*pos] == "READWORD" {
+        Ok(Expression::ReadWord)
+    } else if tokens[*pos] == "GETENV" {
+        *pos += 1;
+        let name = tokens[*pos].trim_start_matches('"');
+        Ok(Expression::Query(Query::GetEnv(name.to_string())))
+    } else if tokens[*pos] == "QUERY" {
+        *pos += 1;
+        let name = tokens[*pos].trim_start_matches('"');
+        Ok(Expression::Query(Query::Provided(name.to_string())))
+    } else if let Some(procedure) = env.procedures.get(tokens[*pos]) {
+        // Procedures that `OUTPUT` a value can be used as expressions.
+        let name = procedure.name.clone();
+        let arity = procedure.params.len();
+        let mut args = Vec::with_capacity(arity);
+        for _ in 0..arity {
+            *pos += 1;
+            args.push(parse_word(tokens, pos, env)?);
+        }
+        Ok(Expression::Call(name, args))
     } else {
         parse_query(tokens, *pos).map(Expression::Query)
     }
 }
 
+/// Parses a value that may be a word or a list as well as a number, such as
+/// the value given to `MAKE`. A quoted token that isn't a number is taken as a
+/// word.
+///
+/// # Example
+///
+/// ```rust
+/// let mut env = Environment::default();
+/// let tokens = vec!["\"hello"];
+/// let expr = parse_word(&tokens, &mut 0, &mut env).unwrap();
+///
+/// assert_eq!(expr, Expression::Word("hello".to_string()));
+/// ```
+pub fn parse_word(
+    tokens: &[&str],
+    pos: &mut usize,
+    env: &mut Environment,
+) -> Result<Expression, ParseError> {
+    if tokens[*pos] == "[" {
+        parse_list(tokens, pos)
+    } else if tokens[*pos].starts_with('"') && parse_expression(tokens, *pos).is_err() {
+        Ok(Expression::Word(tokens[*pos][1..].to_string()))
+    } else {
+        match_parse(tokens, pos, env)
+    }
+}
+
+/// Parses a list from the `[` at `pos` up to its matching `]`, where `pos` is
+/// left. As in other Logos the items are not evaluated, so `[ 90 :X ]` holds
+/// the word `:X` rather than the value of `X`.
+///
+/// # Example
+///
+/// ```rust
+/// let tokens = vec!["[", "90", "\"45", "]"];
+/// let list = parse_list(&tokens, &mut 0).unwrap();
+///
+/// assert_eq!(list, Expression::List(vec![Expression::Float(90.0), Expression::Float(45.0)]));
+/// ```
+fn parse_list(tokens: &[&str], pos: &mut usize) -> Result<Expression, ParseError> {
+    let mut items = Vec::new();
+    *pos += 1;
+    while let Some(token) = tokens.get(*pos) {
+        match *token {
+            "]" => return Ok(Expression::List(items)),
+            "[" => items.push(parse_list(tokens, pos)?),
+            _ => {
+                let word = token.strip_prefix('"').unwrap_or(token);
+                items.push(match word.parse::<f64>() {
+                    Ok(val) if val.is_finite() => Expression::Float(val),
+                    _ => Expression::Word(word.to_string()),
+                });
+            }
+        }
+        *pos += 1;
+    }
+
+    Err(ParseError {
+        kind: ParseErrorKind::InvalidSyntax {
+            msg: "Expected the end of a list: ']'".to_string(),
+        },
+    })
+}
+
 /// Parse an expression from a token.
 ///
-/// This expression defaults to a f32 value.
+/// This expression defaults to a f64 value.
 ///
 /// # Example
 ///
@@ -68,7 +278,7 @@ pub fn match_parse(
 ///
 /// assert_eq!(expr, Expression::Float(100.0));
 /// ```
-pub fn parse_expression(tokens: &[&str], pos: usize) -> Result<f32, ParseError> {
+pub fn parse_expression(tokens: &[&str], pos: usize) -> Result<f64, ParseError> {
     if tokens[pos].starts_with('"') {
         let token = tokens[pos].trim_start_matches('"');
         if token == "TRUE" {
@@ -76,11 +286,14 @@ pub fn parse_expression(tokens: &[&str], pos: usize) -> Result<f32, ParseError>
         } else if token == "FALSE" {
             Ok(0.0)
         } else {
-            token.parse::<f32>().map_err(|_| ParseError {
-                kind: ParseErrorKind::InvalidSyntax {
-                    msg: format!("Cannot parse this expression as a float: {:?}", token),
-                },
-            })
+            match token.parse::<f64>() {
+                Ok(val) if val.is_finite() => Ok(val),
+                _ => Err(ParseError {
+                    kind: ParseErrorKind::InvalidSyntax {
+                        msg: format!("Cannot parse this expression as a float: {:?}", token),
+                    },
+                }),
+            }
         }
     } else {
         Err(ParseError {
@@ -109,6 +322,21 @@ pub fn parse_query(tokens: &[&str], pos: usize) -> Result<Query, ParseError> {
         "YCOR" => Query::YCor,
         "HEADING" => Query::Heading,
         "COLOR" => Query::Color,
+        "DELTAX" => Query::DeltaX,
+        "DELTAY" => Query::DeltaY,
+        "ODOMETER" => Query::Odometer,
+        "TRIPMETER" => Query::Tripmeter,
+        "MINX" => Query::MinX,
+        "MAXX" => Query::MaxX,
+        "MINY" => Query::MinY,
+        "MAXY" => Query::MaxY,
+        "YEAR" => Query::Year,
+        "MONTH" => Query::Month,
+        "DAY" => Query::Day,
+        "HOUR" => Query::Hour,
+        "MINUTE" => Query::Minute,
+        "SECOND" => Query::Second,
+        "READNUMBER" => Query::ReadNumber,
         _ => {
             return Err(ParseError {
                 kind: ParseErrorKind::InvalidSyntax {
@@ -125,25 +353,26 @@ pub fn parse_query(tokens: &[&str], pos: usize) -> Result<Query, ParseError> {
 /// # Example
 ///
 /// ```rust
-/// use std::collections::HashMap;
-///
-/// let mut vars: HashMap<String, Expression> = HashMap::new();
+/// let mut env = Environment::default();
 /// let tokens = vec!["EQ", "\"100", "\"100"];
 ///
-/// let condition = parse_conditions(&tokens, &mut 0, &vars).unwrap();
+/// let condition = parse_conditions(&tokens, &mut 0, &mut env).unwrap();
 /// assert_eq!(condition, Condition::Equals(Expression::Float(100.0), Expression::Float(100.0)));
 /// ```
 pub fn parse_conditions(
     tokens: &[&str],
     curr_pos: &mut usize,
-    vars: &mut HashMap<String, Expression>,
+    env: &mut Environment,
 ) -> Result<Condition, ParseError> {
     let condition_idx = *curr_pos;
 
     // If condition_idx is not an condition but a boolean, we parse the
     // boolean as a condition and return early.
-    if !matches!(tokens[condition_idx], "EQ" | "LT" | "GT" | "AND" | "OR") {
-        let res = match_parse(tokens, curr_pos, vars)
+    if !matches!(
+        tokens[condition_idx],
+        "EQ" | "LT" | "GT" | "LE" | "GE" | "AND" | "OR" | "XOR"
+    ) {
+        let res = match_parse(tokens, curr_pos, env)
             .map(|expr| Condition::Equals(expr, Expression::Float(1.0)));
         *curr_pos += 1;
         return res;
@@ -151,18 +380,21 @@ pub fn parse_conditions(
 
     // Otherwise, we parse the condition as normal.
     *curr_pos += 1;
-    let expr_1 = match_parse(tokens, curr_pos, vars)?;
+    let expr_1 = match_parse(tokens, curr_pos, env)?;
 
     *curr_pos += 1;
-    let expr_2 = match_parse(tokens, curr_pos, vars)?;
+    let expr_2 = match_parse(tokens, curr_pos, env)?;
 
     *curr_pos += 1;
     let condition = match tokens[condition_idx] {
         "EQ" => Condition::Equals(expr_1, expr_2),
         "LT" => Condition::LessThan(expr_1, expr_2),
         "GT" => Condition::GreaterThan(expr_1, expr_2),
+        "LE" => Condition::LessEqual(expr_1, expr_2),
+        "GE" => Condition::GreaterEqual(expr_1, expr_2),
         "AND" => Condition::And(expr_1, expr_2),
         "OR" => Condition::Or(expr_1, expr_2),
+        "XOR" => Condition::Xor(expr_1, expr_2),
         _ => unreachable!(),
     };
 
@@ -174,20 +406,18 @@ pub fn parse_conditions(
 ///
 /// # Example
 /// ```rust
-/// use std::collections::HashMap;
-///
-/// let mut vars: HashMap<String, Expression> = HashMap::new();
+/// let mut env = Environment::default();
 /// let tokens = vec!["[", "PENDOWN", "FORWARD", "\"100", "]"];
 /// let mut curr_pos = 0;
 ///
-/// let block = parse_conditional_blocks(&tokens, &mut curr_pos, &mut vars).unwrap();
+/// let block = parse_conditional_blocks(&tokens, &mut curr_pos, &mut env).unwrap();
 /// assert_eq!(block, vec![ASTNode::Command(Command::PenDown),
 ///        ASTNode::Command(Command::Forward(Expression::Float(100.0)))]);
 /// ```
 pub fn parse_conditional_blocks(
     tokens: &[&str],
     curr_pos: &mut usize,
-    vars: &mut HashMap<String, Expression>,
+    env: &mut Environment,
 ) -> Result<Vec<ASTNode>, ParseError> {
     if tokens[*curr_pos] != "[" {
         return Err(ParseError {
@@ -201,12 +431,7 @@ pub fn parse_conditional_blocks(
     }
     *curr_pos += 1; // skipping '['
 
-    let mut block: Vec<ASTNode> = Vec::new();
-
-    while *curr_pos < tokens.len() && tokens[*curr_pos] != "]" {
-        let ast = parse_tokens(tokens.to_vec(), curr_pos, vars)?;
-        block.extend(ast);
-    }
+    let block = parse_block(tokens, curr_pos, env)?;
 
     // If we reach the end of the tokens and the block hasn't been closed yet,
     // we return an error.
@@ -221,13 +446,215 @@ pub fn parse_conditional_blocks(
     Ok(block)
 }
 
-/// Parse mathematical expressions. Includes both basic and logical arithmetics.
+/// Parses a `FOR [var start end step] [ ... ]` loop, where the step is
+/// optional. The current position should be just after the `FOR` token, and
+/// will be left at the `]` closing the body.
+///
+/// The loop variable is declared while the body is parsed so that `:var`
+/// references resolve.
+///
+/// # Example
+/// ```rust
+/// let mut env = Environment::default();
+/// let tokens = vec!["[", "I", "\"1", "\"3", "]", "[", "FORWARD", ":I", "]"];
+/// let mut curr_pos = 0;
+///
+/// let for_loop = parse_for(&tokens, &mut curr_pos, &mut env).unwrap();
+/// assert!(matches!(for_loop, ControlFlow::For { step: None, .. }));
+/// ```
+pub fn parse_for(
+    tokens: &[&str],
+    curr_pos: &mut usize,
+    env: &mut Environment,
+) -> Result<ControlFlow, ParseError> {
+    let expected = |msg: &str| ParseError {
+        kind: ParseErrorKind::InvalidSyntax {
+            msg: format!("Expected {} in FOR [var start end step]", msg),
+        },
+    };
+
+    if tokens.get(*curr_pos) != Some(&"[") {
+        return Err(expected("'['"));
+    }
+    *curr_pos += 1;
+    let var = tokens
+        .get(*curr_pos)
+        .ok_or_else(|| expected("a loop variable"))?
+        .trim_start_matches('"')
+        .to_string();
+    *curr_pos += 1;
+    let start = match_parse(tokens, curr_pos, env)?;
+    *curr_pos += 1;
+    let end = match_parse(tokens, curr_pos, env)?;
+    *curr_pos += 1;
+    let step = match tokens.get(*curr_pos) {
+        Some(&"]") => None,
+        Some(_) => {
+            let step = match_parse(tokens, curr_pos, env)?;
+            *curr_pos += 1;
+            Some(step)
+        }
+        None => return Err(expected("']'")),
+    };
+    if tokens.get(*curr_pos) != Some(&"]") {
+        return Err(expected("']'"));
+    }
+    *curr_pos += 1;
+
+    // The loop variable shadows any existing variable while the body is
+    // parsed.
+    let prev = env.vars.insert(var.clone(), Expression::Float(0.0));
+    let block = parse_conditional_blocks(tokens, curr_pos, env);
+    match prev {
+        Some(expr) => env.vars.insert(var.clone(), expr),
+        None => env.vars.remove(&var),
+    };
+
+    Ok(ControlFlow::For {
+        var,
+        start,
+        end,
+        step,
+        block: block?,
+    })
+}
+
+/// Parses a `FOREACH list [ ... ]` loop. The current position should be at
+/// the list, just after the `FOREACH` token, and will be left at the closing
+/// `]` of the block.
+///
+/// # Example
+/// ```rust
+/// let mut env = Environment::default();
+/// let tokens = vec!["[", "10", "20", "]", "[", "FORWARD", ":?", "]"];
+///
+/// let foreach = parse_foreach(&tokens, &mut 0, &mut env).unwrap();
+/// assert!(matches!(foreach, ControlFlow::ForEach { .. }));
+/// ```
+pub fn parse_foreach(
+    tokens: &[&str],
+    curr_pos: &mut usize,
+    env: &mut Environment,
+) -> Result<ControlFlow, ParseError> {
+    let list = parse_word(tokens, curr_pos, env)?;
+    *curr_pos += 1;
+    let block = with_item_var(env, |env| parse_conditional_blocks(tokens, curr_pos, env))?;
+
+    Ok(ControlFlow::ForEach { list, block })
+}
+
+/// Declares `:?`, the item a `FOREACH` block or `MAP` template is run with,
+/// while `parse` is run, shadowing any existing `?` variable.
+fn with_item_var<T>(
+    env: &mut Environment,
+    parse: impl FnOnce(&mut Environment) -> Result<T, ParseError>,
+) -> Result<T, ParseError> {
+    let prev = env.vars.insert("?".to_string(), Expression::Float(0.0));
+    let res = parse(env);
+    match prev {
+        Some(expr) => env.vars.insert("?".to_string(), expr),
+        None => env.vars.remove("?"),
+    };
+    res
+}
+
+/// Parses a procedure definition into a `Procedure`. The current position
+/// should be at the procedure name, i.e. just after the `TO` token, and will be
+/// left at the closing `END` token.
+///
+/// The name can't be one of the built-in commands, except for the native
+/// shapes such as `POLYGON`, which the procedure is then called in place of.
+///
+/// Parameters are the quoted words directly following the procedure name. They
+/// are declared while the body is parsed so that `:param` references resolve,
+/// and the procedure itself is registered beforehand to allow recursion.
 ///
 /// # Example
 /// ```rust
-/// use std::collections::HashMap;
+/// let mut env = Environment::default();
+/// let tokens = vec!["SQUARE", "\"SIZE", "FORWARD", ":SIZE", "END"];
+/// let mut curr_pos = 0;
+///
+/// let procedure = parse_procedure(&tokens, &mut curr_pos, &mut env).unwrap();
+/// assert_eq!(procedure.params, vec!["SIZE".to_string()]);
+/// assert_eq!(procedure.block, vec![ASTNode::Command(Command::Forward(
+///        Expression::Variable("SIZE".to_string())))]);
+/// ```
+pub fn parse_procedure(
+    tokens: &[&str],
+    curr_pos: &mut usize,
+    env: &mut Environment,
+) -> Result<Procedure, ParseError> {
+    let Some(name) = tokens.get(*curr_pos) else {
+        return Err(ParseError {
+            kind: ParseErrorKind::InvalidSyntax {
+                msg: "Expected a procedure name after 'TO'".to_string(),
+            },
+        });
+    };
+    if BUILTINS.contains(name) {
+        return Err(ParseError {
+            kind: ParseErrorKind::InvalidSyntax {
+                msg: format!("Can't redefine the built-in command {:?}", name),
+            },
+        });
+    }
+
+    let mut params = Vec::new();
+    while *curr_pos + 1 < tokens.len() && tokens[*curr_pos + 1].starts_with('"') {
+        *curr_pos += 1;
+        params.push(tokens[*curr_pos].trim_start_matches('"').to_string());
+    }
+    *curr_pos += 1; // skipping to the start of the body
+
+    env.procedures.insert(
+        name.to_string(),
+        Procedure {
+            name: name.to_string(),
+            params: params.clone(),
+            block: Vec::new(),
+        },
+    );
+
+    // Parameters shadow any existing variables while the body is parsed.
+    let shadowed: Vec<(String, Option<Expression>)> = params
+        .iter()
+        .map(|param| {
+            let prev = env.vars.insert(param.clone(), Expression::Float(0.0));
+            (param.clone(), prev)
+        })
+        .collect();
+
+    let block = parse_block(tokens, curr_pos, env);
+
+    for (param, prev) in shadowed {
+        match prev {
+            Some(expr) => env.vars.insert(param, expr),
+            None => env.vars.remove(&param),
+        };
+    }
+    let block = block?;
+
+    if *curr_pos >= tokens.len() || tokens[*curr_pos] != "END" {
+        return Err(ParseError {
+            kind: ParseErrorKind::InvalidSyntax {
+                msg: format!("Expected 'END' to close the procedure: {:?}", name),
+            },
+        });
+    }
+
+    Ok(Procedure {
+        name: name.to_string(),
+        params,
+        block,
+    })
+}
+
+/// Parse mathematical expressions. Includes both basic and logical arithmetics.
 ///
-/// let mut vars: HashMap<String, Expression> = HashMap::new();
+/// # Example
+/// ```rust
+/// let mut env = Environment::default();
 /// let tokens = vec!["+", "\"100", "\"100"];
 /// let mut curr_pos = 0;
 ///
@@ -237,29 +664,40 @@ pub fn parse_conditional_blocks(
 pub fn parse_maths(
     tokens: &[&str],
     curr_pos: &mut usize,
-    vars: &mut HashMap<String, Expression>,
+    env: &mut Environment,
 ) -> Result<Expression, ParseError> {
     // Maths will usually be in the form of: <operator> <expression> <expression>
-    // operators will be +, -, *, /, "EQ", "LT", "GT", "NE", "AND", "OR".
+    // operators will be +, -, *, /, "MOD", "REMAINDER", "EQ", "LT", "GT",
+    // "NE", "LE", "GE", "AND", "OR", "XOR" or the "MIN", "MAX", "NOISE" and "PIXEL" functions.
     let operator = tokens[*curr_pos];
     let res = match operator {
-        "+" | "-" | "*" | "/" | "EQ" | "LT" | "GT" | "NE" | "AND" | "OR" => {
+        "+" | "-" | "*" | "/" | "MOD" | "REMAINDER" | "MIN" | "MAX" | "EQ" | "LT" | "GT" | "NE"
+        | "LE" | "GE" | "AND" | "OR" | "XOR" | "NOISE" | "PIXEL" => {
             *curr_pos += 1;
-            let expr_1 = match_parse(tokens, curr_pos, vars)?;
+            let expr_1 = match_parse(tokens, curr_pos, env)?;
             *curr_pos += 1;
-            let expr_2 = match_parse(tokens, curr_pos, vars)?;
+            let expr_2 = match_parse(tokens, curr_pos, env)?;
 
             match operator {
                 "+" => Expression::Math(Box::new(Math::Add(expr_1, expr_2))),
                 "-" => Expression::Math(Box::new(Math::Sub(expr_1, expr_2))),
                 "*" => Expression::Math(Box::new(Math::Mul(expr_1, expr_2))),
                 "/" => Expression::Math(Box::new(Math::Div(expr_1, expr_2))),
+                "MOD" => Expression::Math(Box::new(Math::Mod(expr_1, expr_2))),
+                "REMAINDER" => Expression::Math(Box::new(Math::Remainder(expr_1, expr_2))),
+                "MIN" => Expression::Math(Box::new(Math::Min(expr_1, expr_2))),
+                "MAX" => Expression::Math(Box::new(Math::Max(expr_1, expr_2))),
                 "EQ" => Expression::Math(Box::new(Math::Eq(expr_1, expr_2))),
                 "LT" => Expression::Math(Box::new(Math::Lt(expr_1, expr_2))),
                 "GT" => Expression::Math(Box::new(Math::Gt(expr_1, expr_2))),
                 "NE" => Expression::Math(Box::new(Math::Ne(expr_1, expr_2))),
+                "LE" => Expression::Math(Box::new(Math::Le(expr_1, expr_2))),
+                "GE" => Expression::Math(Box::new(Math::Ge(expr_1, expr_2))),
                 "AND" => Expression::Math(Box::new(Math::And(expr_1, expr_2))),
                 "OR" => Expression::Math(Box::new(Math::Or(expr_1, expr_2))),
+                "XOR" => Expression::Math(Box::new(Math::Xor(expr_1, expr_2))),
+                "NOISE" => Expression::Math(Box::new(Math::Noise(expr_1, expr_2))),
+                "PIXEL" => Expression::Math(Box::new(Math::Pixel(expr_1, expr_2))),
                 _ => unreachable!(),
             }
         }
@@ -275,6 +713,45 @@ pub fn parse_maths(
     Ok(res)
 }
 
+/// Parses a function of a single value, such as `SIN :ANGLE`.
+///
+/// # Example
+/// ```rust
+/// let mut env = Environment::default();
+/// let tokens = vec!["COS", "\"60"];
+///
+/// let expr = parse_function(&tokens, &mut 0, &mut env).unwrap();
+/// assert_eq!(expr, Expression::Function(Box::new(Function::Cos(Expression::Float(60.0)))));
+/// ```
+pub fn parse_function(
+    tokens: &[&str],
+    curr_pos: &mut usize,
+    env: &mut Environment,
+) -> Result<Expression, ParseError> {
+    let name = tokens[*curr_pos];
+    *curr_pos += 1;
+    let expr = match_parse(tokens, curr_pos, env)?;
+
+    let function = match name {
+        "SIN" => Function::Sin(expr),
+        "COS" => Function::Cos(expr),
+        "TAN" => Function::Tan(expr),
+        "ARCTAN" => Function::ArcTan(expr),
+        "RANDOM" => Function::Random(expr),
+        "ROUND" => Function::Round(expr),
+        "INT" => Function::Int(expr),
+        "ABS" => Function::Abs(expr),
+        _ => {
+            return Err(ParseError {
+                kind: ParseErrorKind::InvalidSyntax {
+                    msg: format!("Invalid function provided: {:?}", name),
+                },
+            })
+        }
+    };
+    Ok(Expression::Function(Box::new(function)))
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -322,20 +799,70 @@ mod tests {
         assert!(expr.is_err());
     }
 
+    #[test]
+    fn test_invalid_parse_expr_non_finite() {
+        for token in ["\"inf", "\"-infinity", "\"NaN"] {
+            assert!(parse_expression(&[token], 0).is_err());
+        }
+    }
+
     #[test]
     fn test_parse_query() {
         let tokens = vec!["XCOR"];
         let query = parse_query(&tokens, 0).unwrap();
 
         assert_eq!(query, Query::XCor);
+
+        let tokens = vec!["DELTAY"];
+        let query = parse_query(&tokens, 0).unwrap();
+
+        assert_eq!(query, Query::DeltaY);
+
+        let tokens = vec!["TRIPMETER"];
+        let query = parse_query(&tokens, 0).unwrap();
+
+        assert_eq!(query, Query::Tripmeter);
+
+        let tokens = vec!["MAXY"];
+        let query = parse_query(&tokens, 0).unwrap();
+
+        assert_eq!(query, Query::MaxY);
+    }
+
+    #[test]
+    fn test_parse_getenv() {
+        let mut env = Environment::default();
+        let tokens = vec!["GETENV", "\"COLUMNS"];
+        let mut pos = 0;
+        let expr = match_parse(&tokens, &mut pos, &mut env).unwrap();
+
+        assert_eq!(
+            expr,
+            Expression::Query(Query::GetEnv("COLUMNS".to_string()))
+        );
+        assert_eq!(pos, 1);
+    }
+
+    #[test]
+    fn test_parse_provided_query() {
+        let mut env = Environment::default();
+        let tokens = vec!["QUERY", "\"mousex"];
+        let mut pos = 0;
+        let expr = match_parse(&tokens, &mut pos, &mut env).unwrap();
+
+        assert_eq!(
+            expr,
+            Expression::Query(Query::Provided("mousex".to_string()))
+        );
+        assert_eq!(pos, 1);
     }
 
     #[test]
     fn test_parse_conditions() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["EQ", "\"100", "\"100"];
 
-        let condition = parse_conditions(&tokens, &mut 0, &mut vars).unwrap();
+        let condition = parse_conditions(&tokens, &mut 0, &mut env).unwrap();
 
         assert_eq!(
             condition,
@@ -345,11 +872,11 @@ mod tests {
 
     #[test]
     fn test_parse_condition_bool() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
-        vars.insert("x".to_string(), Expression::Float(1.0));
+        let mut env = Environment::default();
+        env.vars.insert("x".to_string(), Expression::Float(1.0));
 
         let tokens = vec![":x"];
-        let condition = parse_conditions(&tokens, &mut 0, &mut vars).unwrap();
+        let condition = parse_conditions(&tokens, &mut 0, &mut env).unwrap();
 
         assert_eq!(
             condition,
@@ -362,10 +889,10 @@ mod tests {
 
     #[test]
     fn test_parse_conditions_lt() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["LT", "\"80", "\"100"];
 
-        let condition = parse_conditions(&tokens, &mut 0, &mut vars).unwrap();
+        let condition = parse_conditions(&tokens, &mut 0, &mut env).unwrap();
 
         assert_eq!(
             condition,
@@ -375,10 +902,10 @@ mod tests {
 
     #[test]
     fn test_parse_conditions_gt() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["GT", "\"100", "\"80"];
 
-        let condition = parse_conditions(&tokens, &mut 0, &mut vars).unwrap();
+        let condition = parse_conditions(&tokens, &mut 0, &mut env).unwrap();
 
         assert_eq!(
             condition,
@@ -388,10 +915,10 @@ mod tests {
 
     #[test]
     fn test_parse_conditions_and() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["AND", "\"100", "\"100"];
 
-        let condition = parse_conditions(&tokens, &mut 0, &mut vars).unwrap();
+        let condition = parse_conditions(&tokens, &mut 0, &mut env).unwrap();
 
         assert_eq!(
             condition,
@@ -401,10 +928,10 @@ mod tests {
 
     #[test]
     fn test_parse_conditions_or() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["OR", "\"100", "\"100"];
 
-        let condition = parse_conditions(&tokens, &mut 0, &mut vars).unwrap();
+        let condition = parse_conditions(&tokens, &mut 0, &mut env).unwrap();
 
         assert_eq!(
             condition,
@@ -414,22 +941,22 @@ mod tests {
 
     #[test]
     fn test_parse_invalid_cond() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["INVALID", "\"100", "\"100"];
 
-        let condition = parse_conditions(&tokens, &mut 0, &mut vars);
+        let condition = parse_conditions(&tokens, &mut 0, &mut env);
 
         assert!(condition.is_err());
     }
 
     #[test]
     fn test_parse_conditional_blocks() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
 
         let tokens = vec!["[", "PENDOWN", "FORWARD", "\"100", "]"];
         let mut curr_pos = 0;
 
-        let block = parse_conditional_blocks(&tokens, &mut curr_pos, &mut vars).unwrap();
+        let block = parse_conditional_blocks(&tokens, &mut curr_pos, &mut env).unwrap();
         assert_eq!(
             block,
             vec![
@@ -441,34 +968,81 @@ mod tests {
 
     #[test]
     fn test_parse_cond_block_inval_start() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
 
         let tokens = vec!["PENDOWN", "FORWARD", "\"100", "]"];
         let mut curr_pos = 0;
 
-        let block = parse_conditional_blocks(&tokens, &mut curr_pos, &mut vars);
+        let block = parse_conditional_blocks(&tokens, &mut curr_pos, &mut env);
 
         assert!(block.is_err());
     }
 
     #[test]
     fn test_parse_cond_block_inval_end() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
 
         let tokens = vec!["[", "PENDOWN", "FORWARD", "\"100"];
         let mut curr_pos = 0;
 
-        let block = parse_conditional_blocks(&tokens, &mut curr_pos, &mut vars);
+        let block = parse_conditional_blocks(&tokens, &mut curr_pos, &mut env);
 
         assert!(block.is_err());
     }
 
+    #[test]
+    fn test_parse_procedure() {
+        let mut env = Environment::default();
+
+        let tokens = vec!["SQUARE", "\"SIZE", "FORWARD", ":SIZE", "END"];
+        let mut curr_pos = 0;
+
+        let procedure = parse_procedure(&tokens, &mut curr_pos, &mut env).unwrap();
+        assert_eq!(
+            procedure,
+            Procedure {
+                name: "SQUARE".to_string(),
+                params: vec!["SIZE".to_string()],
+                block: vec![ASTNode::Command(Command::Forward(Expression::Variable(
+                    "SIZE".to_string()
+                )))],
+            }
+        );
+        assert_eq!(tokens[curr_pos], "END");
+
+        // Parameters are only in scope for the body of the procedure.
+        assert!(!env.vars.contains_key("SIZE"));
+    }
+
+    #[test]
+    fn test_parse_procedure_no_end() {
+        let mut env = Environment::default();
+
+        let tokens = vec!["SQUARE", "FORWARD", "\"100"];
+        let mut curr_pos = 0;
+
+        let procedure = parse_procedure(&tokens, &mut curr_pos, &mut env);
+
+        assert!(procedure.is_err());
+    }
+
+    #[test]
+    fn test_parse_procedure_builtin_name() {
+        let mut env = Environment::default();
+
+        let tokens = vec!["FORWARD", "\"SIZE", "BACK", ":SIZE", "END"];
+        let procedure = parse_procedure(&tokens, &mut 0, &mut env);
+
+        assert!(procedure.is_err());
+        assert!(!env.procedures.contains_key("FORWARD"));
+    }
+
     #[test]
     fn test_parse_maths_add() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["+", "\"100", "\"100"];
         let mut curr_pos = 0;
-        let expr = parse_maths(&tokens, &mut curr_pos, &mut vars).unwrap();
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env).unwrap();
         assert_eq!(
             expr,
             Expression::Math(Box::new(Math::Add(
@@ -480,10 +1054,10 @@ mod tests {
 
     #[test]
     fn test_parse_maths_sub() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["-", "\"100", "\"100"];
         let mut curr_pos = 0;
-        let expr = parse_maths(&tokens, &mut curr_pos, &mut vars).unwrap();
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env).unwrap();
         assert_eq!(
             expr,
             Expression::Math(Box::new(Math::Sub(
@@ -495,10 +1069,10 @@ mod tests {
 
     #[test]
     fn test_parse_maths_mul() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["*", "\"100", "\"100"];
         let mut curr_pos = 0;
-        let expr = parse_maths(&tokens, &mut curr_pos, &mut vars).unwrap();
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env).unwrap();
         assert_eq!(
             expr,
             Expression::Math(Box::new(Math::Mul(
@@ -510,10 +1084,10 @@ mod tests {
 
     #[test]
     fn test_parse_maths_div() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["/", "\"100", "\"100"];
         let mut curr_pos = 0;
-        let expr = parse_maths(&tokens, &mut curr_pos, &mut vars).unwrap();
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env).unwrap();
         assert_eq!(
             expr,
             Expression::Math(Box::new(Math::Div(
@@ -523,12 +1097,89 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_maths_mod() {
+        let mut env = Environment::default();
+        let tokens = vec!["MOD", "HEADING", "\"90", "REMAINDER", "\"7", "\"2"];
+        let mut curr_pos = 0;
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Math(Box::new(Math::Mod(
+                Expression::Query(Query::Heading),
+                Expression::Float(90.0)
+            )))
+        );
+
+        curr_pos += 1;
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Math(Box::new(Math::Remainder(
+                Expression::Float(7.0),
+                Expression::Float(2.0)
+            )))
+        );
+    }
+
+    #[test]
+    fn test_parse_maths_min_max() {
+        let mut env = Environment::default();
+        let tokens = vec!["MAX", "\"0", "MIN", "XCOR", "\"100"];
+        let expr = parse_maths(&tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Math(Box::new(Math::Max(
+                Expression::Float(0.0),
+                Expression::Math(Box::new(Math::Min(
+                    Expression::Query(Query::XCor),
+                    Expression::Float(100.0)
+                )))
+            )))
+        );
+    }
+
+    #[test]
+    fn test_parse_function() {
+        let mut env = Environment::default();
+        let tokens = vec!["*", "\"10", "SIN", "+", "HEADING", "\"90", "ARCTAN", "\"1"];
+        let expr = match_parse(&tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Math(Box::new(Math::Mul(
+                Expression::Float(10.0),
+                Expression::Function(Box::new(Function::Sin(Expression::Math(Box::new(
+                    Math::Add(Expression::Query(Query::Heading), Expression::Float(90.0))
+                )))))
+            )))
+        );
+
+        let mut curr_pos = 6;
+        let expr = match_parse(&tokens, &mut curr_pos, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Function(Box::new(Function::ArcTan(Expression::Float(1.0))))
+        );
+        assert_eq!(curr_pos, 7);
+
+        let tokens = vec!["ROUND", "ABS", "INT", "XCOR"];
+        let expr = match_parse(&tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Function(Box::new(Function::Round(Expression::Function(Box::new(
+                Function::Abs(Expression::Function(Box::new(Function::Int(
+                    Expression::Query(Query::XCor)
+                ))))
+            )))))
+        );
+    }
+
     #[test]
     fn test_parse_maths_eq() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["EQ", "\"100", "\"100"];
         let mut curr_pos = 0;
-        let expr = parse_maths(&tokens, &mut curr_pos, &mut vars).unwrap();
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env).unwrap();
         assert_eq!(
             expr,
             Expression::Math(Box::new(Math::Eq(
@@ -540,10 +1191,10 @@ mod tests {
 
     #[test]
     fn test_parse_maths_lt() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["LT", "\"100", "\"100"];
         let mut curr_pos = 0;
-        let expr = parse_maths(&tokens, &mut curr_pos, &mut vars).unwrap();
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env).unwrap();
         assert_eq!(
             expr,
             Expression::Math(Box::new(Math::Lt(
@@ -553,12 +1204,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_maths_le_ge() {
+        let mut env = Environment::default();
+        let tokens = vec!["LE", "\"100", "\"100"];
+        let expr = parse_maths(&tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Math(Box::new(Math::Le(
+                Expression::Float(100.0),
+                Expression::Float(100.0)
+            )))
+        );
+
+        let tokens = vec!["GE", "\"100", "\"80"];
+        let condition = parse_conditions(&tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            condition,
+            Condition::GreaterEqual(Expression::Float(100.0), Expression::Float(80.0))
+        );
+    }
+
     #[test]
     fn test_parse_maths_gt() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["GT", "\"100", "\"100"];
         let mut curr_pos = 0;
-        let expr = parse_maths(&tokens, &mut curr_pos, &mut vars).unwrap();
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env).unwrap();
         assert_eq!(
             expr,
             Expression::Math(Box::new(Math::Gt(
@@ -570,10 +1242,10 @@ mod tests {
 
     #[test]
     fn test_parse_maths_ne() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["NE", "\"100", "\"100"];
         let mut curr_pos = 0;
-        let expr = parse_maths(&tokens, &mut curr_pos, &mut vars).unwrap();
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env).unwrap();
         assert_eq!(
             expr,
             Expression::Math(Box::new(Math::Ne(
@@ -585,10 +1257,10 @@ mod tests {
 
     #[test]
     fn test_parse_maths_and() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["AND", "\"100", "\"100"];
         let mut curr_pos = 0;
-        let expr = parse_maths(&tokens, &mut curr_pos, &mut vars).unwrap();
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env).unwrap();
         assert_eq!(
             expr,
             Expression::Math(Box::new(Math::And(
@@ -600,10 +1272,10 @@ mod tests {
 
     #[test]
     fn test_parse_maths_or() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["OR", "\"100", "\"100"];
         let mut curr_pos = 0;
-        let expr = parse_maths(&tokens, &mut curr_pos, &mut vars).unwrap();
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env).unwrap();
         assert_eq!(
             expr,
             Expression::Math(Box::new(Math::Or(
@@ -613,50 +1285,147 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_maths_xor() {
+        let mut env = Environment::default();
+        let tokens = vec!["XOR", "\"1", "\"0"];
+        let expr = parse_maths(&tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Math(Box::new(Math::Xor(
+                Expression::Float(1.0),
+                Expression::Float(0.0)
+            )))
+        );
+
+        let condition = parse_conditions(&tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            condition,
+            Condition::Xor(Expression::Float(1.0), Expression::Float(0.0))
+        );
+    }
+
     #[test]
     fn test_parse_maths_invalid_operator() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["INVALID", "\"100", "\"100"];
         let mut curr_pos = 0;
-        let expr = parse_maths(&tokens, &mut curr_pos, &mut vars);
+        let expr = parse_maths(&tokens, &mut curr_pos, &mut env);
 
         assert!(expr.is_err());
     }
 
     #[test]
     fn test_match_parse() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["\"100"];
-        let expr = match_parse(&tokens, &mut 0, &mut vars).unwrap();
+        let expr = match_parse(&tokens, &mut 0, &mut env).unwrap();
 
         assert_eq!(expr, Expression::Float(100.0));
     }
 
     #[test]
     fn test_match_parse_variable() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
-        vars.insert("x".to_string(), Expression::Float(100.0));
+        let mut env = Environment::default();
+        env.vars.insert("x".to_string(), Expression::Float(100.0));
         let tokens = vec![":x"];
-        let expr = match_parse(&tokens, &mut 0, &mut vars).unwrap();
+        let expr = match_parse(&tokens, &mut 0, &mut env).unwrap();
 
         assert_eq!(expr, Expression::Variable("x".to_string()));
     }
 
     #[test]
     fn test_match_parse_invalid_var() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec![":x"];
-        let expr = match_parse(&tokens, &mut 0, &mut vars);
+        let expr = match_parse(&tokens, &mut 0, &mut env);
 
         assert!(expr.is_err());
     }
 
+    #[test]
+    fn test_match_parse_parentheses() {
+        let mut env = Environment::default();
+        let tokens = vec!["*", "(", "+", "\"1", "\"2", ")", "\"3"];
+        let mut pos = 0;
+        let expr = match_parse(&tokens, &mut pos, &mut env).unwrap();
+
+        assert_eq!(
+            expr,
+            Expression::Math(Box::new(Math::Mul(
+                Expression::Math(Box::new(Math::Add(
+                    Expression::Float(1.0),
+                    Expression::Float(2.0)
+                ))),
+                Expression::Float(3.0)
+            )))
+        );
+        assert_eq!(pos, tokens.len() - 1);
+
+        for tokens in [vec!["(", "\"1", "\"2", ")"], vec!["(", "\"1"]] {
+            assert!(match_parse(&tokens, &mut 0, &mut env).is_err());
+        }
+    }
+
+    #[test]
+    fn test_match_parse_word() {
+        let mut env = Environment::default();
+        let tokens = vec!["WORD", "\"turtle", "\"2"];
+        let expr = match_parse(&tokens, &mut 0, &mut env).unwrap();
+
+        assert_eq!(
+            expr,
+            Expression::Concat(Box::new((
+                Expression::Word("turtle".to_string()),
+                Expression::Float(2.0)
+            )))
+        );
+
+        // Words are only taken where a word is expected.
+        assert!(match_parse(&["\"turtle"], &mut 0, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_match_parse_list_ops() {
+        let mut env = Environment::default();
+        let tokens = vec![
+            "ITEM", "\"2", "[", "90", "left", "[", "1", "]", "]", "COUNT", "\"turtle",
+        ];
+
+        let mut pos = 0;
+        let item = match_parse(&tokens, &mut pos, &mut env).unwrap();
+        assert_eq!(
+            item,
+            Expression::ListOp(Box::new(ListOp::Item(
+                Expression::Float(2.0),
+                Expression::List(vec![
+                    Expression::Float(90.0),
+                    Expression::Word("left".to_string()),
+                    Expression::List(vec![Expression::Float(1.0)]),
+                ])
+            )))
+        );
+        assert_eq!(tokens[pos], "]");
+
+        pos += 1;
+        let count = match_parse(&tokens, &mut pos, &mut env).unwrap();
+        assert_eq!(
+            count,
+            Expression::ListOp(Box::new(ListOp::Count(Expression::Word(
+                "turtle".to_string()
+            ))))
+        );
+
+        let tokens = vec!["FIRST", "[", "90"];
+        assert!(match_parse(&tokens, &mut 0, &mut env).is_err());
+    }
+
     #[test]
     fn test_match_parse_maths() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["+", "\"100", "\"100"];
         let mut curr_pos = 0;
-        let expr = match_parse(&tokens, &mut curr_pos, &mut vars).unwrap();
+        let expr = match_parse(&tokens, &mut curr_pos, &mut env).unwrap();
         assert_eq!(
             expr,
             Expression::Math(Box::new(Math::Add(
@@ -666,11 +1435,96 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_match_parse_noise() {
+        let mut env = Environment::default();
+        let tokens = vec!["NOISE", "XCOR", "\"0.5"];
+        let mut curr_pos = 0;
+        let expr = match_parse(&tokens, &mut curr_pos, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Math(Box::new(Math::Noise(
+                Expression::Query(Query::XCor),
+                Expression::Float(0.5)
+            )))
+        );
+        assert_eq!(curr_pos, 2);
+    }
+
+    #[test]
+    fn test_match_parse_pixel() {
+        let mut env = Environment::default();
+        let tokens = vec!["PIXEL", "XCOR", "YCOR"];
+        let expr = match_parse(&tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Math(Box::new(Math::Pixel(
+                Expression::Query(Query::XCor),
+                Expression::Query(Query::YCor)
+            )))
+        );
+    }
+
+    #[test]
+    fn test_match_parse_collision_queries() {
+        let mut env = Environment::default();
+        let tokens = vec!["TOUCHING", "XCOR", "YCOR", "\"2", "INTERSECTS", "\"10"];
+        let mut curr_pos = 0;
+
+        let expr = match_parse(&tokens, &mut curr_pos, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Query(Query::Touching(Box::new((
+                Expression::Query(Query::XCor),
+                Expression::Query(Query::YCor),
+                Expression::Float(2.0)
+            ))))
+        );
+        assert_eq!(curr_pos, 3);
+
+        curr_pos += 1;
+        let expr = match_parse(&tokens, &mut curr_pos, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Query(Query::Intersects(Box::new(Expression::Float(10.0))))
+        );
+    }
+
+    #[test]
+    fn test_match_parse_towards() {
+        let mut env = Environment::default();
+        let tokens = vec!["TOWARDS", "\"10", "+", "XCOR", "\"1", "\"20"];
+        let mut curr_pos = 0;
+
+        let expr = match_parse(&tokens, &mut curr_pos, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Query(Query::Towards(Box::new((
+                Expression::Float(10.0),
+                Expression::Math(Box::new(Math::Add(
+                    Expression::Query(Query::XCor),
+                    Expression::Float(1.0)
+                )))
+            ))))
+        );
+        assert_eq!(curr_pos, 4);
+
+        let tokens = vec!["DISTANCE", "\"10", "\"20"];
+        let expr = match_parse(&tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            expr,
+            Expression::Query(Query::Distance(Box::new((
+                Expression::Float(10.0),
+                Expression::Float(20.0)
+            ))))
+        );
+    }
+
     #[test]
     fn test_match_parse_query() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let tokens = vec!["XCOR"];
-        let query = match_parse(&tokens, &mut 0, &mut vars).unwrap();
+        let query = match_parse(&tokens, &mut 0, &mut env).unwrap();
 
         assert_eq!(query, Expression::Query(Query::XCor));
     }
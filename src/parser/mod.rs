@@ -1,4 +1,7 @@
+pub mod dialect;
 mod errors;
 mod helpers;
 pub mod parse;
+pub mod stdlib;
+pub mod syntax;
 pub mod tokenise;
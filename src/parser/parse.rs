@@ -6,27 +6,124 @@
 //! used to represent the different types of expressions that can be parsed from
 //! the Logo script, such as floats, numbers, queries, and vars.
 
-use std::collections::HashMap;
-
-use crate::ast::{ASTNode, Command, ControlFlow, Expression};
+use crate::{
+    ast::{ASTNode, Command, ControlFlow, Expression},
+    canvas::{
+        colors::{color_names, named_color, NamedColor},
+        FillPattern, PenPattern, TextAlign,
+    },
+    environment::Environment,
+    interpreter::angles::AngleUnit,
+};
 
 use super::{
+    dialect::Dialect,
     errors::{ParseError, ParseErrorKind},
-    helpers::{match_parse, parse_conditional_blocks, parse_conditions},
+    helpers::{
+        match_parse, parse_conditional_blocks, parse_conditions, parse_for, parse_foreach,
+        parse_procedure, parse_word,
+    },
+    stdlib::parse_library,
+    syntax::Syntax,
 };
 
+/// The commands `parse_tokens` parses itself, which a procedure can't be
+/// named after.
+pub const BUILTINS: &[&str] = &[
+    "ADDASSIGN",
+    "ASSERT",
+    "BACK",
+    "CATCH",
+    "CIRCLE",
+    "CLEARCLIP",
+    "CONST",
+    "DO.WHILE",
+    "END",
+    "ERASE",
+    "ERN",
+    "FENCE",
+    "FOR",
+    "FOREACH",
+    "FORWARD",
+    "HIDETURTLE",
+    "HOME",
+    "IF",
+    "INCLUDE",
+    "JUMPTO",
+    "LABEL",
+    "LEFT",
+    "LOCAL",
+    "LOCALMAKE",
+    "MAKE",
+    "MARK",
+    "OUTPUT",
+    "PENDOWN",
+    "PENPAINT",
+    "PENREVERSE",
+    "PENUP",
+    "POPSTATE",
+    "POPTRANSFORM",
+    "PPROP",
+    "PUSHSTATE",
+    "PUSHTRANSFORM",
+    "REMPROP",
+    "REPEAT",
+    "RERANDOM",
+    "RIGHT",
+    "ROTATE",
+    "SCALE",
+    "SETANGLEUNIT",
+    "SETCLIP",
+    "SETDIALECT",
+    "SETEFFECT",
+    "SETFILLPATTERN",
+    "SETHEADING",
+    "SETITEM",
+    "SETKALEIDOSCOPE",
+    "SETLABELALIGN",
+    "SETLABELFONT",
+    "SETLABELHEIGHT",
+    "SETLAYER",
+    "SETPENALPHA",
+    "SETPENCOLOR",
+    "SETPENHSV",
+    "SETPENPATTERN",
+    "SETPENSIZE",
+    "SETPOS",
+    "SETPOSREL",
+    "SETSYMMETRY",
+    "SETSYNTAX",
+    "SETTRAILFADE",
+    "SETX",
+    "SETXY",
+    "SETY",
+    "SHOWTURTLE",
+    "STOP",
+    "THROW",
+    "TO",
+    "TRANSLATE",
+    "TURN",
+    "UNTIL",
+    "WHILE",
+    "WINDOW",
+    "WRAP",
+];
+
+/// The native shape commands. Unlike the other built-in commands, a procedure
+/// can be named after one, such as the standard library's `POLYGON`, and is
+/// then called in its place.
+pub const SHAPES: &[&str] = &["RECT", "ELLIPSE", "POLYGON", "STAR"];
+
 /// Parse tokens into an Abstract Syntax Tree (AST).
 ///
 /// # Examples
 ///
 /// ```rust
-/// use std::collections::HashMap;
-///
 /// // Tokens is generated from the tokenize_script function.
 /// tokens = vec!["PENDOWN", "FORWARD", "\"100"]
 ///
-/// let mut vars: HashMap<String, Expression> = HashMap::new();
-/// let ast = parse_tokens(tokens, &mut vars).unwrap();
+/// let mut env = Environment::default();
+/// let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
 ///
 /// assert_eq!(ast, vec![ASTNode::Command(Command::PenDown),
 ///         ASTNode::Command(Command::Forward(Expression::Float(100.0)))]);
@@ -34,11 +131,79 @@ use super::{
 pub fn parse_tokens(
     tokens: Vec<&str>,
     curr_pos: &mut usize,
-    vars: &mut HashMap<String, Expression>,
+    env: &mut Environment,
+) -> Result<Vec<ASTNode>, ParseError> {
+    let ast = parse_block(&tokens, curr_pos, env)?;
+
+    // Blocks and procedures stop at their closing token, so one left over
+    // here has nothing to close.
+    match tokens.get(*curr_pos) {
+        Some(&"END") => Err(ParseError {
+            kind: ParseErrorKind::InvalidSyntax {
+                msg: "Found 'END' without a matching 'TO'".to_string(),
+            },
+        }),
+        Some(&"]") => Err(ParseError {
+            kind: ParseErrorKind::InvalidSyntax {
+                msg: "Found ']' without a matching '['".to_string(),
+            },
+        }),
+        _ => Ok(ast),
+    }
+}
+
+/// Parses the statements of a block or procedure body, up to the `]` or
+/// `END` closing it, where the current position is left.
+pub(crate) fn parse_block(
+    tokens: &[&str],
+    curr_pos: &mut usize,
+    env: &mut Environment,
+) -> Result<Vec<ASTNode>, ParseError> {
+    // Names made with LOCAL or LOCALMAKE, along with whatever they hid, so
+    // they're only known until the block or procedure they're in ends.
+    let mut locals = Vec::new();
+    let ast = parse_statements(tokens, curr_pos, env, &mut locals);
+    for (name, prev) in locals.into_iter().rev() {
+        match prev {
+            Some(expr) => env.vars.insert(name, expr),
+            None => env.vars.remove(&name),
+        };
+    }
+    ast
+}
+
+/// Records that `name` is a local variable of the block being parsed.
+fn declare_local(
+    name: &str,
+    expr: Expression,
+    env: &mut Environment,
+    locals: &mut Vec<(String, Option<Expression>)>,
+) {
+    let prev = env.vars.insert(name.to_string(), expr);
+    if !locals.iter().any(|(local, _)| local == name) {
+        locals.push((name.to_string(), prev));
+    }
+}
+
+fn parse_statements(
+    tokens: &[&str],
+    curr_pos: &mut usize,
+    env: &mut Environment,
+    locals: &mut Vec<(String, Option<Expression>)>,
 ) -> Result<Vec<ASTNode>, ParseError> {
     let mut ast = Vec::new();
 
     while *curr_pos < tokens.len() {
+        if !matches!(tokens[*curr_pos], "]" | "END" | "TO") {
+            if let Some((script, line)) = env
+                .coverage
+                .as_mut()
+                .and_then(|coverage| coverage.locate(tokens[*curr_pos]))
+            {
+                ast.push(ASTNode::Line { script, line });
+            }
+        }
+
         match tokens[*curr_pos] {
             "PENUP" => {
                 ast.push(ASTNode::Command(Command::PenUp));
@@ -46,50 +211,149 @@ pub fn parse_tokens(
             "PENDOWN" => {
                 ast.push(ASTNode::Command(Command::PenDown));
             }
+            "PENPAINT" => {
+                ast.push(ASTNode::Command(Command::PenPaint));
+            }
+            "PENREVERSE" => {
+                ast.push(ASTNode::Command(Command::PenReverse));
+            }
+            "SHOWTURTLE" => {
+                ast.push(ASTNode::Command(Command::ShowTurtle));
+            }
+            "HIDETURTLE" => {
+                ast.push(ASTNode::Command(Command::HideTurtle));
+            }
+            "HOME" => {
+                ast.push(ASTNode::Command(Command::Home));
+            }
+            "WRAP" => {
+                ast.push(ASTNode::Command(Command::Wrap));
+            }
+            "FENCE" => {
+                ast.push(ASTNode::Command(Command::Fence));
+            }
+            "WINDOW" => {
+                ast.push(ASTNode::Command(Command::Window));
+            }
             "FORWARD" => {
                 *curr_pos += 1;
-                let expr = match_parse(&tokens, curr_pos, vars)?;
+                let expr = match_parse(tokens, curr_pos, env)?;
                 ast.push(ASTNode::Command(Command::Forward(expr)));
             }
             "BACK" => {
                 *curr_pos += 1;
-                let expr = match_parse(&tokens, curr_pos, vars)?;
+                let expr = match_parse(tokens, curr_pos, env)?;
                 ast.push(ASTNode::Command(Command::Back(expr)));
             }
             "LEFT" => {
                 *curr_pos += 1;
-                let expr = match_parse(&tokens, curr_pos, vars)?;
-                ast.push(ASTNode::Command(Command::Left(expr)));
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(match env.dialect {
+                    Dialect::Rslogo => Command::Left(expr),
+                    Dialect::Classic => Command::TurnLeft(expr),
+                }));
             }
             "RIGHT" => {
                 *curr_pos += 1;
-                let expr = match_parse(&tokens, curr_pos, vars)?;
-                ast.push(ASTNode::Command(Command::Right(expr)));
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(match env.dialect {
+                    Dialect::Rslogo => Command::Right(expr),
+                    Dialect::Classic => Command::TurnRight(expr),
+                }));
             }
             "SETHEADING" => {
                 *curr_pos += 1;
-                let expr = match_parse(&tokens, curr_pos, vars)?;
+                let expr = match_parse(tokens, curr_pos, env)?;
                 ast.push(ASTNode::Command(Command::SetHeading(expr)));
             }
+            "SETPOS" | "SETXY" => {
+                let bracketed =
+                    tokens[*curr_pos] == "SETPOS" && tokens.get(*curr_pos + 1) == Some(&"[");
+                if bracketed {
+                    *curr_pos += 1;
+                }
+                *curr_pos += 1;
+                let x = match_parse(tokens, curr_pos, env)?;
+                *curr_pos += 1;
+                let y = match_parse(tokens, curr_pos, env)?;
+                if bracketed {
+                    *curr_pos += 1;
+                    if tokens.get(*curr_pos) != Some(&"]") {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::InvalidSyntax {
+                                msg: "Expected ']' after the x and y of SETPOS".to_string(),
+                            },
+                        });
+                    }
+                }
+                ast.push(ASTNode::Command(Command::SetPos(x, y)));
+            }
             "SETX" => {
                 *curr_pos += 1;
-                let expr = match_parse(&tokens, curr_pos, vars)?;
+                let expr = match_parse(tokens, curr_pos, env)?;
                 ast.push(ASTNode::Command(Command::SetX(expr)));
             }
             "SETY" => {
                 *curr_pos += 1;
-                let expr = match_parse(&tokens, curr_pos, vars)?;
+                let expr = match_parse(tokens, curr_pos, env)?;
                 ast.push(ASTNode::Command(Command::SetY(expr)));
             }
+            "SETPENCOLOR" if tokens.get(*curr_pos + 1) == Some(&"[") => {
+                *curr_pos += 2;
+                let red = match_parse(tokens, curr_pos, env)?;
+                *curr_pos += 1;
+                let green = match_parse(tokens, curr_pos, env)?;
+                *curr_pos += 1;
+                let blue = match_parse(tokens, curr_pos, env)?;
+                *curr_pos += 1;
+                if tokens.get(*curr_pos) != Some(&"]") {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::InvalidSyntax {
+                            msg: "Expected ']' after the red, green and blue of SETPENCOLOR"
+                                .to_string(),
+                        },
+                    });
+                }
+                ast.push(ASTNode::Command(Command::SetPenRgb(red, green, blue)));
+            }
+            "SETPENCOLOR" if is_color_name(tokens.get(*curr_pos + 1)) => {
+                *curr_pos += 1;
+                let name = tokens[*curr_pos].trim_start_matches('"');
+                let command = match named_color(name, &env.palette) {
+                    Some(NamedColor::Palette(index)) => {
+                        Command::SetPenColor(Expression::Usize(index))
+                    }
+                    Some(NamedColor::Rgb(color)) => Command::SetPenRgb(
+                        Expression::Float(color.red as f64),
+                        Expression::Float(color.green as f64),
+                        Expression::Float(color.blue as f64),
+                    ),
+                    None => {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::InvalidSyntax {
+                                msg: format!(
+                                    "Unknown color \"{}\", expected one of: {}",
+                                    name,
+                                    color_names(&env.palette).join(", ")
+                                ),
+                            },
+                        });
+                    }
+                };
+                ast.push(ASTNode::Command(command));
+            }
             "SETPENCOLOR" => {
                 *curr_pos += 1;
-                let expr = match_parse(&tokens, curr_pos, vars)?;
+                let expr = match_parse(tokens, curr_pos, env)?;
 
                 if let Expression::Float(color) = expr {
-                    if !(0..=15).contains(&(color as usize)) {
+                    if !(0..env.palette.len()).contains(&(color as usize)) {
                         return Err(ParseError {
                             kind: ParseErrorKind::InvalidSyntax {
-                                msg: "Colour index must be between 0 and 15 inclusive.".to_string(),
+                                msg: format!(
+                                    "Colour index must be between 0 and {} inclusive.",
+                                    env.palette.len() - 1
+                                ),
                             },
                         });
                     }
@@ -97,25 +361,341 @@ pub fn parse_tokens(
 
                 ast.push(ASTNode::Command(Command::SetPenColor(expr)));
             }
+            "CIRCLE" => {
+                *curr_pos += 1;
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::Circle(expr)));
+            }
+            "SETPENSIZE" => {
+                *curr_pos += 1;
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::SetPenSize(expr)));
+            }
+            "SETPENALPHA" => {
+                *curr_pos += 1;
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::SetPenAlpha(expr)));
+            }
+            "SETPENHSV" => {
+                *curr_pos += 1;
+                let hue = match_parse(tokens, curr_pos, env)?;
+                *curr_pos += 1;
+                let saturation = match_parse(tokens, curr_pos, env)?;
+                *curr_pos += 1;
+                let value = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::SetPenHsv(hue, saturation, value)));
+            }
             "TURN" => {
                 *curr_pos += 1;
-                let expr = match_parse(&tokens, curr_pos, vars)?;
+                let expr = match_parse(tokens, curr_pos, env)?;
                 ast.push(ASTNode::Command(Command::Turn(expr)));
             }
-            "MAKE" => {
+            command if SHAPES.contains(&command) && !env.procedures.contains_key(command) => {
                 *curr_pos += 1;
-                let var_name = tokens[*curr_pos].trim_start_matches('"');
-
+                let expr_1 = match_parse(tokens, curr_pos, env)?;
                 *curr_pos += 1;
-                let expr: Result<Expression, ParseError> = match_parse(&tokens, curr_pos, vars);
+                let expr_2 = match_parse(tokens, curr_pos, env)?;
 
-                match expr {
-                    Ok(expr) => {
-                        vars.insert(var_name.to_string(), expr.clone());
-                        ast.push(ASTNode::Command(Command::Make(var_name.to_string(), expr)));
+                ast.push(ASTNode::Command(match command {
+                    "RECT" => Command::Rect(expr_1, expr_2),
+                    "ELLIPSE" => Command::Ellipse(expr_1, expr_2),
+                    "POLYGON" => Command::Polygon(expr_1, expr_2),
+                    "STAR" => Command::Star(expr_1, expr_2),
+                    _ => unreachable!(),
+                }));
+            }
+            "SETFILLPATTERN" => {
+                *curr_pos += 1;
+                let name = tokens[*curr_pos].trim_start_matches('"');
+                let Some(pattern) = FillPattern::from_name(name) else {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::InvalidSyntax {
+                            msg: format!(
+                                "Unknown fill pattern {:?}, expected one of: {}",
+                                name,
+                                FillPattern::NAMES.join(", ")
+                            ),
+                        },
+                    });
+                };
+                ast.push(ASTNode::Command(Command::SetFillPattern(pattern)));
+            }
+            "SETPENPATTERN" => {
+                *curr_pos += 1;
+                let name = tokens[*curr_pos].trim_start_matches('"');
+                let Some(pattern) = PenPattern::from_name(name) else {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::InvalidSyntax {
+                            msg: format!(
+                                "Unknown pen pattern {:?}, expected one of: {}",
+                                name,
+                                PenPattern::NAMES.join(", ")
+                            ),
+                        },
+                    });
+                };
+                ast.push(ASTNode::Command(Command::SetPenPattern(pattern)));
+            }
+            "SETANGLEUNIT" => {
+                *curr_pos += 1;
+                let name = tokens[*curr_pos].trim_start_matches('"');
+                let Some(unit) = AngleUnit::from_name(name) else {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::InvalidSyntax {
+                            msg: format!(
+                                "Unknown angle unit {:?}, expected one of: {}",
+                                name,
+                                AngleUnit::NAMES.join(", ")
+                            ),
+                        },
+                    });
+                };
+                ast.push(ASTNode::Command(Command::SetAngleUnit(unit)));
+            }
+            "SETSYNTAX" => {
+                // Only changes how the rest of the script is parsed.
+                *curr_pos += 1;
+                let name = tokens[*curr_pos].trim_start_matches('"');
+                let Some(syntax) = Syntax::from_name(name) else {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::InvalidSyntax {
+                            msg: format!(
+                                "Unknown syntax {:?}, expected one of: {}",
+                                name,
+                                Syntax::NAMES.join(", ")
+                            ),
+                        },
+                    });
+                };
+                env.syntax = syntax;
+            }
+            "SETDIALECT" => {
+                // Only changes how the rest of the script is parsed.
+                *curr_pos += 1;
+                let name = tokens[*curr_pos].trim_start_matches('"');
+                let Some(dialect) = Dialect::from_name(name) else {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::InvalidSyntax {
+                            msg: format!(
+                                "Unknown dialect {:?}, expected one of: {}",
+                                name,
+                                Dialect::NAMES.join(", ")
+                            ),
+                        },
+                    });
+                };
+                env.dialect = dialect;
+            }
+            "SETLAYER" => {
+                *curr_pos += 1;
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::SetLayer(expr)));
+            }
+            "SETPOSREL" => {
+                *curr_pos += 1;
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::SetPosRel(expr)));
+            }
+            "PPROP" => {
+                *curr_pos += 1;
+                let list = tokens[*curr_pos].trim_start_matches('"').to_string();
+                *curr_pos += 1;
+                let prop = tokens[*curr_pos].trim_start_matches('"').to_string();
+                *curr_pos += 1;
+                let value = parse_word(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::PProp(list, prop, value)));
+            }
+            "REMPROP" => {
+                *curr_pos += 1;
+                let list = tokens[*curr_pos].trim_start_matches('"').to_string();
+                *curr_pos += 1;
+                let prop = tokens[*curr_pos].trim_start_matches('"').to_string();
+                ast.push(ASTNode::Command(Command::RemProp(list, prop)));
+            }
+            "SETITEM" => {
+                *curr_pos += 1;
+                let index = match_parse(tokens, curr_pos, env)?;
+                *curr_pos += 1;
+                let var_name = match tokens.get(*curr_pos) {
+                    Some(token) if token.starts_with(':') => token.trim_start_matches(':'),
+                    _ => {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::InvalidSyntax {
+                                msg: "Expected the :variable holding the array after SETITEM"
+                                    .to_string(),
+                            },
+                        })
+                    }
+                };
+                if !env.vars.contains_key(var_name) {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::VariableNotFound {
+                            var: var_name.to_string(),
+                        },
+                    });
+                }
+                *curr_pos += 1;
+                let value = parse_word(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::SetItem(
+                    index,
+                    var_name.to_string(),
+                    value,
+                )));
+            }
+            "MARK" | "JUMPTO" => {
+                let is_mark = tokens[*curr_pos] == "MARK";
+                *curr_pos += 1;
+                let name = tokens[*curr_pos].trim_start_matches('"').to_string();
+                if is_mark {
+                    ast.push(ASTNode::Command(Command::Mark(name)));
+                } else {
+                    ast.push(ASTNode::Command(Command::JumpTo(name)));
+                }
+            }
+            "PUSHTRANSFORM" => ast.push(ASTNode::Command(Command::PushTransform)),
+            "POPTRANSFORM" => ast.push(ASTNode::Command(Command::PopTransform)),
+            "PUSHSTATE" => ast.push(ASTNode::Command(Command::PushState)),
+            "POPSTATE" => ast.push(ASTNode::Command(Command::PopState)),
+            "SCALE" => {
+                *curr_pos += 1;
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::Scale(expr)));
+            }
+            "ROTATE" => {
+                *curr_pos += 1;
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::Rotate(expr)));
+            }
+            "TRANSLATE" => {
+                *curr_pos += 1;
+                let dx = match_parse(tokens, curr_pos, env)?;
+                *curr_pos += 1;
+                let dy = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::Translate(dx, dy)));
+            }
+            "SETCLIP" => {
+                *curr_pos += 1;
+                let x = match_parse(tokens, curr_pos, env)?;
+                *curr_pos += 1;
+                let y = match_parse(tokens, curr_pos, env)?;
+                *curr_pos += 1;
+                let width = match_parse(tokens, curr_pos, env)?;
+                *curr_pos += 1;
+                let height = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::SetClip(x, y, width, height)));
+            }
+            "CLEARCLIP" => ast.push(ASTNode::Command(Command::ClearClip)),
+            "SETSYMMETRY" => {
+                *curr_pos += 1;
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::SetSymmetry(expr)));
+            }
+            "SETKALEIDOSCOPE" => {
+                *curr_pos += 1;
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::SetKaleidoscope(expr)));
+            }
+            "SETTRAILFADE" => {
+                *curr_pos += 1;
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::SetTrailFade(expr)));
+            }
+            "ERASE" => {
+                *curr_pos += 1;
+                match parse_word(tokens, curr_pos, env)? {
+                    // Calls earlier in the script look the procedure up by
+                    // name as they run, so it is only removed once the ERASE
+                    // runs too.
+                    Expression::Word(name) => {
+                        ast.push(ASTNode::Command(Command::EraseProcedure(name)))
+                    }
+                    expr => ast.push(ASTNode::Command(Command::Erase(expr))),
+                }
+            }
+            "ERN" => {
+                *curr_pos += 1;
+                let var_name = tokens[*curr_pos].trim_start_matches('"');
+                check_not_const(var_name, env)?;
+                if env.vars.remove(var_name).is_none() {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::VariableNotFound {
+                            var: var_name.to_string(),
+                        },
+                    });
+                }
+                ast.push(ASTNode::Command(Command::EraseName(var_name.to_string())));
+            }
+            "SETEFFECT" => {
+                *curr_pos += 1;
+                let effect = tokens[*curr_pos].trim_start_matches('"');
+                let command = match effect.to_lowercase().as_str() {
+                    "shadow" => {
+                        *curr_pos += 1;
+                        let dx = match_parse(tokens, curr_pos, env)?;
+                        *curr_pos += 1;
+                        let dy = match_parse(tokens, curr_pos, env)?;
+                        *curr_pos += 1;
+                        let color = parse_color(tokens, curr_pos, env)?;
+                        Command::SetShadow(dx, dy, color)
+                    }
+                    "outline" => {
+                        *curr_pos += 1;
+                        let width = match_parse(tokens, curr_pos, env)?;
+                        *curr_pos += 1;
+                        let color = parse_color(tokens, curr_pos, env)?;
+                        Command::SetOutline(width, color)
+                    }
+                    "none" => Command::ClearEffect,
+                    _ => {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::InvalidSyntax {
+                                msg: format!(
+                                    "Unknown effect {:?}, expected one of: shadow, outline, none",
+                                    effect
+                                ),
+                            },
+                        });
                     }
-                    Err(_) => unreachable!(),
                 };
+                ast.push(ASTNode::Command(command));
+            }
+            "MAKE" | "CONST" => {
+                let is_const = tokens[*curr_pos] == "CONST";
+                *curr_pos += 1;
+                let var_name = tokens[*curr_pos].trim_start_matches('"');
+                check_not_const(var_name, env)?;
+                if is_const {
+                    env.consts.insert(var_name.to_string());
+                }
+
+                *curr_pos += 1;
+                let expr = parse_word(tokens, curr_pos, env)?;
+                env.vars.insert(var_name.to_string(), expr.clone());
+                ast.push(ASTNode::Command(Command::Make(var_name.to_string(), expr)));
+            }
+            "RERANDOM" => {
+                *curr_pos += 1;
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::Rerandom(expr)));
+            }
+            "LOCAL" => {
+                *curr_pos += 1;
+                let var_name = tokens[*curr_pos].trim_start_matches('"');
+                check_not_const(var_name, env)?;
+                declare_local(var_name, Expression::Float(0.0), env, locals);
+                ast.push(ASTNode::Command(Command::Local(var_name.to_string())));
+            }
+            "LOCALMAKE" => {
+                *curr_pos += 1;
+                let var_name = tokens[*curr_pos].trim_start_matches('"');
+                check_not_const(var_name, env)?;
+                *curr_pos += 1;
+                let expr = parse_word(tokens, curr_pos, env)?;
+                declare_local(var_name, expr.clone(), env, locals);
+                ast.push(ASTNode::Command(Command::LocalMake(
+                    var_name.to_string(),
+                    expr,
+                )));
             }
             "ADDASSIGN" => {
                 // ADDASSIGN can only work on vars
@@ -129,16 +709,17 @@ pub fn parse_tokens(
                 }
 
                 let var_name = tokens[*curr_pos].trim_start_matches('"');
-                if !vars.contains_key(var_name) {
+                if !env.vars.contains_key(var_name) {
                     return Err(ParseError {
                         kind: ParseErrorKind::VariableNotFound {
                             var: var_name.to_string(),
                         },
                     });
                 }
+                check_not_const(var_name, env)?;
 
                 *curr_pos += 1;
-                let expr = match_parse(&tokens, curr_pos, vars)?;
+                let expr = match_parse(tokens, curr_pos, env)?;
 
                 ast.push(ASTNode::Command(Command::AddAssign(
                     var_name.to_string(),
@@ -147,29 +728,157 @@ pub fn parse_tokens(
             }
             "IF" => {
                 *curr_pos += 1; // Skip the IF token
-                let condition = parse_conditions(&tokens, &mut *curr_pos, vars)?;
-                let block = parse_conditional_blocks(&tokens, &mut *curr_pos, vars)?;
+                let condition = parse_conditions(tokens, &mut *curr_pos, env)?;
+                let block = parse_conditional_blocks(tokens, &mut *curr_pos, env)?;
                 ast.push(ASTNode::ControlFlow(ControlFlow::If { condition, block }));
             }
+            "ASSERT" => {
+                *curr_pos += 1; // Skip the ASSERT token
+                let condition = parse_conditions(tokens, &mut *curr_pos, env)?;
+                let msg = parse_message(tokens, curr_pos, "the condition of ASSERT")?;
+                ast.push(ASTNode::Command(Command::Assert(condition, msg)));
+            }
+            "SETLABELFONT" => {
+                *curr_pos += 1;
+                let font = parse_message(tokens, curr_pos, "SETLABELFONT")?;
+                ast.push(ASTNode::Command(Command::SetLabelFont(font)));
+            }
+            "SETLABELHEIGHT" => {
+                *curr_pos += 1;
+                let expr = match_parse(tokens, curr_pos, env)?;
+                ast.push(ASTNode::Command(Command::SetLabelHeight(expr)));
+            }
+            "SETLABELALIGN" => {
+                *curr_pos += 1;
+                let name = tokens[*curr_pos].trim_start_matches('"');
+                let Some(align) = TextAlign::from_name(name) else {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::InvalidSyntax {
+                            msg: format!(
+                                "Unknown label alignment {:?}, expected one of: {}",
+                                name,
+                                TextAlign::NAMES.join(", ")
+                            ),
+                        },
+                    });
+                };
+                ast.push(ASTNode::Command(Command::SetLabelAlign(align)));
+            }
+            "LABEL" => {
+                *curr_pos += 1; // Skip the LABEL token
+                let text = match tokens.get(*curr_pos) {
+                    Some(token) if *token != "[" => parse_word(tokens, curr_pos, env)?,
+                    _ => Expression::Word(parse_message(tokens, curr_pos, "LABEL")?),
+                };
+                ast.push(ASTNode::Command(Command::Label(text)));
+            }
             "WHILE" => {
                 *curr_pos += 1; // Skip the WHILE token
-                let condition = parse_conditions(&tokens, &mut *curr_pos, vars)?;
-                let block = parse_conditional_blocks(&tokens, &mut *curr_pos, vars)?;
+                let condition = parse_conditions(tokens, &mut *curr_pos, env)?;
+                let block = parse_conditional_blocks(tokens, &mut *curr_pos, env)?;
                 ast.push(ASTNode::ControlFlow(ControlFlow::While {
                     condition,
                     block,
                 }));
             }
-            "]" => {
-                // This is the end of a conditional block, we can skip this token
-                // and return the ast directly.
+            "DO.WHILE" => {
+                *curr_pos += 1; // Skip the DO.WHILE token
+                let block = parse_conditional_blocks(tokens, &mut *curr_pos, env)?;
+                *curr_pos += 1;
+                if *curr_pos >= tokens.len() {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::InvalidSyntax {
+                            msg: "Expected a condition after the block of DO.WHILE".to_string(),
+                        },
+                    });
+                }
+                let condition = parse_conditions(tokens, &mut *curr_pos, env)?;
+                // Step back onto the condition's last token, as the loop
+                // moves past it.
+                *curr_pos -= 1;
+                ast.push(ASTNode::ControlFlow(ControlFlow::DoWhile {
+                    block,
+                    condition,
+                }));
+            }
+            "UNTIL" => {
+                *curr_pos += 1; // Skip the UNTIL token
+                let condition = parse_conditions(tokens, &mut *curr_pos, env)?;
+                let block = parse_conditional_blocks(tokens, &mut *curr_pos, env)?;
+                ast.push(ASTNode::ControlFlow(ControlFlow::Until {
+                    condition,
+                    block,
+                }));
+            }
+            "CATCH" => {
+                *curr_pos += 1; // Skip the CATCH token
+                let tag = parse_tag(tokens, curr_pos, "CATCH")?;
+                *curr_pos += 1;
+                let block = parse_conditional_blocks(tokens, curr_pos, env)?;
+                ast.push(ASTNode::ControlFlow(ControlFlow::Catch { tag, block }));
+            }
+            "THROW" => {
+                *curr_pos += 1; // Skip the THROW token
+                let tag = parse_tag(tokens, curr_pos, "THROW")?;
+                ast.push(ASTNode::ControlFlow(ControlFlow::Throw(tag)));
+            }
+            "FOREACH" => {
+                *curr_pos += 1; // Skip the FOREACH token
+                let foreach = parse_foreach(tokens, curr_pos, env)?;
+                ast.push(ASTNode::ControlFlow(foreach));
+            }
+            "FOR" => {
+                *curr_pos += 1; // Skip the FOR token
+                let for_loop = parse_for(tokens, curr_pos, env)?;
+                ast.push(ASTNode::ControlFlow(for_loop));
+            }
+            "OUTPUT" => {
+                *curr_pos += 1; // Skip the OUTPUT token
+                let expr = parse_word(tokens, curr_pos, env)?;
+                ast.push(ASTNode::ControlFlow(ControlFlow::Output(expr)));
+            }
+            "STOP" => {
+                ast.push(ASTNode::ControlFlow(ControlFlow::Stop));
+            }
+            "REPEAT" => {
+                *curr_pos += 1; // Skip the REPEAT token
+                let count = match_parse(tokens, curr_pos, env)?;
+                *curr_pos += 1;
+                let block = parse_conditional_blocks(tokens, &mut *curr_pos, env)?;
+                ast.push(ASTNode::ControlFlow(ControlFlow::Repeat { count, block }));
+            }
+            "]" | "END" => {
+                // This is the end of a conditional block or procedure, we can
+                // skip this token and return the ast directly.
                 return Ok(ast);
             }
             "TO" => {
-                unimplemented!();
+                *curr_pos += 1; // Skip the TO token
+                let procedure = parse_procedure(tokens, curr_pos, env)?;
+                env.procedures.insert(procedure.name.clone(), procedure);
+            }
+            "INCLUDE" => {
+                *curr_pos += 1;
+                let Some(library) = tokens.get(*curr_pos) else {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::InvalidSyntax {
+                            msg: "Expected a library name after 'INCLUDE'".to_string(),
+                        },
+                    });
+                };
+                ast.extend(parse_library(library.trim_start_matches('"'), env)?);
             }
-            "END" => {
-                unimplemented!();
+            token if env.procedures.contains_key(token) => {
+                let arity = env.procedures[token].params.len();
+                let mut args = Vec::with_capacity(arity);
+                for _ in 0..arity {
+                    *curr_pos += 1;
+                    args.push(parse_word(tokens, curr_pos, env)?);
+                }
+                ast.push(ASTNode::ControlFlow(ControlFlow::Call {
+                    name: token.to_string(),
+                    args,
+                }));
             }
             _ => {
                 return Err(ParseError {
@@ -185,17 +894,89 @@ pub fn parse_tokens(
     Ok(ast)
 }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
+/// Parses the message of an `ASSERT`, the text of a `LABEL` or the font of a
+/// `SETLABELFONT`, either a
+/// quoted word or a list of words in brackets such as `[ Out of bounds ]`. The
+/// current position is left at the last token of the message.
+fn parse_message(tokens: &[&str], curr_pos: &mut usize, after: &str) -> Result<String, ParseError> {
+    match tokens.get(*curr_pos) {
+        Some(&"[") => {
+            let start = *curr_pos + 1;
+            let Some(len) = tokens[start..].iter().position(|token| *token == "]") else {
+                return Err(ParseError {
+                    kind: ParseErrorKind::InvalidSyntax {
+                        msg: "Expected the end of a message: ']'".to_string(),
+                    },
+                });
+            };
+            *curr_pos = start + len;
+            Ok(tokens[start..*curr_pos].join(" "))
+        }
+        Some(word) if word.starts_with('"') => Ok(word.trim_start_matches('"').to_string()),
+        _ => Err(ParseError {
+            kind: ParseErrorKind::InvalidSyntax {
+                msg: format!("Expected a message after {}", after),
+            },
+        }),
+    }
+}
 
-    use crate::ast::Condition;
+/// Parses the quoted tag of a `CATCH` or `THROW`, such as `"ERROR`.
+fn parse_tag(tokens: &[&str], curr_pos: &mut usize, keyword: &str) -> Result<String, ParseError> {
+    match tokens.get(*curr_pos) {
+        Some(tag) if tag.len() > 1 && tag.starts_with('"') => Ok(tag[1..].to_string()),
+        _ => Err(ParseError {
+            kind: ParseErrorKind::InvalidSyntax {
+                msg: format!("Expected a quoted tag after {}", keyword),
+            },
+        }),
+    }
+}
 
-    use super::*;
+/// Returns an error if `var_name` was declared with `CONST`.
+fn check_not_const(var_name: &str, env: &Environment) -> Result<(), ParseError> {
+    if env.consts.contains(var_name) {
+        return Err(ParseError {
+            kind: ParseErrorKind::ConstantReassigned {
+                var: var_name.to_string(),
+            },
+        });
+    }
+    Ok(())
+}
 
-    #[test]
+/// Whether a token is a quoted word naming a color, rather than a number.
+fn is_color_name(token: Option<&&str>) -> bool {
+    token
+        .and_then(|token| token.strip_prefix('"'))
+        .is_some_and(|word| word.parse::<f64>().is_err())
+}
+
+/// Parses a color argument, which is either a palette name such as `"red` or
+/// an expression evaluating to a palette index.
+fn parse_color(
+    tokens: &[&str],
+    curr_pos: &mut usize,
+    env: &mut Environment,
+) -> Result<Expression, ParseError> {
+    match env
+        .palette
+        .index_of_name(tokens[*curr_pos].trim_start_matches('"'))
+    {
+        Some(index) => Ok(Expression::Usize(index)),
+        None => match_parse(tokens, curr_pos, env),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Condition, Function, Math, Procedure, Query};
+
+    use super::*;
+
+    #[test]
     fn test_parse_basic_tokens() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let mut curr_pos = 0;
 
         let tokens = vec![
@@ -220,7 +1001,7 @@ mod tests {
             "TURN",
             "\"100",
         ];
-        let ast = parse_tokens(tokens, &mut curr_pos, &mut vars).unwrap();
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
 
         assert_eq!(
             ast,
@@ -240,13 +1021,510 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_shapes() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec![
+            "RECT", "\"20", "\"10", "POLYGON", "\"6", "\"50", "STAR", "\"5", "\"50",
+        ];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::Rect(
+                    Expression::Float(20.0),
+                    Expression::Float(10.0)
+                )),
+                ASTNode::Command(Command::Polygon(
+                    Expression::Float(6.0),
+                    Expression::Float(50.0)
+                )),
+                ASTNode::Command(Command::Star(
+                    Expression::Float(5.0),
+                    Expression::Float(50.0)
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_fill_pattern() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec!["SETFILLPATTERN", "\"crosshatch"];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![ASTNode::Command(Command::SetFillPattern(
+                FillPattern::Crosshatch
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_parse_pen_pattern() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec!["SETPENPATTERN", "\"dotted"];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![ASTNode::Command(Command::SetPenPattern(PenPattern::Dotted))]
+        );
+
+        let mut curr_pos = 0;
+        let tokens = vec!["SETPENPATTERN", "\"wavy"];
+        assert_eq!(
+            parse_tokens(tokens, &mut curr_pos, &mut env),
+            Err(ParseError {
+                kind: ParseErrorKind::InvalidSyntax {
+                    msg: "Unknown pen pattern \"wavy\", expected one of: solid, dashed, dotted"
+                        .to_string()
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_assert() {
+        let mut env = Environment::default();
+        env.vars.insert("X".to_string(), Expression::Float(0.0));
+
+        let tokens = vec![
+            "ASSERT", "LT", ":X", "\"100", "\"inside", "ASSERT", "GT", ":X", "\"0", "[", "Out",
+            "of", "bounds", "]", "PENDOWN",
+        ];
+        let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::Assert(
+                    Condition::LessThan(
+                        Expression::Variable("X".to_string()),
+                        Expression::Float(100.0)
+                    ),
+                    "inside".to_string()
+                )),
+                ASTNode::Command(Command::Assert(
+                    Condition::GreaterThan(
+                        Expression::Variable("X".to_string()),
+                        Expression::Float(0.0)
+                    ),
+                    "Out of bounds".to_string()
+                )),
+                ASTNode::Command(Command::PenDown),
+            ]
+        );
+
+        let tokens = vec!["ASSERT", "LT", ":X", "\"100"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_parse_clip() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+        let tokens = vec!["SETCLIP", "\"0", "\"10", "\"200", "XCOR", "CLEARCLIP"];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::SetClip(
+                    Expression::Float(0.0),
+                    Expression::Float(10.0),
+                    Expression::Float(200.0),
+                    Expression::Query(Query::XCor),
+                )),
+                ASTNode::Command(Command::ClearClip),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_label_font() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+        let tokens = vec![
+            "SETLABELFONT",
+            "[",
+            "Times",
+            "New",
+            "Roman",
+            "]",
+            "SETLABELALIGN",
+            "\"Centre",
+            "SETLABELHEIGHT",
+            "\"20",
+        ];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::SetLabelFont("Times New Roman".to_string())),
+                ASTNode::Command(Command::SetLabelAlign(TextAlign::Center)),
+                ASTNode::Command(Command::SetLabelHeight(Expression::Float(20.0))),
+            ]
+        );
+
+        let mut curr_pos = 0;
+        let tokens = vec!["SETLABELALIGN", "\"justify"];
+        assert_eq!(
+            parse_tokens(tokens, &mut curr_pos, &mut env),
+            Err(ParseError {
+                kind: ParseErrorKind::InvalidSyntax {
+                    msg:
+                        "Unknown label alignment \"justify\", expected one of: left, center, right"
+                            .to_string()
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_label() {
+        let mut env = Environment::default();
+
+        let tokens = vec!["LABEL", "\"Start", "LABEL", "[", "Hello", "world", "]"];
+        let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::Label(Expression::Word("Start".to_string()))),
+                ASTNode::Command(Command::Label(Expression::Word("Hello world".to_string()))),
+            ]
+        );
+
+        let tokens = vec!["LABEL", "XCOR"];
+        let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            ast,
+            vec![ASTNode::Command(Command::Label(Expression::Query(
+                crate::ast::Query::XCor
+            )))]
+        );
+
+        let tokens = vec!["LABEL"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_parse_angle_unit() {
+        let mut env = Environment::default();
+
+        let tokens = vec!["SETANGLEUNIT", "\"radians"];
+        let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(
+            ast,
+            vec![ASTNode::Command(Command::SetAngleUnit(AngleUnit::Radians))]
+        );
+
+        let tokens = vec!["SETANGLEUNIT", "\"turns"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_syntax() {
+        let mut env = Environment::default();
+        env.vars.insert("X".to_string(), Expression::Float(0.0));
+        let tokens = vec!["SETSYNTAX", "\"infix", "FORWARD", ":X", "*", "2", "+", "10"];
+        let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(env.syntax, Syntax::Infix);
+        assert_eq!(
+            ast,
+            vec![ASTNode::Command(Command::Forward(Expression::Math(
+                Box::new(Math::Add(
+                    Expression::Math(Box::new(Math::Mul(
+                        Expression::Variable("X".to_string()),
+                        Expression::Float(2.0)
+                    ))),
+                    Expression::Float(10.0)
+                ))
+            )))]
+        );
+
+        let tokens = vec!["SETSYNTAX", "\"postfix"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_dialect() {
+        let mut env = Environment::default();
+        let tokens = vec!["LEFT", "\"10", "SETDIALECT", "\"classic", "LEFT", "\"90"];
+        let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
+        assert_eq!(env.dialect, Dialect::Classic);
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::Left(Expression::Float(10.0))),
+                ASTNode::Command(Command::TurnLeft(Expression::Float(90.0))),
+            ]
+        );
+
+        let tokens = vec!["SETDIALECT", "\"ucb"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_parse_marks() {
+        let mut env = Environment::default();
+        let tokens = vec!["MARK", "\"corner", "JUMPTO", "\"corner"];
+        let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::Mark("corner".to_string())),
+                ASTNode::Command(Command::JumpTo("corner".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_effects() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec![
+            "SETLAYER",
+            "\"1",
+            "SETEFFECT",
+            "\"shadow",
+            "\"4",
+            "\"4",
+            "\"gray",
+            "SETEFFECT",
+            "\"outline",
+            "\"2",
+            "\"3",
+            "SETEFFECT",
+            "\"none",
+        ];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::SetLayer(Expression::Float(1.0))),
+                ASTNode::Command(Command::SetShadow(
+                    Expression::Float(4.0),
+                    Expression::Float(4.0),
+                    Expression::Usize(15)
+                )),
+                ASTNode::Command(Command::SetOutline(
+                    Expression::Float(2.0),
+                    Expression::Float(3.0)
+                )),
+                ASTNode::Command(Command::ClearEffect),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_symmetry() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec!["SETSYMMETRY", "\"6", "SETTRAILFADE", "\"20", "ERASE", "\"2"];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::SetSymmetry(Expression::Float(6.0))),
+                ASTNode::Command(Command::SetTrailFade(Expression::Float(20.0))),
+                ASTNode::Command(Command::Erase(Expression::Float(2.0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_random() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec!["RERANDOM", "\"7", "TURN", "RANDOM", "\"360"];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::Rerandom(Expression::Float(7.0))),
+                ASTNode::Command(Command::Turn(Expression::Function(Box::new(
+                    Function::Random(Expression::Float(360.0))
+                )))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_set_pos() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec![
+            "SETPOS", "\"10", "\"20", "SETPOS", "[", "\"10", "\"20", "]", "SETXY", "\"10", "\"20",
+        ];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        let set_pos = ASTNode::Command(Command::SetPos(
+            Expression::Float(10.0),
+            Expression::Float(20.0),
+        ));
+        assert_eq!(ast, vec![set_pos.clone(), set_pos.clone(), set_pos]);
+
+        let mut curr_pos = 0;
+        let tokens = vec!["SETPOS", "[", "\"10", "\"20", "\"30", "]"];
+        assert!(parse_tokens(tokens, &mut curr_pos, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_pos_rel() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec!["SETPOSREL", "\"TRUE"];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![ASTNode::Command(Command::SetPosRel(Expression::Float(1.0)))]
+        );
+    }
+
+    #[test]
+    fn test_parse_transforms() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec![
+            "PUSHTRANSFORM",
+            "SCALE",
+            "\"2",
+            "ROTATE",
+            "\"45",
+            "TRANSLATE",
+            "\"10",
+            "\"-5",
+            "POPTRANSFORM",
+        ];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::PushTransform),
+                ASTNode::Command(Command::Scale(Expression::Float(2.0))),
+                ASTNode::Command(Command::Rotate(Expression::Float(45.0))),
+                ASTNode::Command(Command::Translate(
+                    Expression::Float(10.0),
+                    Expression::Float(-5.0)
+                )),
+                ASTNode::Command(Command::PopTransform),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_effect_err() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec!["SETEFFECT", "\"glow"];
+        let result = parse_tokens(tokens, &mut curr_pos, &mut env);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_fill_pattern_err() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec!["SETFILLPATTERN", "\"stripes"];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env);
+
+        assert_eq!(
+            ast,
+            Err(ParseError {
+                kind: ParseErrorKind::InvalidSyntax {
+                    msg: "Unknown fill pattern \"stripes\", expected one of: none, solid, hatch, crosshatch, dots".to_string()
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_pen_rgb() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec!["SETPENCOLOR", "[", "\"255", "\"100", "\"0", "]", "PENUP"];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::SetPenRgb(
+                    Expression::Float(255.0),
+                    Expression::Float(100.0),
+                    Expression::Float(0.0)
+                )),
+                ASTNode::Command(Command::PenUp),
+            ]
+        );
+
+        let mut curr_pos = 0;
+        let tokens = vec!["SETPENCOLOR", "[", "\"255", "\"100", "]"];
+        assert!(parse_tokens(tokens, &mut curr_pos, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_parse_pen_color_name() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec!["SETPENCOLOR", "\"Red", "SETPENCOLOR", "\"NAVY"];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::SetPenColor(Expression::Usize(4))),
+                ASTNode::Command(Command::SetPenRgb(
+                    Expression::Float(0.0),
+                    Expression::Float(0.0),
+                    Expression::Float(128.0)
+                )),
+            ]
+        );
+
+        let mut curr_pos = 0;
+        let tokens = vec!["SETPENCOLOR", "\"rde"];
+        let Err(ParseError {
+            kind: ParseErrorKind::InvalidSyntax { msg },
+        }) = parse_tokens(tokens, &mut curr_pos, &mut env)
+        else {
+            panic!("expected an unknown color error");
+        };
+        assert!(msg.starts_with("Unknown color \"rde\", expected one of: black, blue"));
+    }
+
     #[test]
     fn test_parse_pen_color_err() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let mut curr_pos = 0;
 
         let tokens = vec!["SETPENCOLOR", "\"16"];
-        let ast = parse_tokens(tokens, &mut curr_pos, &mut vars);
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env);
 
         assert_eq!(
             ast,
@@ -260,11 +1538,11 @@ mod tests {
 
     #[test]
     fn test_parse_make() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let mut curr_pos = 0;
 
         let tokens = vec!["MAKE", "\"x", "\"100"];
-        let ast = parse_tokens(tokens, &mut curr_pos, &mut vars).unwrap();
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
 
         assert_eq!(
             ast,
@@ -275,14 +1553,102 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_local() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec![
+            "LOCAL",
+            "\"X",
+            "LOCALMAKE",
+            "\"Y",
+            "+",
+            ":X",
+            "\"1",
+            "FORWARD",
+            ":Y",
+        ];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::Local("X".to_string())),
+                ASTNode::Command(Command::LocalMake(
+                    "Y".to_string(),
+                    Expression::Math(Box::new(Math::Add(
+                        Expression::Variable("X".to_string()),
+                        Expression::Float(1.0)
+                    )))
+                )),
+                ASTNode::Command(Command::Forward(Expression::Variable("Y".to_string()))),
+            ]
+        );
+
+        let tokens = vec!["CONST", "\"STEP", "\"3", "LOCAL", "\"STEP"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_parse_make_invalid_value() {
+        let mut env = Environment::default();
+
+        let tokens = vec!["MAKE", "\"A", "TOWARDS", "[", "1", "1", "]"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+
+        let tokens = vec!["MAKE", "\"A", ":MISSING"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+        assert!(!env.vars.contains_key("A"));
+    }
+
+    #[test]
+    fn test_parse_const() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec!["CONST", "\"STEP", "\"10", "FORWARD", ":STEP"];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::Make("STEP".to_string(), Expression::Float(10.0))),
+                ASTNode::Command(Command::Forward(Expression::Variable("STEP".to_string()))),
+            ]
+        );
+        assert!(env.consts.contains("STEP"));
+    }
+
+    #[test]
+    fn test_parse_const_reassigned() {
+        for tokens in [
+            vec!["CONST", "\"STEP", "\"3", "MAKE", "\"STEP", "\"4"],
+            vec!["CONST", "\"STEP", "\"3", "ADDASSIGN", "\"STEP", "\"1"],
+            vec!["CONST", "\"STEP", "\"3", "CONST", "\"STEP", "\"4"],
+        ] {
+            let mut env = Environment::default();
+            let ast = parse_tokens(tokens, &mut 0, &mut env);
+
+            assert_eq!(
+                ast,
+                Err(ParseError {
+                    kind: ParseErrorKind::ConstantReassigned {
+                        var: "STEP".to_string()
+                    }
+                })
+            );
+        }
+    }
+
     #[test]
     fn test_parse_add_assign() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
-        vars.insert("x".to_string(), Expression::Float(100.0));
+        let mut env = Environment::default();
+        env.vars.insert("x".to_string(), Expression::Float(100.0));
         let mut curr_pos = 0;
 
         let tokens = vec!["ADDASSIGN", "\"x", "\"100"];
-        let ast = parse_tokens(tokens, &mut curr_pos, &mut vars).unwrap();
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
 
         assert_eq!(
             ast,
@@ -295,11 +1661,11 @@ mod tests {
 
     #[test]
     fn test_parse_add_assign_not_var() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let mut curr_pos = 0;
 
         let tokens = vec!["ADDASSIGN", "x", "\"100"];
-        let ast = parse_tokens(tokens, &mut curr_pos, &mut vars);
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env);
 
         assert_eq!(
             ast,
@@ -313,11 +1679,11 @@ mod tests {
 
     #[test]
     fn test_parse_add_assign_no_var() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let mut curr_pos = 0;
 
         let tokens = vec!["ADDASSIGN", "\"x", "\"100"];
-        let ast = parse_tokens(tokens, &mut curr_pos, &mut vars);
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env);
 
         assert_eq!(
             ast,
@@ -331,11 +1697,11 @@ mod tests {
 
     #[test]
     fn test_parse_if() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let mut curr_pos = 0;
 
         let tokens = vec!["IF", "EQ", "\"100", "\"100", "[", "FORWARD", "\"100", "]"];
-        let ast = parse_tokens(tokens, &mut curr_pos, &mut vars).unwrap();
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
 
         assert_eq!(
             ast,
@@ -346,15 +1712,273 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_repeat() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec![
+            "REPEAT", "\"36", "[", "FORWARD", "\"10", "TURN", "\"10", "]",
+        ];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![ASTNode::ControlFlow(ControlFlow::Repeat {
+                count: Expression::Float(36.0),
+                block: vec![
+                    ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+                    ASTNode::Command(Command::Turn(Expression::Float(10.0))),
+                ]
+            })]
+        );
+
+        let tokens = vec!["REPEAT", "\"36", "FORWARD", "\"10"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_parse_stop() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec!["IF", "EQ", "XCOR", "\"0", "[", "STOP", "]", "PENDOWN"];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::ControlFlow(ControlFlow::If {
+                    condition: Condition::Equals(
+                        Expression::Query(Query::XCor),
+                        Expression::Float(0.0)
+                    ),
+                    block: vec![ASTNode::ControlFlow(ControlFlow::Stop)],
+                }),
+                ASTNode::Command(Command::PenDown),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_do_while_and_until() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec![
+            "DO.WHILE", "[", "FORWARD", "\"1", "]", "LT", "XCOR", "\"10", "PENDOWN", "UNTIL", "GT",
+            "YCOR", "\"10", "[", "BACK", "\"1", "]",
+        ];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::ControlFlow(ControlFlow::DoWhile {
+                    block: vec![ASTNode::Command(Command::Forward(Expression::Float(1.0)))],
+                    condition: Condition::LessThan(
+                        Expression::Query(Query::XCor),
+                        Expression::Float(10.0)
+                    ),
+                }),
+                ASTNode::Command(Command::PenDown),
+                ASTNode::ControlFlow(ControlFlow::Until {
+                    condition: Condition::GreaterThan(
+                        Expression::Query(Query::YCor),
+                        Expression::Float(10.0)
+                    ),
+                    block: vec![ASTNode::Command(Command::Back(Expression::Float(1.0)))],
+                }),
+            ]
+        );
+
+        let tokens = vec!["DO.WHILE", "[", "PENUP", "]"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_parse_erase_names() {
+        let mut env = Environment::default();
+
+        let tokens = vec![
+            "TO", "STEP", "END", "MAKE", "\"X", "\"1", "ERN", "\"X", "ERASE", "\"STEP", "ERASE",
+            "\"2",
+        ];
+        let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
+
+        assert_eq!(
+            ast[1..],
+            [
+                ASTNode::Command(Command::EraseName("X".to_string())),
+                ASTNode::Command(Command::EraseProcedure("STEP".to_string())),
+                ASTNode::Command(Command::Erase(Expression::Float(2.0))),
+            ]
+        );
+        assert!(!env.vars.contains_key("X"));
+
+        for tokens in [
+            vec!["FORWARD", ":X"],
+            vec!["ERN", "\"X"],
+            vec!["CONST", "\"Y", "\"1", "ERN", "\"Y"],
+        ] {
+            assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_set_item() {
+        let mut env = Environment::default();
+
+        let tokens = vec![
+            "MAKE", "\"GRID", "ARRAY", "\"3", "SETITEM", "\"1", ":GRID", "\"wall",
+        ];
+        let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
+
+        assert_eq!(
+            ast[1],
+            ASTNode::Command(Command::SetItem(
+                Expression::Float(1.0),
+                "GRID".to_string(),
+                Expression::Word("wall".to_string())
+            ))
+        );
+
+        for tokens in [
+            vec!["SETITEM", "\"1", "\"GRID", "\"0"],
+            vec!["SETITEM", "\"1", ":MISSING", "\"0"],
+        ] {
+            assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_props() {
+        let mut env = Environment::default();
+
+        let tokens = vec![
+            "PPROP", "\"SQUARE", "\"NAME", "\"red", "FORWARD", "GPROP", "\"SQUARE", "\"SIZE",
+            "REMPROP", "\"SQUARE", "\"NAME",
+        ];
+        let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::PProp(
+                    "SQUARE".to_string(),
+                    "NAME".to_string(),
+                    Expression::Word("red".to_string())
+                )),
+                ASTNode::Command(Command::Forward(Expression::GetProp(
+                    "SQUARE".to_string(),
+                    "SIZE".to_string()
+                ))),
+                ASTNode::Command(Command::RemProp("SQUARE".to_string(), "NAME".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_catch() {
+        let mut env = Environment::default();
+
+        let tokens = vec![
+            "CATCH", "\"ERROR", "[", "FORWARD", "\"10", "THROW", "\"ERROR", "]",
+        ];
+        let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![ASTNode::ControlFlow(ControlFlow::Catch {
+                tag: "ERROR".to_string(),
+                block: vec![
+                    ASTNode::Command(Command::Forward(Expression::Float(10.0))),
+                    ASTNode::ControlFlow(ControlFlow::Throw("ERROR".to_string())),
+                ],
+            })]
+        );
+
+        for tokens in [vec!["THROW"], vec!["CATCH", "ERROR", "[", "]"]] {
+            assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_foreach() {
+        let mut env = Environment::default();
+
+        let tokens = vec![
+            "MAKE", "\"SIDES", "MAP", "[", "*", ":?", "\"2", "]", "[", "10", "20", "]", "FOREACH",
+            ":SIDES", "[", "FORWARD", ":?", "]",
+        ];
+        let ast = parse_tokens(tokens, &mut 0, &mut env).unwrap();
+
+        assert_eq!(
+            ast[1],
+            ASTNode::ControlFlow(ControlFlow::ForEach {
+                list: Expression::Variable("SIDES".to_string()),
+                block: vec![ASTNode::Command(Command::Forward(Expression::Variable(
+                    "?".to_string()
+                )))],
+            })
+        );
+        assert!(!env.vars.contains_key("?"));
+
+        // `:?` is only declared inside the block.
+        let tokens = vec!["FOREACH", "[", "1", "]", "[", "]", "FORWARD", ":?"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_parse_for() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec![
+            "FOR", "[", "I", "\"10", "\"1", "-", "\"0", "\"3", "]", "[", "FORWARD", ":I", "]",
+            "FOR", "[", "\"J", "\"1", "\"2", "]", "[", "]",
+        ];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::ControlFlow(ControlFlow::For {
+                    var: "I".to_string(),
+                    start: Expression::Float(10.0),
+                    end: Expression::Float(1.0),
+                    step: Some(Expression::Math(Box::new(Math::Sub(
+                        Expression::Float(0.0),
+                        Expression::Float(3.0)
+                    )))),
+                    block: vec![ASTNode::Command(Command::Forward(Expression::Variable(
+                        "I".to_string()
+                    )))]
+                }),
+                ASTNode::ControlFlow(ControlFlow::For {
+                    var: "J".to_string(),
+                    start: Expression::Float(1.0),
+                    end: Expression::Float(2.0),
+                    step: None,
+                    block: vec![]
+                }),
+            ]
+        );
+        assert!(!env.vars.contains_key("I"));
+
+        let tokens = vec!["FOR", "I", "\"1", "\"2", "[", "]"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+    }
+
     #[test]
     fn test_parse_while() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let mut curr_pos = 0;
 
         let tokens = vec![
             "WHILE", "EQ", "\"100", "\"100", "[", "FORWARD", "\"100", "]",
         ];
-        let ast = parse_tokens(tokens, &mut curr_pos, &mut vars).unwrap();
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
 
         assert_eq!(
             ast,
@@ -367,11 +1991,11 @@ mod tests {
 
     #[test]
     fn test_parse_unexpected_token() {
-        let mut vars: HashMap<String, Expression> = HashMap::new();
+        let mut env = Environment::default();
         let mut curr_pos = 0;
 
         let tokens = vec!["INVALID"];
-        let ast = parse_tokens(tokens, &mut curr_pos, &mut vars);
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env);
 
         assert_eq!(
             ast,
@@ -382,4 +2006,127 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn test_parse_procedure_call() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec![
+            "TO", "STEP", "\"SIZE", "FORWARD", ":SIZE", "END", "STEP", "\"10",
+        ];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![ASTNode::ControlFlow(ControlFlow::Call {
+                name: "STEP".to_string(),
+                args: vec![Expression::Float(10.0)]
+            })]
+        );
+        assert_eq!(
+            env.procedures.get("STEP"),
+            Some(&Procedure {
+                name: "STEP".to_string(),
+                params: vec!["SIZE".to_string()],
+                block: vec![ASTNode::Command(Command::Forward(Expression::Variable(
+                    "SIZE".to_string()
+                )))],
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_procedure_replaces_shape() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec![
+            "POLYGON", "\"6", "\"50", "TO", "POLYGON", "\"SIDES", "\"SIZE", "FORWARD", ":SIZE",
+            "END", "POLYGON", "\"6", "\"50",
+        ];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![
+                ASTNode::Command(Command::Polygon(
+                    Expression::Float(6.0),
+                    Expression::Float(50.0)
+                )),
+                ASTNode::ControlFlow(ControlFlow::Call {
+                    name: "POLYGON".to_string(),
+                    args: vec![Expression::Float(6.0), Expression::Float(50.0)]
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec![
+            "TO", "DOUBLE", "\"N", "OUTPUT", "*", ":N", "\"2", "END", "FORWARD", "+", "DOUBLE",
+            "DOUBLE", "\"3", "\"1",
+        ];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        let double = |arg| Expression::Call("DOUBLE".to_string(), vec![arg]);
+        assert_eq!(
+            ast,
+            vec![ASTNode::Command(Command::Forward(Expression::Math(
+                Box::new(Math::Add(
+                    double(double(Expression::Float(3.0))),
+                    Expression::Float(1.0)
+                ))
+            )))]
+        );
+        assert_eq!(
+            env.procedures["DOUBLE"].block,
+            vec![ASTNode::ControlFlow(ControlFlow::Output(Expression::Math(
+                Box::new(Math::Mul(
+                    Expression::Variable("N".to_string()),
+                    Expression::Float(2.0)
+                ))
+            )))]
+        );
+    }
+
+    #[test]
+    fn test_parse_include_std() {
+        let mut env = Environment::default();
+        let mut curr_pos = 0;
+
+        let tokens = vec!["INCLUDE", "\"std", "SQUARE", "\"50"];
+        let ast = parse_tokens(tokens, &mut curr_pos, &mut env).unwrap();
+
+        assert_eq!(
+            ast,
+            vec![ASTNode::ControlFlow(ControlFlow::Call {
+                name: "SQUARE".to_string(),
+                args: vec![Expression::Float(50.0)]
+            })]
+        );
+    }
+
+    #[test]
+    fn test_parse_include_without_library() {
+        let mut env = Environment::default();
+
+        let tokens = vec!["FORWARD", "\"10", "INCLUDE"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+    }
+
+    #[test]
+    fn test_parse_unmatched_end() {
+        let mut env = Environment::default();
+
+        let tokens = vec!["FORWARD", "\"10", "END", "FORWARD", "\"20"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+
+        let tokens = vec!["FORWARD", "\"10", "]", "FORWARD", "\"20"];
+        assert!(parse_tokens(tokens, &mut 0, &mut env).is_err());
+    }
 }
@@ -0,0 +1,71 @@
+//! The bundled standard library of shape procedures.
+//!
+//! The library is written in Logo and parsed through the normal `TO`/`END`
+//! machinery, so its procedures behave exactly like user-defined ones.
+
+use crate::{ast::ASTNode, environment::Environment};
+
+use super::{
+    errors::{ParseError, ParseErrorKind},
+    parse::parse_tokens,
+    tokenise::tokenize_script,
+};
+
+/// Logo source for the standard library.
+pub const STDLIB: &str = include_str!("stdlib.lg");
+
+/// Parses the library with the given name into the environment. Only the
+/// bundled `std` library is currently available.
+///
+/// # Example
+///
+/// ```rust
+/// let mut env = Environment::default();
+/// parse_library("std", &mut env).unwrap();
+///
+/// assert!(env.procedures.contains_key("SQUARE"));
+/// ```
+pub fn parse_library(name: &str, env: &mut Environment) -> Result<Vec<ASTNode>, ParseError> {
+    match name {
+        "std" | "STD" => {
+            // The library is written in prefix syntax and rslogo's own
+            // dialect, whatever the script uses.
+            let syntax = std::mem::take(&mut env.syntax);
+            let dialect = std::mem::take(&mut env.dialect);
+            let ast = parse_tokens(tokenize_script(STDLIB), &mut 0, env);
+            env.syntax = syntax;
+            env.dialect = dialect;
+            ast
+        }
+        _ => Err(ParseError {
+            kind: ParseErrorKind::InvalidSyntax {
+                msg: format!("Unknown library: {:?}", name),
+            },
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stdlib() {
+        let mut env = Environment::default();
+        let ast = parse_library("std", &mut env).unwrap();
+
+        assert!(ast.is_empty());
+        for name in ["SQUARE", "POLYGON", "STAR", "SPIRAL", "CIRCLEAPPROX"] {
+            assert!(env.procedures.contains_key(name));
+        }
+        assert_eq!(env.procedures["POLYGON"].params, vec!["SIDES", "SIZE"]);
+        assert_eq!(env.procedures["SPIRAL"].params, vec!["STEP", "STEPS"]);
+    }
+
+    #[test]
+    fn test_parse_unknown_library() {
+        let mut env = Environment::default();
+
+        assert!(parse_library("missing", &mut env).is_err());
+    }
+}
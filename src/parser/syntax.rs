@@ -0,0 +1,235 @@
+//! The syntax expressions are written in, set with `--syntax` or `SETSYNTAX`.
+//!
+//! Logo's own syntax puts operators first, as in `+ * :X "2 "10`. Infix syntax
+//! also accepts `:X * 2 + 10`, where `*` and `/` bind tighter than `+` and
+//! `-`, which bind tighter than the comparisons `=`, `<>`, `<`, `>`, `<=` and
+//! `>=`.
+//! Operators of the same precedence group to the left, a leading `-` negates,
+//! and bare numbers can be written without a quote. Both syntaxes parse into
+//! the same `Math` expressions, so they run identically.
+
+use crate::{
+    ast::{Expression, Math},
+    environment::Environment,
+};
+
+use super::{
+    errors::{ParseError, ParseErrorKind},
+    helpers::parse_prefix,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Syntax {
+    #[default]
+    Prefix,
+    Infix,
+}
+
+impl Syntax {
+    /// The names accepted by `SETSYNTAX`.
+    pub const NAMES: [&'static str; 2] = ["prefix", "infix"];
+
+    pub fn from_name(name: &str) -> Option<Syntax> {
+        match name.to_lowercase().as_str() {
+            "prefix" => Some(Syntax::Prefix),
+            "infix" => Some(Syntax::Infix),
+            _ => None,
+        }
+    }
+}
+
+/// How tightly an infix operator binds, or `None` if the token isn't one.
+fn precedence(token: &str) -> Option<u8> {
+    match token {
+        "=" | "<>" | "<" | ">" | "<=" | ">=" => Some(1),
+        "+" | "-" => Some(2),
+        "*" | "/" => Some(3),
+        _ => None,
+    }
+}
+
+/// Parses an infix expression such as `:X * 2 + 10`, leaving the position at
+/// its last token.
+///
+/// # Example
+///
+/// ```rust
+/// let mut env = Environment::default();
+/// let tokens = vec!["1", "+", "2", "*", "3"];
+/// let expr = parse_infix(&tokens, &mut 0, &mut env).unwrap();
+///
+/// // 1 + (2 * 3)
+/// assert!(matches!(expr, Expression::Math(math) if matches!(*math, Math::Add(..))));
+/// ```
+pub fn parse_infix(
+    tokens: &[&str],
+    pos: &mut usize,
+    env: &mut Environment,
+) -> Result<Expression, ParseError> {
+    parse_binary(tokens, pos, env, 0)
+}
+
+/// Parses operands joined by operators binding at least as tightly as
+/// `min_precedence`.
+fn parse_binary(
+    tokens: &[&str],
+    pos: &mut usize,
+    env: &mut Environment,
+    min_precedence: u8,
+) -> Result<Expression, ParseError> {
+    let mut lhs = parse_operand(tokens, pos, env)?;
+    while let Some(&operator) = tokens.get(*pos + 1) {
+        let Some(prec) = precedence(operator).filter(|&prec| prec >= min_precedence) else {
+            break;
+        };
+        *pos += 2;
+        let rhs = parse_binary(tokens, pos, env, prec + 1)?;
+        let math = match operator {
+            "+" => Math::Add(lhs, rhs),
+            "-" => Math::Sub(lhs, rhs),
+            "*" => Math::Mul(lhs, rhs),
+            "/" => Math::Div(lhs, rhs),
+            "=" => Math::Eq(lhs, rhs),
+            "<>" => Math::Ne(lhs, rhs),
+            "<" => Math::Lt(lhs, rhs),
+            ">" => Math::Gt(lhs, rhs),
+            "<=" => Math::Le(lhs, rhs),
+            ">=" => Math::Ge(lhs, rhs),
+            _ => unreachable!(),
+        };
+        lhs = Expression::Math(Box::new(math));
+    }
+    Ok(lhs)
+}
+
+/// Parses a single operand: a bare number, a negated operand, or anything the
+/// prefix syntax accepts, including parentheses and prefix operators.
+fn parse_operand(
+    tokens: &[&str],
+    pos: &mut usize,
+    env: &mut Environment,
+) -> Result<Expression, ParseError> {
+    let Some(&token) = tokens.get(*pos) else {
+        return Err(ParseError {
+            kind: ParseErrorKind::InvalidSyntax {
+                msg: "Expected an operand".to_string(),
+            },
+        });
+    };
+    if token == "-" {
+        *pos += 1;
+        let operand = parse_operand(tokens, pos, env)?;
+        return Ok(Expression::Math(Box::new(Math::Sub(
+            Expression::Float(0.0),
+            operand,
+        ))));
+    }
+    match token.parse::<f64>() {
+        Ok(val) if val.is_finite() => Ok(Expression::Float(val)),
+        _ => parse_prefix(tokens, pos, env),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(tokens: &[&str]) -> Expression {
+        let mut env = Environment {
+            syntax: Syntax::Infix,
+            ..Default::default()
+        };
+        env.vars.insert("X".to_string(), Expression::Float(0.0));
+        let mut pos = 0;
+        let expr = parse_infix(tokens, &mut pos, &mut env).unwrap();
+        assert_eq!(pos, tokens.len() - 1);
+        expr
+    }
+
+    fn math(math: Math) -> Expression {
+        Expression::Math(Box::new(math))
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Syntax::from_name("Infix"), Some(Syntax::Infix));
+        assert_eq!(Syntax::from_name("postfix"), None);
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        let x = || Expression::Variable("X".to_string());
+        let expr = parse(&[":X", "*", "2", "+", "10"]);
+        assert_eq!(
+            expr,
+            math(Math::Add(
+                math(Math::Mul(x(), Expression::Float(2.0))),
+                Expression::Float(10.0)
+            ))
+        );
+
+        let expr = parse(&["10", "+", ":X", "*", "2"]);
+        assert_eq!(
+            expr,
+            math(Math::Add(
+                Expression::Float(10.0),
+                math(Math::Mul(x(), Expression::Float(2.0)))
+            ))
+        );
+
+        let expr = parse(&["1", "+", "2", "<", "4"]);
+        assert_eq!(
+            expr,
+            math(Math::Lt(
+                math(Math::Add(Expression::Float(1.0), Expression::Float(2.0))),
+                Expression::Float(4.0)
+            ))
+        );
+
+        let expr = parse(&[":X", ">=", "2", "*", "3"]);
+        assert_eq!(
+            expr,
+            math(Math::Ge(
+                x(),
+                math(Math::Mul(Expression::Float(2.0), Expression::Float(3.0)))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_left_associative() {
+        let expr = parse(&["8", "-", "4", "-", "2"]);
+        assert_eq!(
+            expr,
+            math(Math::Sub(
+                math(Math::Sub(Expression::Float(8.0), Expression::Float(4.0))),
+                Expression::Float(2.0)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_parentheses_and_negation() {
+        let expr = parse(&["(", "1", "+", "2", ")", "*", "-", "3"]);
+        assert_eq!(
+            expr,
+            math(Math::Mul(
+                math(Math::Add(Expression::Float(1.0), Expression::Float(2.0))),
+                math(Math::Sub(Expression::Float(0.0), Expression::Float(3.0)))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_prefix_operand() {
+        // The operands of prefix operators are infix expressions themselves.
+        let expr = parse(&["MAX", "\"1", "\"2", "+", "1"]);
+        assert_eq!(
+            expr,
+            math(Math::Max(
+                Expression::Float(1.0),
+                math(Math::Add(Expression::Float(2.0), Expression::Float(1.0)))
+            ))
+        );
+    }
+}
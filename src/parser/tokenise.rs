@@ -1,5 +1,7 @@
 /// Tokenises an Logo script into a vector of tokens. Each token is an instruction
-/// or value.
+/// or value. Lines starting with `//` are skipped, as is anything after a `;`
+/// on a line. Brackets and parentheses are tokens of their own, whether or
+/// not there is whitespace around them, so `[FORWARD "100]` is four tokens.
 ///
 /// # Examples
 ///
@@ -8,7 +10,7 @@
 /// PENDOWN
 ///
 /// SETPENCOLOR "1
-/// FORWARD "100
+/// FORWARD "100 ; draw an edge
 /// ```
 ///
 /// Tokenising this script would result in the following vector:
@@ -19,7 +21,7 @@
 pub fn tokenize_script(contents: &str) -> Vec<&str> {
     let tokens: Vec<&str> = contents
         .lines()
-        .map(|line| line.trim())
+        .map(|line| line.split(';').next().unwrap_or(line).trim())
         .filter(|line| !line.is_empty())
         .filter(|line| !line.starts_with("//"))
         .collect();
@@ -27,9 +29,32 @@ pub fn tokenize_script(contents: &str) -> Vec<&str> {
     tokens
         .iter()
         .flat_map(|line| line.split_whitespace())
+        .flat_map(split_delimiters)
         .collect()
 }
 
+/// The characters that are always tokens of their own.
+const DELIMITERS: [char; 4] = ['[', ']', '(', ')'];
+
+/// Splits the delimiters out of a word, keeping the rest of it together.
+fn split_delimiters(word: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    for (i, c) in word.char_indices() {
+        if DELIMITERS.contains(&c) {
+            if start < i {
+                tokens.push(&word[start..i]);
+            }
+            tokens.push(&word[i..i + c.len_utf8()]);
+            start = i + c.len_utf8();
+        }
+    }
+    if start < word.len() {
+        tokens.push(&word[start..]);
+    }
+    tokens
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -46,4 +71,31 @@ mod tests {
         let expected = vec!["PENDOWN", "SETPENCOLOR", "\"1", "FORWARD", "\"100"];
         assert_eq!(tokenize_script(script), expected);
     }
+
+    #[test]
+    fn test_tokenize_brackets() {
+        let script = r#"IF EQ :x "1 [FORWARD "100]
+            REPEAT "2 [[FORWARD (+ :x "1)]]"#;
+
+        let expected = vec![
+            "IF", "EQ", ":x", "\"1", "[", "FORWARD", "\"100", "]", "REPEAT", "\"2", "[", "[",
+            "FORWARD", "(", "+", ":x", "\"1", ")", "]", "]",
+        ];
+        assert_eq!(tokenize_script(script), expected);
+    }
+
+    #[test]
+    fn test_tokenize_semicolon_comments() {
+        let script = r#"
+            ; A square
+            REPEAT "4 [ FORWARD "100 ; draw an edge
+              TURN "90 ;turn the corner
+            ]
+        "#;
+
+        let expected = vec![
+            "REPEAT", "\"4", "[", "FORWARD", "\"100", "TURN", "\"90", "]",
+        ];
+        assert_eq!(tokenize_script(script), expected);
+    }
 }
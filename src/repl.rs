@@ -0,0 +1,92 @@
+//! An interactive session that runs statements as they are typed.
+//!
+//! Statements are read with the same splitting as `--stream`, so a `[` block
+//! or `TO ... END` definition runs once it is closed. The drawing is saved
+//! after every statement, so an image viewer that reloads on change shows it
+//! growing. Errors are reported without ending the session.
+//!
+//! Entering `TO NAME ... END` for a procedure that already exists replaces it
+//! in place, with a warning, so a procedure can be reworked without starting
+//! over. Earlier calls look the procedure up by name, so they use the new
+//! definition from then on. If the new definition fails to parse, the old one
+//! is kept.
+
+use std::{error::Error, io::BufRead, path::Path};
+
+use crate::{
+    environment::Environment,
+    interpreter::turtle::Turtle,
+    parser::tokenise::tokenize_script,
+    save_image,
+    stream::{run_statement, statements},
+};
+
+/// Runs the statements read from `input` until it ends, saving the drawing to
+/// `image_path` after each one.
+pub fn repl(
+    input: impl BufRead,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+    image_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    for statement in statements(input) {
+        let statement = statement?;
+
+        let redefined = redefined_procedure(&statement, env);
+        let previous = redefined.and_then(|name| env.procedures.get(name).cloned());
+        if let Some(name) = redefined {
+            eprintln!("Warning: replacing the existing definition of {name}");
+        }
+
+        match run_statement(&statement, turtle, env) {
+            Ok(()) => save_image(turtle.canvas, image_path)?,
+            Err(e) => {
+                eprintln!("Error: {e}");
+                if let Some(previous) = previous {
+                    env.procedures.insert(previous.name.clone(), previous);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The name of the procedure `statement` defines, if one of that name already
+/// exists.
+fn redefined_procedure<'a>(statement: &'a str, env: &Environment) -> Option<&'a str> {
+    match tokenize_script(statement)[..] {
+        ["TO", name, ..] if env.procedures.contains_key(name) => Some(name),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::canvas::Canvas;
+
+    use super::*;
+
+    #[test]
+    fn test_repl_redefines_procedure() {
+        let path = std::env::temp_dir().join("rslogo_test_repl.svg");
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        let input = "TO STEP\nFORWARD \"10\nEND\n\
+            STEP\n\
+            TO STEP\nFORWARD \"20\nEND\n\
+            STEP\n\
+            TO STEP\nFORWARD\nEND\n\
+            STEP\n";
+
+        repl(input.as_bytes(), &mut turtle, &mut env, &path).unwrap();
+
+        // Starting from the centre, at 50, the turtle moves 10 and then 20
+        // twice, as the broken third definition is rejected.
+        assert_eq!(turtle.ycor(), 0.0);
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with("<svg"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+//! Loading script files into memory.
+//!
+//! With the `mmap` feature, scripts of at least `MMAP_THRESHOLD` bytes are
+//! memory-mapped rather than read into a `String`, so tokenising borrows
+//! straight from the mapped file and large generated scripts start running
+//! without first being copied. Smaller scripts are always read normally,
+//! since mapping costs more than it saves for them.
+
+use std::{error::Error, fs::File, io::Read, path::Path};
+
+/// Scripts at least this many bytes long are memory-mapped.
+#[cfg(feature = "mmap")]
+pub const MMAP_THRESHOLD: u64 = 1 << 20;
+
+/// The contents of a script file.
+pub enum Source {
+    Owned(String),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl Source {
+    /// Opens the script at `path`, checking that it is valid UTF-8.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut file = File::open(path)?;
+
+        #[cfg(feature = "mmap")]
+        if file.metadata()?.len() >= MMAP_THRESHOLD {
+            // SAFETY: the map is only read, and the script isn't expected to
+            // be modified while it is being run.
+            let map = unsafe { memmap2::Mmap::map(&file)? };
+            std::str::from_utf8(&map)?;
+            return Ok(Source::Mapped(map));
+        }
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        Ok(Source::Owned(contents))
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Source::Owned(contents) => contents,
+            // SAFETY: the contents were checked to be UTF-8 in `open`.
+            #[cfg(feature = "mmap")]
+            Source::Mapped(map) => unsafe { std::str::from_utf8_unchecked(map) },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open() {
+        let path = std::env::temp_dir().join("rslogo_test_open.lg");
+        std::fs::write(&path, "PENDOWN\nFORWARD \"10\n").unwrap();
+
+        let source = Source::open(&path).unwrap();
+        assert_eq!(source.as_str(), "PENDOWN\nFORWARD \"10\n");
+        assert!(matches!(source, Source::Owned(_)));
+
+        std::fs::write(&path, [0xff, 0xfe]).unwrap();
+        assert!(Source::open(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_open_mapped() {
+        let path = std::env::temp_dir().join("rslogo_test_open_mapped.lg");
+        let script = "FORWARD \"1\n".repeat(MMAP_THRESHOLD as usize / 10);
+        std::fs::write(&path, &script).unwrap();
+
+        let source = Source::open(&path).unwrap();
+        assert!(matches!(source, Source::Mapped(_)));
+        assert_eq!(source.as_str(), script);
+
+        drop(source);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
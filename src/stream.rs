@@ -0,0 +1,185 @@
+//! Streaming execution of scripts too large to hold in memory at once.
+//!
+//! Instead of tokenising and parsing the whole file upfront, the script is
+//! read a line at a time and each top-level statement is parsed and executed
+//! as soon as it is complete. Lines are buffered while a `[` block or a
+//! `TO ... END` definition is still open, so only the statement currently
+//! being run is ever held as tokens and AST nodes.
+//!
+//! Top-level statements can't refer forward, so running them in order is
+//! exactly what the whole-file path does. Variables and procedures declared by
+//! earlier statements stay in the environment for later ones.
+
+use std::{
+    error::Error,
+    io::{self, BufRead, Lines},
+};
+
+use crate::{
+    environment::Environment,
+    interpreter::{execute::execute, limits::count_nodes, turtle::Turtle},
+    parser::{parse::parse_tokens, tokenise::tokenize_script},
+};
+
+/// Parses and executes the script read from `reader` statement by statement.
+///
+/// # Example
+///
+/// ```rust
+/// let script = "PENDOWN\nFORWARD \"100\n";
+/// stream_script(script.as_bytes(), &mut turtle, &mut env).unwrap();
+///
+/// assert_eq!(turtle.ycor(), -100.0);
+/// ```
+pub fn stream_script(
+    reader: impl BufRead,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<(), Box<dyn Error>> {
+    for statement in statements(reader) {
+        run_statement(&statement?, turtle, env)?;
+    }
+
+    Ok(())
+}
+
+/// Splits the script read from `reader` into its top-level statements.
+pub fn statements<R: BufRead>(reader: R) -> Statements<R> {
+    Statements {
+        lines: reader.lines(),
+    }
+}
+
+/// An iterator over the top-level statements of a script, each holding the
+/// lines it spans. A statement left with an unclosed block at the end of the
+/// script is still returned, so that the parser can report it.
+pub struct Statements<R> {
+    lines: Lines<R>,
+}
+
+impl<R: BufRead> Iterator for Statements<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut statement = String::new();
+        let mut depth = 0usize;
+
+        for line in self.lines.by_ref() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            let tokens = tokenize_script(&line);
+            for token in &tokens {
+                match *token {
+                    "[" | "TO" => depth += 1,
+                    "]" | "END" => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+            if tokens.is_empty() && depth == 0 {
+                continue;
+            }
+            statement.push_str(&line);
+            statement.push('\n');
+
+            if depth == 0 {
+                return Some(Ok(statement));
+            }
+        }
+
+        (!statement.is_empty()).then_some(Ok(statement))
+    }
+}
+
+/// Parses and executes a single top-level statement.
+pub fn run_statement(
+    statement: &str,
+    turtle: &mut Turtle,
+    env: &mut Environment,
+) -> Result<(), Box<dyn Error>> {
+    let tokens = tokenize_script(statement);
+    if tokens.is_empty() {
+        return Ok(());
+    }
+
+    let ast = parse_tokens(tokens, &mut 0, env)?;
+    env.limits.ast_nodes = count_nodes(&ast)
+        + env
+            .procedures
+            .values()
+            .map(|procedure| count_nodes(&procedure.block))
+            .sum::<usize>();
+    execute(&ast, turtle, env)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::canvas::Canvas;
+
+    use super::*;
+
+    const SCRIPT: &str = r#"
+        MAKE "SIDE "40
+        TO SQUARE
+            MAKE "I "0
+            WHILE LT :I "4 [
+                FORWARD :SIDE
+                RIGHT "90
+                ADDASSIGN "I "1
+            ]
+        END
+
+        PENDOWN
+        SQUARE
+        IF EQ :SIDE "40 [
+            SETX "10
+        ]
+    "#;
+
+    #[test]
+    fn test_stream_script() {
+        let mut expected = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut expected);
+        let mut env = Environment::default();
+        let ast = parse_tokens(tokenize_script(SCRIPT), &mut 0, &mut env).unwrap();
+        execute(&ast, &mut turtle, &mut env).unwrap();
+
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        stream_script(SCRIPT.as_bytes(), &mut turtle, &mut env).unwrap();
+
+        assert_eq!(turtle.xcor(), 10.0);
+        assert_eq!(canvas.to_svg(), expected.to_svg());
+    }
+
+    #[test]
+    fn test_statements() {
+        let script = "PENDOWN\n\nTO STEP\nFORWARD \"1\nEND\n// done\nIF EQ \"1 \"1 [\n";
+        let statements = statements(script.as_bytes())
+            .collect::<io::Result<Vec<String>>>()
+            .unwrap();
+
+        assert_eq!(
+            statements,
+            vec![
+                "PENDOWN\n",
+                "TO STEP\nFORWARD \"1\nEND\n",
+                "IF EQ \"1 \"1 [\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stream_script_unclosed_block() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut turtle = Turtle::new(&mut canvas);
+        let mut env = Environment::default();
+        let script = "PENDOWN\nIF EQ \"1 \"1 [\nFORWARD \"10\n";
+
+        assert!(stream_script(script.as_bytes(), &mut turtle, &mut env).is_err());
+    }
+}